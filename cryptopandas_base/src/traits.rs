@@ -33,7 +33,7 @@ impl<U: PandaAttribute> PandaTrait for U {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
 #[DieselType = "Physique"]
 #[repr(u8)]
 pub enum PhysiqueTrait {
@@ -58,7 +58,7 @@ impl PandaAttribute for PhysiqueTrait {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
 #[DieselType = "Pattern"]
 #[repr(u8)]
 pub enum PatternTrait {
@@ -85,7 +85,7 @@ impl PandaAttribute for PatternTrait {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
 #[DieselType = "Eye_color"]
 #[repr(u8)]
 pub enum EyeColorTrait {
@@ -149,7 +149,7 @@ impl PandaAttribute for EyeColorTrait {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
 #[DieselType = "Eye_shape"]
 #[repr(u8)]
 pub enum EyeShapeTrait {
@@ -173,7 +173,7 @@ impl PandaAttribute for EyeShapeTrait {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
 #[DieselType = "Base_color"]
 #[repr(u8)]
 pub enum BaseColorTrait {
@@ -237,7 +237,7 @@ impl PandaAttribute for BaseColorTrait {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
 #[DieselType = "Highlight_color"]
 #[repr(u8)]
 pub enum HighlightColorTrait {
@@ -301,7 +301,7 @@ impl PandaAttribute for HighlightColorTrait {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
 #[DieselType = "Accent_color"]
 #[repr(u8)]
 pub enum AccentColorTrait {
@@ -365,7 +365,7 @@ impl PandaAttribute for AccentColorTrait {
     }
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, TryFromPrimitive, IntoPrimitive, DbEnum)]
 #[DieselType = "Wild_element"]
 #[repr(u8)]
 pub enum WildElementTrait {
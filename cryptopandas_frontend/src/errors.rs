@@ -33,3 +33,24 @@ pub enum SelectionError {
     Serde(serde_json::Error),
     Handlebars,
 }
+
+#[derive(Debug)]
+pub enum MarketError {
+    Diesel(DieselError),
+    Connection(String), // TODO: This is string because Diesel pub use
+    Serde(serde_json::Error),
+    Handlebars,
+}
+
+#[derive(Debug)]
+pub enum TransferError {
+    Diesel(DieselError),
+    Connection(String), // TODO: This is string because Diesel pub use
+    Address(AddressError),
+    Hex(FromHexError),
+    InvalidTokenId,
+    InvalidFeeInput(String),
+    PandaUtxoWithoutAddress,
+    Tx(slpdexdb_base::Error),
+    Io(std::io::Error),
+}
@@ -11,7 +11,7 @@ use std::{convert::TryInto, io};
 
 use actix_web::{error::BlockingError, web, Error};
 use actix_web::{App, HttpResponse, HttpServer};
-use cashcontracts::{Address, AddressType};
+use cashcontracts::{Address, AddressType, TxOutpoint, tx_hex_to_hash};
 use diesel::{
     prelude::*,
     r2d2::{self, ConnectionManager},
@@ -21,7 +21,9 @@ use handlebars::Handlebars;
 use panda_base::traits::*;
 
 use crate::errors::*;
-use dex_db::{models::DbPandaFull, panda_tools::*};
+use dex_db::{models::DbPandaFull, panda::PandaTransferTx, panda_tools::*, tx_hash_from_le_slice,
+             PandaForSale, PandaSaleFilter};
+use slpdexdb_base::{SLPDEXConfig, convert_numeric::PrettyRational};
 
 type Pool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
@@ -71,6 +73,74 @@ impl From<DbPandaFull> for PandaFrontEnd {
     }
 }
 
+#[derive(Serialize)]
+struct PandaForSaleFrontEnd {
+    pub token_id: String,
+    pub offer_tx: String,
+    pub offer_vout: i32,
+    pub price_satoshis: String,
+    pub seller_address: String,
+    #[serde(flatten)]
+    pub attributes: PandaAttributes,
+}
+
+impl From<PandaForSale> for PandaForSaleFrontEnd {
+    fn from(panda: PandaForSale) -> PandaForSaleFrontEnd {
+        PandaForSaleFrontEnd {
+            token_id: hex::encode(&panda.token_hash[..]),
+            offer_tx: hex::encode(&panda.offer_tx[..]),
+            offer_vout: panda.offer_output_idx,
+            price_satoshis: format!("{}", PrettyRational(panda.price_satoshis())),
+            seller_address: panda.seller_address
+                .with_prefix("simpleledger".to_string())
+                .cash_addr()
+                .to_string(),
+            attributes: panda.attributes,
+        }
+    }
+}
+
+/// Get Pandas for sale
+fn market(
+    hb: web::Data<Handlebars>,
+    pool: web::Data<Pool>,
+    filter: web::Query<PandaSaleFilter>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    web::block(move || {
+        // Get connection
+        let conn: &PgConnection = &*pool
+            .get()
+            .map_err(|err| MarketError::Connection(err.to_string()))?;
+
+        // Grab pandas for sale from DB
+        let config = SLPDEXConfig::default();
+        let pandas = get_pandas_for_sale(&config.panda_token_hash, None, &conn)
+            .map_err(MarketError::Diesel)?;
+
+        // Filter and sort
+        let pandas: Vec<PandaForSaleFrontEnd> = filter
+            .apply(pandas)
+            .into_iter()
+            .map(PandaForSaleFrontEnd::from)
+            .collect();
+
+        // Convert to JSON
+        let data = serde_json::to_value(pandas).map_err(MarketError::Serde)?;
+
+        // Render using handle bars
+        Ok(hb
+            .render("market", &data)
+            .map_err(|_| MarketError::Handlebars)?)
+    })
+    .then(
+        // TODO: Fine grained error matching
+        |res: Result<String, BlockingError<MarketError>>| match res {
+            Ok(body) => Ok(HttpResponse::Ok().body(body)),
+            Err(_) => Ok(HttpResponse::NotFound().finish()),
+        },
+    )
+}
+
 /// Get Breeders
 fn breeders(
     hb: web::Data<Handlebars>,
@@ -273,6 +343,121 @@ fn panda_by_token_id(
     )
 }
 
+#[derive(Deserialize)]
+struct TransferQuery {
+    token_id: String,
+    destination: String,
+    /// Comma separated `txid:vout:satoshis` of the UTXOs paying the fee
+    fee_inputs: String,
+    /// Comma separated hex DER signatures, one per input
+    signatures: Option<String>,
+    pub_key: Option<String>,
+}
+
+fn parse_fee_inputs(fee_inputs: &str) -> Result<Vec<(TxOutpoint, u64)>, TransferError> {
+    fee_inputs
+        .split(',')
+        .filter(|fee_input| !fee_input.is_empty())
+        .map(|fee_input| {
+            let invalid = || TransferError::InvalidFeeInput(fee_input.to_string());
+            let parts: Vec<&str> = fee_input.split(':').collect();
+            if parts.len() != 3 {
+                return Err(invalid());
+            }
+            let tx_hash = tx_hex_to_hash(parts[0]).ok_or_else(invalid)?;
+            let vout = parts[1].parse::<u32>().map_err(|_| invalid())?;
+            let amount = parts[2].parse::<u64>().map_err(|_| invalid())?;
+            Ok((TxOutpoint { tx_hash: tx_hash_from_le_slice(&tx_hash), vout }, amount))
+        })
+        .collect()
+}
+
+/// Build a transfer of a panda to another address. Without signatures, returns the pre-images
+/// of the inputs for external signing; with signatures, returns the signed tx.
+fn transfer(
+    pool: web::Data<Pool>,
+    config: web::Data<SLPDEXConfig>,
+    query: web::Query<TransferQuery>,
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    web::block(move || {
+        // Get connection
+        let conn: &PgConnection = &*pool
+            .get()
+            .map_err(|err| TransferError::Connection(err.to_string()))?;
+
+        // Decode token id
+        let raw_token_id = hex::decode(&query.token_id).map_err(TransferError::Hex)?;
+        let token_id: [u8; 32] = (&raw_token_id[..])
+            .try_into()
+            .map_err(|_| TransferError::InvalidTokenId)?;
+
+        // Decode destination
+        let destination_address =
+            Address::from_cash_addr(query.destination.to_string()).map_err(TransferError::Address)?;
+
+        // Grab the panda's current UTXO from DB
+        let panda_utxo = get_panda_utxo(&token_id, &conn).map_err(TransferError::Diesel)?;
+        let owner_address = panda_utxo
+            .address
+            .ok_or(TransferError::PandaUtxoWithoutAddress)?;
+
+        let transfer_tx = PandaTransferTx {
+            token_id,
+            panda_utxo: (
+                TxOutpoint {
+                    tx_hash: tx_hash_from_le_slice(&panda_utxo.tx_hash),
+                    vout: panda_utxo.output_idx as u32,
+                },
+                panda_utxo.value_satoshis as u64,
+            ),
+            owner_address: Address::from_slice(AddressType::P2PKH, &owner_address)
+                .ok_or(TransferError::PandaUtxoWithoutAddress)?,
+            destination_address,
+            fee_inputs: parse_fee_inputs(&query.fee_inputs)?,
+            fee_policy: config.fee_policy,
+        };
+
+        match (&query.signatures, &query.pub_key) {
+            (Some(signatures), Some(pub_key)) => {
+                let signatures = signatures
+                    .split(',')
+                    .map(hex::decode)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(TransferError::Hex)?;
+                let pub_key = hex::decode(pub_key).map_err(TransferError::Hex)?;
+                let tx = transfer_tx
+                    .sign(signatures, pub_key)
+                    .map_err(TransferError::Tx)?;
+                let mut tx_ser = Vec::new();
+                tx.write_to_stream(&mut tx_ser).map_err(TransferError::Io)?;
+                Ok(json!({ "tx": hex::encode(&tx_ser) }))
+            }
+            _ => {
+                let estimate = transfer_tx
+                    .estimate()
+                    .map_err(TransferError::Tx)?;
+                let pre_images = transfer_tx
+                    .pre_images()
+                    .map_err(TransferError::Tx)?;
+                Ok(json!({
+                    "preImages": pre_images.iter().map(hex::encode).collect::<Vec<_>>(),
+                    "fee": estimate.fee,
+                    "size": estimate.size,
+                }))
+            }
+        }
+    })
+    .then(
+        |res: Result<serde_json::Value, BlockingError<TransferError>>| match res {
+            Ok(data) => Ok(HttpResponse::Ok().json(data)),
+            Err(BlockingError::Error(TransferError::Diesel(_))) => {
+                Ok(HttpResponse::NotFound().finish())
+            }
+            Err(_) => Ok(HttpResponse::BadRequest().finish()),
+        },
+    )
+}
+
 fn main() -> io::Result<()> {
     // Init handlebars
     let mut handlebars = Handlebars::new();
@@ -281,6 +466,13 @@ fn main() -> io::Result<()> {
         .unwrap();
     let handlebars_ref = web::Data::new(handlebars);
 
+    // Fee policy of the txs we build; pre-images and signed txs must use the same one
+    let mut config = SLPDEXConfig::default();
+    if let Ok(sats_per_byte) = std::env::var("SATS_PER_BYTE") {
+        config.fee_policy.sats_per_byte = sats_per_byte.parse().expect("SATS_PER_BYTE");
+    }
+    let config_ref = web::Data::new(config);
+
     // Init SQL connection
     let connection_str = std::env::var("DATABASE_URL").expect("DATABASE_URL");
     let manager = ConnectionManager::<PgConnection>::new(connection_str);
@@ -291,6 +483,7 @@ fn main() -> io::Result<()> {
     HttpServer::new(move || {
         App::new()
             .register_data(handlebars_ref.clone())
+            .register_data(config_ref.clone())
             .data(pool.clone())
             .service(index)
             .service(
@@ -303,6 +496,8 @@ fn main() -> io::Result<()> {
             .service(web::resource("/breeders").route(web::get().to_async(breeders)))
             .service(web::resource("/selection").route(web::get().to_async(selection)))
             .service(web::resource("/breed").route(web::get().to(breed)))
+            .service(web::resource("/market").route(web::get().to_async(market)))
+            .service(web::resource("/transfer").route(web::get().to_async(transfer)))
     })
     .bind("127.0.0.1:8080")?
     .run()
@@ -1,15 +1,21 @@
 use cashcontracts::tx_hex_to_hash;
+use crate::fee_policy::FeePolicy;
 
 #[derive(Clone, Debug)]
 pub struct SLPDEXConfig {
     pub fee_address: cashcontracts::Address,
     pub fee_divisor: u64,
-    pub dust_limit: u64,
+    pub fee_policy: FeePolicy,
     pub exch_lokad: &'static str,
     pub exch_lokad_b64: String,
-    pub exch_version: i32,
+    pub exch_versions: Vec<u8>,
     pub panda_token_hash: [u8; 32],
     pub panda_fee: u64,
+    pub pnd1_chain_id: &'static str,
+    pub cash_addr_prefix: &'static str,
+    pub pnd1_legacy_cutoff_height: i32,
+    /// REST API returning the raw hex of the tx with the appended txid, to validate SLP txs.
+    pub raw_tx_url: String,
 }
 
 impl Default for SLPDEXConfig {
@@ -19,12 +25,16 @@ impl Default for SLPDEXConfig {
                 "bitcoincash:qr4tqy4xye3y7cxtwxskr0l445lf55tnnchv8474jd".to_string()
             ).unwrap(),
             fee_divisor: 500,
-            dust_limit: 0x222,
+            fee_policy: FeePolicy::default(),
             exch_lokad: "EXCH",
             exch_lokad_b64: base64::encode("EXCH"),
-            exch_version: 2,
+            exch_versions: vec![2],
             panda_token_hash: tx_hex_to_hash("af5fb817275c12a403df832cf61af135d0cd7a63f9c0fedb10ff3b2b50799533").unwrap(),
             panda_fee: 100_000,
+            pnd1_chain_id: "bitcoincash",
+            cash_addr_prefix: "bitcoincash",
+            pnd1_legacy_cutoff_height: 610_000,
+            raw_tx_url: "https://rest.bitcoin.com/v2/rawtransactions/getRawTransaction/".to_string(),
         }
     }
 }
@@ -8,6 +8,13 @@ pub enum SLPError {
     InvalidSLPType(String),
     InvalidTokenTypeLength(String),
     InvalidTokenHashLength(String),
+    InvalidDocumentHashLength(String),
+    InvalidDecimals(String),
+    InvalidMintBatonVout(String),
+    InvalidAmountLength(String),
+    NFT1ChildDecimals(u8),  // NFT1 child GENESIS must have 0 decimals
+    NFT1ChildQuantity(u64),  // NFT1 child GENESIS must mint exactly 1
+    NFT1ChildMintBaton,  // NFT1 child GENESIS must not have a mint baton
     NoMatch,
 }
 
@@ -22,6 +29,9 @@ pub enum NumericError {
 pub enum TradeOfferError {
     InvalidPrice(Vec<u8>),
     InvalidPower(Vec<u8>),
+    InsufficientTokens(i128),
+    /// The tx hash of an ad tx without the offer's ad output
+    InvalidAdTx(String),
 }
 
 #[derive(Debug)]
@@ -54,13 +64,65 @@ pub enum PNDError {
     FatherNotOwnedByAddress(String, String),
     MotherNotOwnedByAddress(String, String),
     DoesntPayPandaFee,
+    UnsupportedVersion(String),
+    InvalidRecipientAddress(String),
+    LegacyMessageExpired(i32),  // legacy PND1 messages aren't accepted at this height anymore
+    InvalidSireHash(String),
+    InvalidSireOutputIdx(String),
+    InvalidSireFee(String),
+    SireDoesntExist(String),
+    WrongSireToken(String),
+    InvalidSireUTXO(String),
+    SireNotOwnedByAddress(String, String),
+    DoesntPaySireFee(u64),
+    InvalidAuctionedHash(String),
+    InvalidAuctionedOutputIdx(String),
+    AuctionedPandaDoesntExist(String),
+    WrongAuctionedToken(String),
+    InvalidAuctionedUTXO(String),
+    AuctionedPandaNotOwnedByAddress(String, String),
+    InvalidAuctionPrice(String),
+    InvalidAuctionHeight(String),
+    InvalidAuctionDuration(String),
 }
 
 
+#[derive(Debug)]
+pub enum SignerError {
+    InvalidSecretKey,
+    InvalidKeystore(String),
+    WrongPassword,
+    InvalidResponse(String),
+    Remote(String),  // error reported by an out-of-process signer
+    FeeAddressMismatch(String, String),  // fee address of the config, of the wallet
+    InvalidWalletAddress(String),
+    InvalidNonce,  // deterministic Schnorr nonce is 0 mod n
+}
+
+#[derive(Debug)]
+pub enum ChainSourceError {
+    UnsupportedFilter(String),  // filter the backend can't serve, e.g. addresses from a node
+    Rpc(String),  // error reported by the node
+    InvalidResponse(String),
+    InvalidFixture(String),
+}
+
+#[derive(Debug)]
+pub enum SubmitTxError {
+    InvalidTx(String),
+    TrailingBytes(usize),
+    BurnsTokens(String),  // spends token UTXOs of a token it doesn't send
+    InsufficientTokenInputs(String, String),  // input sum, output sum
+    BurnsTokenAmount(String, String),  // input sum, output sum of a SEND sending less than it spends
+    MintWithoutBaton(String),  // token id
+    NFT1ChildWithoutGroup(String),  // token id
+}
+
 #[derive(Debug)]
 pub enum PandaError {
     NoParentUtxosLeft,
     InsufficientFunds(u64),
+    FeeOverflow(usize),  // tx size
 }
 
 error_chain! {
@@ -92,6 +154,11 @@ error_chain! {
             display("Invalid SLP Output: {} {:?}", script_hex, slp_error)
         }
 
+        SLPParentNotFound(tx_hash_hex: String) {
+            description("SLP parent tx not found")
+            display("SLP parent tx not found: {}", tx_hash_hex)
+        }
+
         InvalidTradeOffer(trade_offer_error: TradeOfferError) {
             description("Invalid trade offer")
             display("Invalid trade offer: {:?}", trade_offer_error)
@@ -106,5 +173,20 @@ error_chain! {
             description("Panda Error")
             display("Panda Error: {:?}", panda_error)
         }
+
+        SignerError(signer_error: SignerError) {
+            description("Signer Error")
+            display("Signer Error: {:?}", signer_error)
+        }
+
+        SubmitTxError(submit_tx_error: SubmitTxError) {
+            description("Tx rejected")
+            display("Tx rejected: {:?}", submit_tx_error)
+        }
+
+        ChainSourceError(chain_source_error: ChainSourceError) {
+            description("Chain source error")
+            display("Chain source error: {:?}", chain_source_error)
+        }
     }
 }
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+/// Fee rate and dust threshold used by all tx builders.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FeePolicy {
+    pub sats_per_byte: u64,
+    /// Rate below which nodes don't relay txs, in satoshis per 1000 bytes.
+    pub min_relay_fee_per_kb: u64,
+    pub dust_limit: u64,
+    /// Median `feefilter` of the connected peers, in satoshis per 1000 bytes.
+    pub peer_fee_filter_per_kb: Option<u64>,
+    /// Ceiling for `peer_fee_filter_per_kb`, so peers can't make us overpay arbitrarily.
+    pub max_peer_fee_filter_per_kb: u64,
+}
+
+impl FeePolicy {
+    /// Effective rate, i.e. the per-byte rate raised to what the network relays.
+    pub fn fee_per_kb(&self) -> u64 {
+        self.sats_per_byte.checked_mul(1000).unwrap_or(std::u64::MAX)
+            .max(self.min_relay_fee_per_kb)
+            .max(self.peer_fee_filter_per_kb.unwrap_or(0))
+    }
+
+    /// `None` if the fee doesn't fit into a u64.
+    pub fn fee_for_size(&self, size: usize) -> Option<u64> {
+        let fee = (size as u64).checked_mul(self.fee_per_kb())?;
+        Some(fee / 1000 + if fee % 1000 != 0 { 1 } else { 0 })
+    }
+
+    pub fn set_peer_fee_filter(&mut self, fee_filter_per_kb: Option<u64>) {
+        self.peer_fee_filter_per_kb = fee_filter_per_kb
+            .map(|fee_per_kb| fee_per_kb.min(self.max_peer_fee_filter_per_kb));
+    }
+}
+
+impl Default for FeePolicy {
+    fn default() -> Self {
+        FeePolicy {
+            sats_per_byte: 1,
+            min_relay_fee_per_kb: 1000,
+            dust_limit: 0x222,
+            peer_fee_filter_per_kb: None,
+            max_peer_fee_filter_per_kb: 10_000,
+        }
+    }
+}
+
+/// `feefilter`s announced by the currently connected peers.
+#[derive(Clone, Debug, Default)]
+pub struct PeerFeeFilters {
+    filters: HashMap<SocketAddr, u64>,
+}
+
+impl PeerFeeFilters {
+    pub fn insert(&mut self, peer_addr: SocketAddr, fee_per_kb: u64) {
+        self.filters.insert(peer_addr, fee_per_kb);
+    }
+
+    pub fn remove(&mut self, peer_addr: &SocketAddr) {
+        self.filters.remove(peer_addr);
+    }
+
+    /// Upper median, so a single peer can't drive up our fees.
+    pub fn median(&self) -> Option<u64> {
+        let mut filters = self.filters.values().cloned().collect::<Vec<_>>();
+        filters.sort();
+        filters.get(filters.len() / 2).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_fee_filters() {
+        let peer = |port| SocketAddr::from(([127, 0, 0, 1], port));
+        let mut filters = PeerFeeFilters::default();
+        assert_eq!(filters.median(), None);
+        filters.insert(peer(1), 1000);
+        filters.insert(peer(2), 2000);
+        filters.insert(peer(3), 1_000_000);
+        assert_eq!(filters.median(), Some(2000));
+        filters.remove(&peer(2));
+        filters.remove(&peer(3));
+        assert_eq!(filters.median(), Some(1000));
+        filters.insert(peer(1), 1500);
+        assert_eq!(filters.median(), Some(1500));
+    }
+
+    #[test]
+    fn test_peer_fee_filter_cap() {
+        let mut fee_policy = FeePolicy::default();
+        fee_policy.set_peer_fee_filter(Some(1_000_000));
+        assert_eq!(fee_policy.fee_per_kb(), 10_000);
+        fee_policy.set_peer_fee_filter(None);
+        assert_eq!(fee_policy.fee_per_kb(), 1000);
+    }
+
+    #[test]
+    fn test_fee_overflow() {
+        let mut fee_policy = FeePolicy::default();
+        fee_policy.sats_per_byte = std::u64::MAX / 10;
+        assert_eq!(fee_policy.fee_per_kb(), std::u64::MAX);
+        assert_eq!(fee_policy.fee_for_size(2), None);
+        assert_eq!(FeePolicy::default().fee_for_size(250), Some(250));
+        assert_eq!(FeePolicy::default().fee_for_size(0), Some(0));
+    }
+}
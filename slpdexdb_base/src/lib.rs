@@ -8,11 +8,13 @@ pub mod convert_numeric;
 mod errors;
 mod slp_amount;
 mod block;
+mod fee_policy;
 
 pub use config::*;
-pub use errors::{Error, ErrorKind, TradeOfferError, NumericError, SLPError, TokenError, Result, PNDError, PandaError};
+pub use errors::{Error, ErrorKind, TradeOfferError, NumericError, SLPError, TokenError, Result, PNDError, PandaError, SignerError, SubmitTxError, ChainSourceError};
 pub use slp_amount::*;
 pub use block::*;
+pub use fee_policy::*;
 
 #[cfg(test)]
 mod tests {
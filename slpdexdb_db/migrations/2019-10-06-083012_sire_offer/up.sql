@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+CREATE TABLE sire_offer (
+    "tx"              BIGINT NOT NULL PRIMARY KEY REFERENCES tx (id) ON DELETE CASCADE,
+    "panda"           BIGINT NOT NULL REFERENCES panda (id) ON DELETE CASCADE,
+    "sire_tx"         BIGINT NOT NULL,
+    "sire_output_idx" INT NOT NULL,
+    "fee_satoshis"    BIGINT NOT NULL,
+    "owner_address"   BYTEA NOT NULL,
+    FOREIGN KEY ("sire_tx", "sire_output_idx") REFERENCES tx_output ("tx", "idx") ON DELETE CASCADE
+);
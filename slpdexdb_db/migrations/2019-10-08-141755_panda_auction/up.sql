@@ -0,0 +1,16 @@
+-- Your SQL goes here
+
+CREATE TABLE panda_auction (
+    "tx"               BIGINT NOT NULL PRIMARY KEY REFERENCES tx (id) ON DELETE CASCADE,
+    "panda"            BIGINT NOT NULL REFERENCES panda (id) ON DELETE CASCADE,
+    "panda_tx"         BIGINT NOT NULL,
+    "panda_output_idx" INT NOT NULL,
+    "start_price"      BIGINT NOT NULL,
+    "end_price"        BIGINT NOT NULL,
+    "start_height"     INT NOT NULL,
+    "duration_blocks"  INT NOT NULL,
+    "seller_address"   BYTEA NOT NULL,
+    "closing_tx"       BIGINT REFERENCES tx (id) ON DELETE SET NULL,
+    "settled_price"    BIGINT,
+    FOREIGN KEY ("panda_tx", "panda_output_idx") REFERENCES tx_output ("tx", "idx") ON DELETE CASCADE
+);
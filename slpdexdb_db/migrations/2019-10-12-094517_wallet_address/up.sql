@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+CREATE TABLE wallet_address (
+    "address" BYTEA NOT NULL PRIMARY KEY,
+    "role"    INT NOT NULL,
+    "chain"   INT NOT NULL,
+    "idx"     INT NOT NULL,
+    UNIQUE ("role", "chain", "idx")
+);
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+CREATE TABLE outgoing_tx (
+    "hash"            BYTEA NOT NULL PRIMARY KEY,
+    "kind"            INT NOT NULL,
+    "raw_tx"          BYTEA NOT NULL,
+    "status"          INT NOT NULL,
+    "reject_reason"   TEXT,
+    "first_broadcast" BIGINT NOT NULL,
+    "last_broadcast"  BIGINT NOT NULL,
+    "n_broadcasts"    INT NOT NULL
+);
+
+CREATE INDEX outgoing_tx_status ON outgoing_tx ("status");
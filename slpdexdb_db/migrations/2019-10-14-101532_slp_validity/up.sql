@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+CREATE TABLE slp_validity (
+    "hash"     BYTEA NOT NULL PRIMARY KEY,
+    "is_valid" BOOLEAN NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+CREATE TABLE mint_baton (
+    "token_hash"       BYTEA NOT NULL PRIMARY KEY,
+    "mint_tx"          BYTEA NOT NULL,
+    "baton_output_idx" INT
+);
+
+CREATE INDEX mint_baton_mint_tx_idx ON mint_baton ("mint_tx");
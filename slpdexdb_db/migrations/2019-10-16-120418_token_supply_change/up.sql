@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TABLE token_supply_change (
+    "tx_hash"     BYTEA NOT NULL,
+    "token_hash"  BYTEA NOT NULL,
+    "kind"        INT NOT NULL,
+    "amount_base" NUMERIC(26) NOT NULL,
+    "timestamp"   BIGINT NOT NULL,
+    PRIMARY KEY ("tx_hash", "token_hash", "kind")
+);
+
+CREATE INDEX token_supply_change_token_hash ON token_supply_change ("token_hash");
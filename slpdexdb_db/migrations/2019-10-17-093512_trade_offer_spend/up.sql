@@ -0,0 +1,15 @@
+-- Your SQL goes here
+
+CREATE TABLE trade_offer_spend (
+    "offer_tx"          BYTEA NOT NULL,
+    "offer_output_idx"  INT NOT NULL,
+    "spending_tx"       BYTEA NOT NULL,
+    "token_hash"        BYTEA NOT NULL,
+    "kind"              INT NOT NULL,
+    "price_per_token"   NUMERIC(52, 26) NOT NULL,
+    "amount_token_base" NUMERIC(26) NOT NULL,
+    "timestamp"         BIGINT NOT NULL,
+    PRIMARY KEY ("offer_tx", "offer_output_idx")
+);
+
+CREATE INDEX trade_offer_spend_token_hash ON trade_offer_spend ("token_hash", "timestamp");
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX trade_offer_exch_version;
+
+ALTER TABLE trade_offer
+    DROP COLUMN "exch_version";
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+ALTER TABLE trade_offer
+    ADD COLUMN "exch_version" INT NOT NULL DEFAULT 2;
+
+CREATE INDEX trade_offer_exch_version ON trade_offer ("exch_version");
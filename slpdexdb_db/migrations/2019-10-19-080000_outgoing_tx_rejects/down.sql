@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE outgoing_tx
+    DROP COLUMN "n_rejects";
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE outgoing_tx
+    ADD COLUMN "n_rejects" INT NOT NULL DEFAULT 0;
@@ -0,0 +1,282 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use cashcontracts::{Address, tx_hash_to_hex};
+use slpdexdb_base::{SLPDEXConfig, Result, Error, ErrorKind, ChainSourceError};
+use crate::tx_source::{tx_result, TxSource, TxFilter, SortKey, Confirmedness};
+use crate::token_source::{token_result, TokenSource};
+use crate::exch::ExchVersion;
+use crate::slp_validator::TxProvider;
+
+mod node_rpc;
+
+pub use self::node_rpc::RpcChainSource;
+
+/// Where txs and tokens are indexed from. Results are in the format of BitDB and SLPDB, which
+/// was the only source at first.
+pub trait ChainSource {
+    fn request_txs(&self,
+                   filters: &[TxFilter],
+                   config: &SLPDEXConfig,
+                   confirmedness: Confirmedness) -> Result<Vec<tx_result::TxEntry>>;
+
+    /// SLP validity of the matching SLP txs.
+    fn request_slp_tx_validity(&self,
+                               filters: &[TxFilter],
+                               config: &SLPDEXConfig,
+                               confirmedness: Confirmedness)
+            -> Result<Vec<tx_result::TxSLPValidity>> {
+        Ok(self.request_txs(filters, config, confirmedness)?
+            .into_iter()
+            .filter_map(|entry| Some(tx_result::TxSLPValidity { tx: entry.tx, slp: entry.slp? }))
+            .collect())
+    }
+
+    fn request_tokens(&self, filters: &[TxFilter]) -> Result<Vec<token_result::TokenEntry>>;
+
+    /// Raw txs to validate SLP txs with, `None` if the source only has indexed results.
+    fn tx_provider(&self) -> Option<&dyn TxProvider> {
+        None
+    }
+}
+
+fn _chain_source_error(chain_source_error: ChainSourceError) -> Error {
+    ErrorKind::ChainSourceError(chain_source_error).into()
+}
+
+fn _matches_confirmedness(blk: &Option<tx_result::Blk>, confirmedness: Confirmedness) -> bool {
+    match confirmedness {
+        Confirmedness::Confirmed => blk.is_some(),
+        Confirmedness::Unconfirmed => blk.is_none(),
+        Confirmedness::Both => true,
+    }
+}
+
+/// Filters every backend can apply to txs it has already fetched, see `TxFilter::base_conditions`.
+fn _matches_base(entry: &tx_result::TxEntry, filters: &[TxFilter]) -> bool {
+    let tx_hashes = filters.iter()
+        .filter_map(|filter| match filter {
+            TxFilter::TxHash(tx_hash) => Some(tx_hash_to_hex(tx_hash)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if tx_hashes.len() > 0 && !tx_hashes.contains(&entry.tx.h) {
+        return false
+    }
+    filters.iter().all(|filter| match filter {
+        TxFilter::MinBlockHeight(height) =>
+            entry.blk.as_ref().map(|blk| blk.i >= *height).unwrap_or(true),
+        TxFilter::MinTxHash(tx_hash) => entry.tx.h > tx_hash_to_hex(tx_hash),
+        _ => true,
+    })
+}
+
+fn _sort_txs(entries: &mut Vec<tx_result::TxEntry>, filters: &[TxFilter]) {
+    if filters.contains(&TxFilter::SortBy(SortKey::TxHash)) {
+        entries.sort_by(|a, b| a.tx.h.cmp(&b.tx.h));
+    }
+}
+
+/// The BitDB and SLPDB endpoints of `Endpoint`.
+pub struct BitDbChainSource {
+    tx_source: TxSource,
+    token_source: TokenSource,
+}
+
+impl BitDbChainSource {
+    pub fn new() -> Self {
+        BitDbChainSource {
+            tx_source: TxSource::new(),
+            token_source: TokenSource::new(),
+        }
+    }
+}
+
+impl ChainSource for BitDbChainSource {
+    fn request_txs(&self,
+                   filters: &[TxFilter],
+                   config: &SLPDEXConfig,
+                   confirmedness: Confirmedness) -> Result<Vec<tx_result::TxEntry>> {
+        Ok(self.tx_source.request_txs(filters, config, confirmedness)?)
+    }
+
+    fn request_slp_tx_validity(&self,
+                               filters: &[TxFilter],
+                               config: &SLPDEXConfig,
+                               confirmedness: Confirmedness)
+            -> Result<Vec<tx_result::TxSLPValidity>> {
+        Ok(self.tx_source.request_slp_tx_validity(filters, config, confirmedness)?)
+    }
+
+    fn request_tokens(&self, filters: &[TxFilter]) -> Result<Vec<token_result::TokenEntry>> {
+        Ok(self.token_source.request_tokens(filters)?)
+    }
+}
+
+/// Serves the txs and tokens of JSON files in the result format of BitDB and SLPDB, for tests
+/// and offline runs. Applies the filters the way the BitDB and SLPDB queries do.
+pub struct FixtureChainSource {
+    txs_json: String,
+    tokens_json: String,
+}
+
+impl FixtureChainSource {
+    /// `txs_json` is an array of tx entries, `tokens_json` one of token entries.
+    pub fn new(txs_json: String, tokens_json: String) -> Self {
+        FixtureChainSource { txs_json, tokens_json }
+    }
+
+    /// Reads `txs.json` and `tokens.json` of `dir`; missing files have no entries.
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let read = |file_name: &str| -> Result<String> {
+            match fs::read_to_string(dir.as_ref().join(file_name)) {
+                Ok(json) => Ok(json),
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok("[]".to_string()),
+                Err(err) => Err(err.into()),
+            }
+        };
+        Ok(FixtureChainSource::new(read("txs.json")?, read("tokens.json")?))
+    }
+
+    fn _parse<T: serde::de::DeserializeOwned>(json: &str) -> Result<Vec<T>> {
+        serde_json::from_str(json)
+            .map_err(|err| _chain_source_error(ChainSourceError::InvalidFixture(err.to_string())))
+    }
+
+    fn _address(addr: &Option<String>) -> Option<Address> {
+        let addr = addr.as_ref()?;
+        if addr.contains(":") {
+            Address::from_cash_addr(addr.to_string()).ok()
+        } else {
+            Address::from_cash_addr("bitcoincash:".to_string() + addr).ok()
+        }
+    }
+
+    fn _matches(entry: &tx_result::TxEntry, filters: &[TxFilter], config: &SLPDEXConfig) -> bool {
+        let is_exch = filters.contains(&TxFilter::Exch);
+        let is_valid_slp = entry.slp.as_ref().map(|slp| slp.valid);
+        let addresses = filters.iter()
+            .filter_map(|filter| match filter {
+                TxFilter::Address(address) => Some(address.bytes().to_vec()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let exch_ops = ExchVersion::all(config).iter()
+            .map(|version| version.op() as i32)
+            .collect::<Vec<_>>();
+        let matches_filter = |filter: &TxFilter| match filter {
+            TxFilter::TokenId(token_hash) => entry.slp.as_ref()
+                .map(|slp| slp.valid && slp.detail.token_id == tx_hash_to_hex(token_hash))
+                .unwrap_or(false),
+            TxFilter::Exch => is_valid_slp == Some(true) && entry.inputs.iter().any(|input| {
+                let is_exch_version = match input.b1 {
+                    tx_result::StackItem::Op {op} => exch_ops.contains(&op),
+                    _ => false,
+                };
+                input.b0 == tx_result::StackItem::Str(config.exch_lokad_b64.clone()) &&
+                    is_exch_version &&
+                    (addresses.len() == 0 || input.b4.get_str()
+                        .and_then(|address| base64::decode(address).ok())
+                        .map(|address| addresses.contains(&address))
+                        .unwrap_or(false))
+            }),
+            _ => true,
+        };
+        let matches_address = is_exch || addresses.len() == 0 || entry.inputs.iter()
+            .filter_map(|input| Self::_address(&input.e.a))
+            .chain(entry.outputs.iter().filter_map(|output| Self::_address(&output.e.a)))
+            .any(|address| addresses.contains(&address.bytes().to_vec()));
+        // like the queries, which don't return invalid SLP txs
+        is_valid_slp != Some(false) &&
+            matches_address &&
+            filters.iter().all(matches_filter) &&
+            _matches_base(entry, filters)
+    }
+}
+
+impl ChainSource for FixtureChainSource {
+    fn request_txs(&self,
+                   filters: &[TxFilter],
+                   config: &SLPDEXConfig,
+                   confirmedness: Confirmedness) -> Result<Vec<tx_result::TxEntry>> {
+        let mut entries = Self::_parse::<tx_result::TxEntry>(&self.txs_json)?
+            .into_iter()
+            .filter(|entry| _matches_confirmedness(&entry.blk, confirmedness))
+            .filter(|entry| Self::_matches(entry, filters, config))
+            .collect::<Vec<_>>();
+        _sort_txs(&mut entries, filters);
+        Ok(entries)
+    }
+
+    fn request_tokens(&self, filters: &[TxFilter]) -> Result<Vec<token_result::TokenEntry>> {
+        let mut tokens = Self::_parse::<token_result::TokenEntry>(&self.tokens_json)?
+            .into_iter()
+            .filter(|token| filters.iter().all(|filter| match filter {
+                TxFilter::TokenId(token_hash) =>
+                    token.token_details.token_id_hex == tx_hash_to_hex(token_hash),
+                TxFilter::MinTxHash(token_hash) =>
+                    token.token_details.token_id_hex > tx_hash_to_hex(token_hash),
+                TxFilter::MinBlockHeight(height) =>
+                    token.token_stats.block_created.map(|block| block >= *height).unwrap_or(false),
+                _ => true,
+            }))
+            .collect::<Vec<_>>();
+        if filters.contains(&TxFilter::SortBy(SortKey::TxHash)) {
+            tokens.sort_by(|a, b| a.token_details.token_id_hex.cmp(&b.token_details.token_id_hex));
+        }
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cashcontracts::tx_hex_to_hash;
+
+    fn source() -> FixtureChainSource {
+        FixtureChainSource::new(
+            include_str!("../test_data/chain_source/txs.json").to_string(),
+            include_str!("../test_data/chain_source/tokens.json").to_string(),
+        )
+    }
+
+    fn tx_hashes(entries: &[tx_result::TxEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| &entry.tx.h[..4]).collect()
+    }
+
+    #[test]
+    fn fixture_txs() {
+        let config = SLPDEXConfig::default();
+        let source = source();
+        let token_hash = tx_hex_to_hash(
+            "1111111111111111111111111111111111111111111111111111111111111111"
+        ).unwrap();
+        let all = source.request_txs(&[TxFilter::SortBy(SortKey::TxHash)], &config,
+                                     Confirmedness::Both).unwrap();
+        assert_eq!(tx_hashes(&all), vec!["aaaa", "bbbb", "cccc"]);  // the invalid SLP tx is skipped
+        let confirmed = source.request_txs(&[], &config, Confirmedness::Confirmed).unwrap();
+        assert_eq!(tx_hashes(&confirmed), vec!["aaaa", "bbbb"]);
+        let token_txs = source.request_txs(&[TxFilter::TokenId(token_hash)], &config,
+                                           Confirmedness::Both).unwrap();
+        assert_eq!(tx_hashes(&token_txs), vec!["cccc", "bbbb"]);
+        let exch = source.request_txs(&[TxFilter::Exch, TxFilter::Address(config.fee_address.clone())],
+                                      &config, Confirmedness::Both).unwrap();
+        assert_eq!(tx_hashes(&exch), vec!["cccc"]);
+        let validity = source.request_slp_tx_validity(&[TxFilter::MinBlockHeight(600_001)],
+                                                      &config, Confirmedness::Both).unwrap();
+        assert_eq!(validity.len(), 1);
+        assert_eq!(&validity[0].tx.h[..4], "cccc");
+    }
+
+    #[test]
+    fn fixture_tokens() {
+        let source = source();
+        let token_hash = tx_hex_to_hash(
+            "1111111111111111111111111111111111111111111111111111111111111111"
+        ).unwrap();
+        let tokens = source.request_tokens(&[TxFilter::TokenId(token_hash)]).unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_details.symbol, "TST");
+        assert_eq!(source.request_tokens(&[TxFilter::MinTxHash(token_hash)]).unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,499 @@
+use std::io::{self, Read};
+use byteorder::{LittleEndian, ReadBytesExt};
+use cashcontracts::{Tx, tx_hash_to_hex};
+use json::{JsonValue, object, array};
+use serde_json::Value;
+use slpdexdb_base::{SLPDEXConfig, SLPAmount, Result, ChainSourceError};
+use crate::tx_source::{tx_result, TxFilter, Confirmedness};
+use crate::token_source::token_result;
+use crate::tx_history::{TxHistory, OutputType, SLPTxType, TokenType};
+use crate::slp_validator::{SlpMessage, SlpValidator, TxProvider, parse_slp_message};
+use super::{ChainSource, BitDbChainSource, _chain_source_error, _matches_confirmedness, _matches_base,
+            _sort_txs};
+
+/// "No such mempool or blockchain transaction"
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+
+#[derive(Debug, PartialEq)]
+enum _ScriptOp {
+    Push(Vec<u8>),
+    Code(u8),
+}
+
+/// Splits a serialized script into pushes and opcodes, stopping at the first truncated push.
+/// OP_0 counts as opcode, like in BitDB.
+fn _script_ops(script: &[u8]) -> Vec<_ScriptOp> {
+    let mut ops = Vec::new();
+    let mut cursor = io::Cursor::new(script);
+    while let Ok(op) = cursor.read_u8() {
+        let len = match op {
+            0x01..=0x4b => Ok(op as usize),
+            0x4c => cursor.read_u8().map(|len| len as usize),
+            0x4d => cursor.read_u16::<LittleEndian>().map(|len| len as usize),
+            0x4e => cursor.read_u32::<LittleEndian>().map(|len| len as usize),
+            _ => {
+                ops.push(_ScriptOp::Code(op));
+                continue
+            },
+        };
+        let len = match len {
+            Ok(len) => len,
+            Err(_) => break,
+        };
+        let mut data = vec![0; len];
+        if cursor.read_exact(&mut data).is_err() {
+            break
+        }
+        ops.push(_ScriptOp::Push(data));
+    }
+    ops
+}
+
+fn _stack_item(ops: &[_ScriptOp], idx: usize) -> tx_result::StackItem {
+    match ops.get(idx) {
+        Some(_ScriptOp::Push(data)) => tx_result::StackItem::Str(base64::encode(data)),
+        Some(_ScriptOp::Code(op)) => tx_result::StackItem::Op { op: *op as i32 },
+        None => tx_result::StackItem::Undefined,
+    }
+}
+
+fn _push_str(ops: &[_ScriptOp], idx: usize) -> String {
+    match ops.get(idx) {
+        Some(_ScriptOp::Push(data)) => String::from_utf8_lossy(data).to_string(),
+        _ => String::new(),
+    }
+}
+
+fn _address_with_prefix(output_type: OutputType, prefix: &str) -> Option<String> {
+    match output_type {
+        OutputType::Address(address) => Some(address.with_prefix(prefix.to_string()).cash_addr().to_string()),
+        _ => None,
+    }
+}
+
+/// Addresses without prefix, like BitDB has them.
+fn _bitdb_address(output_type: OutputType) -> Option<String> {
+    let prefix = "bitcoincash";
+    _address_with_prefix(output_type, prefix).map(|address| address[prefix.len() + 1..].to_string())
+}
+
+fn _slp_tx_type_str(slp_type: &SLPTxType) -> &'static str {
+    match slp_type {
+        SLPTxType::Genesis => "GENESIS",
+        SLPTxType::Mint    => "MINT",
+        SLPTxType::Send    => "SEND",
+        SLPTxType::Commit  => "COMMIT",
+    }
+}
+
+#[derive(Copy, Clone)]
+struct _BlockInfo {
+    height: i32,
+    timestamp: u64,
+}
+
+/// A full node's JSON-RPC, e.g. of Bitcoin Cash Node. A node has no address or token index, so it
+/// only serves txs by hash and tokens by id, validating SLP txs locally; all other queries go to
+/// BitDB and SLPDB.
+pub struct RpcChainSource {
+    url: String,
+    user: String,
+    password: String,
+    client: reqwest::Client,
+    index: BitDbChainSource,
+}
+
+impl RpcChainSource {
+    pub fn new(url: String, user: String, password: String) -> Self {
+        RpcChainSource {
+            url,
+            user,
+            password,
+            client: reqwest::Client::new(),
+            index: BitDbChainSource::new(),
+        }
+    }
+
+    fn _is_node_tx_filter(filter: &TxFilter) -> bool {
+        match filter {
+            TxFilter::TxHash(_) | TxFilter::MinBlockHeight(_) | TxFilter::MinTxHash(_) |
+            TxFilter::SortBy(_) => true,
+            _ => false,
+        }
+    }
+
+    /// `None` if the node doesn't know the requested object.
+    fn _call(&self, method: &str, params: JsonValue) -> Result<Option<Value>> {
+        let request = json::stringify(object!{
+            "jsonrpc" => "1.0",
+            "id" => "slpdexdb",
+            "method" => method,
+            "params" => params,
+        });
+        let text = self.client.post(&self.url)
+            .basic_auth(&self.user, Some(&self.password))
+            .body(request)
+            .send()?
+            .text()?;
+        let mut response: Value = serde_json::from_str(&text)
+            .map_err(|_| _chain_source_error(ChainSourceError::InvalidResponse(text.clone())))?;
+        let error = &response["error"];
+        if !error.is_null() {
+            if error["code"].as_i64() == Some(RPC_INVALID_ADDRESS_OR_KEY) {
+                return Ok(None)
+            }
+            return Err(_chain_source_error(ChainSourceError::Rpc(error.to_string())));
+        }
+        Ok(Some(response["result"].take()))
+    }
+
+    fn _fetch(&self, tx_hash: &[u8; 32]) -> Result<Option<(Tx, Option<_BlockInfo>)>> {
+        let result = match self._call("getrawtransaction", array![tx_hash_to_hex(tx_hash), true])? {
+            Some(result) => result,
+            None => return Ok(None),
+        };
+        let invalid_response = || _chain_source_error(ChainSourceError::InvalidResponse(result.to_string()));
+        let tx_bytes = hex::decode(result["hex"].as_str().ok_or_else(invalid_response)?)?;
+        let tx = Tx::read_from_stream(&mut io::Cursor::new(tx_bytes))?;
+        let block_hash = match result["blockhash"].as_str() {
+            Some(block_hash) => block_hash,
+            None => return Ok(Some((tx, None))),
+        };
+        let height = self._call("getblockheader", array![block_hash])?
+            .and_then(|header| header["height"].as_i64())
+            .ok_or_else(invalid_response)?;
+        Ok(Some((tx, Some(_BlockInfo {
+            height: height as i32,
+            timestamp: result["blocktime"].as_u64().ok_or_else(invalid_response)?,
+        }))))
+    }
+
+    fn _slp_message(tx: &Tx) -> Option<SlpMessage> {
+        parse_slp_message(&tx.outputs().get(0)?.script).ok()?
+    }
+
+    /// The SLP details of `tx`, validated with `validator`, which caches verdicts and txs across
+    /// the txs of a request.
+    fn _slp(validator: &mut SlpValidator, tx: &Tx) -> Result<Option<tx_result::TxSLP>> {
+        let message = match Self::_slp_message(tx) {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+        let tx_hash = tx.hash();
+        let token_hash = message.token_hash_of(&tx_hash);
+        let decimals = match message.decimals {
+            Some(decimals) => decimals,
+            None => match validator.tx(&token_hash)? {
+                Some(genesis) => Self::_slp_message(genesis).and_then(|genesis| genesis.decimals),
+                None => None,
+            }.unwrap_or(0),
+        };
+        let valid = validator.validate(&tx_hash)?;
+        Ok(Some(tx_result::TxSLP {
+            valid,
+            detail: tx_result::TxSLPDetail {
+                decimals: decimals as i32,
+                token_id: tx_hash_to_hex(&token_hash),
+                transaction_type: _slp_tx_type_str(&message.slp_type).to_string(),
+                version_type: message.token_type as i32,
+                outputs: message.amounts.iter().enumerate()
+                    .map(|(i, amount)| tx_result::TxSLPOutput {
+                        address: tx.outputs().get(i + 1)
+                            .and_then(|output| _address_with_prefix(
+                                TxHistory::_process_output_script(&output.script),
+                                "simpleledger",
+                            ))
+                            .unwrap_or_default(),
+                        amount: SLPAmount::new(*amount as i128, decimals as u32).to_string(),
+                    })
+                    .collect(),
+                baton_vout: match message.slp_type {
+                    SLPTxType::Genesis | SLPTxType::Mint =>
+                        message.mint_baton_vout.map(|vout| vout as i32),
+                    _ => None,
+                },
+            },
+        }))
+    }
+
+    /// Address of the output an input spends. The input script alone doesn't tell it reliably,
+    /// so this fetches the spent tx; coinbase inputs have none.
+    fn _input_address(validator: &mut SlpValidator, tx_hash: &[u8; 32], vout: u32)
+            -> Result<OutputType> {
+        Ok(validator.tx(tx_hash)?
+            .and_then(|spent_tx| {
+                let output = spent_tx.outputs().get(vout as usize)?;
+                Some(TxHistory::_process_output_script(&output.script))
+            })
+            .unwrap_or(OutputType::Unknown))
+    }
+
+    /// `tx` in the format of BitDB. `validator` has to know `tx`, or be able to fetch it.
+    fn _entry(validator: &mut SlpValidator, tx: &Tx, block: Option<_BlockInfo>)
+            -> Result<tx_result::TxEntry> {
+        let inputs = tx.inputs().iter()
+            .map(|input| {
+                let ops = _script_ops(&input.script.to_vec());
+                let address = Self::_input_address(validator, &input.outpoint.tx_hash,
+                                                   input.outpoint.vout)?;
+                Ok(tx_result::TxInput {
+                    e: tx_result::TxInputEdge {
+                        a: _bitdb_address(address),
+                        h: tx_hash_to_hex(&input.outpoint.tx_hash),
+                        i: input.outpoint.vout as i32,
+                    },
+                    b0: _stack_item(&ops, 0),
+                    b1: _stack_item(&ops, 1),
+                    b2: _stack_item(&ops, 2),
+                    b3: _stack_item(&ops, 3),
+                    b4: _stack_item(&ops, 4),
+                    b5: _stack_item(&ops, 5),
+                    b6: _stack_item(&ops, 6),
+                    b7: _stack_item(&ops, 7),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(tx_result::TxEntry {
+            blk: block.map(|block| tx_result::Blk { t: block.timestamp, i: block.height }),
+            tx: tx_result::Tx { h: tx_hash_to_hex(&tx.hash()) },
+            inputs,
+            outputs: tx.outputs().iter()
+                .map(|output| tx_result::TxOutput {
+                    e: tx_result::TxOutputEdge {
+                        v: output.value,
+                        a: _bitdb_address(TxHistory::_process_output_script(&output.script)),
+                    },
+                    b0: _stack_item(&_script_ops(&output.script.to_vec()), 0),
+                })
+                .collect(),
+            slp: Self::_slp(validator, tx)?,
+        })
+    }
+
+    /// The token of a valid GENESIS. The node doesn't track the supply, so the circulating
+    /// supply is the genesis quantity.
+    fn _token_entry(&self, token_hash: &[u8; 32]) -> Result<Option<token_result::TokenEntry>> {
+        let (genesis, block) = match self._fetch(token_hash)? {
+            Some(genesis) => genesis,
+            None => return Ok(None),
+        };
+        let message = match Self::_slp_message(&genesis) {
+            Some(message @ SlpMessage { slp_type: SLPTxType::Genesis, .. }) => message,
+            _ => return Ok(None),
+        };
+        if !SlpValidator::new(self, None).validate(token_hash)? {
+            return Ok(None)
+        }
+        let nft_parent_id = match message.token_type {
+            TokenType::NFT1Child => {
+                let parent_tx_hash = genesis.inputs()[0].outpoint.tx_hash;
+                self.fetch_tx(&parent_tx_hash)?
+                    .and_then(|parent_tx| Self::_slp_message(&parent_tx))
+                    .map(|parent| tx_hash_to_hex(&parent.token_hash_of(&parent_tx_hash)))
+            },
+            _ => None,
+        };
+        let ops = _script_ops(&genesis.outputs()[0].script.to_vec());
+        let decimals = message.decimals.unwrap_or(0);
+        let quantity = SLPAmount::new(message.amount_at(1) as i128, decimals as u32).to_string();
+        let token_id_hex = tx_hash_to_hex(token_hash);
+        Ok(Some(token_result::TokenEntry {
+            schema_version: 0,
+            last_updated_block: block.map(|block| block.height).unwrap_or(0),
+            mint_baton_utxo: message.mint_baton_vout
+                .map(|vout| format!("{}:{}", token_id_hex, vout))
+                .unwrap_or_default(),
+            token_stats: token_result::TokenStats {
+                block_created: block.map(|block| block.height),
+                block_last_active_send: None,
+                block_last_active_mint: None,
+                qty_valid_txns_since_genesis: 0,
+                qty_valid_token_utxos: 0,
+                qty_valid_token_addresses: 0,
+                qty_token_minted: quantity.clone(),
+                qty_token_burned: "0".to_string(),
+                qty_token_circulating_supply: quantity.clone(),
+                qty_satoshis_locked_up: 0,
+                minting_baton_status: match message.mint_baton_vout {
+                    Some(_) => "ALIVE",
+                    None => "NEVER_CREATED",
+                }.to_string(),
+            },
+            token_details: token_result::TokenDetails {
+                decimals: decimals as i32,
+                token_id_hex,
+                timestamp: None,
+                timestamp_unix: block.map(|block| block.timestamp as i64),
+                transaction_type: "GENESIS".to_string(),
+                version_type: message.token_type as i16,
+                // OP_RETURN <lokad id> <token type> <"GENESIS"> <ticker> <name> <uri> <hash> ...
+                symbol: _push_str(&ops, 4),
+                name: _push_str(&ops, 5),
+                document_uri: _push_str(&ops, 6),
+                document_sha256_hex: match ops.get(7) {
+                    Some(_ScriptOp::Push(hash)) if hash.len() == 32 => Some(hex::encode(hash)),
+                    _ => None,
+                },
+                baton_vout: message.mint_baton_vout.map(|vout| vout as i32),
+                contains_baton: message.mint_baton_vout.is_some(),
+                genesis_or_mint_quantity: quantity,
+            },
+            nft_parent_id,
+        }))
+    }
+}
+
+impl TxProvider for RpcChainSource {
+    fn fetch_tx(&self, tx_hash: &[u8; 32]) -> Result<Option<Tx>> {
+        Ok(self._fetch(tx_hash)?.map(|(tx, _)| tx))
+    }
+}
+
+impl ChainSource for RpcChainSource {
+    fn request_txs(&self,
+                   filters: &[TxFilter],
+                   config: &SLPDEXConfig,
+                   confirmedness: Confirmedness) -> Result<Vec<tx_result::TxEntry>> {
+        if !filters.iter().all(Self::_is_node_tx_filter) {
+            return self.index.request_txs(filters, config, confirmedness)
+        }
+        let mut fetched = Vec::new();
+        for filter in filters {
+            if let TxFilter::TxHash(tx_hash) = filter {
+                fetched.extend(self._fetch(tx_hash)?);
+            }
+        }
+        let mut validator = SlpValidator::new(self, None);
+        validator.add_txs(&fetched.iter().map(|(tx, _)| tx.clone()).collect::<Vec<_>>());
+        let mut entries = Vec::new();
+        for (tx, block) in fetched {
+            let entry = Self::_entry(&mut validator, &tx, block)?;
+            if _matches_confirmedness(&entry.blk, confirmedness) && _matches_base(&entry, filters) {
+                entries.push(entry);
+            }
+        }
+        _sort_txs(&mut entries, filters);
+        Ok(entries)
+    }
+
+    fn request_tokens(&self, filters: &[TxFilter]) -> Result<Vec<token_result::TokenEntry>> {
+        let is_node_filter = |filter: &TxFilter| match filter {
+            TxFilter::TokenId(_) | TxFilter::SortBy(_) => true,
+            _ => false,
+        };
+        if !filters.iter().all(is_node_filter) {
+            return self.index.request_tokens(filters)
+        }
+        let mut tokens = Vec::new();
+        for filter in filters {
+            if let TxFilter::TokenId(token_hash) = filter {
+                tokens.extend(self._token_entry(token_hash)?);
+            }
+        }
+        Ok(tokens)
+    }
+
+    fn tx_provider(&self) -> Option<&dyn TxProvider> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::collections::HashMap;
+    use cashcontracts::{Tx, tx_hash_to_hex};
+    use crate::slp_validator::SlpValidator;
+    use crate::slp_validator::test_txs::typed_genesis;
+    use crate::tx_source::tx_result::StackItem;
+    use super::{RpcChainSource, _ScriptOp, _script_ops};
+
+    /// NFT1 child GENESIS of a CryptoPanda (TPANDA) on mainnet, spending P2PKH inputs.
+    const PANDA_GENESIS_HEX: &str = "0100000002f7cf2ac976eb7ff1435cebe7f634f15d0e91e8afa227741106f72a4f2a963d92010000006a473044022014f382515b206c87313fa43b7a744a73adc62c6bf38983d6aa3f7c2b4e49821202200bc1c6d73e4462ac800daa39702098075e497fe4804991a48906e10c87b4354e4121031162a9a8f307b8e1efcafe3ce76b35ad293fad97ec885bcb8dbc6756d18ca941ffffffff1ca7052b7bf8e941aeeaaa3c10a783af0a31d1d4b5ba758f3694a2275b40faaf020000006a47304402206e38e36193f527d0679b49d56a84728fb473d981b229dd6d26fb4636c1b7d13e02203996d8c705d93f3f5b57c7d289d41ea17cb30c88931649271f07b295b13388ef4121031162a9a8f307b8e1efcafe3ce76b35ad293fad97ec885bcb8dbc6756d18ca941ffffffff030000000000000000896a04534c500001410747454e45534953065450414e4441044164616d4c5c68747470733a2f2f70616e642e61732e636173682f67656e6f6d652f303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030303030304c0001004c0008000000000000000122020000000000001976a9141431a2d4241cf1aa9df855cfd329304935a0383488acfa220000000000001976a9142cb677ece4990b3f587e90130f99660bfe4554f488ac00000000";
+
+    fn _panda_genesis() -> Tx {
+        Tx::read_from_stream(&mut io::Cursor::new(hex::decode(PANDA_GENESIS_HEX).unwrap())).unwrap()
+    }
+
+    #[test]
+    fn test_script_ops() {
+        let tx = _panda_genesis();
+        let ops = _script_ops(&tx.outputs()[0].script.to_vec());
+        assert_eq!(ops.len(), 11);
+        assert_eq!(ops[0], _ScriptOp::Code(0x6a));
+        assert_eq!(ops[1], _ScriptOp::Push(b"SLP\0".to_vec()));
+        assert_eq!(ops[2], _ScriptOp::Push(vec![0x41]));
+        assert_eq!(ops[3], _ScriptOp::Push(b"GENESIS".to_vec()));
+        assert_eq!(ops[4], _ScriptOp::Push(b"TPANDA".to_vec()));
+        // OP_PUSHDATA1 pushes, also empty ones
+        match &ops[6] {
+            _ScriptOp::Push(uri) => assert_eq!(uri.len(), 92),
+            op => panic!("expected document uri, got {:?}", op),
+        }
+        assert_eq!(ops[7], _ScriptOp::Push(vec![]));
+        assert_eq!(ops[10], _ScriptOp::Push(vec![0, 0, 0, 0, 0, 0, 0, 1]));
+        let ops = _script_ops(&tx.outputs()[1].script.to_vec());
+        assert_eq!(ops, vec![
+            _ScriptOp::Code(0x76),
+            _ScriptOp::Code(0xa9),
+            _ScriptOp::Push(hex::decode("1431a2d4241cf1aa9df855cfd329304935a03834").unwrap()),
+            _ScriptOp::Code(0x88),
+            _ScriptOp::Code(0xac),
+        ]);
+        // truncated pushes end the script
+        assert_eq!(_script_ops(&[0x00, 0x4c]), vec![_ScriptOp::Code(0x00)]);
+        assert_eq!(_script_ops(&[0x51, 0x03, 0x01, 0x02]), vec![_ScriptOp::Code(0x51)]);
+    }
+
+    #[test]
+    fn test_entry() {
+        let tx = _panda_genesis();
+        // only the group token input is known, as a genesis of one group token at vout 1
+        let group_hash = tx.inputs()[0].outpoint.tx_hash;
+        let mut provider = HashMap::new();
+        provider.insert(group_hash, typed_genesis(0x81, &[([0xff; 32], 0)], 0, vec![], 1));
+        let mut validator = SlpValidator::new(&provider, None);
+        validator.add_txs(&[tx.clone()]);
+        let entry = RpcChainSource::_entry(&mut validator, &tx, None).unwrap();
+
+        assert!(entry.blk.is_none());
+        assert_eq!(entry.tx.h, tx_hash_to_hex(&tx.hash()));
+        assert_eq!(entry.inputs.len(), 2);
+        let pub_key = "AxFiqajzB7jh78r+POdrNa0pP62X7Ihby428Z1bRjKlB";
+        for input in entry.inputs.iter() {
+            assert!(input.b0.get_str().is_some());
+            assert_eq!(input.b1, StackItem::Str(pub_key.to_string()));
+            assert_eq!(input.b2, StackItem::Undefined);
+        }
+        assert_eq!(entry.inputs[0].e.h, tx_hash_to_hex(&group_hash));
+        assert_eq!(entry.inputs[0].e.i, 1);
+        assert_eq!(entry.inputs[0].e.a.as_ref().map(String::as_str),
+                   Some("qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqfnhks603"));
+        // the spent tx of input 1 is unknown
+        assert_eq!(entry.inputs[1].e.i, 2);
+        assert_eq!(entry.inputs[1].e.a, None);
+
+        assert_eq!(entry.outputs.len(), 3);
+        assert_eq!(entry.outputs[0].b0, StackItem::Op { op: 0x6a });
+        assert_eq!(entry.outputs[0].e.v, 0);
+        assert_eq!(entry.outputs[0].e.a, None);
+        assert_eq!(entry.outputs[1].b0, StackItem::Op { op: 0x76 });
+        assert_eq!(entry.outputs[1].e.v, 546);
+        assert_eq!(entry.outputs[1].e.a.as_ref().map(String::as_str),
+                   Some("qq2rrgk5ysw0r25alp2ul5efxpyntgpcxsp72lwk9h"));
+        assert_eq!(entry.outputs[2].e.v, 8954);
+        assert_eq!(entry.outputs[2].e.a.as_ref().map(String::as_str),
+                   Some("qqktvalvujvsk06c06gpxruevc9lu3257s8ylwcfty"));
+
+        let slp = entry.slp.unwrap();
+        assert!(slp.valid);
+        assert_eq!(slp.detail.transaction_type, "GENESIS");
+        assert_eq!(slp.detail.version_type, 0x41);
+        assert_eq!(slp.detail.decimals, 0);
+        assert_eq!(slp.detail.token_id, tx_hash_to_hex(&tx.hash()));
+        assert_eq!(slp.detail.baton_vout, None);
+        assert_eq!(slp.detail.outputs.len(), 1);
+        assert_eq!(slp.detail.outputs[0].address, "simpleledger:qq2rrgk5ysw0r25alp2ul5efxpyntgpcxsd9pymkmf");
+        assert_eq!(slp.detail.outputs[0].amount, "1");
+    }
+}
@@ -0,0 +1,237 @@
+use cashcontracts::TxOutpoint;
+use slpdexdb_base::FeePolicy;
+use crate::data::{Utxo, tx_hash_from_le_slice};
+
+pub const TX_OVERHEAD_SIZE: usize = 10;
+pub const P2PKH_INPUT_SIZE: usize = 148;
+pub const P2PKH_OUTPUT_SIZE: usize = 34;
+
+const BNB_MAX_TRIES: usize = 100_000;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CoinSelectStrategy {
+    /// Spend the largest UTXOs first, minimizing the number of inputs.
+    LargestFirst,
+    /// Search for a set of UTXOs that pays the target without a change output; falls back to
+    /// `LargestFirst` if there is none.
+    BranchAndBound,
+    /// Like `LargestFirst`, but additionally sweeps the smallest economical UTXOs into the tx,
+    /// up to `max_inputs` inputs in total.
+    Consolidate { max_inputs: usize },
+}
+
+/// What the selected coins have to pay for. `base_size` is the size of the tx without any
+/// inputs, i.e. the overhead and all outputs except the change output.
+#[derive(Copy, Clone, Debug)]
+pub struct CoinSelectTarget {
+    pub amount: u64,
+    pub base_size: usize,
+    pub fee_per_kb: u64,
+    pub dust_limit: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct CoinSelection {
+    pub inputs: Vec<(TxOutpoint, u64)>,
+    pub fee: u64,
+    /// Zero if the leftover is below the dust limit and goes to the miners.
+    pub change: u64,
+}
+
+impl CoinSelectTarget {
+    pub fn new(amount: u64, base_size: usize, fee_policy: &FeePolicy) -> Self {
+        CoinSelectTarget {
+            amount,
+            base_size,
+            fee_per_kb: fee_policy.fee_per_kb(),
+            dust_limit: fee_policy.dust_limit,
+        }
+    }
+
+    fn fee(&self, n_inputs: usize, with_change: bool) -> u64 {
+        let size = self.base_size + n_inputs * P2PKH_INPUT_SIZE +
+            if with_change { P2PKH_OUTPUT_SIZE } else { 0 };
+        (size as u64 * self.fee_per_kb + 999) / 1000
+    }
+
+    fn input_fee(&self) -> u64 {
+        (P2PKH_INPUT_SIZE as u64 * self.fee_per_kb + 999) / 1000
+    }
+
+    /// Fee and change if `total` satoshis from `n_inputs` inputs pay for the target.
+    fn settle(&self, n_inputs: usize, total: u64) -> Option<(u64, u64)> {
+        let fee_with_change = self.fee(n_inputs, true);
+        if total >= self.amount + fee_with_change + self.dust_limit {
+            return Some((fee_with_change, total - self.amount - fee_with_change));
+        }
+        if total >= self.amount + self.fee(n_inputs, false) {
+            return Some((total - self.amount, 0));
+        }
+        None
+    }
+}
+
+/// UTXOs which may be spent as fee inputs: no token UTXOs (including NFT1 pandas and group
+/// outputs), and only UTXOs worth more than the fee to spend them.
+fn _spendable<'a>(utxos: &'a [Utxo], target: &CoinSelectTarget) -> Vec<&'a Utxo> {
+    let input_fee = target.input_fee();
+    utxos.iter()
+        .filter(|utxo| utxo.token_hash.is_none() && utxo.value_token.base_amount() == 0)
+        .filter(|utxo| utxo.value_satoshis > input_fee)
+        .collect()
+}
+
+fn _largest_first(utxos: &[&Utxo], target: &CoinSelectTarget) -> Option<Vec<usize>> {
+    let mut total = 0;
+    for idx in 0..utxos.len() {
+        total += utxos[idx].value_satoshis;
+        if target.settle(idx + 1, total).is_some() {
+            return Some((0..=idx).collect());
+        }
+    }
+    None
+}
+
+fn _branch_and_bound(utxos: &[&Utxo], target: &CoinSelectTarget) -> Option<Vec<usize>> {
+    // effective values, i.e. what each input contributes after paying for itself
+    let input_fee = target.input_fee();
+    let values = utxos.iter()
+        .map(|utxo| utxo.value_satoshis - input_fee)
+        .collect::<Vec<_>>();
+    let lower = target.amount + target.fee(0, false);
+    // anything above this would be better off with a change output
+    let upper = lower + target.fee(0, true) - target.fee(0, false) + target.dust_limit;
+    let mut remaining = values.iter().sum::<u64>();
+    if remaining < lower {
+        return None;
+    }
+    let mut selected = Vec::new();
+    let mut total = 0;
+    let mut idx = 0;
+    for _ in 0..BNB_MAX_TRIES {
+        let backtrack = if total > upper || total + remaining < lower {
+            true
+        } else if total >= lower {
+            return Some(selected);
+        } else {
+            idx >= values.len()
+        };
+        if backtrack {
+            // undo the last inclusion and try omitting it instead
+            let last = loop {
+                match selected.pop() {
+                    Some(last) => break last,
+                    None => return None,
+                }
+            };
+            total -= values[last];
+            for omitted in last + 1..idx {
+                remaining += values[omitted];
+            }
+            idx = last + 1;
+            continue;
+        }
+        remaining -= values[idx];
+        total += values[idx];
+        selected.push(idx);
+        idx += 1;
+    }
+    None
+}
+
+/// Select coins from `utxos` paying for `target`, or `None` if the UTXOs don't suffice.
+pub fn select_coins(utxos: &[Utxo],
+                    target: &CoinSelectTarget,
+                    strategy: CoinSelectStrategy) -> Option<CoinSelection> {
+    let mut spendable = _spendable(utxos, target);
+    spendable.sort_by(|a, b| b.value_satoshis.cmp(&a.value_satoshis));
+    let selected = match strategy {
+        CoinSelectStrategy::LargestFirst => _largest_first(&spendable, target)?,
+        CoinSelectStrategy::BranchAndBound =>
+            _branch_and_bound(&spendable, target)
+                .or_else(|| _largest_first(&spendable, target))?,
+        CoinSelectStrategy::Consolidate { max_inputs } => {
+            let mut selected = _largest_first(&spendable, target)?;
+            let n_sweep = max_inputs.saturating_sub(selected.len())
+                .min(spendable.len() - selected.len());
+            selected.extend((spendable.len() - n_sweep..spendable.len()).rev());
+            selected
+        },
+    };
+    let total = selected.iter().map(|&idx| spendable[idx].value_satoshis).sum::<u64>();
+    let (fee, change) = target.settle(selected.len(), total)?;
+    Some(CoinSelection {
+        inputs: selected.iter()
+            .map(|&idx| {
+                let utxo = spendable[idx];
+                (TxOutpoint {
+                    tx_hash: tx_hash_from_le_slice(&utxo.tx_hash),
+                    vout: utxo.vout as u32,
+                }, utxo.value_satoshis)
+            })
+            .collect(),
+        fee,
+        change,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slpdexdb_base::SLPAmount;
+
+    fn utxo(vout: i32, value_satoshis: u64, token_hash: Option<[u8; 32]>) -> Utxo {
+        Utxo {
+            tx_hash: [0; 32],
+            vout,
+            value_satoshis,
+            value_token: SLPAmount::new(if token_hash.is_some() { 1 } else { 0 }, 0),
+            token_hash,
+        }
+    }
+
+    fn target(amount: u64) -> CoinSelectTarget {
+        CoinSelectTarget {
+            amount,
+            base_size: TX_OVERHEAD_SIZE + P2PKH_OUTPUT_SIZE,
+            fee_per_kb: 1000,
+            dust_limit: 546,
+        }
+    }
+
+    fn vouts(selection: &CoinSelection) -> Vec<u32> {
+        selection.inputs.iter().map(|(outpoint, _)| outpoint.vout).collect()
+    }
+
+    #[test]
+    fn coin_select() {
+        let utxos = vec![
+            utxo(0, 1_000_000, Some([1; 32])),
+            utxo(1, 50_000, None),
+            utxo(2, 20_000, None),
+            utxo(3, 10_192, None),
+            utxo(4, 100, None),
+        ];
+
+        let selection = select_coins(&utxos, &target(30_000), CoinSelectStrategy::LargestFirst)
+            .unwrap();
+        assert_eq!(vouts(&selection), vec![1]);
+        assert_eq!(selection.fee, 226);
+        assert_eq!(selection.change, 50_000 - 30_000 - 226);
+
+        // 20_000 + 10_192 pays 29_852 plus the 340 sats fee of a 2-in-1-out tx, without change
+        let selection = select_coins(&utxos, &target(29_852), CoinSelectStrategy::BranchAndBound)
+            .unwrap();
+        assert_eq!(vouts(&selection), vec![2, 3]);
+        assert_eq!(selection.change, 0);
+
+        let selection = select_coins(&utxos, &target(30_000),
+                                     CoinSelectStrategy::Consolidate { max_inputs: 3 })
+            .unwrap();
+        assert_eq!(vouts(&selection), vec![1, 3, 2]);
+
+        // the token UTXO is never spent
+        assert!(select_coins(&utxos, &target(100_000), CoinSelectStrategy::LargestFirst)
+            .is_none());
+    }
+}
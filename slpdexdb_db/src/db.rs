@@ -2,22 +2,73 @@ use diesel::pg::PgConnection;
 use diesel::data_types::PgNumeric;
 use diesel::prelude::*;
 
-use cashcontracts::{Address, AddressType};
+use cashcontracts::{Address, AddressType, Tx};
 use slpdexdb_base::{BlockHeader, GENESIS};
 use slpdexdb_base::SLPAmount;
 use slpdexdb_base::convert_numeric::{rational_to_pg_numeric, pg_numeric_to_rational};
-use crate::tx_history::{TxHistory, TxType, TradeOffer, TokenType};
+use crate::tx_history::{TxHistory, TxType, TradeOffer, TokenType, MintBaton};
 use crate::update_history::{UpdateHistory, UpdateSubject};
 use crate::token::Token;
 use crate::{models, schema::*};
 use crate::convert::pg_safe_string;
 use crate::data::{Utxo, NewUtxo, SpentUtxo, TxDelta, tx_hash_from_slice, address_hash_from_slice,
                   TradeOfferFilter};
+use crate::market::{PandaForSale, AuctionListing, DutchAuction};
+use crate::panda_tools::get_pandas_for_sale;
+use crate::coin_select::{CoinSelectStrategy, CoinSelectTarget, CoinSelection, select_coins};
+use crate::outgoing_tx::{OutgoingTx, OutgoingTxKind, OutgoingTxStatus};
+use crate::token_supply::{SupplyChange, SupplyChangeKind};
+use crate::trade_fill::{TradeSpend, TradeSpendKind, Candle, CandleInterval, candles};
+use crate::exch::ExchVersion;
 
 use std::collections::{HashMap, HashSet, BTreeSet};
 
 const PRICE_DIGITS: u16 = 26;
 
+type TradeOfferRow = (Vec<u8>,  Option<i32>, Vec<u8>,   i32,         i64,
+                     PgNumeric, Vec<u8>,     PgNumeric, bool,        i32,
+                     Vec<u8>,   i32);
+
+const TRADE_OFFER_COLUMNS: (tx::hash, trade_offer::output_idx, trade_offer::input_tx,
+                            trade_offer::input_idx, trade_offer::script_price,
+                            trade_offer::sell_amount_token_base, trade_offer::receiving_address,
+                            trade_offer::price_per_token, trade_offer::is_inverted,
+                            token::decimals, token::hash, trade_offer::exch_version) =
+    (tx::hash, trade_offer::output_idx, trade_offer::input_tx,
+     trade_offer::input_idx, trade_offer::script_price,
+     trade_offer::sell_amount_token_base, trade_offer::receiving_address,
+     trade_offer::price_per_token, trade_offer::is_inverted,
+     token::decimals, token::hash, trade_offer::exch_version);
+
+fn _trade_offer_from_row(row: TradeOfferRow) -> Option<([u8; 32], TradeOffer)> {
+    let (tx_hash, output_idx, input_tx, input_idx, script_price, sell_amount_token_base,
+         receiving_address, price_per_token, is_inverted, decimals, token_hash, exch_version) = row;
+    Some((tx_hash_from_slice(&token_hash), TradeOffer {
+        tx: tx_hash_from_slice(&tx_hash),
+        output_idx,
+        input_tx: tx_hash_from_slice(&input_tx),
+        input_idx,
+        exch_version: ExchVersion::from_version(exch_version as u8)?,
+        price_per_token: pg_numeric_to_rational(&price_per_token).ok()?,
+        script_price,
+        is_inverted,
+        sell_amount_token: SLPAmount::from_numeric_decimals(&sell_amount_token_base, decimals as u32),
+        receiving_address: Address
+            ::from_bytes(AddressType::P2PKH, address_hash_from_slice(&receiving_address)),
+    }))
+}
+
+/// Offers of `rows` whose contract output is one of `utxos`.
+fn _trade_offers_at(rows: Vec<TradeOfferRow>, utxos: &[SpentUtxo]) -> Vec<([u8; 32], TradeOffer)> {
+    let outpoints = utxos.iter().map(|utxo| (utxo.tx_hash, utxo.vout)).collect::<HashSet<_>>();
+    rows.into_iter()
+        .filter_map(_trade_offer_from_row)
+        .filter(|(_, offer)| {
+            offer.output_idx.map_or(false, |output_idx| outpoints.contains(&(offer.tx, output_idx)))
+        })
+        .collect()
+}
+
 pub struct Db {
     connection: PgConnection,
 }
@@ -105,6 +156,167 @@ impl Db {
         Ok(())
     }
 
+    /// Records a derived operator address and activates it, so it is synced like a subscribed one.
+    pub fn add_wallet_address(&self, role: i32, chain: i32, idx: i32, address: &Address)
+            -> QueryResult<()> {
+        diesel::insert_into(wallet_address::table)
+            .values(models::WalletAddress { address: address.bytes().to_vec(), role, chain, idx })
+            .on_conflict_do_nothing()
+            .execute(&self.connection)?;
+        self.set_address_active(address, true)
+    }
+
+    pub fn next_wallet_address_idx(&self, role: i32, chain: i32) -> QueryResult<i32> {
+        let max_idx = wallet_address::table
+            .filter(wallet_address::role.eq(role))
+            .filter(wallet_address::chain.eq(chain))
+            .select(diesel::dsl::max(wallet_address::idx))
+            .first::<Option<i32>>(&self.connection)?;
+        Ok(max_idx.map(|idx| idx + 1).unwrap_or(0))
+    }
+
+    pub fn wallet_addresses(&self) -> QueryResult<Vec<models::WalletAddress>> {
+        wallet_address::table
+            .order((wallet_address::role, wallet_address::chain, wallet_address::idx))
+            .load(&self.connection)
+    }
+
+    /// Verdict of the local SLP validator, if it validated the tx before.
+    pub fn slp_validity(&self, tx_hash: &[u8; 32]) -> QueryResult<Option<bool>> {
+        slp_validity::table
+            .filter(slp_validity::hash.eq(tx_hash.to_vec()))
+            .select(slp_validity::is_valid)
+            .first::<bool>(&self.connection)
+            .optional()
+    }
+
+    pub fn add_slp_validity(&self, tx_hash: &[u8; 32], is_valid: bool) -> QueryResult<()> {
+        diesel::insert_into(slp_validity::table)
+            .values(models::SlpValidity { hash: tx_hash.to_vec(), is_valid })
+            .on_conflict_do_nothing()
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    fn _mint_baton(baton: models::MintBaton) -> MintBaton {
+        MintBaton {
+            token_hash: tx_hash_from_slice(&baton.token_hash),
+            mint_tx: tx_hash_from_slice(&baton.mint_tx),
+            baton_vout: baton.baton_output_idx,
+        }
+    }
+
+    pub fn mint_batons(&self, token_hashes: impl Iterator<Item=[u8; 32]>)
+            -> QueryResult<HashMap<[u8; 32], MintBaton>> {
+        let token_hashes = token_hashes.map(|hash| hash.to_vec()).collect::<Vec<_>>();
+        Ok(mint_baton::table
+            .filter(mint_baton::token_hash.eq_any(token_hashes))
+            .load::<models::MintBaton>(&self.connection)?
+            .into_iter()
+            .map(Self::_mint_baton)
+            .map(|baton| (baton.token_hash, baton))
+            .collect())
+    }
+
+    /// Batons which are outputs of one of the `tx_hashes`.
+    pub fn mint_batons_of_txs(&self, tx_hashes: impl Iterator<Item=[u8; 32]>)
+            -> QueryResult<Vec<MintBaton>> {
+        let tx_hashes = tx_hashes.map(|hash| hash.to_vec()).collect::<Vec<_>>();
+        Ok(mint_baton::table
+            .filter(mint_baton::mint_tx.eq_any(tx_hashes))
+            .filter(mint_baton::baton_output_idx.is_not_null())
+            .load::<models::MintBaton>(&self.connection)?
+            .into_iter()
+            .map(Self::_mint_baton)
+            .collect())
+    }
+
+    /// Tracks a tx we just broadcast, so it is rebroadcast until it confirms. If handing it to
+    /// our peers failed (`is_sent` is false), it is due for a rebroadcast right away.
+    pub fn add_outgoing_tx(&self, tx: &Tx, kind: OutgoingTxKind, now: i64, is_sent: bool)
+            -> QueryResult<()> {
+        let mut raw_tx = Vec::new();
+        tx.write_to_stream(&mut raw_tx).unwrap();
+        diesel::insert_into(outgoing_tx::table)
+            .values(models::OutgoingTx {
+                hash: tx.hash().to_vec(),
+                kind: kind as i32,
+                raw_tx,
+                status: OutgoingTxStatus::Pending as i32,
+                reject_reason: None,
+                first_broadcast: now,
+                last_broadcast: if is_sent { now } else { 0 },
+                n_broadcasts: if is_sent { 1 } else { 0 },
+                n_rejects: 0,
+            })
+            .on_conflict_do_nothing()
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    pub fn outgoing_txs(&self, statuses: &[OutgoingTxStatus]) -> QueryResult<Vec<OutgoingTx>> {
+        Ok(outgoing_tx::table
+            .filter(outgoing_tx::status.eq_any(
+                statuses.iter().map(|&status| status as i32).collect::<Vec<_>>()
+            ))
+            .order(outgoing_tx::first_broadcast)
+            .load::<models::OutgoingTx>(&self.connection)?
+            .into_iter()
+            .filter_map(OutgoingTx::from_model)
+            .collect())
+    }
+
+    pub fn record_outgoing_tx_broadcast(&self, hash: &[u8; 32], now: i64) -> QueryResult<()> {
+        diesel::update(outgoing_tx::table.filter(outgoing_tx::hash.eq(hash.to_vec())))
+            .set((
+                outgoing_tx::last_broadcast.eq(now),
+                outgoing_tx::n_broadcasts.eq(outgoing_tx::n_broadcasts + 1),
+            ))
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    /// Counts a peer's reject of the tx if it is tracked and unconfirmed; returns the number of
+    /// rejects so far, if so.
+    pub fn record_outgoing_tx_reject(&self, hash: &[u8; 32], reason: &str) -> QueryResult<Option<i32>> {
+        diesel::update(
+            outgoing_tx::table
+                .filter(outgoing_tx::hash.eq(hash.to_vec()))
+                .filter(outgoing_tx::status.eq_any(vec![OutgoingTxStatus::Pending as i32,
+                                                        OutgoingTxStatus::Seen as i32]))
+        )
+            .set((
+                outgoing_tx::n_rejects.eq(outgoing_tx::n_rejects + 1),
+                outgoing_tx::reject_reason.eq(reason),
+            ))
+            .returning(outgoing_tx::n_rejects)
+            .get_result::<i32>(&self.connection)
+            .optional()
+    }
+
+    /// Moves those of `hashes` which are tracked and unconfirmed to `status`; returns how many.
+    /// Rejected txs only move on to confirmed, as a single peer's reject isn't final.
+    pub fn update_outgoing_txs_status(&self,
+                                      hashes: impl Iterator<Item=[u8; 32]>,
+                                      status: OutgoingTxStatus,
+                                      reject_reason: Option<String>) -> QueryResult<usize> {
+        let mut open_statuses = vec![OutgoingTxStatus::Pending as i32, OutgoingTxStatus::Seen as i32];
+        if status == OutgoingTxStatus::Confirmed {
+            open_statuses.push(OutgoingTxStatus::Rejected as i32);
+        }
+        let hashes = hashes.map(|hash| hash.to_vec()).collect::<Vec<_>>();
+        diesel::update(
+            outgoing_tx::table
+                .filter(outgoing_tx::hash.eq_any(hashes))
+                .filter(outgoing_tx::status.eq_any(open_statuses))
+        )
+            .set((
+                outgoing_tx::status.eq(status as i32),
+                outgoing_tx::reject_reason.eq(reject_reason),
+            ))
+            .execute(&self.connection)
+    }
+
     pub fn add_tx_history(&self, tx_history: &TxHistory) -> QueryResult<()> {
         self.connection.transaction(|| {
             let token_hashes = tx_history.txs.iter()
@@ -200,6 +412,7 @@ impl Db {
                         output_idx: trade_offer.output_idx,
                         input_idx: trade_offer.input_idx,
                         input_tx: trade_offer.input_tx.to_vec(),
+                        exch_version: trade_offer.exch_version as i32,
                         price_per_token: rational_to_pg_numeric(trade_offer.price_per_token.clone(),
                                                                 PRICE_DIGITS),
                         is_inverted: trade_offer.is_inverted,
@@ -221,7 +434,7 @@ impl Db {
                         tx: tx_ids[*tx_idx],
                         father: pnd_tx.father_id,
                         mother: pnd_tx.mother_id,
-                        owner_address: pnd_tx.owner_address.bytes().to_vec(),
+                        owner_address: pnd_tx.recipient_address.bytes().to_vec(),
                     }
                 })
                 .collect::<Vec<_>>();
@@ -229,10 +442,169 @@ impl Db {
                 .values(&new_pending_pnd1_txs)
                 .on_conflict_do_nothing()
                 .execute(&self.connection)?;
+            let new_sire_offers = tx_history.sire_offers
+                .iter()
+                .map(|(tx_idx, sire_offer)| {
+                    models::SireOffer {
+                        tx: tx_ids[*tx_idx],
+                        panda: sire_offer.sire_id,
+                        sire_tx: sire_offer.sire_tx_id,
+                        sire_output_idx: sire_offer.sire_output_idx as i32,
+                        fee_satoshis: sire_offer.fee_satoshis as i64,
+                        owner_address: sire_offer.owner_address.bytes().to_vec(),
+                    }
+                })
+                .collect::<Vec<_>>();
+            diesel::insert_into(sire_offer::table)
+                .values(&new_sire_offers)
+                .on_conflict_do_nothing()
+                .execute(&self.connection)?;
+            let new_auctions = tx_history.auctions
+                .iter()
+                .map(|(tx_idx, auction)| {
+                    models::PandaAuction {
+                        tx: tx_ids[*tx_idx],
+                        panda: auction.panda_id,
+                        panda_tx: auction.panda_tx_id,
+                        panda_output_idx: auction.panda_output_idx as i32,
+                        start_price: auction.auction.start_price as i64,
+                        end_price: auction.auction.end_price as i64,
+                        start_height: auction.auction.start_height,
+                        duration_blocks: auction.auction.duration_blocks,
+                        seller_address: auction.seller_address.bytes().to_vec(),
+                        closing_tx: None,
+                        settled_price: None,
+                    }
+                })
+                .collect::<Vec<_>>();
+            diesel::insert_into(panda_auction::table)
+                .values(&new_auctions)
+                .on_conflict_do_nothing()
+                .execute(&self.connection)?;
+            for baton in tx_history.mint_batons.values() {
+                diesel::insert_into(mint_baton::table)
+                    .values(&models::MintBaton {
+                        token_hash: baton.token_hash.to_vec(),
+                        mint_tx: baton.mint_tx.to_vec(),
+                        baton_output_idx: baton.baton_vout,
+                    })
+                    .on_conflict(mint_baton::token_hash)
+                    .do_update().set((
+                        mint_baton::mint_tx.eq(baton.mint_tx.to_vec()),
+                        mint_baton::baton_output_idx.eq(baton.baton_vout),
+                    ))
+                    .execute(&self.connection)?;
+            }
+            self._apply_supply_changes(&tx_history.supply_changes)?;
+            let new_trade_spends = tx_history.trade_spends.iter()
+                .map(|spend| models::TradeOfferSpend {
+                    offer_tx: spend.offer_tx.to_vec(),
+                    offer_output_idx: spend.offer_output_idx,
+                    spending_tx: spend.spending_tx.to_vec(),
+                    token_hash: spend.token_hash.to_vec(),
+                    kind: spend.kind as i32,
+                    price_per_token: rational_to_pg_numeric(spend.price_per_token.clone(),
+                                                            PRICE_DIGITS),
+                    amount_token_base: spend.amount.into(),
+                    timestamp: spend.timestamp,
+                })
+                .collect::<Vec<_>>();
+            diesel::insert_into(trade_offer_spend::table)
+                .values(&new_trade_spends)
+                .on_conflict_do_nothing()
+                .execute(&self.connection)?;
             Ok(())
         })
     }
 
+    /// Records the supply changes and updates the current supply of their tokens. Changes which
+    /// were recorded before are skipped, so txs can be added again.
+    fn _apply_supply_changes(&self, supply_changes: &[SupplyChange]) -> QueryResult<()> {
+        let new_changes = supply_changes.iter()
+            .map(|change| models::TokenSupplyChange {
+                tx_hash: change.tx_hash.to_vec(),
+                token_hash: change.token_hash.to_vec(),
+                kind: change.kind as i32,
+                amount_base: change.amount.into(),
+                timestamp: change.timestamp,
+            })
+            .collect::<Vec<_>>();
+        let inserted = diesel::insert_into(token_supply_change::table)
+            .values(&new_changes)
+            .on_conflict_do_nothing()
+            .get_results::<models::TokenSupplyChange>(&self.connection)?;
+        for change in inserted {
+            let changed_token = token::table.filter(token::hash.eq(change.token_hash));
+            match num::FromPrimitive::from_i32(change.kind) {
+                Some(SupplyChangeKind::Genesis) => diesel::update(changed_token)
+                    .set(token::current_supply.eq(change.amount_base))
+                    .execute(&self.connection)?,
+                Some(SupplyChangeKind::Mint) => diesel::update(changed_token)
+                    .set(token::current_supply.eq(token::current_supply + change.amount_base))
+                    .execute(&self.connection)?,
+                Some(_) => diesel::update(changed_token)
+                    .set(token::current_supply.eq(token::current_supply - change.amount_base))
+                    .execute(&self.connection)?,
+                None => 0,
+            };
+        }
+        Ok(())
+    }
+
+    pub fn token_burns(&self, token_hash: &[u8; 32]) -> QueryResult<Vec<SupplyChange>> {
+        let decimals = match self.token(token_hash)? {
+            Some(token) => token.decimals as u32,
+            None => return Ok(vec![]),
+        };
+        Ok(token_supply_change::table
+            .filter(token_supply_change::token_hash.eq(token_hash.to_vec()))
+            .order(token_supply_change::timestamp.asc())
+            .load::<models::TokenSupplyChange>(&self.connection)?
+            .into_iter()
+            .filter_map(|change| SupplyChange::from_model(change, decimals))
+            .filter(|change| change.kind.is_burn())
+            .collect())
+    }
+
+    /// The last `n_candles` candles of the token up to `now`.
+    pub fn candles(&self, token_hash: &[u8; 32], interval: CandleInterval, n_candles: i64, now: i64)
+            -> QueryResult<Vec<Candle>> {
+        let current_start = now - now % interval.seconds();
+        let fills = self.trade_fills(token_hash, current_start - interval.seconds() * (n_candles - 1))?;
+        Ok(candles(&fills, interval))
+    }
+
+    /// Fills of the token's trade offers since `since_timestamp`, oldest first.
+    pub fn trade_fills(&self, token_hash: &[u8; 32], since_timestamp: i64)
+            -> QueryResult<Vec<TradeSpend>> {
+        let decimals = match self.token(token_hash)? {
+            Some(token) => token.decimals as u32,
+            None => return Ok(vec![]),
+        };
+        Ok(trade_offer_spend::table
+            .filter(trade_offer_spend::token_hash.eq(token_hash.to_vec()))
+            .filter(trade_offer_spend::kind.eq(TradeSpendKind::Fill as i32))
+            .filter(trade_offer_spend::timestamp.ge(since_timestamp))
+            .order(trade_offer_spend::timestamp.asc())
+            .load::<models::TradeOfferSpend>(&self.connection)?
+            .into_iter()
+            .filter_map(|spend| TradeSpend::from_model(spend, decimals))
+            .collect())
+    }
+
+    /// NFT1 children of the group whose token has been burned entirely, e.g. destroyed pandas.
+    pub fn destroyed_nft1_children(&self, group_hash: &[u8; 32]) -> QueryResult<Vec<[u8; 32]>> {
+        let zero: PgNumeric = SLPAmount::new(0, 0).into();
+        Ok(token::table
+            .filter(token::parent_token_hash.eq(group_hash.to_vec()))
+            .filter(token::current_supply.eq(zero))
+            .select(token::hash)
+            .load::<Vec<u8>>(&self.connection)?
+            .iter()
+            .map(|hash| tx_hash_from_slice(hash))
+            .collect())
+    }
+
     pub fn last_update(&self, subject: UpdateSubject) -> QueryResult<Option<UpdateHistory>> {
         let query = update_history::table
             .filter(update_history::subject_type.eq(subject.subject_type as i32))
@@ -419,6 +791,14 @@ impl Db {
             .collect())
     }
 
+    /// Select fee inputs for a tx of the operator at `address` from its BCH-only UTXOs.
+    pub fn select_coins(&self,
+                        address: &Address,
+                        target: &CoinSelectTarget,
+                        strategy: CoinSelectStrategy) -> QueryResult<Option<CoinSelection>> {
+        Ok(select_coins(&self.utxos_address(address)?, target, strategy))
+    }
+
     pub fn address_tx_deltas(&self, address: &Address) -> QueryResult<Vec<TxDelta>> {
         use diesel::sql_types::Binary;
         let input_query = diesel::sql_query("\
@@ -523,50 +903,90 @@ impl Db {
             .collect())
     }
 
-    pub fn trade_offer_utxos(&self, filter: TradeOfferFilter) -> QueryResult<Vec<TradeOffer>> {
-        use super::schema::trade_offer as t;
-        type Q = (Vec<u8>, Option<i32>,   Vec<u8>,     i32,          i64,
-                  PgNumeric,                Vec<u8>,              PgNumeric,          bool,
-                  i32);
-        let s = (tx::hash, t::output_idx, t::input_tx, t::input_idx, t::script_price,
-                 t::sell_amount_token_base, t::receiving_address, t::price_per_token, t::is_inverted,
-                 token::decimals);
-        let tables = trade_offer::table
+    /// Unspent trade offers matching `filter`, of any version if `exch_versions` is `None`.
+    pub fn trade_offer_utxos(&self,
+                             filter: TradeOfferFilter,
+                             exch_versions: Option<&[ExchVersion]>) -> QueryResult<Vec<TradeOffer>> {
+        let query = trade_offer::table
             .inner_join(tx::table)
-            .inner_join(utxo_trade_offer::table.on(tx::id.eq(utxo_trade_offer::tx)))
+            .inner_join(utxo_trade_offer::table.on(
+                tx::id.eq(utxo_trade_offer::tx)
+                    .and(trade_offer::output_idx.eq(utxo_trade_offer::idx.nullable()))
+            ))
             .inner_join(slp_tx::table.on(tx::id.eq(slp_tx::tx)))
             .inner_join(token::table.on(slp_tx::token.eq(token::id)))
-            .select(s);
-        let result = match filter {
-            TradeOfferFilter::TokenHash(token_hash) => tables
-                .filter(tx::hash.eq(token_hash.to_vec()))
-                .load::<Q>(&self.connection)?,
-            TradeOfferFilter::ReceivingAddress(address) => tables
-                .filter(trade_offer::receiving_address.eq(address.bytes().to_vec()))
-                .load::<Q>(&self.connection)?,
+            .select(TRADE_OFFER_COLUMNS)
+            .into_boxed();
+        let mut query = match filter {
+            TradeOfferFilter::TokenHash(token_hash) => query
+                .filter(token::hash.eq(token_hash.to_vec())),
+            TradeOfferFilter::ReceivingAddress(address) => query
+                .filter(trade_offer::receiving_address.eq(address.bytes().to_vec())),
         };
-        Ok(result
+        if let Some(exch_versions) = exch_versions {
+            query = query.filter(trade_offer::exch_version.eq_any(
+                exch_versions.iter().map(|version| *version as i32).collect::<Vec<_>>()
+            ));
+        }
+        Ok(query.load::<TradeOfferRow>(&self.connection)?
             .into_iter()
-            .filter_map(|(tx_hash, output_idx, input_tx, input_idx, script_price,
-                          sell_amount_token_base, receiving_address, price_per_token, is_inverted,
-                          decimals)| {
-                Some(TradeOffer {
-                    tx: tx_hash_from_slice(&tx_hash),
-                    output_idx,
-                    input_tx: tx_hash_from_slice(&input_tx),
-                    input_idx,
-                    price_per_token: pg_numeric_to_rational(&price_per_token).ok()?,
-                    script_price,
-                    is_inverted,
-                    sell_amount_token: SLPAmount
-                        ::from_numeric_decimals(&sell_amount_token_base, decimals as u32),
-                    receiving_address: Address
-                        ::from_bytes(AddressType::P2PKH, address_hash_from_slice(&receiving_address)),
-                })
-            })
+            .filter_map(|row| _trade_offer_from_row(row).map(|(_, offer)| offer))
+            .collect())
+    }
+
+    /// Trade offers whose contract output is one of `utxos`, spent or not, with their token hash.
+    pub fn trade_offers_at(&self, utxos: &[SpentUtxo]) -> QueryResult<Vec<([u8; 32], TradeOffer)>> {
+        let tx_hashes = utxos.iter().map(|utxo| utxo.tx_hash.to_vec()).collect::<Vec<_>>();
+        let rows = trade_offer::table
+            .inner_join(tx::table)
+            .inner_join(slp_tx::table.on(tx::id.eq(slp_tx::tx)))
+            .inner_join(token::table.on(slp_tx::token.eq(token::id)))
+            .filter(tx::hash.eq_any(tx_hashes))
+            .select(TRADE_OFFER_COLUMNS)
+            .load::<TradeOfferRow>(&self.connection)?;
+        Ok(_trade_offers_at(rows, utxos))
+    }
+
+    /// Like `trade_offers_at`, but only the offers still in the UTXO set.
+    pub fn trade_offer_utxos_at(&self, utxos: &[SpentUtxo])
+            -> QueryResult<Vec<([u8; 32], TradeOffer)>> {
+        let tx_hashes = utxos.iter().map(|utxo| utxo.tx_hash.to_vec()).collect::<Vec<_>>();
+        let rows = trade_offer::table
+            .inner_join(tx::table)
+            .inner_join(utxo_trade_offer::table.on(
+                tx::id.eq(utxo_trade_offer::tx)
+                    .and(trade_offer::output_idx.eq(utxo_trade_offer::idx.nullable()))
+            ))
+            .inner_join(slp_tx::table.on(tx::id.eq(slp_tx::tx)))
+            .inner_join(token::table.on(slp_tx::token.eq(token::id)))
+            .filter(tx::hash.eq_any(tx_hashes))
+            .select(TRADE_OFFER_COLUMNS)
+            .load::<TradeOfferRow>(&self.connection)?;
+        Ok(_trade_offers_at(rows, utxos))
+    }
+
+    /// The ones of `utxos` which are spent by any known tx.
+    pub fn spent_utxos(&self, utxos: &[SpentUtxo]) -> QueryResult<HashSet<([u8; 32], i32)>> {
+        let outpoints = utxos.iter().map(|utxo| (utxo.tx_hash, utxo.vout)).collect::<HashSet<_>>();
+        Ok(tx_input::table
+            .filter(tx_input::output_tx.eq_any(
+                utxos.iter().map(|utxo| utxo.tx_hash.to_vec()).collect::<Vec<_>>()
+            ))
+            .select((tx_input::output_tx, tx_input::output_idx))
+            .load::<(Vec<u8>, i32)>(&self.connection)?
+            .into_iter()
+            .map(|(tx_hash, output_idx)| (tx_hash_from_slice(&tx_hash), output_idx))
+            .filter(|outpoint| outpoints.contains(outpoint))
             .collect())
     }
 
+    /// Trade offers whose contract output still holds a panda. Restricted to the given offer txs
+    /// if `offer_txs` is set.
+    pub fn pandas_for_sale(&self, panda_token_hash: &[u8; 32], offer_txs: Option<Vec<[u8; 32]>>)
+            -> QueryResult<Vec<PandaForSale>> {
+        get_pandas_for_sale(panda_token_hash, offer_txs, &self.connection)
+    }
+
     pub fn txs(&self, tx_hashes: impl Iterator<Item=[u8; 32]>)
             -> QueryResult<HashMap<[u8; 32], models::Tx>> {
         Ok(tx::table
@@ -664,6 +1084,107 @@ impl Db {
             .load(&self.connection)
     }
 
+    /// Latest sire offer for the panda at the given UTXO. Offers for previous UTXOs of the
+    /// panda are void, so moving a panda withdraws its offer.
+    pub fn sire_offer(&self, panda_id: i64, owner_tx: i64, owner_tx_idx: i32)
+            -> QueryResult<Option<models::SireOffer>> {
+        sire_offer::table
+            .filter(sire_offer::panda.eq(panda_id))
+            .filter(sire_offer::sire_tx.eq(owner_tx))
+            .filter(sire_offer::sire_output_idx.eq(owner_tx_idx))
+            .order(sire_offer::tx.desc())
+            .first::<models::SireOffer>(&self.connection)
+            .optional()
+    }
+
+    pub fn active_sire_offers(&self) -> QueryResult<Vec<models::SireOffer>> {
+        sire_offer::table
+            .inner_join(panda::table.on(
+                sire_offer::panda.eq(panda::id)
+                    .and(sire_offer::sire_tx.eq(panda::owner_tx))
+                    .and(sire_offer::sire_output_idx.eq(panda::owner_tx_idx))
+            ))
+            .select(sire_offer::all_columns)
+            .order(sire_offer::tx.desc())
+            .load::<models::SireOffer>(&self.connection)
+    }
+
+    fn _auction_listings(&self, auctions: Vec<(models::PandaAuction, i64)>)
+            -> QueryResult<Vec<AuctionListing>> {
+        let tx_ids = auctions.iter()
+            .flat_map(|(auction, genesis_tx)| vec![auction.tx, auction.panda_tx, *genesis_tx])
+            .collect::<Vec<_>>();
+        let tx_hashes = tx::table
+            .filter(tx::id.eq_any(tx_ids))
+            .select((tx::id, tx::hash))
+            .load::<(i64, Vec<u8>)>(&self.connection)?
+            .into_iter()
+            .map(|(id, hash)| (id, tx_hash_from_slice(&hash)))
+            .collect::<HashMap<_, _>>();
+        Ok(auctions.into_iter()
+            .map(|(auction, genesis_tx)| AuctionListing {
+                auction_tx: tx_hashes[&auction.tx],
+                token_hash: tx_hashes[&genesis_tx],
+                panda_tx_hash: tx_hashes[&auction.panda_tx],
+                panda_output_idx: auction.panda_output_idx,
+                auction: DutchAuction {
+                    start_price: auction.start_price as u64,
+                    end_price: auction.end_price as u64,
+                    start_height: auction.start_height,
+                    duration_blocks: auction.duration_blocks,
+                },
+                seller_address: Address::from_bytes(AddressType::P2PKH,
+                                                    address_hash_from_slice(&auction.seller_address)),
+            })
+            .collect())
+    }
+
+    /// Auctions that haven't been closed and whose panda is still at the auctioned UTXO.
+    pub fn active_auctions(&self) -> QueryResult<Vec<AuctionListing>> {
+        let auctions = panda_auction::table
+            .inner_join(panda::table.on(
+                panda::id.eq(panda_auction::panda)
+                    .and(panda::owner_tx.eq(panda_auction::panda_tx))
+                    .and(panda::owner_tx_idx.eq(panda_auction::panda_output_idx))
+            ))
+            .filter(panda_auction::closing_tx.is_null())
+            .select((panda_auction::all_columns, panda::genesis_tx))
+            .load::<(models::PandaAuction, i64)>(&self.connection)?;
+        self._auction_listings(auctions)
+    }
+
+    /// Auctions that haven't been closed yet on any of the given UTXOs.
+    pub fn open_auctions_at(&self, utxos: &[SpentUtxo]) -> QueryResult<Vec<AuctionListing>> {
+        let txs = self.txs(utxos.iter().map(|utxo| utxo.tx_hash))?;
+        let outpoints = utxos.iter()
+            .filter_map(|utxo| Some((txs.get(&utxo.tx_hash)?.id, utxo.vout)))
+            .collect::<HashSet<_>>();
+        let auctions = panda_auction::table
+            .inner_join(panda::table.on(panda::id.eq(panda_auction::panda)))
+            .filter(panda_auction::panda_tx.eq_any(
+                outpoints.iter().map(|&(tx_id, _)| tx_id).collect::<Vec<_>>()
+            ))
+            .filter(panda_auction::closing_tx.is_null())
+            .select((panda_auction::all_columns, panda::genesis_tx))
+            .load::<(models::PandaAuction, i64)>(&self.connection)?
+            .into_iter()
+            .filter(|(auction, _)| outpoints.contains(&(auction.panda_tx, auction.panda_output_idx)))
+            .collect();
+        self._auction_listings(auctions)
+    }
+
+    pub fn close_auction(&self, auction_tx: &[u8; 32], closing_tx: &[u8; 32], settled_price: Option<u64>)
+            -> QueryResult<()> {
+        let txs = self.txs(vec![auction_tx.clone(), closing_tx.clone()].into_iter())?;
+        if let (Some(auction_tx), Some(closing_tx)) = (txs.get(auction_tx), txs.get(closing_tx)) {
+            diesel::update(panda_auction::table.filter(panda_auction::tx.eq(auction_tx.id)))
+                .set((panda_auction::closing_tx.eq(closing_tx.id),
+                      panda_auction::settled_price.eq(settled_price.map(|price| price as i64))))
+                .execute(&self.connection)?;
+        }
+        Ok(())
+    }
+
     pub fn get_some_pandaop_utxo(&self) -> QueryResult<Option<models::PandaopUtxo>> {
         let utxo = pandaop_utxo::table
             .limit(1)
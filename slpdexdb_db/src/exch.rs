@@ -0,0 +1,634 @@
+use cashcontracts::{UnsignedTx, UnsignedInput, Tx, TxOutpoint, TxOutput, Output, Script, PreImage,
+                    Address, P2PKHOutput, SLPSend, AdvancedTradeOffer, AdvancedTradeOfferSpendParams,
+                    hash160, tx_hash_to_hex};
+use slpdexdb_base::{SLPDEXConfig, SLPAmount, FeePolicy, Result, Error, ErrorKind, PandaError,
+                    TradeOfferError};
+use crate::tx_fee::{FeeEstimate, estimate_fee};
+use crate::signer::{serialize_pre_images, sign_with_signatures};
+use byteorder::{BigEndian, ReadBytesExt};
+use rug::Rational;
+use std::io;
+
+/// Output of the offer tx which is locked by the covenant.
+pub const EXCH_CONTRACT_VOUT: u32 = 1;
+
+const _FACTORS: [u64; 10] = [
+    1,
+    10,
+    100,
+    1_000,
+    10_000,
+    100_000,
+    1_000_000,
+    10_000_000,
+    100_000_000,
+    1_000_000_000,
+];
+
+/// Version of the EXCH protocol, pushed as small int after the lokad id. Each version has its
+/// own price encoding and covenant; all versions in `SLPDEXConfig::exch_versions` are indexed
+/// side by side.
+#[derive(Copy, Clone, Debug, FromPrimitive, Eq, PartialEq, Hash)]
+pub enum ExchVersion {
+    V2 = 2,
+}
+
+impl ExchVersion {
+    pub fn from_version(version: u8) -> Option<Self> {
+        num::FromPrimitive::from_u8(version)
+    }
+
+    /// Supported versions, in the order of the config.
+    pub fn all(config: &SLPDEXConfig) -> Vec<Self> {
+        config.exch_versions.iter().cloned().filter_map(Self::from_version).collect()
+    }
+
+    pub fn is_supported(&self, config: &SLPDEXConfig) -> bool {
+        config.exch_versions.contains(&(*self as u8))
+    }
+
+    /// Opcode pushing the version, OP_2 to OP_16.
+    pub fn op(&self) -> u8 {
+        0x50 + *self as u8
+    }
+
+    pub fn from_op(op: u8) -> Option<Self> {
+        if op < 0x52 || op > 0x60 {
+            return None
+        }
+        Self::from_version(op - 0x50)
+    }
+
+    pub fn op_code(&self) -> cashcontracts::OpCodeType {
+        use cashcontracts::OpCodeType::*;
+        match self {
+            ExchVersion::V2 => Op2,
+        }
+    }
+
+    pub fn from_op_code(op_code: &cashcontracts::OpCodeType) -> Option<Self> {
+        use cashcontracts::OpCodeType::*;
+        match op_code {
+            Op2 => Some(ExchVersion::V2),
+            _ => None,
+        }
+    }
+
+    /// Dust amount baked into the covenant. It is part of the contract hash, so it's fixed per
+    /// version instead of following the fee policy, which only applies to the outputs we build.
+    pub fn dust_amount(&self) -> u64 {
+        match self {
+            ExchVersion::V2 => 0x222,
+        }
+    }
+
+    /// Decodes the power and price pushes into the script price and the price per token.
+    pub fn decode_price(&self, slp_decimals: i32, power_bytes: &[u8], price_bytes: &[u8])
+            -> Result<(ExchPrice, Rational)> {
+        match self {
+            ExchVersion::V2 => _decode_price_v2(slp_decimals, power_bytes, price_bytes),
+        }
+    }
+
+    /// Covenant locking `sell_amount` of the token at `price`, spent with `spend_params`.
+    pub fn contract(&self,
+                    config: &SLPDEXConfig,
+                    value: u64,
+                    token_id: [u8; 32],
+                    token_type: u8,
+                    sell_amount: SLPAmount,
+                    price: ExchPrice,
+                    receiving_address: &Address,
+                    spend_params: Option<AdvancedTradeOfferSpendParams>) -> Box<dyn Output> {
+        match self {
+            ExchVersion::V2 => Box::new(AdvancedTradeOffer {
+                value,
+                lokad_id: config.exch_lokad.as_bytes().to_vec(),
+                version: *self as u8,
+                power: price.power,
+                is_inverted: price.is_inverted,
+                token_id,
+                token_type,
+                sell_amount_token: sell_amount.base_amount() as u64,
+                price: price.script_price,
+                dust_amount: self.dust_amount(),
+                address: receiving_address.clone(),
+                fee_address: Some(config.fee_address.clone()),
+                fee_divisor: Some(config.fee_divisor),
+                spend_params,
+            }),
+        }
+    }
+}
+
+fn _decode_price_v2(slp_decimals: i32, power_bytes: &[u8], price_bytes: &[u8])
+        -> Result<(ExchPrice, Rational)> {
+    let invalid_price = || -> Error {
+        ErrorKind::InvalidTradeOffer(TradeOfferError::InvalidPrice(price_bytes.to_vec())).into()
+    };
+    let is_inverted = power_bytes.get(1) == Some(&1);
+    let script_price = io::Cursor::new(price_bytes)
+        .read_u32::<BigEndian>()
+        .map_err(|_| invalid_price())?;
+    let factor = *_FACTORS.get(slp_decimals as usize).ok_or_else(invalid_price)?;
+    let factor_rational = Rational::from((factor, 1));
+    let price_per_token = if is_inverted {
+        if script_price == 0 {
+            return Err(invalid_price())
+        }
+        Rational::from((1, script_price)) * factor_rational
+    } else {
+        Rational::from((script_price, 1)) * factor_rational
+    };
+    let power = *power_bytes.get(0).ok_or_else(|| -> Error {
+        ErrorKind::InvalidTradeOffer(TradeOfferError::InvalidPower(power_bytes.to_vec())).into()
+    })?;
+    Ok((ExchPrice { power, is_inverted, script_price }, price_per_token))
+}
+
+/// Price as pushed by the EXCH input. Not inverted, `script_price` is the price of one base
+/// token unit in satoshis; inverted, it's the number of base units per satoshi.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ExchPrice {
+    pub power: u8,
+    pub is_inverted: bool,
+    pub script_price: u32,
+}
+
+impl ExchPrice {
+    fn _power_bytes(&self) -> Vec<u8> {
+        vec![self.power, self.is_inverted as u8]
+    }
+
+    fn _price_bytes(&self) -> Vec<u8> {
+        self.script_price.to_be_bytes().to_vec()
+    }
+
+    /// Satoshis the maker asks for `amount`, rounded up.
+    pub fn payment_satoshis(&self, amount: SLPAmount) -> Result<u64> {
+        let invalid_price = || -> Error {
+            ErrorKind::InvalidTradeOffer(TradeOfferError::InvalidPrice(self._price_bytes())).into()
+        };
+        let amount = amount.base_amount() as u128;
+        let script_price = self.script_price as u128;
+        let payment = if self.is_inverted {
+            if script_price == 0 {
+                return Err(invalid_price());
+            }
+            (amount + script_price - 1) / script_price
+        } else {
+            amount * script_price
+        };
+        if payment > std::u64::MAX as u128 {
+            return Err(invalid_price());
+        }
+        Ok(payment as u64)
+    }
+}
+
+fn _p2sh_script(redeem_script: &Script) -> Script {
+    use cashcontracts::{Op::*, OpCodeType::*};
+    Script::new(vec![
+        Code(OpHash160),
+        Push(hash160(&redeem_script.to_vec()).to_vec()),
+        Code(OpEqual),
+    ])
+}
+
+fn _add_p2pkh_inputs(tx_build: &mut UnsignedTx, address: &Address, inputs: &[(TxOutpoint, u64)]) {
+    for (outpoint, amount) in inputs.iter() {
+        tx_build.add_input(UnsignedInput {
+            outpoint: outpoint.clone(),
+            output: Box::new(P2PKHOutput {
+                address: address.clone(),
+                value: *amount,
+            }),
+            sequence: 0xffff_ffff,
+        });
+    }
+}
+
+fn _add_p2pkh_output(tx_build: &mut UnsignedTx, address: &Address, value: u64) {
+    tx_build.add_output(TxOutput {
+        value,
+        script: P2PKHOutput {
+            value: 0,
+            address: address.clone(),
+        }.script(),
+    });
+}
+
+fn _add_leftover_output(tx_build: &mut UnsignedTx,
+                        address: &Address,
+                        fee_policy: &FeePolicy) -> Result<()> {
+    tx_build.add_leftover_output(address.clone(), fee_policy.fee_per_kb(), fee_policy.dust_limit)
+        .map_err(|missing_funds| -> Error {
+            ErrorKind::PandaError(PandaError::InsufficientFunds(missing_funds)).into()
+        })?;
+    Ok(())
+}
+
+fn _input_total(inputs: &[(TxOutpoint, u64)]) -> u64 {
+    inputs.iter().map(|(_, amount)| *amount).sum()
+}
+
+/// P2SH output whose spending input carries the EXCH push data, which is how offers are found:
+/// `<"EXCH"> <version> <power> <price> <receiving address> <sig> <pub key> <redeem script>`. The
+/// redeem script checks the maker's signature and drops the push data.
+pub struct ExchAdOutput {
+    pub value: u64,
+    pub version: ExchVersion,
+    pub maker_address: Address,
+    pub lokad_id: Vec<u8>,
+    pub price: ExchPrice,
+    pub receiving_address: Address,
+}
+
+impl Output for ExchAdOutput {
+    fn value(&self) -> u64 {
+        self.value
+    }
+
+    fn script(&self) -> Script {
+        use cashcontracts::{Op::*, OpCodeType::*};
+        Script::new(vec![
+            Code(OpDup),
+            Code(OpHash160),
+            Push(self.maker_address.bytes().to_vec()),
+            Code(OpEqualVerify),
+            Code(OpCheckSigVerify),
+            Code(Op2Drop),
+            Code(Op2Drop),
+            Code(OpDrop),
+            Code(Op1),
+        ])
+    }
+
+    fn script_code(&self) -> Script {
+        self.script()
+    }
+
+    fn sig_script(&self,
+                  serialized_sig: Vec<u8>,
+                  serialized_pub_key: Vec<u8>,
+                  _pre_image: &PreImage,
+                  _outputs: &[TxOutput]) -> Script {
+        use cashcontracts::Op::*;
+        Script::new(vec![
+            Push(self.lokad_id.clone()),
+            Code(self.version.op_code()),
+            Push(self.price._power_bytes()),
+            Push(self.price._price_bytes()),
+            Push(self.receiving_address.bytes().to_vec()),
+            Push(serialized_sig),
+            Push(serialized_pub_key),
+            Push(self.script().to_vec()),
+        ])
+    }
+}
+
+/// Maker side of an EXCH offer, selling `sell_amount` of the tokens in `token_inputs`. The push
+/// data has to be in an input, so it takes two txs: the ad tx funds an `ExchAdOutput` from
+/// `fee_inputs`, and the offer tx spends it together with the tokens, sending `sell_amount` to
+/// the covenant at `EXCH_CONTRACT_VOUT` and the rest back to `maker_address`, which owns all
+/// inputs. Both are signed externally, see `PandaTransferTx`.
+pub struct ExchOfferTx {
+    pub version: ExchVersion,
+    pub token_id: [u8; 32],
+    pub token_type: u8,
+    pub token_inputs: Vec<(TxOutpoint, u64)>,
+    pub token_input_amount: SLPAmount,
+    pub sell_amount: SLPAmount,
+    pub price: ExchPrice,
+    pub maker_address: Address,
+    pub receiving_address: Address,
+    pub fee_inputs: Vec<(TxOutpoint, u64)>,
+    pub fee_policy: FeePolicy,
+}
+
+impl ExchOfferTx {
+    fn _token_change(&self) -> Result<SLPAmount> {
+        let token_change = self.token_input_amount - self.sell_amount;
+        if self.sell_amount.base_amount() <= 0 || token_change.base_amount() < 0 {
+            return Err(ErrorKind::InvalidTradeOffer(
+                TradeOfferError::InsufficientTokens(-token_change.base_amount())
+            ).into());
+        }
+        Ok(token_change)
+    }
+
+    fn _ad_output(&self, config: &SLPDEXConfig, value: u64) -> ExchAdOutput {
+        ExchAdOutput {
+            value,
+            version: self.version,
+            maker_address: self.maker_address.clone(),
+            lokad_id: config.exch_lokad.as_bytes().to_vec(),
+            price: self.price,
+            receiving_address: self.receiving_address.clone(),
+        }
+    }
+
+    /// The ad output pays for the outputs and fee of the offer tx.
+    fn _ad_value(&self, config: &SLPDEXConfig) -> Result<u64> {
+        let reserve = std::u32::MAX as u64;
+        let placeholder_outpoint = TxOutpoint { tx_hash: [0; 32], vout: 0 };
+        let estimate = estimate_fee(
+            self._unsigned_offer_tx(config, placeholder_outpoint, reserve)?,
+            reserve + _input_total(&self.token_inputs),
+        );
+        let fee = self.fee_policy.fee_for_size(estimate.size).ok_or_else(|| -> Error {
+            ErrorKind::PandaError(PandaError::FeeOverflow(estimate.size)).into()
+        })?;
+        Ok(2 * self.fee_policy.dust_limit + fee)
+    }
+
+    fn _unsigned_ad_tx(&self, config: &SLPDEXConfig) -> Result<UnsignedTx> {
+        let mut tx_build = UnsignedTx::new_simple();
+        _add_p2pkh_inputs(&mut tx_build, &self.maker_address, &self.fee_inputs);
+        let ad_output = self._ad_output(config, self._ad_value(config)?);
+        tx_build.add_output(TxOutput {
+            value: ad_output.value,
+            script: _p2sh_script(&ad_output.script()),
+        });
+        _add_leftover_output(&mut tx_build, &self.maker_address, &self.fee_policy)?;
+        Ok(tx_build)
+    }
+
+    fn _unsigned_offer_tx(&self, config: &SLPDEXConfig, ad_outpoint: TxOutpoint, ad_value: u64)
+            -> Result<UnsignedTx> {
+        let token_change = self._token_change()?;
+        let mut tx_build = UnsignedTx::new_simple();
+        tx_build.add_input(UnsignedInput {
+            outpoint: ad_outpoint,
+            output: Box::new(self._ad_output(config, ad_value)),
+            sequence: 0xffff_ffff,
+        });
+        _add_p2pkh_inputs(&mut tx_build, &self.maker_address, &self.token_inputs);
+        let mut output_quantities = vec![self.sell_amount.base_amount() as u64];
+        if token_change.base_amount() > 0 {
+            output_quantities.push(token_change.base_amount() as u64);
+        }
+        tx_build.add_output(TxOutput {
+            value: 0,
+            script: SLPSend {
+                token_id: self.token_id,
+                token_type: self.token_type,
+                output_quantities,
+            }.into_output().script(),
+        });
+        let contract = self.version.contract(config, self.fee_policy.dust_limit, self.token_id,
+                                             self.token_type, self.sell_amount, self.price,
+                                             &self.receiving_address, None);
+        tx_build.add_output(TxOutput {
+            value: contract.value(),
+            script: _p2sh_script(&contract.script()),
+        });
+        if token_change.base_amount() > 0 {
+            _add_p2pkh_output(&mut tx_build, &self.maker_address, self.fee_policy.dust_limit);
+        }
+        _add_leftover_output(&mut tx_build, &self.maker_address, &self.fee_policy)?;
+        Ok(tx_build)
+    }
+
+    /// Outpoint and value of the ad output of the signed `ad_tx`, whose value is what the offer
+    /// tx spends, however the ad tx's fee turned out.
+    fn _ad_utxo(&self, config: &SLPDEXConfig, ad_tx: &Tx) -> Result<(TxOutpoint, u64)> {
+        let ad_output = ad_tx.outputs().get(0)
+            .filter(|output| {
+                let ad_script = _p2sh_script(&self._ad_output(config, output.value).script());
+                output.script.to_vec() == ad_script.to_vec()
+            })
+            .ok_or_else(|| -> Error {
+                ErrorKind::InvalidTradeOffer(
+                    TradeOfferError::InvalidAdTx(tx_hash_to_hex(&ad_tx.hash()))
+                ).into()
+            })?;
+        Ok((TxOutpoint { tx_hash: ad_tx.hash(), vout: 0 }, ad_output.value))
+    }
+
+    pub fn estimate_ad(&self, config: &SLPDEXConfig) -> Result<FeeEstimate> {
+        Ok(estimate_fee(self._unsigned_ad_tx(config)?, _input_total(&self.fee_inputs)))
+    }
+
+    /// Serialized pre-images of the ad tx's inputs, to be signed with sighash type 0x41.
+    pub fn ad_pre_images(&self, config: &SLPDEXConfig) -> Result<Vec<Vec<u8>>> {
+        Ok(serialize_pre_images(&self._unsigned_ad_tx(config)?))
+    }
+
+    pub fn sign_ad(&self, config: &SLPDEXConfig, signatures: Vec<Vec<u8>>, pub_key: Vec<u8>)
+            -> Result<Tx> {
+        Ok(sign_with_signatures(self._unsigned_ad_tx(config)?, signatures, pub_key))
+    }
+
+    /// Serialized pre-images of the offer tx's inputs, the first spending the signed `ad_tx`.
+    pub fn offer_pre_images(&self, config: &SLPDEXConfig, ad_tx: &Tx) -> Result<Vec<Vec<u8>>> {
+        let (ad_outpoint, ad_value) = self._ad_utxo(config, ad_tx)?;
+        Ok(serialize_pre_images(&self._unsigned_offer_tx(config, ad_outpoint, ad_value)?))
+    }
+
+    pub fn sign_offer(&self,
+                      config: &SLPDEXConfig,
+                      ad_tx: &Tx,
+                      signatures: Vec<Vec<u8>>,
+                      pub_key: Vec<u8>) -> Result<Tx> {
+        let (ad_outpoint, ad_value) = self._ad_utxo(config, ad_tx)?;
+        let tx_build = self._unsigned_offer_tx(config, ad_outpoint, ad_value)?;
+        Ok(sign_with_signatures(tx_build, signatures, pub_key))
+    }
+}
+
+/// Taker side: buys all tokens of the offer at `offer_utxo`, paying the maker's receiving address
+/// and `fee_address` a `fee_divisor`th of the payment, at least dust. The tokens and change go to
+/// `taker_address`, which owns `inputs`. The covenant input is signed like the others, so all
+/// pre-images can be signed with the taker's key, see `PandaTransferTx`.
+pub struct ExchAcceptTx {
+    pub version: ExchVersion,
+    pub token_id: [u8; 32],
+    pub token_type: u8,
+    pub offer_utxo: (TxOutpoint, u64),
+    pub sell_amount: SLPAmount,
+    pub price: ExchPrice,
+    pub receiving_address: Address,
+    pub taker_address: Address,
+    pub inputs: Vec<(TxOutpoint, u64)>,
+    pub fee_policy: FeePolicy,
+}
+
+impl ExchAcceptTx {
+    pub fn payment_satoshis(&self) -> Result<u64> {
+        self.price.payment_satoshis(self.sell_amount)
+    }
+
+    pub fn fee_satoshis(&self, config: &SLPDEXConfig) -> Result<u64> {
+        Ok((self.payment_satoshis()? / config.fee_divisor).max(self.fee_policy.dust_limit))
+    }
+
+    fn _unsigned_tx(&self, config: &SLPDEXConfig) -> Result<UnsignedTx> {
+        let mut tx_build = UnsignedTx::new_simple();
+        let (offer_outpoint, offer_value) = &self.offer_utxo;
+        tx_build.add_input(UnsignedInput {
+            outpoint: offer_outpoint.clone(),
+            output: self.version.contract(config, *offer_value, self.token_id, self.token_type,
+                                          self.sell_amount, self.price, &self.receiving_address,
+                                          Some(AdvancedTradeOfferSpendParams::TokenTrade)),
+            sequence: 0xffff_ffff,
+        });
+        _add_p2pkh_inputs(&mut tx_build, &self.taker_address, &self.inputs);
+        tx_build.add_output(TxOutput {
+            value: 0,
+            script: SLPSend {
+                token_id: self.token_id,
+                token_type: self.token_type,
+                output_quantities: vec![self.sell_amount.base_amount() as u64],
+            }.into_output().script(),
+        });
+        _add_p2pkh_output(&mut tx_build, &self.taker_address, self.fee_policy.dust_limit);
+        _add_p2pkh_output(&mut tx_build, &self.receiving_address, self.payment_satoshis()?);
+        _add_p2pkh_output(&mut tx_build, &config.fee_address, self.fee_satoshis(config)?);
+        _add_leftover_output(&mut tx_build, &self.taker_address, &self.fee_policy)?;
+        Ok(tx_build)
+    }
+
+    pub fn estimate(&self, config: &SLPDEXConfig) -> Result<FeeEstimate> {
+        Ok(estimate_fee(self._unsigned_tx(config)?,
+                        self.offer_utxo.1 + _input_total(&self.inputs)))
+    }
+
+    /// Serialized pre-images of all inputs, to be signed with sighash type 0x41 (ALL|FORKID).
+    pub fn pre_images(&self, config: &SLPDEXConfig) -> Result<Vec<Vec<u8>>> {
+        Ok(serialize_pre_images(&self._unsigned_tx(config)?))
+    }
+
+    /// DER encoded signatures of the double SHA256 of the pre-images, in input order.
+    pub fn sign(&self, config: &SLPDEXConfig, signatures: Vec<Vec<u8>>, pub_key: Vec<u8>)
+            -> Result<Tx> {
+        Ok(sign_with_signatures(self._unsigned_tx(config)?, signatures, pub_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payment_satoshis() {
+        let amount = SLPAmount::new(1_000, 2);
+        let price = ExchPrice { power: 0, is_inverted: false, script_price: 3 };
+        assert_eq!(price.payment_satoshis(amount).unwrap(), 3_000);
+        let price = ExchPrice { power: 0, is_inverted: true, script_price: 3 };
+        assert_eq!(price.payment_satoshis(amount).unwrap(), 334);
+        let price = ExchPrice { power: 0, is_inverted: true, script_price: 0 };
+        assert!(price.payment_satoshis(amount).is_err());
+    }
+
+    #[test]
+    fn decode_price() {
+        let (price, price_per_token) = ExchVersion::V2.decode_price(2, &[3, 0], &[0, 0, 1, 0]).unwrap();
+        assert_eq!(price, ExchPrice { power: 3, is_inverted: false, script_price: 256 });
+        assert_eq!(price_per_token, Rational::from(25_600));
+        let (price, price_per_token) = ExchVersion::V2.decode_price(2, &[3, 1], &[0, 0, 0, 4]).unwrap();
+        assert!(price.is_inverted);
+        assert_eq!(price_per_token, Rational::from(25));
+        assert!(ExchVersion::V2.decode_price(2, &[3, 1], &[0, 0, 0, 0]).is_err());
+        assert_eq!(ExchVersion::from_op(0x52), Some(ExchVersion::V2));
+        assert_eq!(ExchVersion::from_op(0x53), None);
+    }
+
+    #[test]
+    fn contract_ignores_fee_policy() {
+        use cashcontracts::AddressType;
+        let contract_script = |config: &SLPDEXConfig| {
+            let price = ExchPrice { power: 0, is_inverted: false, script_price: 20 };
+            let receiving_address = Address::from_bytes(AddressType::P2PKH, [6; 20]);
+            ExchVersion::V2.contract(config, 546, [1; 32], 0x01, SLPAmount::new(600, 2), price,
+                                     &receiving_address, None).script().to_vec()
+        };
+        let config = SLPDEXConfig::default();
+        let mut high_dust_config = config.clone();
+        high_dust_config.fee_policy.dust_limit = 1_000;
+        assert_eq!(contract_script(&config), contract_script(&high_dust_config));
+    }
+
+    fn accept_tx(config: &SLPDEXConfig, script_price: u32) -> (ExchAcceptTx, Tx) {
+        use cashcontracts::AddressType;
+        let accept = ExchAcceptTx {
+            version: ExchVersion::V2,
+            token_id: [1; 32],
+            token_type: 0x01,
+            offer_utxo: (TxOutpoint { tx_hash: [2; 32], vout: EXCH_CONTRACT_VOUT }, 546),
+            sell_amount: SLPAmount::new(600, 2),
+            price: ExchPrice { power: 0, is_inverted: false, script_price },
+            receiving_address: Address::from_bytes(AddressType::P2PKH, [6; 20]),
+            taker_address: Address::from_bytes(AddressType::P2PKH, [7; 20]),
+            inputs: vec![(TxOutpoint { tx_hash: [3; 32], vout: 0 }, 10_000_000)],
+            fee_policy: config.fee_policy,
+        };
+        let tx = accept.sign(config, vec![vec![0x30; 72]; 2], vec![0x02; 33]).unwrap();
+        (accept, tx)
+    }
+
+    fn p2pkh_script(address: &Address) -> Vec<u8> {
+        P2PKHOutput { value: 0, address: address.clone() }.script().to_vec()
+    }
+
+    #[test]
+    fn accept_tx_outputs() {
+        use crate::slp_validator::parse_slp_message;
+        let config = SLPDEXConfig::default();
+        let dust = config.fee_policy.dust_limit;
+        // 12,000 satoshis; a 500th is below dust
+        let (accept, tx) = accept_tx(&config, 20);
+        let outputs = tx.outputs();
+        let message = parse_slp_message(&outputs[0].script).unwrap().unwrap();
+        assert_eq!(message.token_hash, Some([1; 32]));
+        assert_eq!(message.amounts, vec![600]);
+        assert_eq!((outputs[1].value, outputs[1].script.to_vec()),
+                   (dust, p2pkh_script(&accept.taker_address)));
+        assert_eq!((outputs[2].value, outputs[2].script.to_vec()),
+                   (12_000, p2pkh_script(&accept.receiving_address)));
+        assert_eq!((outputs[3].value, outputs[3].script.to_vec()),
+                   (dust, p2pkh_script(&config.fee_address)));
+        assert_eq!(outputs[4].script.to_vec(), p2pkh_script(&accept.taker_address));
+        assert_eq!(outputs.len(), 5);
+
+        // 6,000,000 satoshis, of which a 500th goes to the fee address
+        let (accept, tx) = accept_tx(&config, 10_000);
+        assert_eq!(accept.payment_satoshis().unwrap(), 6_000_000);
+        assert_eq!(tx.outputs()[2].value, 6_000_000);
+        assert_eq!(tx.outputs()[3].value, 6_000_000 / config.fee_divisor);
+        assert_eq!(accept.fee_satoshis(&config).unwrap(), 12_000);
+    }
+
+    fn offer_tx(config: &SLPDEXConfig, script_price: u32) -> ExchOfferTx {
+        use cashcontracts::AddressType;
+        ExchOfferTx {
+            version: ExchVersion::V2,
+            token_id: [1; 32],
+            token_type: 0x01,
+            token_inputs: vec![(TxOutpoint { tx_hash: [2; 32], vout: 1 }, 546)],
+            token_input_amount: SLPAmount::new(1000, 2),
+            sell_amount: SLPAmount::new(600, 2),
+            price: ExchPrice { power: 0, is_inverted: false, script_price },
+            maker_address: Address::from_bytes(AddressType::P2PKH, [5; 20]),
+            receiving_address: Address::from_bytes(AddressType::P2PKH, [6; 20]),
+            fee_inputs: vec![(TxOutpoint { tx_hash: [3; 32], vout: 0 }, 100_000)],
+            fee_policy: config.fee_policy,
+        }
+    }
+
+    #[test]
+    fn offer_spends_ad_output() {
+        let config = SLPDEXConfig::default();
+        let offer = offer_tx(&config, 20);
+        let ad_tx = offer.sign_ad(&config, vec![vec![0x30; 72]], vec![0x02; 33]).unwrap();
+        let (ad_outpoint, ad_value) = offer._ad_utxo(&config, &ad_tx).unwrap();
+        assert_eq!((ad_outpoint.tx_hash, ad_outpoint.vout), (ad_tx.hash(), 0));
+        assert_eq!(ad_value, ad_tx.outputs()[0].value);
+        assert_eq!(offer.offer_pre_images(&config, &ad_tx).unwrap().len(), 2);
+        // an ad tx of another offer
+        let other_ad_tx = offer_tx(&config, 21)
+            .sign_ad(&config, vec![vec![0x30; 72]], vec![0x02; 33])
+            .unwrap();
+        assert!(offer.offer_pre_images(&config, &other_ad_tx).is_err());
+    }
+}
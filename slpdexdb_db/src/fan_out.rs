@@ -1,21 +1,48 @@
 use cashcontracts::{UnsignedTx, UnsignedInput, Tx, Address, TxOutpoint, TxOutput, P2PKHOutput,
-                    SLPSend, Output, double_sha256, AddressType};
-use slpdexdb_base::SLPAmount;
+                    SLPSend, Output};
+use slpdexdb_base::{SLPAmount, FeePolicy, Result, Error, ErrorKind, PandaError};
+use crate::tx_fee::{FeeEstimate, estimate_fee};
+use crate::signer::{Signer, sign_tx};
+use crate::coin_select::{CoinSelectTarget, TX_OVERHEAD_SIZE, P2PKH_INPUT_SIZE, P2PKH_OUTPUT_SIZE};
 
-pub fn slp_fan_out_tx(secret: &[u8],
-                      inputs: &[(TxOutpoint, u64)],
-                      token_id: [u8; 32],
-                      token_type: u8,
+/// Size of an SLP SEND output with `n_outputs` amounts: value, script length and
+/// `OP_RETURN <"SLP\0"> <token type> <"SEND"> <token id> <amount>...`.
+fn _slp_send_output_size(n_outputs: usize) -> usize {
+    8 + 1 + 1 + 5 + 2 + 5 + 33 + 9 * n_outputs
+}
+
+/// What the fee inputs of a fan-out of `token_inputs` have to pay for, i.e. the BCH of the
+/// outputs (including a token change output, if any) the token inputs don't cover, and the fee.
+/// See `Db::select_coins`.
+pub fn fan_out_target(token_inputs: &[(TxOutpoint, u64)],
                       n_outputs: usize,
-                      slp_amount_each: SLPAmount,
+                      with_token_change: bool,
                       bch_amount_each: u64,
-                      dust_limit: u64,
-                      fee_per_kb: u64) -> Result<Tx, u64> {
-    let curve = secp256k1::Secp256k1::new();
-    let secret_key = secp256k1::SecretKey::from_slice(secret).unwrap();
-    let pub_key = secp256k1::PublicKey::from_secret_key(&curve, &secret_key).serialize().to_vec();
-    let address = Address::from_serialized_pub_key("bitcoincash", AddressType::P2PKH, &pub_key);
+                      fee_policy: &FeePolicy) -> CoinSelectTarget {
+    let token_input_total = token_inputs.iter().map(|(_, amount)| *amount).sum::<u64>();
+    let n_token_outputs = n_outputs + if with_token_change { 1 } else { 0 };
+    let base_size = TX_OVERHEAD_SIZE +
+        token_inputs.len() * P2PKH_INPUT_SIZE +
+        _slp_send_output_size(n_token_outputs) +
+        n_token_outputs * P2PKH_OUTPUT_SIZE;
+    let token_change_value = if with_token_change { fee_policy.dust_limit } else { 0 };
+    CoinSelectTarget::new(
+        (n_outputs as u64 * bch_amount_each + token_change_value).saturating_sub(token_input_total),
+        base_size,
+        fee_policy,
+    )
+}
 
+fn _fan_out_unsigned_tx(address: &Address,
+                        output_address: &Address,
+                        inputs: &[(TxOutpoint, u64)],
+                        token_id: [u8; 32],
+                        token_type: u8,
+                        n_outputs: usize,
+                        slp_amount_each: SLPAmount,
+                        bch_amount_each: u64,
+                        token_change: SLPAmount,
+                        fee_policy: &FeePolicy) -> Result<UnsignedTx> {
     let mut tx_build = UnsignedTx::new_simple();
     for (outpoint, amount) in inputs {
         tx_build.add_input(UnsignedInput {
@@ -34,28 +61,67 @@ pub fn slp_fan_out_tx(secret: &[u8],
             token_type,
             output_quantities: (0..n_outputs).into_iter()
                 .map(|_| slp_amount_each.base_amount() as u64)
+                .chain(Some(token_change.base_amount() as u64).filter(|&change| change > 0))
                 .collect(),
         }.into_output().script(),
     });
     for _ in 0..n_outputs {
         tx_build.add_output(TxOutput {
             value: bch_amount_each,
+            script: P2PKHOutput {
+                value: 0,
+                address: output_address.clone(),
+            }.script(),
+        });
+    }
+    if token_change.base_amount() > 0 {
+        tx_build.add_output(TxOutput {
+            value: fee_policy.dust_limit,
             script: P2PKHOutput {
                 value: 0,
                 address: address.clone(),
             }.script(),
         });
     }
-    tx_build.add_leftover_output(address.clone(), fee_per_kb, dust_limit)?;
-    let pre_images = tx_build.pre_images(0x41);
-    Ok(tx_build.sign(
-        pre_images.iter().map(|pre_image| {
-            let mut pre_image_ser = Vec::new();
-            pre_image.write_to_stream(&mut pre_image_ser).unwrap();
-            let hash = double_sha256(&pre_image_ser);
-            curve.sign(&secp256k1::Message::from_slice(&hash).unwrap(),
-                       &secret_key).serialize_der().as_ref().to_vec()
-        }).collect(),
-        pre_images.iter().map(|_| pub_key.clone()).collect(),
-    ))
+    tx_build.add_leftover_output(address.clone(), fee_policy.fee_per_kb(), fee_policy.dust_limit)
+        .map_err(|missing_funds| -> Error {
+            ErrorKind::PandaError(PandaError::InsufficientFunds(missing_funds)).into()
+        })?;
+    Ok(tx_build)
+}
+
+/// Size and fee `slp_fan_out_tx` would produce with the same arguments.
+pub fn slp_fan_out_estimate(signer: &dyn Signer,
+                            output_address: &Address,
+                            inputs: &[(TxOutpoint, u64)],
+                            token_id: [u8; 32],
+                            token_type: u8,
+                            n_outputs: usize,
+                            slp_amount_each: SLPAmount,
+                            bch_amount_each: u64,
+                            token_change: SLPAmount,
+                            fee_policy: &FeePolicy) -> Result<FeeEstimate> {
+    let tx_build = _fan_out_unsigned_tx(&signer.address(), output_address, inputs, token_id,
+                                        token_type, n_outputs, slp_amount_each, bch_amount_each,
+                                        token_change, fee_policy)?;
+    Ok(estimate_fee(tx_build, inputs.iter().map(|(_, amount)| *amount).sum()))
+}
+
+/// Splits the signer's tokens into `n_outputs` outputs to `output_address`, e.g. the births
+/// address of the operator wallet. The leftover, and `token_change` tokens if not zero, go back
+/// to the signer.
+pub fn slp_fan_out_tx(signer: &dyn Signer,
+                      output_address: &Address,
+                      inputs: &[(TxOutpoint, u64)],
+                      token_id: [u8; 32],
+                      token_type: u8,
+                      n_outputs: usize,
+                      slp_amount_each: SLPAmount,
+                      bch_amount_each: u64,
+                      token_change: SLPAmount,
+                      fee_policy: &FeePolicy) -> Result<Tx> {
+    let tx_build = _fan_out_unsigned_tx(&signer.address(), output_address, inputs, token_id,
+                                        token_type, n_outputs, slp_amount_each, bch_amount_each,
+                                        token_change, fee_policy)?;
+    sign_tx(tx_build, signer)
 }
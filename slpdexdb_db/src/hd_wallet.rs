@@ -0,0 +1,152 @@
+use std::path::Path;
+use cashcontracts::Address;
+use ring::hmac;
+use slpdexdb_base::{Result, Error, ErrorKind, SignerError};
+use crate::db::Db;
+use crate::signer::{Signer, InMemorySigner, read_keystore};
+
+pub const HARDENED: u32 = 0x8000_0000;
+pub const BIP44_PURPOSE: u32 = 44;
+pub const BCH_COIN_TYPE: u32 = 145;
+
+/// What a derived key is used for. Each role is its own BIP44 account.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, FromPrimitive)]
+pub enum WalletRole {
+    /// Receives the panda fees of PND1 txs
+    Fees = 0,
+    /// Holds the NFT1 group UTXOs spent by birth txs
+    Births = 1,
+    /// Holds the NFT1 group tokens and splits them into birth UTXOs
+    FanOuts = 3,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, FromPrimitive)]
+pub enum WalletChain {
+    External = 0,
+    Change = 1,
+}
+
+fn _invalid_key() -> Error {
+    ErrorKind::SignerError(SignerError::InvalidSecretKey).into()
+}
+
+/// BIP32 extended private key.
+#[derive(Clone)]
+pub struct ExtendedPrivKey {
+    pub secret_key: secp256k1::SecretKey,
+    pub chain_code: [u8; 32],
+}
+
+impl ExtendedPrivKey {
+    /// Splits an HMAC-SHA512 into the key (or tweak) and the chain code.
+    fn _split_hmac(tag: &[u8]) -> (&[u8], [u8; 32]) {
+        let mut chain_code = [0; 32];
+        chain_code.copy_from_slice(&tag[32..]);
+        (&tag[..32], chain_code)
+    }
+
+    pub fn from_seed(seed: &[u8]) -> Result<Self> {
+        let key = hmac::Key::new(hmac::HMAC_SHA512, b"Bitcoin seed");
+        let tag = hmac::sign(&key, seed);
+        let (secret, chain_code) = Self::_split_hmac(tag.as_ref());
+        Ok(ExtendedPrivKey {
+            secret_key: secp256k1::SecretKey::from_slice(secret).map_err(|_| _invalid_key())?,
+            chain_code,
+        })
+    }
+
+    /// Hardened if `index >= HARDENED`.
+    pub fn derive_child(&self, index: u32) -> Result<Self> {
+        let mut data = Vec::with_capacity(37);
+        if index >= HARDENED {
+            data.push(0);
+            data.extend_from_slice(&self.secret_key[..]);
+        } else {
+            let curve = secp256k1::Secp256k1::new();
+            data.extend_from_slice(
+                &secp256k1::PublicKey::from_secret_key(&curve, &self.secret_key).serialize()
+            );
+        }
+        data.extend_from_slice(&index.to_be_bytes());
+        let key = hmac::Key::new(hmac::HMAC_SHA512, &self.chain_code);
+        let tag = hmac::sign(&key, &data);
+        let (tweak, chain_code) = Self::_split_hmac(tag.as_ref());
+        let mut secret_key = self.secret_key.clone();
+        secret_key.add_assign(tweak).map_err(|_| _invalid_key())?;
+        Ok(ExtendedPrivKey { secret_key, chain_code })
+    }
+
+    pub fn derive_path(&self, path: &[u32]) -> Result<Self> {
+        path.iter().try_fold(self.clone(), |key, &index| key.derive_child(index))
+    }
+}
+
+/// Operator wallet deriving `m/44'/145'/role'/chain/index`.
+#[derive(Clone)]
+pub struct HdWallet {
+    master: ExtendedPrivKey,
+}
+
+impl HdWallet {
+    pub fn from_seed(seed: &[u8]) -> Result<Self> {
+        Ok(HdWallet { master: ExtendedPrivKey::from_seed(seed)? })
+    }
+
+    /// Wallet of the seed stored in a keystore, see `signer::write_keystore`.
+    pub fn open_keystore(path: impl AsRef<Path>, password: &str) -> Result<Self> {
+        Self::from_seed(&read_keystore(path, password)?)
+    }
+
+    pub fn signer(&self, role: WalletRole, chain: WalletChain, idx: u32) -> Result<InMemorySigner> {
+        let key = self.master.derive_path(&[
+            BIP44_PURPOSE | HARDENED,
+            BCH_COIN_TYPE | HARDENED,
+            role as u32 | HARDENED,
+            chain as u32,
+            idx,
+        ])?;
+        Ok(InMemorySigner::new(key.secret_key))
+    }
+
+    /// Derives the key at `idx` and records its address, so it is resynced and watched.
+    pub fn used_signer(&self,
+                       db: &Db,
+                       role: WalletRole,
+                       chain: WalletChain,
+                       idx: u32) -> Result<InMemorySigner> {
+        let signer = self.signer(role, chain, idx)?;
+        db.add_wallet_address(role as i32, chain as i32, idx as i32, &signer.address())?;
+        Ok(signer)
+    }
+
+    /// The next unused address of `role` and `chain`, e.g. for change. It is only recorded as
+    /// used once passed to `used_signer`, so it's handed out again until then.
+    pub fn fresh_address(&self, db: &Db, role: WalletRole, chain: WalletChain) -> Result<(u32, Address)> {
+        let idx = db.next_wallet_address_idx(role as i32, chain as i32)? as u32;
+        Ok((idx, self.signer(role, chain, idx)?.address()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bip32_test_vector_1() {
+        let master = ExtendedPrivKey::from_seed(
+            &hex::decode("000102030405060708090a0b0c0d0e0f").unwrap()
+        ).unwrap();
+        assert_eq!(hex::encode(&master.secret_key[..]),
+                   "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35");
+        assert_eq!(hex::encode(&master.chain_code),
+                   "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508");
+        let child = master.derive_child(HARDENED).unwrap();
+        assert_eq!(hex::encode(&child.secret_key[..]),
+                   "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea");
+        assert_eq!(hex::encode(&child.chain_code),
+                   "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141");
+        let grandchild = master.derive_path(&[HARDENED, 1]).unwrap();
+        assert_eq!(hex::encode(&grandchild.secret_key[..]),
+                   "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368");
+    }
+}
@@ -10,22 +10,42 @@ mod endpoint;
 mod token;
 mod token_source;
 mod tx_source;
+mod chain_source;
 mod tx_history;
 mod update_history;
 mod convert;
 mod data;
+mod market;
+mod outgoing_tx;
+mod token_supply;
+mod order_book;
+mod trade_fill;
 pub mod panda_tools;
 pub mod panda;
 pub mod fan_out;
+pub mod exch;
+pub mod coin_select;
+pub mod tx_fee;
+pub mod signature;
+pub mod signer;
+pub mod hd_wallet;
+pub mod tx_submit;
+pub mod slp_validator;
 
 pub use db::*;
 pub use endpoint::*;
 pub use token::*;
 pub use token_source::*;
 pub use tx_source::*;
+pub use chain_source::*;
 pub use tx_history::*;
 pub use update_history::*;
 pub use data::*;
+pub use market::*;
+pub use outgoing_tx::*;
+pub use token_supply::*;
+pub use order_book::*;
+pub use trade_fill::*;
 
 //use slpdexdb_base::Result;
 
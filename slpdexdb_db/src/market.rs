@@ -0,0 +1,158 @@
+use cashcontracts::Address;
+use rug::Rational;
+use serde::Deserialize;
+use panda_base::traits::*;
+use slpdexdb_base::SLPAmount;
+
+/// An EXCH trade offer selling a panda, i.e. an offer whose contract output is the panda's
+/// current UTXO.
+#[derive(Clone, Debug)]
+pub struct PandaForSale {
+    pub token_hash: [u8; 32],
+    pub offer_tx: [u8; 32],
+    pub offer_output_idx: i32,
+    pub price_per_token: Rational,
+    pub sell_amount_token: SLPAmount,
+    pub seller_address: Address,
+    pub attributes: PandaAttributes,
+}
+
+impl PandaForSale {
+    /// NFT1 children have no decimals, so this is simply the price of the single panda token.
+    pub fn price_satoshis(&self) -> Rational {
+        self.price_per_token.clone() * Rational::from(self.sell_amount_token.base_amount())
+    }
+}
+
+/// Declining price auction: the price falls linearly from `start_price` to `end_price` over
+/// `duration_blocks` blocks starting at `start_height`, and stays at `end_price` afterwards.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DutchAuction {
+    pub start_price: u64,
+    pub end_price: u64,
+    pub start_height: i32,
+    pub duration_blocks: i32,
+}
+
+impl DutchAuction {
+    pub fn price_at(&self, height: i32) -> u64 {
+        let elapsed = height.saturating_sub(self.start_height).max(0).min(self.duration_blocks) as u128;
+        let price_drop = (self.start_price - self.end_price) as u128;
+        self.start_price - (price_drop * elapsed / self.duration_blocks as u128) as u64
+    }
+
+    pub fn end_height(&self) -> i32 {
+        self.start_height + self.duration_blocks
+    }
+}
+
+/// A dutch auction of the panda at `panda_tx_hash:panda_output_idx`.
+#[derive(Clone, Debug)]
+pub struct AuctionListing {
+    pub auction_tx: [u8; 32],
+    pub token_hash: [u8; 32],
+    pub panda_tx_hash: [u8; 32],
+    pub panda_output_idx: i32,
+    pub auction: DutchAuction,
+    pub seller_address: Address,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum PandaSaleOrder {
+    #[serde(rename = "priceAsc")]
+    PriceAscending,
+    #[serde(rename = "priceDesc")]
+    PriceDescending,
+}
+
+impl Default for PandaSaleOrder {
+    fn default() -> Self {
+        PandaSaleOrder::PriceAscending
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PandaSaleFilter {
+    pub min_price_satoshis: Option<u64>,
+    pub max_price_satoshis: Option<u64>,
+    pub seller_address: Option<String>,
+    pub physique: Option<PhysiqueTrait>,
+    pub pattern: Option<PatternTrait>,
+    pub eye_color: Option<EyeColorTrait>,
+    pub eye_shape: Option<EyeShapeTrait>,
+    pub base_color: Option<BaseColorTrait>,
+    pub highlight_color: Option<HighlightColorTrait>,
+    pub accent_color: Option<AccentColorTrait>,
+    pub wild_element: Option<WildElementTrait>,
+    pub mouth: Option<MouthTrait>,
+    #[serde(default)]
+    pub order: PandaSaleOrder,
+}
+
+fn _matches_trait<T: PartialEq>(filter: &Option<T>, value: &T) -> bool {
+    filter.as_ref().map(|filter| filter == value).unwrap_or(true)
+}
+
+impl PandaSaleFilter {
+    pub fn matches(&self, panda: &PandaForSale) -> bool {
+        let price = panda.price_satoshis();
+        if let Some(min_price) = self.min_price_satoshis {
+            if price < Rational::from(min_price) { return false; }
+        }
+        if let Some(max_price) = self.max_price_satoshis {
+            if price > Rational::from(max_price) { return false; }
+        }
+        if let Some(seller_address) = &self.seller_address {
+            match Address::from_cash_addr(seller_address.clone()) {
+                Ok(address) if address.bytes() == panda.seller_address.bytes() => {},
+                _ => return false,
+            }
+        }
+        let attributes = &panda.attributes;
+        _matches_trait(&self.physique, &attributes.physique) &&
+            _matches_trait(&self.pattern, &attributes.pattern) &&
+            _matches_trait(&self.eye_color, &attributes.eye_color) &&
+            _matches_trait(&self.eye_shape, &attributes.eye_shape) &&
+            _matches_trait(&self.base_color, &attributes.base_color) &&
+            _matches_trait(&self.highlight_color, &attributes.highlight_color) &&
+            _matches_trait(&self.accent_color, &attributes.accent_color) &&
+            _matches_trait(&self.wild_element, &attributes.wild_element) &&
+            _matches_trait(&self.mouth, &attributes.mouth)
+    }
+
+    pub fn apply(&self, pandas: Vec<PandaForSale>) -> Vec<PandaForSale> {
+        let mut pandas = pandas.into_iter()
+            .filter(|panda| self.matches(panda))
+            .collect::<Vec<_>>();
+        pandas.sort_by(|a, b| {
+            let ordering = a.price_satoshis().cmp(&b.price_satoshis());
+            match self.order {
+                PandaSaleOrder::PriceAscending => ordering,
+                PandaSaleOrder::PriceDescending => ordering.reverse(),
+            }
+        });
+        pandas
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dutch_auction_price() {
+        let auction = DutchAuction {
+            start_price: 100_000,
+            end_price: 10_000,
+            start_height: 600_000,
+            duration_blocks: 144,
+        };
+        assert_eq!(auction.price_at(599_000), 100_000);
+        assert_eq!(auction.price_at(600_000), 100_000);
+        assert_eq!(auction.price_at(600_072), 55_000);
+        assert_eq!(auction.price_at(600_143), 10_625);
+        assert_eq!(auction.price_at(auction.end_height()), 10_000);
+        assert_eq!(auction.price_at(700_000), 10_000);
+    }
+}
@@ -117,7 +117,36 @@ pub struct PND1Tx {
     pub father: i64, // BIGINT NOT NULL REFERENCES panda (id) ON DELETE CASCADE,
     pub mother: i64, // BIGINT NOT NULL REFERENCES panda (id) ON DELETE CASCADE,
     pub name: String, // TEXT NOT NULL
-    pub owner_address: Vec<u8>, // BYTEA NOT NULL
+    pub owner_address: Vec<u8>, // BYTEA NOT NULL -- the baby is minted to this address
+}
+
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="sire_offer"]
+pub struct SireOffer {
+    pub tx:              i64, // BIGINT NOT NULL PRIMARY KEY REFERENCES tx (id) ON DELETE CASCADE,
+    pub panda:           i64, // BIGINT NOT NULL REFERENCES panda (id) ON DELETE CASCADE,
+    pub sire_tx:         i64, // BIGINT NOT NULL,
+    pub sire_output_idx: i32, // INT NOT NULL,
+    pub fee_satoshis:    i64, // BIGINT NOT NULL,
+    pub owner_address:   Vec<u8>, // BYTEA NOT NULL
+}
+
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="panda_auction"]
+pub struct PandaAuction {
+    pub tx:               i64, // BIGINT NOT NULL PRIMARY KEY REFERENCES tx (id) ON DELETE CASCADE,
+    pub panda:            i64, // BIGINT NOT NULL REFERENCES panda (id) ON DELETE CASCADE,
+    pub panda_tx:         i64, // BIGINT NOT NULL,
+    pub panda_output_idx: i32, // INT NOT NULL,
+    pub start_price:      i64, // BIGINT NOT NULL,
+    pub end_price:        i64, // BIGINT NOT NULL,
+    pub start_height:     i32, // INT NOT NULL,
+    pub duration_blocks:  i32, // INT NOT NULL,
+    pub seller_address:   Vec<u8>, // BYTEA NOT NULL,
+    pub closing_tx:       Option<i64>, // BIGINT REFERENCES tx (id) -- set once the panda moved
+    pub settled_price:    Option<i64>, // BIGINT -- set if the closing tx paid the seller
 }
 
 #[derive(Queryable)]
@@ -140,7 +169,8 @@ pub struct TradeOffer {
     pub is_inverted:            bool, // BOOL NOT NULL,
     pub sell_amount_token_base: PgNumeric, // NUMERIC(26) NOT NULL,
     pub receiving_address:      Vec<u8>, // BYTEA NOT NULL,
-    pub spent:                  bool, // BOOL NOT NULL
+    pub spent:                  bool, // BOOL NOT NULL,
+    pub exch_version:           i32, // INT NOT NULL DEFAULT 2
 }
 
 #[derive(Insertable)]
@@ -154,7 +184,8 @@ pub struct NewTradeOffer {
     pub script_price:           i64, // BIGINT NOT NULL,
     pub is_inverted:            bool, // BOOL NOT NULL,
     pub sell_amount_token_base: PgNumeric, // NUMERIC(26) NOT NULL,
-    pub receiving_address:      Vec<u8>, // BYTEA NOT NULL
+    pub receiving_address:      Vec<u8>, // BYTEA NOT NULL,
+    pub exch_version:           i32, // INT NOT NULL DEFAULT 2
 }
 
 #[derive(Queryable)]
@@ -189,6 +220,73 @@ pub struct ActiveAddress {
     pub address: Vec<u8>,
 }
 
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="wallet_address"]
+pub struct WalletAddress {
+    pub address: Vec<u8>,
+    pub role: i32,
+    pub chain: i32,
+    pub idx: i32,
+}
+
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="slp_validity"]
+pub struct SlpValidity {
+    pub hash:     Vec<u8>, // BYTEA NOT NULL PRIMARY KEY,
+    pub is_valid: bool, // BOOLEAN NOT NULL,
+}
+
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="mint_baton"]
+pub struct MintBaton {
+    pub token_hash:       Vec<u8>, // BYTEA NOT NULL PRIMARY KEY,
+    pub mint_tx:          Vec<u8>, // BYTEA NOT NULL,
+    pub baton_output_idx: Option<i32>, // INT,
+}
+
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="token_supply_change"]
+pub struct TokenSupplyChange {
+    pub tx_hash:     Vec<u8>, // BYTEA NOT NULL,
+    pub token_hash:  Vec<u8>, // BYTEA NOT NULL,
+    pub kind:        i32, // INT NOT NULL,
+    pub amount_base: PgNumeric, // NUMERIC(26) NOT NULL,
+    pub timestamp:   i64, // BIGINT NOT NULL,
+}
+
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="trade_offer_spend"]
+pub struct TradeOfferSpend {
+    pub offer_tx:          Vec<u8>, // BYTEA NOT NULL,
+    pub offer_output_idx:  i32, // INT NOT NULL,
+    pub spending_tx:       Vec<u8>, // BYTEA NOT NULL,
+    pub token_hash:        Vec<u8>, // BYTEA NOT NULL,
+    pub kind:              i32, // INT NOT NULL,
+    pub price_per_token:   PgNumeric, // NUMERIC(52, 26) NOT NULL,
+    pub amount_token_base: PgNumeric, // NUMERIC(26) NOT NULL,
+    pub timestamp:         i64, // BIGINT NOT NULL,
+}
+
+#[derive(Queryable)]
+#[derive(Insertable)]
+#[table_name="outgoing_tx"]
+pub struct OutgoingTx {
+    pub hash:            Vec<u8>, // BYTEA NOT NULL PRIMARY KEY,
+    pub kind:            i32, // INT NOT NULL,
+    pub raw_tx:          Vec<u8>, // BYTEA NOT NULL,
+    pub status:          i32, // INT NOT NULL,
+    pub reject_reason:   Option<String>, // TEXT,
+    pub first_broadcast: i64, // BIGINT NOT NULL,
+    pub last_broadcast:  i64, // BIGINT NOT NULL,
+    pub n_broadcasts:    i32, // INT NOT NULL,
+    pub n_rejects:       i32, // INT NOT NULL DEFAULT 0,
+}
+
 #[derive(Queryable)]
 #[derive(Insertable)]
 #[table_name="utxo_address"]
@@ -321,6 +419,34 @@ pub struct DbPandaFull {
     pub genes: Vec<u8>
 }
 
+#[derive(Queryable)]
+pub struct DbPandaUtxo {
+    pub tx_hash: Vec<u8>,
+    pub output_idx: i32,
+    pub value_satoshis: i64,
+    pub address: Option<Vec<u8>>,
+}
+
+#[derive(Queryable)]
+pub struct DbPandaForSale {
+    pub offer_tx_hash: Vec<u8>,
+    pub offer_output_idx: i32,
+    pub price_per_token: PgNumeric,
+    pub sell_amount_token_base: PgNumeric,
+    pub receiving_address: Vec<u8>,
+    pub token_hash: Vec<u8>,
+    pub decimals: i32,
+    pub physique: PhysiqueTrait,
+    pub pattern: PatternTrait,
+    pub eye_color: EyeColorTrait,
+    pub eye_shape: EyeShapeTrait,
+    pub base_color: BaseColorTrait,
+    pub highlight_color: HighlightColorTrait,
+    pub accent_color: AccentColorTrait,
+    pub wild_element: WildElementTrait,
+    pub mouth: MouthTrait,
+}
+
 impl DbPanda {
     pub fn genes(&self) -> [u8; 48] {
         let mut genes: [u8; 48] = [0; 48];
@@ -0,0 +1,189 @@
+use rug::Rational;
+use slpdexdb_base::SLPAmount;
+use std::collections::BTreeMap;
+use crate::tx_history::TradeOffer;
+use crate::exch::ExchVersion;
+
+/// All offers of a token at one exact price.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriceLevel {
+    pub price_per_token: Rational,
+    pub sell_amount_token: SLPAmount,
+    pub num_offers: i64,
+}
+
+/// Live EXCH offers of a token, aggregated by price level in ascending order.
+/// EXCH offers only ever sell tokens, so the book has no bid side.
+#[derive(Clone, Debug)]
+pub struct OrderBook {
+    pub token_hash: [u8; 32],
+    pub decimals: u32,
+    pub levels: Vec<PriceLevel>,
+}
+
+/// Change of the levels of an order book by offers of one EXCH version. Amounts and offer counts
+/// of the levels are signed differences; levels which didn't change are omitted.
+#[derive(Clone, Debug)]
+pub struct OrderBookDelta {
+    pub token_hash: [u8; 32],
+    pub exch_version: ExchVersion,
+    pub levels: Vec<PriceLevel>,
+}
+
+fn _aggregate<'a>(decimals: u32,
+                  offers: impl Iterator<Item=(&'a TradeOffer, i64)>)
+        -> BTreeMap<Rational, PriceLevel> {
+    let mut levels = BTreeMap::new();
+    for (offer, sign) in offers {
+        let level = levels
+            .entry(offer.price_per_token.clone())
+            .or_insert_with(|| PriceLevel {
+                price_per_token: offer.price_per_token.clone(),
+                sell_amount_token: SLPAmount::new(0, decimals),
+                num_offers: 0,
+            });
+        level.sell_amount_token += offer.sell_amount_token * sign as i128;
+        level.num_offers += sign;
+    }
+    levels
+}
+
+impl OrderBook {
+    pub fn from_offers(token_hash: [u8; 32], decimals: u32, offers: &[TradeOffer]) -> Self {
+        OrderBook {
+            token_hash,
+            decimals,
+            levels: _aggregate(decimals, offers.iter().map(|offer| (offer, 1)))
+                .into_iter()
+                .map(|(_, level)| level)
+                .collect(),
+        }
+    }
+
+    pub fn best_price(&self) -> Option<&Rational> {
+        self.levels.first().map(|level| &level.price_per_token)
+    }
+
+    /// Difference between the most and the least expensive offer. There are no bids to compute
+    /// a bid/ask spread from.
+    pub fn price_range(&self) -> Option<Rational> {
+        let lowest = self.levels.first()?;
+        let highest = self.levels.last()?;
+        Some(highest.price_per_token.clone() - &lowest.price_per_token)
+    }
+
+    pub fn total_sell_amount(&self) -> SLPAmount {
+        self.levels.iter()
+            .fold(SLPAmount::new(0, self.decimals), |sum, level| sum + level.sell_amount_token)
+    }
+
+    /// Cumulative amount of tokens which can be bought up to and including each level.
+    pub fn depth(&self) -> Vec<(Rational, SLPAmount)> {
+        let mut total = SLPAmount::new(0, self.decimals);
+        self.levels.iter()
+            .map(|level| {
+                total += level.sell_amount_token;
+                (level.price_per_token.clone(), total)
+            })
+            .collect()
+    }
+
+    pub fn apply(&mut self, delta: &OrderBookDelta) {
+        let mut levels = self.levels.drain(..)
+            .map(|level| (level.price_per_token.clone(), level))
+            .collect::<BTreeMap<_, _>>();
+        for change in delta.levels.iter() {
+            let level = levels
+                .entry(change.price_per_token.clone())
+                .or_insert_with(|| PriceLevel {
+                    price_per_token: change.price_per_token.clone(),
+                    sell_amount_token: SLPAmount::new(0, self.decimals),
+                    num_offers: 0,
+                });
+            level.sell_amount_token += change.sell_amount_token;
+            level.num_offers += change.num_offers;
+        }
+        self.levels = levels.into_iter()
+            .map(|(_, level)| level)
+            .filter(|level| level.num_offers > 0)
+            .collect();
+    }
+}
+
+impl OrderBookDelta {
+    pub fn new(token_hash: [u8; 32],
+               exch_version: ExchVersion,
+               decimals: u32,
+               add_offers: &[TradeOffer],
+               remove_offers: &[TradeOffer]) -> Self {
+        let offers = add_offers.iter().map(|offer| (offer, 1))
+            .chain(remove_offers.iter().map(|offer| (offer, -1)));
+        OrderBookDelta {
+            token_hash,
+            exch_version,
+            levels: _aggregate(decimals, offers)
+                .into_iter()
+                .map(|(_, level)| level)
+                .filter(|level| level.num_offers != 0 || level.sell_amount_token.base_amount() != 0)
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cashcontracts::{Address, AddressType};
+
+    fn offer(tx: u8, price: (u64, u64), amount: i128) -> TradeOffer {
+        TradeOffer {
+            tx: [tx; 32],
+            output_idx: Some(1),
+            input_tx: [0; 32],
+            input_idx: 0,
+            exch_version: ExchVersion::V2,
+            price_per_token: Rational::from(price),
+            script_price: 0,
+            is_inverted: false,
+            sell_amount_token: SLPAmount::new(amount, 2),
+            receiving_address: Address::from_bytes(AddressType::P2PKH, [0; 20]),
+        }
+    }
+
+    #[test]
+    fn aggregate_levels() {
+        let offers = vec![
+            offer(1, (3, 2), 100),
+            offer(2, (1, 1), 50),
+            offer(3, (6, 4), 25),
+            offer(4, (5, 1), 10),
+        ];
+        let book = OrderBook::from_offers([9; 32], 2, &offers);
+        assert_eq!(book.levels.len(), 3);
+        assert_eq!(book.levels[1].price_per_token, Rational::from((3, 2)));
+        assert_eq!(book.levels[1].sell_amount_token, SLPAmount::new(125, 2));
+        assert_eq!(book.levels[1].num_offers, 2);
+        assert_eq!(book.best_price(), Some(&Rational::from(1)));
+        assert_eq!(book.price_range(), Some(Rational::from(4)));
+        assert_eq!(book.total_sell_amount(), SLPAmount::new(185, 2));
+        assert_eq!(
+            book.depth().into_iter().map(|(_, amount)| amount.base_amount()).collect::<Vec<_>>(),
+            vec![50, 175, 185],
+        );
+    }
+
+    #[test]
+    fn apply_delta() {
+        let mut book = OrderBook::from_offers([9; 32], 2, &[offer(1, (3, 2), 100), offer(2, (1, 1), 50)]);
+        let delta = OrderBookDelta::new([9; 32], ExchVersion::V2, 2, &[offer(3, (2, 1), 70)], &[offer(2, (1, 1), 50)]);
+        assert_eq!(delta.levels.len(), 2);
+        book.apply(&delta);
+        let expected = OrderBook::from_offers([9; 32], 2, &[offer(1, (3, 2), 100), offer(3, (2, 1), 70)]);
+        assert_eq!(book.levels, expected.levels);
+        assert_eq!(book.best_price(), Some(&Rational::from((3, 2))));
+    }
+}
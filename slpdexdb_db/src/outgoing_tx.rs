@@ -0,0 +1,120 @@
+use crate::models;
+use crate::data::tx_hash_from_slice;
+
+/// Txs the operator broadcasts.
+#[derive(Copy, Clone, Debug, FromPrimitive, Eq, PartialEq)]
+pub enum OutgoingTxKind {
+    Birth = 0,
+    Refund = 1,
+    FanOut = 2,
+}
+
+#[derive(Copy, Clone, Debug, FromPrimitive, Eq, PartialEq)]
+pub enum OutgoingTxStatus {
+    /// Sent, but no peer announced it back yet
+    Pending = 0,
+    /// A peer announced it in an `inv`, i.e. it is in its mempool
+    Seen = 1,
+    /// Rejected by several peers, or not confirmed in time; it won't be rebroadcast
+    Rejected = 2,
+    Confirmed = 3,
+}
+
+#[derive(Clone, Debug)]
+pub struct OutgoingTx {
+    pub hash: [u8; 32],
+    pub kind: OutgoingTxKind,
+    pub raw_tx: Vec<u8>,
+    pub status: OutgoingTxStatus,
+    pub reject_reason: Option<String>,
+    pub first_broadcast: i64,
+    pub last_broadcast: i64,
+    pub n_broadcasts: i32,
+    /// `reject`s received so far; `reject_reason` is the latest one's reason
+    pub n_rejects: i32,
+}
+
+impl OutgoingTx {
+    pub fn from_model(outgoing_tx: models::OutgoingTx) -> Option<Self> {
+        Some(OutgoingTx {
+            hash: tx_hash_from_slice(&outgoing_tx.hash),
+            kind: num::FromPrimitive::from_i32(outgoing_tx.kind)?,
+            raw_tx: outgoing_tx.raw_tx,
+            status: num::FromPrimitive::from_i32(outgoing_tx.status)?,
+            reject_reason: outgoing_tx.reject_reason,
+            first_broadcast: outgoing_tx.first_broadcast,
+            last_broadcast: outgoing_tx.last_broadcast,
+            n_broadcasts: outgoing_tx.n_broadcasts,
+            n_rejects: outgoing_tx.n_rejects,
+        })
+    }
+
+    /// Unconfirmed and not rejected, so it should be rebroadcast.
+    pub fn is_unconfirmed(&self) -> bool {
+        match self.status {
+            OutgoingTxStatus::Pending | OutgoingTxStatus::Seen => true,
+            OutgoingTxStatus::Rejected | OutgoingTxStatus::Confirmed => false,
+        }
+    }
+
+    /// Unconfirmed and last broadcast at least `interval` seconds ago.
+    pub fn is_due(&self, now: i64, interval: i64) -> bool {
+        self.is_unconfirmed() && now - self.last_broadcast >= interval
+    }
+
+    /// Unconfirmed `give_up_after` seconds after the first broadcast; it then isn't rebroadcast
+    /// anymore.
+    pub fn is_expired(&self, now: i64, give_up_after: i64) -> bool {
+        self.is_unconfirmed() && now - self.first_broadcast > give_up_after
+    }
+
+    /// Rejected, or still unconfirmed `stuck_after` seconds after the first broadcast.
+    pub fn is_stuck(&self, now: i64, stuck_after: i64) -> bool {
+        self.status == OutgoingTxStatus::Rejected ||
+            (self.is_unconfirmed() && now - self.first_broadcast > stuck_after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _outgoing_tx(status: OutgoingTxStatus) -> OutgoingTx {
+        OutgoingTx {
+            hash: [0; 32],
+            kind: OutgoingTxKind::Birth,
+            raw_tx: vec![],
+            status,
+            reject_reason: None,
+            first_broadcast: 1000,
+            last_broadcast: 2000,
+            n_broadcasts: 2,
+            n_rejects: 0,
+        }
+    }
+
+    #[test]
+    fn test_is_due() {
+        let outgoing_tx = _outgoing_tx(OutgoingTxStatus::Seen);
+        assert!(!outgoing_tx.is_due(2599, 600));
+        assert!(outgoing_tx.is_due(2600, 600));
+        assert!(!_outgoing_tx(OutgoingTxStatus::Rejected).is_due(5000, 600));
+        assert!(!_outgoing_tx(OutgoingTxStatus::Confirmed).is_due(5000, 600));
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let outgoing_tx = _outgoing_tx(OutgoingTxStatus::Pending);
+        assert!(!outgoing_tx.is_expired(2000, 1000));
+        assert!(outgoing_tx.is_expired(2001, 1000));
+        assert!(!_outgoing_tx(OutgoingTxStatus::Confirmed).is_expired(5000, 1000));
+    }
+
+    #[test]
+    fn test_is_stuck() {
+        assert!(_outgoing_tx(OutgoingTxStatus::Rejected).is_stuck(1000, 3600));
+        assert!(!_outgoing_tx(OutgoingTxStatus::Seen).is_stuck(4600, 3600));
+        assert!(_outgoing_tx(OutgoingTxStatus::Seen).is_stuck(4601, 3600));
+        assert!(!_outgoing_tx(OutgoingTxStatus::Confirmed).is_stuck(10_000, 3600));
+    }
+}
@@ -1,30 +1,93 @@
-use cashcontracts::{UnsignedTx, UnsignedInput, Tx, TxOutpoint, TxOutput, Output, double_sha256,
-                    single_sha256,
-                    Address, AddressType, P2PKHOutput, SLPGenesis, SLPSend, OpReturnOutput,
-                    tx_hash_to_hex};
-use slpdexdb_base::{SLPDEXConfig, SLPAmount};
+use cashcontracts::{UnsignedTx, UnsignedInput, Tx, TxOutpoint, TxOutput, Output, single_sha256,
+                    Address, P2PKHOutput, SLPGenesis, SLPSend, OpReturnOutput, tx_hash_to_hex};
+use slpdexdb_base::{SLPDEXConfig, SLPAmount, FeePolicy, Result, Error, ErrorKind, PandaError};
 use crate::token::Token;
 use crate::tx_history::TokenType;
 use crate::data::tx_hash_from_le_slice;
+use crate::signature::SignatureScheme;
+use crate::market::DutchAuction;
+use crate::tx_fee::{FeeEstimate, estimate_fee};
+use crate::signer::{Signer, sign_tx, sign_tx_inputs, serialize_pre_images, sign_with_signatures};
+
+pub const PND1_VERSION: u8 = 2;
+pub const PNDS_VERSION: u8 = 1;
+pub const PNDA_VERSION: u8 = 1;
+
+/// Message signed by unversioned PND1 txs. It doesn't commit to the parents' outpoints, so it is
+/// only accepted below `SLPDEXConfig::pnd1_legacy_cutoff_height`.
+pub fn pnd1_legacy_message(father_token: &[u8; 32], mother_token: &[u8; 32]) -> String {
+    format!("PANDA S3X:{}+{}", tx_hash_to_hex(father_token), tx_hash_to_hex(mother_token))
+}
+
+/// Message signed by versioned PND1 txs. It commits to the parent outpoints, the recipient of
+/// the baby and the chain, so the signature becomes useless once either parent has moved.
+pub fn pnd1_message(config: &SLPDEXConfig,
+                    father_token: &[u8; 32],
+                    father_outpoint: (&[u8; 32], u32),
+                    mother_token: &[u8; 32],
+                    mother_outpoint: (&[u8; 32], u32),
+                    recipient: &Address) -> String {
+    format!("PANDA S3X v{}:{}:{}+{}:{}:{}+{}:{}:{}",
+            PND1_VERSION,
+            config.pnd1_chain_id,
+            tx_hash_to_hex(father_token),
+            tx_hash_to_hex(mother_token),
+            tx_hash_to_hex(father_outpoint.0),
+            father_outpoint.1,
+            tx_hash_to_hex(mother_outpoint.0),
+            mother_outpoint.1,
+            recipient.with_prefix(config.cash_addr_prefix.to_string()).cash_addr())
+}
+
+/// Message signed by the owner of a panda offering it as a sire for `fee_satoshis`. It commits to
+/// the panda's outpoint, so the offer is withdrawn by moving the panda.
+pub fn sire_offer_message(config: &SLPDEXConfig,
+                          sire_token: &[u8; 32],
+                          sire_outpoint: (&[u8; 32], u32),
+                          fee_satoshis: u64) -> String {
+    format!("PANDA SIRE v{}:{}:{}:{}:{}:{}",
+            PNDS_VERSION,
+            config.pnd1_chain_id,
+            tx_hash_to_hex(sire_token),
+            tx_hash_to_hex(sire_outpoint.0),
+            sire_outpoint.1,
+            fee_satoshis)
+}
+
+/// Message signed by the owner of a panda listing it in a dutch auction.
+pub fn auction_message(config: &SLPDEXConfig,
+                       panda_token: &[u8; 32],
+                       panda_outpoint: (&[u8; 32], u32),
+                       auction: &DutchAuction) -> String {
+    format!("PANDA AUCTION v{}:{}:{}:{}:{}:{}:{}:{}:{}",
+            PNDA_VERSION,
+            config.pnd1_chain_id,
+            tx_hash_to_hex(panda_token),
+            tx_hash_to_hex(panda_outpoint.0),
+            panda_outpoint.1,
+            auction.start_price,
+            auction.end_price,
+            auction.start_height,
+            auction.duration_blocks)
+}
 
 pub struct PandaTx {
     pub nft1_outpoint: TxOutpoint,
     pub nft1_amount: u64,
-    pub secret_key: secp256k1::SecretKey,
     pub fee_inputs: Vec<(TxOutpoint, u64)>,
     pub owner_address: Address,
     pub panda_ticker: String,
     pub panda_name: String,
     pub genome: Vec<u8>,
-    pub fee_per_kb: u64,
-    pub dust_limit: u64,
+    pub fee_policy: FeePolicy,
+    /// Receives the leftover; the address of the fee inputs if not set
+    pub change_address: Option<Address>,
 }
 
 pub struct PND1Tx {
-    pub secret_key: secp256k1::SecretKey,
+    pub signature_scheme: SignatureScheme,
     pub inputs: Vec<(TxOutpoint, u64)>,
-    pub fee_per_kb: u64,
-    pub dust_limit: u64,
+    pub fee_policy: FeePolicy,
     pub name: String,
     pub father_token: [u8; 32],
     pub father_tx_hash: [u8; 32],
@@ -32,6 +95,40 @@ pub struct PND1Tx {
     pub mother_token: [u8; 32],
     pub mother_tx_hash: [u8; 32],
     pub mother_output_idx: u32,
+    pub recipient_address: Option<Address>,
+    /// Address and fee of the sire offer if the father belongs to someone else
+    pub sire_fee: Option<(Address, u64)>,
+}
+
+pub struct SireOfferTx {
+    pub signature_scheme: SignatureScheme,
+    pub inputs: Vec<(TxOutpoint, u64)>,
+    pub fee_policy: FeePolicy,
+    pub sire_token: [u8; 32],
+    pub sire_tx_hash: [u8; 32],
+    pub sire_output_idx: u32,
+    pub fee_satoshis: u64,
+}
+
+/// Sends a panda to `destination_address`. All inputs belong to `owner_address`; the tx is signed
+/// externally, by signing `pre_images` and passing the signatures to `sign`.
+pub struct PandaTransferTx {
+    pub token_id: [u8; 32],
+    pub panda_utxo: (TxOutpoint, u64),
+    pub owner_address: Address,
+    pub destination_address: Address,
+    pub fee_inputs: Vec<(TxOutpoint, u64)>,
+    pub fee_policy: FeePolicy,
+}
+
+pub struct PandaAuctionTx {
+    pub signature_scheme: SignatureScheme,
+    pub inputs: Vec<(TxOutpoint, u64)>,
+    pub fee_policy: FeePolicy,
+    pub panda_token: [u8; 32],
+    pub panda_tx_hash: [u8; 32],
+    pub panda_output_idx: u32,
+    pub auction: DutchAuction,
 }
 
 impl PandaTx {
@@ -56,30 +153,17 @@ impl PandaTx {
         format!("https://pand.as.cash/genome/{}", hex::encode(&self.genome))
     }
 
-    pub fn tx(&self) -> Result<Tx, u64> {
-        let curve = secp256k1::Secp256k1::new();
+    fn _unsigned_tx(&self, nft1_address: &Address, fee_address: &Address) -> Result<UnsignedTx> {
         let mut tx_build = UnsignedTx::new_simple();
-        let secret_key = self.secret_key;
-        let pub_key = secp256k1::PublicKey::from_secret_key(&curve, &secret_key).serialize().to_vec();
-        let address = Address::from_serialized_pub_key("bitcoincash", AddressType::P2PKH, &pub_key);
         tx_build.add_input(UnsignedInput {
             outpoint: self.nft1_outpoint.clone(),
             output: Box::new(P2PKHOutput {
-                address: address.clone(),
+                address: nft1_address.clone(),
                 value: self.nft1_amount,
             }),
             sequence: 0xffff_ffff,
         });
-        for (outpoint, amount) in self.fee_inputs.iter() {
-            tx_build.add_input(UnsignedInput {
-                outpoint: outpoint.clone(),
-                output: Box::new(P2PKHOutput {
-                    address: address.clone(),
-                    value: *amount,
-                }),
-                sequence: 0xffff_ffff,
-            });
-        }
+        _add_p2pkh_inputs(&mut tx_build, fee_address, &self.fee_inputs);
         tx_build.add_output(TxOutput {
             value: 0,
             script: SLPGenesis {
@@ -94,88 +178,263 @@ impl PandaTx {
             }.into_output().script(),
         });
         tx_build.add_output(TxOutput {
-            value: self.dust_limit,
+            value: self.fee_policy.dust_limit,
             script: P2PKHOutput {
-                value: self.dust_limit,
+                value: self.fee_policy.dust_limit,
                 address: self.owner_address.clone(),
             }.script(),
         });
-        tx_build.add_leftover_output(address.clone(), self.fee_per_kb, self.dust_limit)?;
-        let pre_images = tx_build.pre_images(0x41);
-        Ok(tx_build.sign(
-            pre_images.iter().map(|pre_image| {
-                let mut pre_image_ser = Vec::new();
-                pre_image.write_to_stream(&mut pre_image_ser).unwrap();
-                println!("preimage:{}", hex::encode(&pre_image_ser));
-                let hash = double_sha256(&pre_image_ser);
-                curve.sign(&secp256k1::Message::from_slice(&hash).unwrap(),
-                           &secret_key).serialize_der().as_ref().to_vec()
-            }).collect(),
-            pre_images.iter().map(|_| pub_key.clone()).collect(),
-        ))
+        let change_address = self.change_address.as_ref().unwrap_or(fee_address);
+        _add_leftover_output(&mut tx_build, change_address, &self.fee_policy)?;
+        Ok(tx_build)
+    }
+
+    pub fn estimate(&self, signer: &dyn Signer) -> Result<FeeEstimate> {
+        let address = signer.address();
+        Ok(estimate_fee(self._unsigned_tx(&address, &address)?,
+                        self.nft1_amount + _input_total(&self.fee_inputs)))
+    }
+
+    pub fn tx(&self, signer: &dyn Signer) -> Result<Tx> {
+        self.tx_with_signers(signer, signer)
+    }
+
+    /// For when the NFT1 group UTXO and the fee inputs belong to different keys.
+    pub fn tx_with_signers(&self, nft1_signer: &dyn Signer, fee_signer: &dyn Signer) -> Result<Tx> {
+        let tx_build = self._unsigned_tx(&nft1_signer.address(), &fee_signer.address())?;
+        let mut signers = vec![nft1_signer];
+        signers.extend(self.fee_inputs.iter().map(|_| fee_signer));
+        sign_tx_inputs(tx_build, &signers)
+    }
+}
+
+fn _add_leftover_output(tx_build: &mut UnsignedTx,
+                        address: &Address,
+                        fee_policy: &FeePolicy) -> Result<()> {
+    tx_build.add_leftover_output(address.clone(), fee_policy.fee_per_kb(), fee_policy.dust_limit)
+        .map_err(|missing_funds| -> Error {
+            ErrorKind::PandaError(PandaError::InsufficientFunds(missing_funds)).into()
+        })?;
+    Ok(())
+}
+
+fn _input_total(inputs: &[(TxOutpoint, u64)]) -> u64 {
+    inputs.iter().map(|(_, amount)| *amount).sum()
+}
+
+fn _add_p2pkh_inputs(tx_build: &mut UnsignedTx, address: &Address, inputs: &[(TxOutpoint, u64)]) {
+    for (outpoint, amount) in inputs.iter() {
+        tx_build.add_input(UnsignedInput {
+            outpoint: outpoint.clone(),
+            output: Box::new(P2PKHOutput {
+                address: address.clone(),
+                value: *amount,
+            }),
+            sequence: 0xffff_ffff,
+        });
     }
 }
 
 impl PND1Tx {
-    pub fn into_tx(self, config: &SLPDEXConfig) -> Result<Tx, u64> {
-        let curve = secp256k1::Secp256k1::new();
+    fn _unsigned_tx(&self, config: &SLPDEXConfig, signer: &dyn Signer) -> Result<UnsignedTx> {
         let mut tx_build = UnsignedTx::new_simple();
-        let secret_key = self.secret_key;
-        let pub_key = secp256k1::PublicKey::from_secret_key(&curve, &secret_key).serialize().to_vec();
-        let address = Address::from_serialized_pub_key("bitcoincash", AddressType::P2PKH, &pub_key);
-
-        let message = format!("PANDA S3X:{}+{}",
-                              tx_hash_to_hex(&self.father_token),
-                              tx_hash_to_hex(&self.mother_token));
-        let signature = curve.sign(&secp256k1::Message::from_slice(&single_sha256(message.as_bytes())).unwrap(), &secret_key);
-
-        for (outpoint, amount) in self.inputs {
-            tx_build.add_input(UnsignedInput {
-                outpoint,
-                output: Box::new(P2PKHOutput {
-                    address: address.clone(),
-                    value: amount,
-                }),
-                sequence: 0xffff_ffff,
+        let address = &signer.address();
+        let recipient = self.recipient_address.clone().unwrap_or_else(|| address.clone());
+        let message = pnd1_message(config,
+                                   &self.father_token,
+                                   (&self.father_tx_hash, self.father_output_idx),
+                                   &self.mother_token,
+                                   (&self.mother_tx_hash, self.mother_output_idx),
+                                   &recipient);
+        let signature = signer.sign_message(self.signature_scheme,
+                                            &single_sha256(message.as_bytes()))?;
+
+        _add_p2pkh_inputs(&mut tx_build, address, &self.inputs);
+
+        let mut pushes = vec![
+            b"PND1".to_vec(),
+            vec![PND1_VERSION],
+            self.name.as_bytes().to_vec(),
+            tx_hash_from_le_slice(&self.father_tx_hash).to_vec(),
+            self.father_output_idx.to_le_bytes().to_vec(),
+            tx_hash_from_le_slice(&self.mother_tx_hash).to_vec(),
+            self.mother_output_idx.to_le_bytes().to_vec(),
+            signer.pub_key().to_vec(),
+            signature,
+        ];
+        if let Some(recipient_address) = &self.recipient_address {
+            pushes.push(recipient_address.bytes().to_vec());
+        }
+        tx_build.add_output(TxOutput {
+            value: 0,
+            script: OpReturnOutput {
+                is_minimal_push: false,
+                pushes,
+            }.script(),
+        });
+
+        tx_build.add_output(TxOutput {
+            value: config.panda_fee,
+            script: P2PKHOutput {
+                value: 0,
+                address: config.fee_address.clone(),
+            }.script(),
+        });
+
+        if let Some((sire_owner, sire_fee)) = &self.sire_fee {
+            tx_build.add_output(TxOutput {
+                value: *sire_fee,
+                script: P2PKHOutput {
+                    value: 0,
+                    address: sire_owner.clone(),
+                }.script(),
             });
         }
 
+        _add_leftover_output(&mut tx_build, address, &self.fee_policy)?;
+        Ok(tx_build)
+    }
+
+    pub fn estimate(&self, config: &SLPDEXConfig, signer: &dyn Signer) -> Result<FeeEstimate> {
+        Ok(estimate_fee(self._unsigned_tx(config, signer)?, _input_total(&self.inputs)))
+    }
+
+    pub fn into_tx(self, config: &SLPDEXConfig, signer: &dyn Signer) -> Result<Tx> {
+        sign_tx(self._unsigned_tx(config, signer)?, signer)
+    }
+}
+
+impl SireOfferTx {
+    fn _unsigned_tx(&self, config: &SLPDEXConfig, signer: &dyn Signer) -> Result<UnsignedTx> {
+        let mut tx_build = UnsignedTx::new_simple();
+        let address = &signer.address();
+        let message = sire_offer_message(config,
+                                         &self.sire_token,
+                                         (&self.sire_tx_hash, self.sire_output_idx),
+                                         self.fee_satoshis);
+        let signature = signer.sign_message(self.signature_scheme,
+                                            &single_sha256(message.as_bytes()))?;
+
+        _add_p2pkh_inputs(&mut tx_build, address, &self.inputs);
+
         tx_build.add_output(TxOutput {
             value: 0,
             script: OpReturnOutput {
                 is_minimal_push: false,
                 pushes: vec![
-                    b"PND1".to_vec(),
-                    self.name.into_bytes(),
-                    tx_hash_from_le_slice(&self.father_tx_hash).to_vec(),
-                    self.father_output_idx.to_le_bytes().to_vec(),
-                    tx_hash_from_le_slice(&self.mother_tx_hash).to_vec(),
-                    self.mother_output_idx.to_le_bytes().to_vec(),
-                    pub_key.clone(),
-                    signature.serialize_der().to_vec(),
+                    b"PNDS".to_vec(),
+                    vec![PNDS_VERSION],
+                    tx_hash_from_le_slice(&self.sire_tx_hash).to_vec(),
+                    self.sire_output_idx.to_le_bytes().to_vec(),
+                    self.fee_satoshis.to_le_bytes().to_vec(),
+                    signer.pub_key().to_vec(),
+                    signature,
                 ],
             }.script(),
         });
 
+        _add_leftover_output(&mut tx_build, address, &self.fee_policy)?;
+        Ok(tx_build)
+    }
+
+    pub fn estimate(&self, config: &SLPDEXConfig, signer: &dyn Signer) -> Result<FeeEstimate> {
+        Ok(estimate_fee(self._unsigned_tx(config, signer)?, _input_total(&self.inputs)))
+    }
+
+    pub fn into_tx(self, config: &SLPDEXConfig, signer: &dyn Signer) -> Result<Tx> {
+        sign_tx(self._unsigned_tx(config, signer)?, signer)
+    }
+}
+
+impl PandaAuctionTx {
+    fn _unsigned_tx(&self, config: &SLPDEXConfig, signer: &dyn Signer) -> Result<UnsignedTx> {
+        let mut tx_build = UnsignedTx::new_simple();
+        let address = &signer.address();
+        let message = auction_message(config,
+                                      &self.panda_token,
+                                      (&self.panda_tx_hash, self.panda_output_idx),
+                                      &self.auction);
+        let signature = signer.sign_message(self.signature_scheme,
+                                            &single_sha256(message.as_bytes()))?;
+
+        _add_p2pkh_inputs(&mut tx_build, address, &self.inputs);
+
         tx_build.add_output(TxOutput {
-            value: config.panda_fee,
+            value: 0,
+            script: OpReturnOutput {
+                is_minimal_push: false,
+                pushes: vec![
+                    b"PNDA".to_vec(),
+                    vec![PNDA_VERSION],
+                    tx_hash_from_le_slice(&self.panda_tx_hash).to_vec(),
+                    self.panda_output_idx.to_le_bytes().to_vec(),
+                    self.auction.start_price.to_le_bytes().to_vec(),
+                    self.auction.end_price.to_le_bytes().to_vec(),
+                    (self.auction.start_height as u32).to_le_bytes().to_vec(),
+                    (self.auction.duration_blocks as u32).to_le_bytes().to_vec(),
+                    signer.pub_key().to_vec(),
+                    signature,
+                ],
+            }.script(),
+        });
+
+        _add_leftover_output(&mut tx_build, address, &self.fee_policy)?;
+        Ok(tx_build)
+    }
+
+    pub fn estimate(&self, config: &SLPDEXConfig, signer: &dyn Signer) -> Result<FeeEstimate> {
+        Ok(estimate_fee(self._unsigned_tx(config, signer)?, _input_total(&self.inputs)))
+    }
+
+    pub fn into_tx(self, config: &SLPDEXConfig, signer: &dyn Signer) -> Result<Tx> {
+        sign_tx(self._unsigned_tx(config, signer)?, signer)
+    }
+}
+
+impl PandaTransferTx {
+    fn _unsigned_tx(&self) -> Result<UnsignedTx> {
+        let mut tx_build = UnsignedTx::new_simple();
+        let (panda_outpoint, panda_amount) = &self.panda_utxo;
+        tx_build.add_input(UnsignedInput {
+            outpoint: panda_outpoint.clone(),
+            output: Box::new(P2PKHOutput {
+                address: self.owner_address.clone(),
+                value: *panda_amount,
+            }),
+            sequence: 0xffff_ffff,
+        });
+        _add_p2pkh_inputs(&mut tx_build, &self.owner_address, &self.fee_inputs);
+        tx_build.add_output(TxOutput {
+            value: 0,
+            script: SLPSend {
+                token_id: self.token_id,
+                token_type: TokenType::NFT1Child as u8,
+                output_quantities: vec![1],
+            }.into_output().script(),
+        });
+        tx_build.add_output(TxOutput {
+            value: self.fee_policy.dust_limit,
             script: P2PKHOutput {
                 value: 0,
-                address: config.fee_address.clone(),
+                address: self.destination_address.clone(),
             }.script(),
         });
+        _add_leftover_output(&mut tx_build, &self.owner_address, &self.fee_policy)?;
+        Ok(tx_build)
+    }
+
+    pub fn estimate(&self) -> Result<FeeEstimate> {
+        Ok(estimate_fee(self._unsigned_tx()?,
+                        self.panda_utxo.1 + _input_total(&self.fee_inputs)))
+    }
+
+    /// Serialized pre-images of all inputs, to be signed with sighash type 0x41 (ALL|FORKID).
+    pub fn pre_images(&self) -> Result<Vec<Vec<u8>>> {
+        Ok(serialize_pre_images(&self._unsigned_tx()?))
+    }
 
-        tx_build.add_leftover_output(address.clone(), self.fee_per_kb, self.dust_limit)?;
-        let pre_images = tx_build.pre_images(0x41);
-        Ok(tx_build.sign(
-            pre_images.iter().map(|pre_image| {
-                let mut pre_image_ser = Vec::new();
-                pre_image.write_to_stream(&mut pre_image_ser).unwrap();
-                let hash = double_sha256(&pre_image_ser);
-                curve.sign(&secp256k1::Message::from_slice(&hash).unwrap(),
-                           &secret_key).serialize_der().as_ref().to_vec()
-            }).collect(),
-            pre_images.iter().map(|_| pub_key.clone()).collect(),
-        ))
+    /// DER encoded signatures of the double SHA256 of the pre-images, in input order.
+    pub fn sign(&self, signatures: Vec<Vec<u8>>, pub_key: Vec<u8>) -> Result<Tx> {
+        Ok(sign_with_signatures(self._unsigned_tx()?, signatures, pub_key))
     }
 }
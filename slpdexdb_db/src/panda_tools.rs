@@ -4,8 +4,13 @@ use diesel::{
     result::Error as DieselError
 };
 
+use cashcontracts::{Address, AddressType};
+use slpdexdb_base::SLPAmount;
+use slpdexdb_base::convert_numeric::pg_numeric_to_rational;
 use panda_base::traits::*;
 use crate::{models::*, schema};
+use crate::data::{tx_hash_from_slice, address_hash_from_slice};
+use crate::market::PandaForSale;
 
 pub fn insert_panda_from_traits(
     genesis_tx: &i64, 
@@ -229,6 +234,24 @@ pub fn get_panda_by_owner_utxo(owner_tx_id: i64, owner_output_idx: i32, conn: &P
         .optional()
 }
 
+/// The UTXO currently holding the panda with the given token id.
+pub fn get_panda_utxo(token_id: &[u8], conn: &PgConnection) -> Result<DbPandaUtxo, DieselError> {
+    use self::schema::{tx::dsl as tx_dsl, tx_output::dsl as output_dsl};
+    let panda = get_panda_by_token_id(token_id, conn)?;
+    output_dsl::tx_output
+        .inner_join(tx_dsl::tx)
+        .filter(
+            output_dsl::tx.eq(panda.owner_tx)
+                .and(output_dsl::idx.eq(panda.owner_tx_idx))
+        )
+        .select((
+            tx_dsl::hash,
+            output_dsl::idx,
+            output_dsl::value_satoshis,
+            output_dsl::address))
+        .first::<DbPandaUtxo>(conn)
+}
+
 pub fn switch_owners(token_hash: [u8; 32], to_tx_hash: [u8; 32], to_output_idx: i32, conn: &PgConnection) -> Result<(), DieselError> {
     use self::schema::panda::dsl as panda_dsl;
     use self::schema::tx::dsl as tx_dsl;
@@ -248,6 +271,62 @@ pub fn switch_owners(token_hash: [u8; 32], to_tx_hash: [u8; 32], to_output_idx:
         .execute(conn)?;
     Ok(())
 }
+/// Trade offers whose contract output still holds a panda, i.e. the panda's owner UTXO is the
+/// offer output. Restricted to the given offer txs if `offer_txs` is set.
+pub fn get_pandas_for_sale(panda_token_hash: &[u8; 32],
+                           offer_txs: Option<Vec<[u8; 32]>>,
+                           conn: &PgConnection) -> Result<Vec<PandaForSale>, DieselError> {
+    use self::schema::{trade_offer, tx, slp_tx, token, panda};
+    let mut query = trade_offer::table
+        .inner_join(tx::table)
+        .inner_join(slp_tx::table.on(tx::id.eq(slp_tx::tx)))
+        .inner_join(token::table.on(slp_tx::token.eq(token::id)))
+        .inner_join(panda::table.on(
+            panda::owner_tx.eq(trade_offer::tx)
+                .and(panda::owner_tx_idx.nullable().eq(trade_offer::output_idx))
+        ))
+        .filter(token::parent_token_hash.eq(panda_token_hash.to_vec()))
+        .select((tx::hash, panda::owner_tx_idx, trade_offer::price_per_token,
+                 trade_offer::sell_amount_token_base, trade_offer::receiving_address,
+                 token::hash, token::decimals,
+                 panda::physique, panda::pattern, panda::eye_color, panda::eye_shape,
+                 panda::base_color, panda::highlight_color, panda::accent_color,
+                 panda::wild_element, panda::mouth))
+        .into_boxed();
+    if let Some(offer_txs) = offer_txs {
+        query = query.filter(tx::hash.eq_any(
+            offer_txs.into_iter().map(|tx_hash| tx_hash.to_vec()).collect::<Vec<_>>()
+        ));
+    }
+    Ok(query
+        .load::<DbPandaForSale>(conn)?
+        .into_iter()
+        .filter_map(|for_sale| {
+            Some(PandaForSale {
+                token_hash: tx_hash_from_slice(&for_sale.token_hash),
+                offer_tx: tx_hash_from_slice(&for_sale.offer_tx_hash),
+                offer_output_idx: for_sale.offer_output_idx,
+                price_per_token: pg_numeric_to_rational(&for_sale.price_per_token).ok()?,
+                sell_amount_token: SLPAmount
+                    ::from_numeric_decimals(&for_sale.sell_amount_token_base, for_sale.decimals as u32),
+                seller_address: Address
+                    ::from_bytes(AddressType::P2PKH, address_hash_from_slice(&for_sale.receiving_address)),
+                attributes: PandaAttributes {
+                    physique: for_sale.physique,
+                    pattern: for_sale.pattern,
+                    eye_color: for_sale.eye_color,
+                    eye_shape: for_sale.eye_shape,
+                    base_color: for_sale.base_color,
+                    highlight_color: for_sale.highlight_color,
+                    accent_color: for_sale.accent_color,
+                    wild_element: for_sale.wild_element,
+                    mouth: for_sale.mouth,
+                },
+            })
+        })
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -265,4 +344,4 @@ mod tests {
             assert_eq!(&genes_expected[..], &genes_actual[..]);
         }
     }
-}
\ No newline at end of file
+}
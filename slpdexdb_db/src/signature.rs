@@ -0,0 +1,223 @@
+use rug::{Integer, integer::Order};
+use secp256k1::{Secp256k1, PublicKey, SecretKey, Signature};
+use cashcontracts::single_sha256;
+use slpdexdb_base::{Result, ErrorKind, SignerError};
+
+const FIELD_SIZE_HEX: &str = "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f";
+const CURVE_ORDER_HEX: &str = "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SignatureScheme {
+    Ecdsa,
+    Schnorr,
+}
+
+impl SignatureScheme {
+    /// Schnorr signatures are always exactly 64 bytes, DER encoded ECDSA signatures never are.
+    pub fn detect(signature: &[u8]) -> Self {
+        if signature.len() == 64 { SignatureScheme::Schnorr } else { SignatureScheme::Ecdsa }
+    }
+
+    pub fn is_well_formed(&self, signature: &[u8]) -> bool {
+        match self {
+            SignatureScheme::Ecdsa => Signature::from_der(signature).is_ok(),
+            SignatureScheme::Schnorr => signature.len() == 64,
+        }
+    }
+
+    pub fn sign(&self, secret_key: &SecretKey, message_hash: &[u8; 32]) -> Result<Vec<u8>> {
+        match self {
+            SignatureScheme::Ecdsa => {
+                let curve = Secp256k1::new();
+                Ok(curve.sign(&secp256k1::Message::from_slice(message_hash).unwrap(), secret_key)
+                    .serialize_der().to_vec())
+            },
+            SignatureScheme::Schnorr => schnorr_sign(secret_key, message_hash),
+        }
+    }
+
+    pub fn verify(&self, pubkey: &PublicKey, message_hash: &[u8; 32], signature: &[u8]) -> bool {
+        match self {
+            SignatureScheme::Ecdsa => {
+                let curve = Secp256k1::new();
+                match Signature::from_der(signature) {
+                    Ok(signature) => curve.verify(
+                        &secp256k1::Message::from_slice(message_hash).unwrap(),
+                        &signature,
+                        pubkey,
+                    ).is_ok(),
+                    Err(_) => false,
+                }
+            },
+            SignatureScheme::Schnorr => schnorr_verify(pubkey, message_hash, signature),
+        }
+    }
+}
+
+fn _field_size() -> Integer {
+    Integer::from_str_radix(FIELD_SIZE_HEX, 16).unwrap()
+}
+
+fn _curve_order() -> Integer {
+    Integer::from_str_radix(CURVE_ORDER_HEX, 16).unwrap()
+}
+
+fn _int_from_bytes(bytes: &[u8]) -> Integer {
+    Integer::from_digits(bytes, Order::Msf)
+}
+
+fn _int_to_bytes(int: &Integer) -> [u8; 32] {
+    let digits = int.to_digits::<u8>(Order::Msf);
+    let mut bytes = [0; 32];
+    bytes[32 - digits.len()..].copy_from_slice(&digits);
+    bytes
+}
+
+fn _has_square_y(point: &PublicKey) -> bool {
+    let field_size = _field_size();
+    let y = _int_from_bytes(&point.serialize_uncompressed()[33..]);
+    let exp = (field_size.clone() - 1u32) / 2u32;
+    y.pow_mod(&exp, &field_size).map(|jacobi| jacobi == 1).unwrap_or(false)
+}
+
+fn _challenge(r_x: &[u8], pubkey: &PublicKey, message_hash: &[u8; 32]) -> Integer {
+    let hash = single_sha256(&[r_x, &pubkey.serialize()[..], &message_hash[..]].concat());
+    _int_from_bytes(&hash) % _curve_order()
+}
+
+/// BCH Schnorr signature (r, s) over a 32 byte message hash, with the nonce derived
+/// deterministically as sha256(secret_key || message_hash). Fails if that nonce is 0 mod n.
+pub fn schnorr_sign(secret_key: &SecretKey, message_hash: &[u8; 32]) -> Result<Vec<u8>> {
+    let curve = Secp256k1::new();
+    let curve_order = _curve_order();
+    let d = _int_from_bytes(&secret_key[..]);
+    let k0 = _int_from_bytes(&single_sha256(&[&secret_key[..], &message_hash[..]].concat()))
+        % &curve_order;
+    let nonce = SecretKey::from_slice(&_int_to_bytes(&k0))
+        .map_err(|_| ErrorKind::SignerError(SignerError::InvalidNonce))?;
+    let r_point = PublicKey::from_secret_key(&curve, &nonce);
+    let k = if _has_square_y(&r_point) { k0 } else { curve_order.clone() - k0 };
+    let r_x = r_point.serialize_uncompressed()[1..33].to_vec();
+    let pubkey = PublicKey::from_secret_key(&curve, secret_key);
+    let e = _challenge(&r_x, &pubkey, message_hash);
+    let s = (k + e * d) % &curve_order;
+    let mut signature = r_x;
+    signature.extend_from_slice(&_int_to_bytes(&s));
+    Ok(signature)
+}
+
+/// Verifies R = s*G - e*P has a quadratic residue y coordinate and x(R) == r.
+pub fn schnorr_verify(pubkey: &PublicKey, message_hash: &[u8; 32], signature: &[u8]) -> bool {
+    if signature.len() != 64 { return false; }
+    let curve = Secp256k1::new();
+    let curve_order = _curve_order();
+    let r = _int_from_bytes(&signature[..32]);
+    let s = _int_from_bytes(&signature[32..]);
+    if r >= _field_size() || s >= curve_order { return false; }
+    let e = _challenge(&signature[..32], pubkey, message_hash);
+    let neg_e = (curve_order.clone() - e) % &curve_order;
+    let mut r_point = pubkey.clone();
+    if r_point.mul_assign(&curve, &_int_to_bytes(&neg_e)).is_err() { return false; }
+    if r_point.add_exp_assign(&curve, &_int_to_bytes(&s)).is_err() { return false; }
+    _has_square_y(&r_point) && r_point.serialize_uncompressed()[1..33] == signature[..32]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_pair(secret_hex: &str) -> (SecretKey, PublicKey) {
+        let secret_key = SecretKey::from_slice(&hex::decode(secret_hex).unwrap()).unwrap();
+        let pubkey = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+        (secret_key, pubkey)
+    }
+
+    fn hash32(hex_str: &str) -> [u8; 32] {
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&hex::decode(hex_str).unwrap());
+        hash
+    }
+
+    #[test]
+    fn schnorr_vector_1() {
+        let (secret_key, pubkey) = key_pair(
+            "0000000000000000000000000000000000000000000000000000000000000001"
+        );
+        assert_eq!(
+            hex::encode(&pubkey.serialize()[..]),
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        );
+        let message = [0; 32];
+        let signature = schnorr_sign(&secret_key, &message).unwrap();
+        assert_eq!(
+            hex::encode(&signature),
+            "787a848e71043d280c50470e8e1532b2dd5d20ee912a45dbdd2bd1dfbf187ef6\
+             7031a98831859dc34dffeedda86831842ccd0079e1f92af177f7f22cc1dced05",
+        );
+        assert!(schnorr_verify(&pubkey, &message, &signature));
+    }
+
+    #[test]
+    fn schnorr_vector_2() {
+        let (secret_key, pubkey) = key_pair(
+            "b7e151628aed2a6abf7158809cf4f3c762e7160f38b4da56a784d9045190cfef"
+        );
+        let message = hash32("243f6a8885a308d313198a2e03707344a4093822299f31d0082efa98ec4e6c89");
+        let signature = schnorr_sign(&secret_key, &message).unwrap();
+        assert_eq!(
+            hex::encode(&signature),
+            "2a298dacae57395a15d0795ddbfd1dcb564da82b0f269bc70a74f8220429ba1d\
+             1e51a22ccec35599b8f266912281f8365ffc2d035a230434a1a64dc59f7013fd",
+        );
+        assert!(schnorr_verify(&pubkey, &message, &signature));
+    }
+
+    #[test]
+    fn schnorr_rejects_tampered() {
+        let (secret_key, pubkey) = key_pair(
+            "b7e151628aed2a6abf7158809cf4f3c762e7160f38b4da56a784d9045190cfef"
+        );
+        let message = single_sha256(b"PANDA S3X:00+01");
+        let mut signature = schnorr_sign(&secret_key, &message).unwrap();
+        signature[63] ^= 1;
+        assert!(!schnorr_verify(&pubkey, &message, &signature));
+        assert!(!schnorr_verify(&pubkey, &message, &signature[..63]));
+    }
+
+    #[test]
+    fn ecdsa_vector() {
+        // RFC 6979 nonce, low s
+        let (secret_key, pubkey) = key_pair(
+            "0000000000000000000000000000000000000000000000000000000000000001"
+        );
+        let message = single_sha256(b"Satoshi Nakamoto");
+        let signature = SignatureScheme::Ecdsa.sign(&secret_key, &message).unwrap();
+        assert_eq!(
+            hex::encode(&signature),
+            "3045022100934b1ea10a4b3c1757e2b0c017d0b6143ce3c9a7e6a4a49860d7a6ab210ee3d8\
+             02202442ce9d2b916064108014783e923ec36b49743e2ffa1c4496f01a512aafd9e5",
+        );
+        assert_eq!(SignatureScheme::detect(&signature), SignatureScheme::Ecdsa);
+        assert!(SignatureScheme::Ecdsa.is_well_formed(&signature));
+        assert!(SignatureScheme::Ecdsa.verify(&pubkey, &message, &signature));
+        // a 64 byte signature is taken for Schnorr
+        let schnorr_signature = SignatureScheme::Schnorr.sign(&secret_key, &message).unwrap();
+        assert_eq!(SignatureScheme::detect(&schnorr_signature), SignatureScheme::Schnorr);
+        assert!(!SignatureScheme::Ecdsa.is_well_formed(&schnorr_signature));
+    }
+
+    #[test]
+    fn both_schemes_round_trip() {
+        let (secret_key, pubkey) = key_pair(
+            "b7e151628aed2a6abf7158809cf4f3c762e7160f38b4da56a784d9045190cfef"
+        );
+        let message = single_sha256(b"PANDA S3X:00+01");
+        for scheme in &[SignatureScheme::Ecdsa, SignatureScheme::Schnorr] {
+            let signature = scheme.sign(&secret_key, &message).unwrap();
+            assert_eq!(SignatureScheme::detect(&signature), *scheme);
+            assert!(scheme.is_well_formed(&signature));
+            assert!(scheme.verify(&pubkey, &message, &signature));
+            assert!(!scheme.verify(&pubkey, &single_sha256(b"other"), &signature));
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::num::NonZeroU32;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use cashcontracts::{Address, AddressType, UnsignedTx, Tx, double_sha256};
+use ring::{aead, pbkdf2, rand::{SecureRandom, SystemRandom}};
+use serde::{Serialize, Deserialize};
+use slpdexdb_base::{Result, Error, ErrorKind, SignerError};
+use crate::signature::SignatureScheme;
+
+const KEYSTORE_VERSION: u32 = 1;
+const KEYSTORE_ITERATIONS: u32 = 100_000;
+/// How long the signer waits for a client to send its request.
+const SOCKET_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Holds the operator key, or knows how to reach it.
+pub trait Signer {
+    /// Serialized compressed public key.
+    fn pub_key(&self) -> &[u8];
+
+    /// DER signature of the double SHA256 of a serialized sighash pre-image.
+    fn sign_pre_image(&self, pre_image: &[u8]) -> Result<Vec<u8>>;
+
+    /// Signature of a message hash, e.g. of a PND1 message.
+    fn sign_message(&self, scheme: SignatureScheme, message_hash: &[u8; 32]) -> Result<Vec<u8>>;
+
+    fn address(&self) -> Address {
+        Address::from_serialized_pub_key("bitcoincash", AddressType::P2PKH, self.pub_key())
+    }
+}
+
+/// Signs all inputs of `tx_build`, which must all be P2PKH inputs of the signer's address.
+pub fn sign_tx(tx_build: UnsignedTx, signer: &dyn Signer) -> Result<Tx> {
+    let n_inputs = tx_build.pre_images(0x41).len();
+    sign_tx_inputs(tx_build, &vec![signer; n_inputs])
+}
+
+/// Signs the P2PKH input at index `i` with `signers[i]`.
+pub fn sign_tx_inputs(tx_build: UnsignedTx, signers: &[&dyn Signer]) -> Result<Tx> {
+    let pre_images = serialize_pre_images(&tx_build);
+    assert_eq!(pre_images.len(), signers.len());
+    let signatures = pre_images.iter().zip(signers.iter())
+        .map(|(pre_image, signer)| signer.sign_pre_image(pre_image))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(tx_build.sign(
+        signatures,
+        signers.iter().map(|signer| signer.pub_key().to_vec()).collect(),
+    ))
+}
+
+/// Serialized pre-images of all inputs, to be signed with sighash type 0x41 (ALL|FORKID).
+pub fn serialize_pre_images(tx_build: &UnsignedTx) -> Vec<Vec<u8>> {
+    tx_build.pre_images(0x41).iter()
+        .map(|pre_image| {
+            let mut pre_image_ser = Vec::new();
+            pre_image.write_to_stream(&mut pre_image_ser).unwrap();
+            pre_image_ser
+        })
+        .collect()
+}
+
+/// Signs all P2PKH inputs with signatures made externally, all by the key of `pub_key`.
+pub fn sign_with_signatures(tx_build: UnsignedTx, signatures: Vec<Vec<u8>>, pub_key: Vec<u8>) -> Tx {
+    let pub_keys = signatures.iter().map(|_| pub_key.clone()).collect();
+    tx_build.sign(signatures, pub_keys)
+}
+
+fn _signer_error(signer_error: SignerError) -> Error {
+    ErrorKind::SignerError(signer_error).into()
+}
+
+pub struct InMemorySigner {
+    secret_key: secp256k1::SecretKey,
+    pub_key: Vec<u8>,
+}
+
+impl InMemorySigner {
+    pub fn new(secret_key: secp256k1::SecretKey) -> Self {
+        let curve = secp256k1::Secp256k1::new();
+        let pub_key = secp256k1::PublicKey::from_secret_key(&curve, &secret_key).serialize().to_vec();
+        InMemorySigner { secret_key, pub_key }
+    }
+
+    pub fn from_slice(secret: &[u8]) -> Result<Self> {
+        Ok(InMemorySigner::new(
+            secp256k1::SecretKey::from_slice(secret)
+                .map_err(|_| _signer_error(SignerError::InvalidSecretKey))?
+        ))
+    }
+}
+
+impl Signer for InMemorySigner {
+    fn pub_key(&self) -> &[u8] {
+        &self.pub_key
+    }
+
+    fn sign_pre_image(&self, pre_image: &[u8]) -> Result<Vec<u8>> {
+        let curve = secp256k1::Secp256k1::new();
+        let hash = double_sha256(pre_image);
+        Ok(curve.sign(&secp256k1::Message::from_slice(&hash).unwrap(), &self.secret_key)
+            .serialize_der().as_ref().to_vec())
+    }
+
+    fn sign_message(&self, scheme: SignatureScheme, message_hash: &[u8; 32]) -> Result<Vec<u8>> {
+        scheme.sign(&self.secret_key, message_hash)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Keystore {
+    version: u32,
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn _keystore_key(password: &str, salt: &[u8], iterations: u32) -> Result<aead::LessSafeKey> {
+    let iterations = NonZeroU32::new(iterations)
+        .ok_or_else(|| _signer_error(SignerError::InvalidKeystore("zero iterations".to_string())))?;
+    let mut key = [0; 32];
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, password.as_bytes(), &mut key);
+    Ok(aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, &key).unwrap()))
+}
+
+/// Decrypts the secret of the keystore at `path`, e.g. a key or a wallet seed.
+pub fn read_keystore(path: impl AsRef<Path>, password: &str) -> Result<Vec<u8>> {
+    let invalid = |msg: String| _signer_error(SignerError::InvalidKeystore(msg));
+    let keystore: Keystore = serde_json::from_slice(&fs::read(path)?)
+        .map_err(|err| invalid(err.to_string()))?;
+    if keystore.version != KEYSTORE_VERSION {
+        return Err(invalid(format!("unsupported version {}", keystore.version)));
+    }
+    let salt = hex::decode(&keystore.salt)?;
+    let mut nonce = [0; aead::NONCE_LEN];
+    let nonce_bytes = hex::decode(&keystore.nonce)?;
+    if nonce_bytes.len() != nonce.len() {
+        return Err(invalid("invalid nonce".to_string()));
+    }
+    nonce.copy_from_slice(&nonce_bytes);
+    let mut ciphertext = hex::decode(&keystore.ciphertext)?;
+    let key = _keystore_key(password, &salt, keystore.iterations)?;
+    let secret = key.open_in_place(aead::Nonce::assume_unique_for_key(nonce),
+                                   aead::Aad::empty(),
+                                   &mut ciphertext)
+        .map_err(|_| _signer_error(SignerError::WrongPassword))?;
+    Ok(secret.to_vec())
+}
+
+/// Encrypts `secret` with `password` and writes the keystore to `path`, readable only by the
+/// current user.
+pub fn write_keystore(path: impl AsRef<Path>, secret: &[u8], password: &str) -> Result<()> {
+    let rng = SystemRandom::new();
+    let mut salt = [0; 16];
+    let mut nonce = [0; aead::NONCE_LEN];
+    rng.fill(&mut salt).and_then(|_| rng.fill(&mut nonce))
+        .map_err(|_| _signer_error(SignerError::InvalidKeystore("no randomness".to_string())))?;
+    let key = _keystore_key(password, &salt, KEYSTORE_ITERATIONS)?;
+    let mut ciphertext = secret.to_vec();
+    key.seal_in_place_append_tag(aead::Nonce::assume_unique_for_key(nonce),
+                                 aead::Aad::empty(),
+                                 &mut ciphertext)
+        .unwrap();
+    let keystore = Keystore {
+        version: KEYSTORE_VERSION,
+        iterations: KEYSTORE_ITERATIONS,
+        salt: hex::encode(&salt),
+        nonce: hex::encode(&nonce),
+        ciphertext: hex::encode(&ciphertext),
+    };
+    fs::write(&path, serde_json::to_vec_pretty(&keystore).unwrap())?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
+
+/// Operator key stored in a file, encrypted with AES-256-GCM under a PBKDF2 derived key.
+pub struct KeystoreSigner {
+    signer: InMemorySigner,
+}
+
+impl KeystoreSigner {
+    pub fn open(path: impl AsRef<Path>, password: &str) -> Result<Self> {
+        Ok(KeystoreSigner { signer: InMemorySigner::from_slice(&read_keystore(path, password)?)? })
+    }
+
+    /// Encrypts `secret_key` with `password` and writes the keystore to `path`.
+    pub fn create(path: impl AsRef<Path>,
+                  secret_key: &secp256k1::SecretKey,
+                  password: &str) -> Result<Self> {
+        write_keystore(path, &secret_key[..], password)?;
+        Ok(KeystoreSigner { signer: InMemorySigner::new(secret_key.clone()) })
+    }
+}
+
+impl Signer for KeystoreSigner {
+    fn pub_key(&self) -> &[u8] {
+        self.signer.pub_key()
+    }
+
+    fn sign_pre_image(&self, pre_image: &[u8]) -> Result<Vec<u8>> {
+        self.signer.sign_pre_image(pre_image)
+    }
+
+    fn sign_message(&self, scheme: SignatureScheme, message_hash: &[u8; 32]) -> Result<Vec<u8>> {
+        self.signer.sign_message(scheme, message_hash)
+    }
+}
+
+/// One JSON object per line over the socket.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "camelCase")]
+enum SignerRequest {
+    PubKey,
+    SignPreImage { pre_image: String },
+    SignMessage { scheme: String, message_hash: String },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum SignerResponse {
+    PubKey(String),
+    Signature(String),
+    Error(String),
+}
+
+fn _scheme_name(scheme: SignatureScheme) -> &'static str {
+    match scheme {
+        SignatureScheme::Ecdsa => "ecdsa",
+        SignatureScheme::Schnorr => "schnorr",
+    }
+}
+
+/// Signs through a signer process listening on a local Unix socket (see `serve_unix_socket`),
+/// so the key never enters this process.
+pub struct UnixSocketSigner {
+    path: PathBuf,
+    pub_key: Vec<u8>,
+}
+
+impl UnixSocketSigner {
+    pub fn connect(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let pub_key = match _request(&path, &SignerRequest::PubKey)? {
+            SignerResponse::PubKey(pub_key) => hex::decode(&pub_key)?,
+            _ => return Err(_signer_error(SignerError::InvalidResponse("expected pubKey".to_string()))),
+        };
+        Ok(UnixSocketSigner { path, pub_key })
+    }
+
+    fn _signature(&self, request: &SignerRequest) -> Result<Vec<u8>> {
+        match _request(&self.path, request)? {
+            SignerResponse::Signature(signature) => Ok(hex::decode(&signature)?),
+            _ => Err(_signer_error(SignerError::InvalidResponse("expected signature".to_string()))),
+        }
+    }
+}
+
+fn _request(path: &Path, request: &SignerRequest) -> Result<SignerResponse> {
+    let mut stream = UnixStream::connect(path)?;
+    let mut line = serde_json::to_string(request).unwrap();
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response)?;
+    match serde_json::from_str(&response) {
+        Ok(SignerResponse::Error(err)) => Err(_signer_error(SignerError::Remote(err))),
+        Ok(response) => Ok(response),
+        Err(err) => Err(_signer_error(SignerError::InvalidResponse(err.to_string()))),
+    }
+}
+
+impl Signer for UnixSocketSigner {
+    fn pub_key(&self) -> &[u8] {
+        &self.pub_key
+    }
+
+    fn sign_pre_image(&self, pre_image: &[u8]) -> Result<Vec<u8>> {
+        self._signature(&SignerRequest::SignPreImage { pre_image: hex::encode(pre_image) })
+    }
+
+    fn sign_message(&self, scheme: SignatureScheme, message_hash: &[u8; 32]) -> Result<Vec<u8>> {
+        self._signature(&SignerRequest::SignMessage {
+            scheme: _scheme_name(scheme).to_string(),
+            message_hash: hex::encode(message_hash),
+        })
+    }
+}
+
+fn _handle_request(signer: &dyn Signer, request: SignerRequest) -> Result<SignerResponse> {
+    Ok(match request {
+        SignerRequest::PubKey => SignerResponse::PubKey(hex::encode(signer.pub_key())),
+        SignerRequest::SignPreImage { pre_image } => SignerResponse::Signature(
+            hex::encode(&signer.sign_pre_image(&hex::decode(&pre_image)?)?)
+        ),
+        SignerRequest::SignMessage { scheme, message_hash } => {
+            let scheme = match scheme.as_str() {
+                "ecdsa" => SignatureScheme::Ecdsa,
+                "schnorr" => SignatureScheme::Schnorr,
+                _ => return Ok(SignerResponse::Error(format!("unknown scheme {}", scheme))),
+            };
+            let message_hash = hex::decode(&message_hash)?;
+            if message_hash.len() != 32 {
+                return Ok(SignerResponse::Error("message hash must be 32 bytes".to_string()));
+            }
+            let mut hash = [0; 32];
+            hash.copy_from_slice(&message_hash);
+            SignerResponse::Signature(hex::encode(&signer.sign_message(scheme, &hash)?))
+        },
+    })
+}
+
+fn _serve_connection(stream: UnixStream, signer: &dyn Signer) -> Result<()> {
+    stream.set_read_timeout(Some(SOCKET_READ_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let response = match serde_json::from_str(&line) {
+        Ok(request) => _handle_request(signer, request)
+            .unwrap_or_else(|err| SignerResponse::Error(err.to_string())),
+        Err(err) => SignerResponse::Error(err.to_string()),
+    };
+    let mut response = serde_json::to_string(&response).unwrap();
+    response.push('\n');
+    (&stream).write_all(response.as_bytes())?;
+    Ok(())
+}
+
+/// Binds a Unix socket at `path` which only the current user may connect to.
+pub fn bind_unix_socket(path: impl AsRef<Path>) -> Result<UnixListener> {
+    let listener = UnixListener::bind(&path)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Serves `signer` on `listener`, one request per connection. A failing connection is logged
+/// and doesn't stop the signer. Runs forever.
+pub fn serve_listener(listener: &UnixListener, signer: &dyn Signer) {
+    for stream in listener.incoming() {
+        let result = stream.map_err(Error::from)
+            .and_then(|stream| _serve_connection(stream, signer));
+        if let Err(err) = result {
+            eprintln!("signer: failed to serve connection: {}", err);
+        }
+    }
+}
+
+/// Serves `signer` on a Unix socket at `path`, see `bind_unix_socket` and `serve_listener`.
+pub fn serve_unix_socket(path: impl AsRef<Path>, signer: &dyn Signer) -> Result<()> {
+    let listener = bind_unix_socket(path)?;
+    serve_listener(&listener, signer);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("slpdexdb-signer-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn _secret_key() -> secp256k1::SecretKey {
+        secp256k1::SecretKey::from_slice(&[0x11; 32]).unwrap()
+    }
+
+    #[test]
+    fn test_keystore_round_trip() {
+        let path = _temp_path("keystore.json");
+        let created = KeystoreSigner::create(&path, &_secret_key(), "password").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        let opened = KeystoreSigner::open(&path, "password").unwrap();
+        assert_eq!(opened.pub_key(), created.pub_key());
+        assert_eq!(opened.pub_key(), InMemorySigner::new(_secret_key()).pub_key());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_keystore_wrong_password() {
+        let path = _temp_path("keystore-wrong.json");
+        KeystoreSigner::create(&path, &_secret_key(), "password").unwrap();
+        match KeystoreSigner::open(&path, "wrong password") {
+            Err(Error(ErrorKind::SignerError(SignerError::WrongPassword), _)) => {},
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("opened keystore with wrong password"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unix_socket_protocol() {
+        let path = _temp_path("signer.sock");
+        let listener = bind_unix_socket(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        std::thread::spawn(move || serve_listener(&listener, &InMemorySigner::new(_secret_key())));
+
+        // a client that sends garbage or hangs up doesn't kill the signer
+        UnixStream::connect(&path).unwrap().write_all(b"garbage\n").unwrap();
+        drop(UnixStream::connect(&path).unwrap());
+
+        let local = InMemorySigner::new(_secret_key());
+        let remote = UnixSocketSigner::connect(&path).unwrap();
+        assert_eq!(remote.pub_key(), local.pub_key());
+        let pre_image = b"pre-image";
+        assert_eq!(remote.sign_pre_image(pre_image).unwrap(), local.sign_pre_image(pre_image).unwrap());
+        let message_hash = [0x22; 32];
+        assert_eq!(remote.sign_message(SignatureScheme::Ecdsa, &message_hash).unwrap(),
+                   local.sign_message(SignatureScheme::Ecdsa, &message_hash).unwrap());
+        match _request(&path, &SignerRequest::SignMessage {
+            scheme: "rsa".to_string(),
+            message_hash: hex::encode(&message_hash),
+        }) {
+            Err(Error(ErrorKind::SignerError(SignerError::Remote(_)), _)) => {},
+            _ => panic!("expected remote error"),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
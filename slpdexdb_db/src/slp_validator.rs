@@ -0,0 +1,608 @@
+use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
+use byteorder::{BigEndian, ReadBytesExt};
+use cashcontracts::{Tx, Script, tx_hash_to_hex};
+use slpdexdb_base::{Result, Error, ErrorKind, SLPError};
+use slpdexdb_base::convert_numeric::pg_numeric_to_i128;
+use crate::data::tx_hash_from_slice;
+use crate::db::Db;
+use crate::tx_history::{SLPTxType, TokenType, OutputType};
+
+const MAX_SEND_AMOUNTS: usize = 19;
+const MAX_DECIMALS: u8 = 9;
+const REST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An SLP OP_RETURN, parsed without looking up anything.
+#[derive(Clone, Debug)]
+pub struct SlpMessage {
+    pub token_type: TokenType,
+    pub slp_type: SLPTxType,
+    /// `None` for GENESIS, whose token id is its own tx hash
+    pub token_hash: Option<[u8; 32]>,
+    /// GENESIS only
+    pub decimals: Option<u8>,
+    pub mint_baton_vout: Option<u8>,
+    /// Token amounts of the outputs 1, 2, ...
+    pub amounts: Vec<u64>,
+}
+
+impl SlpMessage {
+    pub fn token_hash_of(&self, tx_hash: &[u8; 32]) -> [u8; 32] {
+        self.token_hash.unwrap_or(*tx_hash)
+    }
+
+    pub fn amount_at(&self, vout: u32) -> u64 {
+        if vout == 0 {
+            return 0;
+        }
+        self.amounts.get(vout as usize - 1).cloned().unwrap_or(0)
+    }
+
+    pub fn output_sum(&self) -> u128 {
+        self.amounts.iter().map(|&amount| amount as u128).sum()
+    }
+}
+
+/// Parses the SLP message of an output script. `Ok(None)` if it isn't an SLP message of a
+/// supported token type, an error if it is one, but malformed.
+pub fn parse_slp_message(script: &Script) -> Result<Option<SlpMessage>> {
+    use cashcontracts::{Op::*, OpCodeType::*};
+    let script_hex = || hex::encode(script.to_vec());
+    let invalid = |slp_error: SLPError| -> Error {
+        ErrorKind::InvalidSLPOutput(script_hex(), slp_error).into()
+    };
+    let ops = script.ops();
+    match (ops.get(0), ops.get(1)) {
+        (Some(Code(OpReturn)), Some(Push(lokad_id))) if lokad_id == b"SLP\0" => {},
+        _ => return Ok(None),
+    }
+    if !script.is_slp_safe() {
+        return Err(invalid(SLPError::NotSLPSafe));
+    }
+    if ops.len() < 5 {
+        return Err(invalid(SLPError::TooFewPushops(ops.len())));
+    }
+    let pushes = ops[2..].iter()
+        .map(|op| match op {
+            Push(push) => push.as_slice(),
+            _ => unreachable!(),  // handled by is_slp_safe
+        })
+        .collect::<Vec<_>>();
+    let token_type = pushes[0];
+    if token_type.len() == 0 || token_type.len() > 2 {
+        return Err(invalid(SLPError::InvalidTokenTypeLength(hex::encode(token_type))));
+    }
+    let token_type = match num::traits::FromPrimitive::from_u16(
+        token_type.iter().fold(0u16, |acc, &byte| (acc << 8) | byte as u16)
+    ) {
+        Some(token_type) => token_type,
+        None => return Ok(None),
+    };
+    let slp_type = SLPTxType::from_bytes(pushes[1])
+        .ok_or_else(|| invalid(SLPError::InvalidSLPType(
+            format!("{} ({})", String::from_utf8_lossy(pushes[1]), hex::encode(pushes[1]))
+        )))?;
+    let read_amount = |push: &[u8]| -> Result<u64> {
+        if push.len() != 8 {
+            return Err(invalid(SLPError::InvalidAmountLength(hex::encode(push))));
+        }
+        Ok(io::Cursor::new(push).read_u64::<BigEndian>()?)
+    };
+    let read_token_hash = |push: &[u8]| -> Result<[u8; 32]> {
+        if push.len() != 32 {
+            return Err(invalid(SLPError::InvalidTokenHashLength(hex::encode(push))));
+        }
+        let mut token_hash = [0; 32];
+        token_hash.copy_from_slice(&push.iter().rev().cloned().collect::<Vec<_>>());
+        Ok(token_hash)
+    };
+    let read_mint_baton_vout = |push: &[u8]| -> Result<Option<u8>> {
+        match push {
+            [] => Ok(None),
+            &[vout] if vout >= 2 => Ok(Some(vout)),
+            _ => Err(invalid(SLPError::InvalidMintBatonVout(hex::encode(push)))),
+        }
+    };
+    match slp_type {
+        SLPTxType::Genesis => {
+            if pushes.len() != 9 {
+                return Err(invalid(SLPError::NoMatch));
+            }
+            let document_hash = pushes[5];
+            if document_hash.len() != 0 && document_hash.len() != 32 {
+                return Err(invalid(SLPError::InvalidDocumentHashLength(hex::encode(document_hash))));
+            }
+            let decimals = match pushes[6] {
+                &[decimals] if decimals <= MAX_DECIMALS => decimals,
+                decimals => return Err(invalid(SLPError::InvalidDecimals(hex::encode(decimals)))),
+            };
+            let mint_baton_vout = read_mint_baton_vout(pushes[7])?;
+            let quantity = read_amount(pushes[8])?;
+            if token_type == TokenType::NFT1Child {
+                if decimals != 0 {
+                    return Err(invalid(SLPError::NFT1ChildDecimals(decimals)));
+                }
+                if quantity != 1 {
+                    return Err(invalid(SLPError::NFT1ChildQuantity(quantity)));
+                }
+                if mint_baton_vout.is_some() {
+                    return Err(invalid(SLPError::NFT1ChildMintBaton));
+                }
+            }
+            Ok(Some(SlpMessage {
+                token_type,
+                slp_type,
+                token_hash: None,
+                decimals: Some(decimals),
+                mint_baton_vout,
+                amounts: vec![quantity],
+            }))
+        },
+        SLPTxType::Mint => {
+            if pushes.len() != 5 {
+                return Err(invalid(SLPError::NoMatch));
+            }
+            Ok(Some(SlpMessage {
+                token_type,
+                slp_type,
+                token_hash: Some(read_token_hash(pushes[2])?),
+                decimals: None,
+                mint_baton_vout: read_mint_baton_vout(pushes[3])?,
+                amounts: vec![read_amount(pushes[4])?],
+            }))
+        },
+        SLPTxType::Send => {
+            if pushes.len() < 4 {
+                return Err(invalid(SLPError::TooFewPushops(ops.len())));
+            }
+            if pushes.len() - 3 > MAX_SEND_AMOUNTS {
+                return Err(invalid(SLPError::TooManyAmounts(pushes.len() - 3)));
+            }
+            Ok(Some(SlpMessage {
+                token_type,
+                slp_type,
+                token_hash: Some(read_token_hash(pushes[2])?),
+                decimals: None,
+                mint_baton_vout: None,
+                amounts: pushes[3..].iter()
+                    .map(|push| read_amount(push))
+                    .collect::<Result<Vec<_>>>()?,
+            }))
+        },
+        SLPTxType::Commit => Err(invalid(SLPError::NoMatch)),
+    }
+}
+
+/// Where the validator gets the parents of a tx from.
+pub trait TxProvider {
+    fn fetch_tx(&self, tx_hash: &[u8; 32]) -> Result<Option<Tx>>;
+}
+
+impl TxProvider for HashMap<[u8; 32], Tx> {
+    fn fetch_tx(&self, tx_hash: &[u8; 32]) -> Result<Option<Tx>> {
+        Ok(self.get(tx_hash).cloned())
+    }
+}
+
+/// Fetches raw txs from a REST API which returns the raw hex of the tx with the appended txid.
+pub struct RestTxProvider {
+    raw_tx_url: String,
+    client: reqwest::Client,
+}
+
+impl RestTxProvider {
+    pub fn new(raw_tx_url: String) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REST_TIMEOUT)
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+        RestTxProvider { raw_tx_url, client }
+    }
+}
+
+impl TxProvider for RestTxProvider {
+    fn fetch_tx(&self, tx_hash: &[u8; 32]) -> Result<Option<Tx>> {
+        let url = format!("{}{}", self.raw_tx_url, tx_hash_to_hex(tx_hash));
+        let mut response = self.client.get(&url).send()?;
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        let text = response.text()?;
+        let tx_bytes = hex::decode(text.trim().trim_matches('"'))?;
+        Ok(Some(Tx::read_from_stream(&mut io::Cursor::new(tx_bytes))?))
+    }
+}
+
+/// What the DB knows about a parent tx, so it doesn't have to be fetched.
+#[derive(Clone, Debug)]
+enum DbParent {
+    /// Invalid, or without an OP_RETURN at output 0, so none of its outputs carry tokens.
+    NoTokens,
+    /// Validated already, with the token amounts of its outputs.
+    Valid { token_hash: [u8; 32], token_type: TokenType, amounts: HashMap<u32, u64> },
+}
+
+/// Token, token type and amount of an output.
+type TokenOutput = ([u8; 32], TokenType, u64);
+
+/// Input spending tokens of the tx being validated: the outpoint and the amount spent.
+type RelevantInput = ([u8; 32], u32, u64);
+
+/// Validates SLP txs by walking their inputs back to the genesis, instead of trusting SLPDB.
+/// Verdicts are cached, and persisted if a `Db` is given. Parents the `Db` knows as non-SLP
+/// or invalid, or has validated already, aren't fetched.
+pub struct SlpValidator<'a> {
+    provider: &'a dyn TxProvider,
+    db: Option<&'a Db>,
+    txs: HashMap<[u8; 32], Tx>,
+    messages: HashMap<[u8; 32], Option<SlpMessage>>,
+    verdicts: HashMap<[u8; 32], bool>,
+    db_parents: HashMap<[u8; 32], Option<DbParent>>,
+}
+
+impl<'a> SlpValidator<'a> {
+    pub fn new(provider: &'a dyn TxProvider, db: Option<&'a Db>) -> Self {
+        SlpValidator {
+            provider,
+            db,
+            txs: HashMap::new(),
+            messages: HashMap::new(),
+            verdicts: HashMap::new(),
+            db_parents: HashMap::new(),
+        }
+    }
+
+    /// Txs the provider might not know yet, e.g. the unconfirmed txs being validated.
+    pub fn add_txs(&mut self, txs: &[Tx]) {
+        for tx in txs {
+            self.txs.insert(tx.hash(), tx.clone());
+        }
+    }
+
+    fn _tx(&mut self, tx_hash: &[u8; 32]) -> Result<&Tx> {
+        if !self.txs.contains_key(tx_hash) {
+            let tx = self.provider.fetch_tx(tx_hash)?
+                .ok_or_else(|| ErrorKind::SLPParentNotFound(tx_hash_to_hex(tx_hash)))?;
+            self.txs.insert(*tx_hash, tx);
+        }
+        Ok(&self.txs[tx_hash])
+    }
+
+    /// A tx added to the validator or fetched from the provider, `None` if the provider doesn't
+    /// have it. Fetched txs are cached.
+    pub fn tx(&mut self, tx_hash: &[u8; 32]) -> Result<Option<&Tx>> {
+        match self._tx(tx_hash) {
+            Ok(tx) => Ok(Some(tx)),
+            Err(Error(ErrorKind::SLPParentNotFound(_), _)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The SLP message of a tx, `None` if it has none or a malformed one.
+    pub fn message(&mut self, tx_hash: &[u8; 32]) -> Result<Option<&SlpMessage>> {
+        if !self.messages.contains_key(tx_hash) {
+            let message = {
+                let tx = self._tx(tx_hash)?;
+                match tx.outputs().get(0) {
+                    Some(output) => parse_slp_message(&output.script()).unwrap_or(None),
+                    None => None,
+                }
+            };
+            self.messages.insert(*tx_hash, message);
+        }
+        Ok(self.messages[tx_hash].as_ref())
+    }
+
+    fn _cached_verdict(&mut self, tx_hash: &[u8; 32]) -> Result<Option<bool>> {
+        if let Some(&is_valid) = self.verdicts.get(tx_hash) {
+            return Ok(Some(is_valid));
+        }
+        if let Some(db) = self.db {
+            if let Some(is_valid) = db.slp_validity(tx_hash)? {
+                self.verdicts.insert(*tx_hash, is_valid);
+                return Ok(Some(is_valid));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Looks up the parents which are neither in memory nor looked up already in the DB.
+    fn _load_db_parents(&mut self, parent_hashes: &[[u8; 32]]) -> Result<()> {
+        let db = match self.db {
+            Some(db) => db,
+            None => return Ok(()),
+        };
+        let mut parent_hashes = parent_hashes.iter()
+            .filter(|parent_hash| {
+                !self.txs.contains_key(*parent_hash) && !self.db_parents.contains_key(*parent_hash)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        parent_hashes.sort();
+        parent_hashes.dedup();
+        if parent_hashes.len() == 0 {
+            return Ok(());
+        }
+        let slp_txs = db.slp_txs(parent_hashes.iter().cloned())?;
+        let outputs = db.tx_outputs(parent_hashes.iter().cloned())?;
+        for parent_hash in parent_hashes {
+            // the DB stores SLP txs of tokens it doesn't know as plain txs, so only the lack of
+            // an OP_RETURN at output 0 proves a tx isn't an SLP tx
+            let has_op_return = outputs.get(&(parent_hash, 0))
+                .map(|output| output.output_type == OutputType::OpReturn.id());
+            let verdict = self._cached_verdict(&parent_hash)?;
+            let db_parent = match (verdict, slp_txs.get(&parent_hash), has_op_return) {
+                (Some(false), _, _) | (_, _, Some(false)) => Some(DbParent::NoTokens),
+                (Some(true), Some((_, _, token)), _) => {
+                    let token_type = num::traits::FromPrimitive::from_i16(token.version_type);
+                    let mut amounts = HashMap::new();
+                    for ((tx_hash, vout), output) in outputs.iter() {
+                        if tx_hash == &parent_hash {
+                            amounts.insert(*vout as u32,
+                                           pg_numeric_to_i128(&output.value_token_base)? as u64);
+                        }
+                    }
+                    token_type.map(|token_type| DbParent::Valid {
+                        token_hash: tx_hash_from_slice(&token.hash),
+                        token_type,
+                        amounts,
+                    })
+                },
+                // unknown to the DB, or an SLP tx we haven't validated ourselves
+                _ => None,
+            };
+            self.db_parents.insert(parent_hash, db_parent);
+        }
+        Ok(())
+    }
+
+    /// Token output at `vout` of a parent, from the DB if it knows the parent, else from the
+    /// parent's SLP message. `None` if the output carries no tokens.
+    fn _parent_output(&mut self, parent_hash: &[u8; 32], vout: u32) -> Result<Option<TokenOutput>> {
+        match self.db_parents.get(parent_hash) {
+            Some(Some(DbParent::NoTokens)) => return Ok(None),
+            Some(Some(DbParent::Valid { token_hash, token_type, amounts })) => {
+                return Ok(amounts.get(&vout)
+                    .filter(|&&amount| amount > 0)
+                    .map(|&amount| (*token_hash, *token_type, amount)));
+            },
+            _ => {},
+        }
+        Ok(match self.message(parent_hash)? {
+            Some(parent) if parent.amount_at(vout) > 0 => {
+                Some((parent.token_hash_of(parent_hash), parent.token_type, parent.amount_at(vout)))
+            },
+            _ => None,
+        })
+    }
+
+    /// Inputs spending outputs which carry the token of the tx, i.e. whose validity matters.
+    fn _relevant_inputs(&mut self, tx_hash: &[u8; 32]) -> Result<Vec<RelevantInput>> {
+        let message = match self.message(tx_hash)? {
+            Some(message) => message.clone(),
+            None => return Ok(vec![]),
+        };
+        match (&message.slp_type, message.token_type) {
+            (SLPTxType::Genesis, TokenType::NFT1Child) => return self._group_input(tx_hash),
+            (SLPTxType::Genesis, _) | (SLPTxType::Commit, _) => return Ok(vec![]),
+            (SLPTxType::Mint, _) | (SLPTxType::Send, _) => {},
+        }
+        let token_hash = message.token_hash_of(tx_hash);
+        let outpoints = self._tx(tx_hash)?.inputs().iter()
+            .map(|input| (input.outpoint.tx_hash, input.outpoint.vout))
+            .collect::<Vec<_>>();
+        let parent_hashes = outpoints.iter()
+            .map(|(parent_hash, _)| *parent_hash)
+            .collect::<Vec<_>>();
+        self._load_db_parents(&parent_hashes)?;
+        let mut relevant_inputs = Vec::new();
+        for (parent_hash, vout) in outpoints {
+            if let SLPTxType::Mint = message.slp_type {
+                // batons carry no amount, so they're only known from the message
+                if let Some(Some(DbParent::NoTokens)) = self.db_parents.get(&parent_hash) {
+                    continue
+                }
+                let is_baton = match self.message(&parent_hash)? {
+                    Some(parent) => parent.token_hash_of(&parent_hash) == token_hash &&
+                        parent.token_type == message.token_type &&
+                        parent.mint_baton_vout.map(|baton_vout| baton_vout as u32) == Some(vout),
+                    None => false,
+                };
+                if is_baton {
+                    relevant_inputs.push((parent_hash, vout, 0));
+                }
+                continue
+            }
+            match self._parent_output(&parent_hash, vout)? {
+                Some((parent_token_hash, token_type, amount))
+                        if parent_token_hash == token_hash && token_type == message.token_type =>
+                    relevant_inputs.push((parent_hash, vout, amount)),
+                _ => {},
+            }
+        }
+        Ok(relevant_inputs)
+    }
+
+    /// Input 0 of an NFT1 child GENESIS, if it spends NFT1 group tokens.
+    fn _group_input(&mut self, tx_hash: &[u8; 32]) -> Result<Vec<RelevantInput>> {
+        let (parent_hash, vout) = match self._tx(tx_hash)?.inputs().get(0) {
+            Some(input) => (input.outpoint.tx_hash, input.outpoint.vout),
+            None => return Ok(vec![]),
+        };
+        self._load_db_parents(&[parent_hash])?;
+        Ok(match self._parent_output(&parent_hash, vout)? {
+            Some((_, TokenType::NFT1Parent, amount)) => vec![(parent_hash, vout, amount)],
+            _ => vec![],
+        })
+    }
+
+    /// Verdict of a tx whose relevant inputs all have verdicts.
+    fn _verdict(&mut self, tx_hash: &[u8; 32], relevant_inputs: &[RelevantInput]) -> Result<bool> {
+        let message = match self.message(tx_hash)? {
+            Some(message) => message.clone(),
+            None => return Ok(false),
+        };
+        let valid_inputs = relevant_inputs.iter()
+            .filter(|(parent_hash, _, _)| self.verdicts.get(parent_hash) == Some(&true))
+            .collect::<Vec<_>>();
+        Ok(match message.slp_type {
+            SLPTxType::Genesis if message.token_type == TokenType::NFT1Child => valid_inputs.len() > 0,
+            SLPTxType::Genesis => true,
+            SLPTxType::Mint => valid_inputs.len() > 0,
+            SLPTxType::Send => {
+                let input_sum = valid_inputs.iter()
+                    .map(|&&(_, _, amount)| amount as u128)
+                    .sum::<u128>();
+                input_sum >= message.output_sum()
+            },
+            SLPTxType::Commit => false,
+        })
+    }
+
+    /// Group token spent by input 0 of an NFT1 child GENESIS, `None` unless the spent group
+    /// tokens are valid.
+    pub fn group_input_token(&mut self, tx_hash: &[u8; 32]) -> Result<Option<[u8; 32]>> {
+        for (parent_hash, vout, _) in self._group_input(tx_hash)? {
+            if self.validate(&parent_hash)? {
+                let group_output = self._parent_output(&parent_hash, vout)?;
+                return Ok(group_output.map(|(token_hash, _, _)| token_hash));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether the tx is a valid SLP tx. Fails if a relevant parent can't be fetched, without
+    /// recording a verdict, so validating the tx again later can still succeed.
+    pub fn validate(&mut self, tx_hash: &[u8; 32]) -> Result<bool> {
+        // depth first, without recursion, as token DAGs can be very deep
+        let mut stack = vec![*tx_hash];
+        while let Some(current) = stack.last().cloned() {
+            if self._cached_verdict(&current)?.is_some() {
+                stack.pop();
+                continue
+            }
+            let relevant_inputs = self._relevant_inputs(&current)?;
+            let mut unvalidated = Vec::new();
+            for (parent_hash, _, _) in relevant_inputs.iter() {
+                if self._cached_verdict(parent_hash)?.is_none() {
+                    unvalidated.push(*parent_hash);
+                }
+            }
+            if unvalidated.len() > 0 {
+                stack.extend(unvalidated);
+                continue
+            }
+            let is_valid = self._verdict(&current, &relevant_inputs)?;
+            self.verdicts.insert(current, is_valid);
+            if let Some(db) = self.db {
+                db.add_slp_validity(&current, is_valid)?;
+            }
+            stack.pop();
+        }
+        Ok(self.verdicts[tx_hash])
+    }
+}
+
+#[cfg(test)]
+mod conformance_tests;
+
+#[cfg(test)]
+pub(crate) mod test_txs;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_txs::*;
+
+    #[test]
+    fn parse_slp_messages() {
+        let genesis = genesis(1000);
+        let message = parse_slp_message(&genesis.outputs()[0].script()).unwrap().unwrap();
+        assert_eq!(message.token_type, TokenType::Standard);
+        assert_eq!(message.decimals, Some(0));
+        assert_eq!(message.mint_baton_vout, Some(2));
+        assert_eq!(message.amounts, vec![1000]);
+
+        let too_many_amounts = send(&[], &genesis.hash(), &[1; 20]);
+        assert!(parse_slp_message(&too_many_amounts.outputs()[0].script()).is_err());
+    }
+
+    #[test]
+    fn validate_dag() {
+        let genesis = genesis(1000);
+        let token_hash = genesis.hash();
+        let send1 = send(&[(token_hash, 1)], &token_hash, &[600, 400]);
+        let send2 = send(&[(send1.hash(), 1), (send1.hash(), 2)], &token_hash, &[1000]);
+        let overspend = send(&[(send1.hash(), 2)], &token_hash, &[401]);
+        let spends_baton = send(&[(token_hash, 2)], &token_hash, &[1]);
+        let mut provider = HashMap::new();
+        for tx in vec![&genesis, &send1] {
+            provider.insert(tx.hash(), tx.clone());
+        }
+        let mut validator = SlpValidator::new(&provider, None);
+        validator.add_txs(&[send2.clone(), overspend.clone(), spends_baton.clone()]);
+        assert!(validator.validate(&send2.hash()).unwrap());
+        assert!(!validator.validate(&overspend.hash()).unwrap());
+        assert!(!validator.validate(&spends_baton.hash()).unwrap());
+
+        let unknown_parent = send(&[([1; 32], 1)], &token_hash, &[1]);
+        validator.add_txs(&[unknown_parent.clone()]);
+        assert!(validator.validate(&unknown_parent.hash()).is_err());
+    }
+
+    #[test]
+    fn missing_parent_is_not_a_verdict() {
+        let genesis = genesis(1000);
+        let token_hash = genesis.hash();
+        let send1 = send(&[(token_hash, 1)], &token_hash, &[1000]);
+        let provider = HashMap::<[u8; 32], Tx>::new();
+        let mut validator = SlpValidator::new(&provider, None);
+        validator.add_txs(&[send1.clone()]);
+        assert!(validator.validate(&send1.hash()).is_err());
+        validator.add_txs(&[genesis.clone()]);
+        assert!(validator.validate(&send1.hash()).unwrap());
+    }
+
+    #[test]
+    fn nft1_child_genesis_params() {
+        // decimals, mint baton vout, quantity and whether the NFT1 spec allows them
+        let vectors: Vec<(u8, Vec<u8>, u64, bool)> = vec![
+            (0, vec![], 1, true),
+            (1, vec![], 1, false),
+            (0, vec![], 0, false),
+            (0, vec![], 2, false),
+            (0, vec![2], 1, false),
+        ];
+        for (decimals, mint_baton_vout, quantity, is_valid) in vectors {
+            let child = typed_genesis(0x41, &[([0xff; 32], 0)], decimals, mint_baton_vout, quantity);
+            assert_eq!(parse_slp_message(&child.outputs()[0].script()).is_ok(), is_valid);
+        }
+    }
+
+    #[test]
+    fn validate_nft1_children() {
+        let group = typed_genesis(0x81, &[([0xff; 32], 0)], 0, vec![2], 10);
+        let group_hash = group.hash();
+        let fan_out = typed_send(0x81, &[(group_hash, 1)], &group_hash, &[1, 9]);
+        let overspent_fan_out = typed_send(0x81, &[(group_hash, 1)], &group_hash, &[11]);
+        let standard = genesis(1000);
+        let child = |inputs: &[([u8; 32], u32)]| typed_genesis(0x41, inputs, 0, vec![], 1);
+        let valid_child = child(&[(fan_out.hash(), 1)]);
+        // the group tokens have to be spent at input 0, not just anywhere
+        let counterfeit = child(&[(standard.hash(), 1), (fan_out.hash(), 2)]);
+        let spends_group_baton = child(&[(group_hash, 2)]);
+        let spends_invalid_group = child(&[(overspent_fan_out.hash(), 1)]);
+        let mut provider = HashMap::new();
+        for tx in vec![&group, &fan_out, &overspent_fan_out, &standard] {
+            provider.insert(tx.hash(), tx.clone());
+        }
+        let mut validator = SlpValidator::new(&provider, None);
+        validator.add_txs(&[valid_child.clone(), counterfeit.clone(),
+                            spends_group_baton.clone(), spends_invalid_group.clone()]);
+        assert!(validator.validate(&valid_child.hash()).unwrap());
+        assert!(!validator.validate(&counterfeit.hash()).unwrap());
+        assert!(!validator.validate(&spends_group_baton.hash()).unwrap());
+        assert!(!validator.validate(&spends_invalid_group.hash()).unwrap());
+    }
+}
@@ -0,0 +1,64 @@
+//! Builders of (unsigned, unfunded) SLP txs for tests.
+use cashcontracts::{Tx, UnsignedTx, UnsignedInput, TxOutpoint, TxOutput, Output, P2PKHOutput,
+                    OpReturnOutput, Address, AddressType};
+
+pub fn typed_tx(token_type: u8,
+                inputs: &[([u8; 32], u32)],
+                slp_pushes: Vec<Vec<u8>>,
+                n_outputs: usize) -> Tx {
+    let address = Address::from_slice(AddressType::P2PKH, &[0; 20]).unwrap();
+    let mut tx_build = UnsignedTx::new_simple();
+    for &(tx_hash, vout) in inputs {
+        tx_build.add_input(UnsignedInput {
+            outpoint: TxOutpoint { tx_hash, vout },
+            output: Box::new(P2PKHOutput { address: address.clone(), value: 546 }),
+            sequence: 0xffff_ffff,
+        });
+    }
+    let mut pushes = vec![b"SLP\0".to_vec(), vec![token_type]];
+    pushes.extend(slp_pushes);
+    tx_build.add_output(TxOutput {
+        value: 0,
+        script: OpReturnOutput { is_minimal_push: false, pushes }.script(),
+    });
+    for _ in 0..n_outputs {
+        tx_build.add_output(TxOutput {
+            value: 546,
+            script: P2PKHOutput { address: address.clone(), value: 546 }.script(),
+        });
+    }
+    tx_build.sign(inputs.iter().map(|_| vec![0; 72]).collect(),
+                  inputs.iter().map(|_| vec![0; 33]).collect())
+}
+
+pub fn typed_genesis(token_type: u8,
+                     inputs: &[([u8; 32], u32)],
+                     decimals: u8,
+                     mint_baton_vout: Vec<u8>,
+                     quantity: u64) -> Tx {
+    typed_tx(token_type, inputs, vec![
+        b"GENESIS".to_vec(), b"TST".to_vec(), b"Test".to_vec(), b"test.com".to_vec(),
+        vec![0; 32], vec![decimals], mint_baton_vout, quantity.to_be_bytes().to_vec(),
+    ], 2)
+}
+
+pub fn genesis(quantity: u64) -> Tx {
+    typed_genesis(0x01, &[([0xff; 32], 0)], 0, vec![2], quantity)
+}
+
+pub fn token_id(token_hash: &[u8; 32]) -> Vec<u8> {
+    token_hash.iter().rev().cloned().collect()
+}
+
+pub fn typed_send(token_type: u8,
+                  inputs: &[([u8; 32], u32)],
+                  token_hash: &[u8; 32],
+                  amounts: &[u64]) -> Tx {
+    let mut pushes = vec![b"SEND".to_vec(), token_id(token_hash)];
+    pushes.extend(amounts.iter().map(|amount| amount.to_be_bytes().to_vec()));
+    typed_tx(token_type, inputs, pushes, amounts.len())
+}
+
+pub fn send(inputs: &[([u8; 32], u32)], token_hash: &[u8; 32], amounts: &[u64]) -> Tx {
+    typed_send(0x01, inputs, token_hash, amounts)
+}
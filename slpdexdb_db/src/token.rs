@@ -2,6 +2,7 @@ use crate::token_source::token_result::TokenEntry;
 use slpdexdb_base::SLPAmount;
 use slpdexdb_base::{Result, ErrorKind, Error, TokenError};
 use crate::tx_history::TokenType;
+use crate::slp_validator::SlpMessage;
 use cashcontracts::tx_hex_to_hash;
 
 #[derive(Clone, Debug)]
@@ -25,6 +26,26 @@ impl Token {
         if string.is_empty() { None } else { Some(string) }
     }
 
+    /// Token of a GENESIS nobody told us about yet, with only what its SLP message says.
+    pub fn from_genesis(token_hash: [u8; 32], message: &SlpMessage) -> Self {
+        let decimals = message.decimals.unwrap_or(0);
+        let quantity = SLPAmount::new(message.amount_at(1) as i128, decimals as u32);
+        Token {
+            hash: token_hash,
+            parent_hash: None,
+            decimals: decimals as i32,
+            timestamp: 0,
+            version_type: message.token_type,
+            document_uri: None,
+            symbol: None,
+            name: None,
+            document_hash: None,
+            initial_supply: quantity,
+            current_supply: quantity,
+            block_created_height: 0,
+        }
+    }
+
     pub fn from_entry(token_entry: TokenEntry) -> Result<Self> {
         let not_mined_yet_err = || -> Error {
             ErrorKind::TokenError(
@@ -0,0 +1,50 @@
+use slpdexdb_base::SLPAmount;
+use crate::models;
+use crate::data::tx_hash_from_slice;
+
+/// Chain events changing the circulating supply of a token.
+#[derive(Copy, Clone, Debug, FromPrimitive, Eq, PartialEq, Hash)]
+pub enum SupplyChangeKind {
+    Genesis = 0,
+    Mint = 1,
+    /// Token inputs which weren't carried to the outputs, e.g. by spending more than is sent,
+    /// or by spending them in a tx of another token
+    BurnNotCarried = 2,
+    /// Tokens sent to OP_RETURN or to outputs the tx doesn't have
+    BurnOpReturn = 3,
+    /// Token inputs of non-SLP or invalid SLP txs
+    BurnInvalidSpend = 4,
+}
+
+#[derive(Clone, Debug)]
+pub struct SupplyChange {
+    pub tx_hash: [u8; 32],
+    pub token_hash: [u8; 32],
+    pub kind: SupplyChangeKind,
+    /// Always positive; burns reduce the supply
+    pub amount: SLPAmount,
+    pub timestamp: i64,
+}
+
+impl SupplyChangeKind {
+    pub fn is_burn(&self) -> bool {
+        match self {
+            SupplyChangeKind::Genesis | SupplyChangeKind::Mint => false,
+            SupplyChangeKind::BurnNotCarried |
+            SupplyChangeKind::BurnOpReturn |
+            SupplyChangeKind::BurnInvalidSpend => true,
+        }
+    }
+}
+
+impl SupplyChange {
+    pub fn from_model(change: models::TokenSupplyChange, decimals: u32) -> Option<Self> {
+        Some(SupplyChange {
+            tx_hash: tx_hash_from_slice(&change.tx_hash),
+            token_hash: tx_hash_from_slice(&change.token_hash),
+            kind: num::FromPrimitive::from_i32(change.kind)?,
+            amount: SLPAmount::from_numeric_decimals(&change.amount_base, decimals),
+            timestamp: change.timestamp,
+        })
+    }
+}
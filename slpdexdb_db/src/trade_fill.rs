@@ -0,0 +1,163 @@
+use rug::Rational;
+use serde::Deserialize;
+use slpdexdb_base::SLPAmount;
+use slpdexdb_base::convert_numeric::pg_numeric_to_rational;
+use crate::models;
+use crate::data::tx_hash_from_slice;
+
+/// How the contract output of a trade offer was spent.
+#[derive(Copy, Clone, Debug, FromPrimitive, Eq, PartialEq)]
+pub enum TradeSpendKind {
+    /// Taken by a buyer, who paid the asking price to the receiving address
+    Fill = 0,
+    /// Spent by the maker, or taken without paying the asking price
+    Cancel = 1,
+}
+
+#[derive(Clone, Debug)]
+pub struct TradeSpend {
+    pub offer_tx: [u8; 32],
+    pub offer_output_idx: i32,
+    pub spending_tx: [u8; 32],
+    pub token_hash: [u8; 32],
+    pub kind: TradeSpendKind,
+    pub price_per_token: Rational,
+    pub amount: SLPAmount,
+    pub timestamp: i64,
+}
+
+impl TradeSpend {
+    pub fn from_model(spend: models::TradeOfferSpend, decimals: u32) -> Option<Self> {
+        Some(TradeSpend {
+            offer_tx: tx_hash_from_slice(&spend.offer_tx),
+            offer_output_idx: spend.offer_output_idx,
+            spending_tx: tx_hash_from_slice(&spend.spending_tx),
+            token_hash: tx_hash_from_slice(&spend.token_hash),
+            kind: num::FromPrimitive::from_i32(spend.kind)?,
+            price_per_token: pg_numeric_to_rational(&spend.price_per_token).ok()?,
+            amount: SLPAmount::from_numeric_decimals(&spend.amount_token_base, decimals),
+            timestamp: spend.timestamp,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum CandleInterval {
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "4h")]
+    FourHours,
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+impl Default for CandleInterval {
+    fn default() -> Self {
+        CandleInterval::OneHour
+    }
+}
+
+impl CandleInterval {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            CandleInterval::FiveMinutes => 5 * 60,
+            CandleInterval::OneHour => 60 * 60,
+            CandleInterval::FourHours => 4 * 60 * 60,
+            CandleInterval::OneDay => 24 * 60 * 60,
+        }
+    }
+}
+
+/// Prices of the fills within `[start_timestamp, start_timestamp + interval)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candle {
+    pub start_timestamp: i64,
+    pub open: Rational,
+    pub high: Rational,
+    pub low: Rational,
+    pub close: Rational,
+    pub volume: SLPAmount,
+    pub num_fills: i64,
+}
+
+/// Candles of the fills of a single token, in ascending order. Intervals without fills are
+/// omitted; cancels are ignored.
+pub fn candles(fills: &[TradeSpend], interval: CandleInterval) -> Vec<Candle> {
+    let mut fills = fills.iter()
+        .filter(|fill| fill.kind == TradeSpendKind::Fill)
+        .collect::<Vec<_>>();
+    fills.sort_by_key(|fill| fill.timestamp);
+    let mut candles: Vec<Candle> = Vec::new();
+    for fill in fills {
+        let start_timestamp = fill.timestamp - fill.timestamp % interval.seconds();
+        match candles.last_mut() {
+            Some(candle) if candle.start_timestamp == start_timestamp => {
+                if fill.price_per_token > candle.high {
+                    candle.high = fill.price_per_token.clone();
+                }
+                if fill.price_per_token < candle.low {
+                    candle.low = fill.price_per_token.clone();
+                }
+                candle.close = fill.price_per_token.clone();
+                candle.volume += fill.amount;
+                candle.num_fills += 1;
+            },
+            _ => candles.push(Candle {
+                start_timestamp,
+                open: fill.price_per_token.clone(),
+                high: fill.price_per_token.clone(),
+                low: fill.price_per_token.clone(),
+                close: fill.price_per_token.clone(),
+                volume: fill.amount,
+                num_fills: 1,
+            }),
+        }
+    }
+    candles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spend(kind: TradeSpendKind, price: u64, amount: i128, timestamp: i64) -> TradeSpend {
+        TradeSpend {
+            offer_tx: [0; 32],
+            offer_output_idx: 1,
+            spending_tx: [1; 32],
+            token_hash: [2; 32],
+            kind,
+            price_per_token: Rational::from(price),
+            amount: SLPAmount::new(amount, 2),
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn hourly_candles() {
+        use TradeSpendKind::*;
+        let spends = vec![
+            spend(Fill, 12, 100, 3_700),
+            spend(Fill, 10, 50, 3_600),
+            spend(Cancel, 1, 500, 4_000),
+            spend(Fill, 15, 10, 5_000),
+            spend(Fill, 11, 20, 7_199),
+            spend(Fill, 9, 5, 10_900),
+        ];
+        let candles = candles(&spends, CandleInterval::OneHour);
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0], Candle {
+            start_timestamp: 3_600,
+            open: Rational::from(10),
+            high: Rational::from(15),
+            low: Rational::from(10),
+            close: Rational::from(11),
+            volume: SLPAmount::new(180, 2),
+            num_fills: 4,
+        });
+        assert_eq!(candles[1].start_timestamp, 10_800);
+        assert_eq!(candles[1].open, candles[1].close);
+    }
+}
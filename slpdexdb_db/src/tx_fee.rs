@@ -0,0 +1,29 @@
+use cashcontracts::UnsignedTx;
+
+/// DER signature of maximum length.
+const MAX_SIGNATURE_SIZE: usize = 72;
+const PUB_KEY_SIZE: usize = 33;
+
+/// Expected size and fee of a tx before it is signed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FeeEstimate {
+    pub size: usize,
+    pub fee: u64,
+}
+
+/// Estimates by signing all (P2PKH) inputs with placeholder signatures of maximum length, so the
+/// actual tx is at most as large as estimated.
+pub fn estimate_fee(tx_build: UnsignedTx, input_total: u64) -> FeeEstimate {
+    let n_inputs = tx_build.pre_images(0x41).len();
+    let tx = tx_build.sign(
+        (0..n_inputs).map(|_| vec![0; MAX_SIGNATURE_SIZE]).collect(),
+        (0..n_inputs).map(|_| vec![0; PUB_KEY_SIZE]).collect(),
+    );
+    let mut tx_ser = Vec::new();
+    tx.write_to_stream(&mut tx_ser).unwrap();
+    let output_total = tx.outputs().iter().map(|output| output.value).sum::<u64>();
+    FeeEstimate {
+        size: tx_ser.len(),
+        fee: input_total - output_total,
+    }
+}
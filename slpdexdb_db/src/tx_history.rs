@@ -1,21 +1,50 @@
-use crate::tx_source::{tx_result, TxSource, TxFilter, Confirmedness};
-use slpdexdb_base::{SLPDEXConfig, SLPAmount, Result, Error, ErrorKind, SLPError, TokenError, TradeOfferError, PNDError};
+use crate::tx_source::{tx_result, TxFilter, Confirmedness};
+use crate::chain_source::ChainSource;
+use slpdexdb_base::{SLPDEXConfig, SLPAmount, Result, Error, ErrorKind, TokenError, TradeOfferError, PNDError};
 use crate::token::Token;
 use crate::db::Db;
-use crate::data::{tx_hash_from_slice, tx_hash_from_le_slice};
-use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use crate::models;
+use crate::data::{SpentUtxo, tx_hash_from_slice, tx_hash_from_le_slice, address_hash_from_slice};
+use byteorder::{LittleEndian, ReadBytesExt};
 use std::io;
 use std::collections::{HashSet, HashMap};
 use cashcontracts::{Output, P2PKHOutput, AddressType, Address, tx_hash_to_hex, tx_hex_to_hash, single_sha256, double_sha256};
 use rug::Rational;
 use crate::panda_tools::get_panda_by_owner_utxo;
+use crate::signature::SignatureScheme;
+use crate::slp_validator::{SlpValidator, parse_slp_message};
+use crate::panda::{PND1_VERSION, PNDS_VERSION, PNDA_VERSION, pnd1_message, pnd1_legacy_message,
+                   sire_offer_message, auction_message};
+use crate::market::DutchAuction;
+use crate::token_supply::{SupplyChange, SupplyChangeKind};
+use crate::trade_fill::{TradeSpend, TradeSpendKind};
+use crate::exch::{ExchVersion, ExchPrice, EXCH_CONTRACT_VOUT};
 
 #[derive(Clone, Debug)]
 pub struct TxHistory {
     pub txs: Vec<HistoricTx>,
     pub trade_offers: HashMap<usize, TradeOffer>,
     pub pnd_txs: HashMap<usize, PND1Tx>,
+    pub sire_offers: HashMap<usize, SireOffer>,
+    pub auctions: HashMap<usize, PandaAuction>,
     pub pandas_slp: HashSet<usize>,
+    /// Baton output of the GENESIS and MINT txs, `None` if they end the baton
+    pub mint_baton_vouts: HashMap<usize, Option<u8>>,
+    /// Batons moved by this history, see `track_mint_batons`
+    pub mint_batons: HashMap<[u8; 32], MintBaton>,
+    /// See `track_token_supply`
+    pub supply_changes: Vec<SupplyChange>,
+    /// See `track_trade_spends`
+    pub trade_spends: Vec<TradeSpend>,
+}
+
+/// Where the mint baton of a token is. `mint_tx` is the GENESIS or MINT which last changed the
+/// supply; `baton_vout` is `None` once the baton has been destroyed.
+#[derive(Clone, Debug)]
+pub struct MintBaton {
+    pub token_hash: [u8; 32],
+    pub mint_tx: [u8; 32],
+    pub baton_vout: Option<i32>,
 }
 
 #[derive(Clone, Debug)]
@@ -86,15 +115,79 @@ pub struct PND1Tx {
     pub mother_output_idx: u32,
     pub public_key: Vec<u8>,
     pub signature: Vec<u8>,
+    pub signature_scheme: SignatureScheme,
+    pub owner_address: Address,
+    pub recipient_address: Address,
+    pub sire_fee: Option<u64>,  // paid to the father's owner if the father was rented
+}
+
+#[derive(Clone, Debug)]
+pub struct SireOffer {
+    pub sire_id: i64,
+    pub sire_tx_id: i64,
+    pub sire_tx_hash: [u8; 32],
+    pub sire_output_idx: u32,
+    pub fee_satoshis: u64,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
     pub owner_address: Address,
 }
 
+#[derive(Clone, Debug)]
+pub struct PandaAuction {
+    pub panda_id: i64,
+    pub panda_tx_id: i64,
+    pub panda_tx_hash: [u8; 32],
+    pub panda_output_idx: u32,
+    pub token_hash: [u8; 32],
+    pub auction: DutchAuction,
+    pub seller_address: Address,
+}
+
+/// Errors of `_listed_panda`, which differ between sire offers and auctions.
+struct _ListingErrors {
+    invalid_hash: fn(String) -> PNDError,
+    invalid_output_idx: fn(String) -> PNDError,
+    doesnt_exist: fn(String) -> PNDError,
+    wrong_token: fn(String) -> PNDError,
+    invalid_utxo: fn(String) -> PNDError,
+    not_owned_by_address: fn(String, String) -> PNDError,
+}
+
+const _SIRE_ERRORS: _ListingErrors = _ListingErrors {
+    invalid_hash: PNDError::InvalidSireHash,
+    invalid_output_idx: PNDError::InvalidSireOutputIdx,
+    doesnt_exist: PNDError::SireDoesntExist,
+    wrong_token: PNDError::WrongSireToken,
+    invalid_utxo: PNDError::InvalidSireUTXO,
+    not_owned_by_address: PNDError::SireNotOwnedByAddress,
+};
+
+const _AUCTION_ERRORS: _ListingErrors = _ListingErrors {
+    invalid_hash: PNDError::InvalidAuctionedHash,
+    invalid_output_idx: PNDError::InvalidAuctionedOutputIdx,
+    doesnt_exist: PNDError::AuctionedPandaDoesntExist,
+    wrong_token: PNDError::WrongAuctionedToken,
+    invalid_utxo: PNDError::InvalidAuctionedUTXO,
+    not_owned_by_address: PNDError::AuctionedPandaNotOwnedByAddress,
+};
+
+struct _ListedPanda {
+    panda_id: i64,
+    tx_id: i64,
+    tx_hash: [u8; 32],
+    output_idx: u32,
+    token_hash: [u8; 32],
+    owner_address: Address,
+}
+
 #[derive(Clone, Debug)]
 pub struct TradeOffer {
     pub tx: [u8; 32],
     pub output_idx: Option<i32>,
     pub input_tx: [u8; 32],
     pub input_idx: i32,
+    pub exch_version: ExchVersion,
     pub price_per_token: Rational,
     pub script_price: i64,
     pub is_inverted: bool,
@@ -131,15 +224,37 @@ impl OutputType {
         }
     }
 
+    pub const UNKNOWN_ID: i32 = 0;
+    pub const OP_RETURN_ID: i32 = 1;
+    pub const P2SH_ID: i32 = 2;
+    pub const P2PKH_ID: i32 = 3;
+    pub const BURNED_ID: i32 = 4;
+
     pub fn id(&self) -> i32 {
         match self {
-            OutputType::Unknown => 0,
-            OutputType::OpReturn => 1,
+            OutputType::Unknown => Self::UNKNOWN_ID,
+            OutputType::OpReturn => Self::OP_RETURN_ID,
             OutputType::Address(addr) => match addr.addr_type() {
-                AddressType::P2SH => 2,
-                AddressType::P2PKH => 3,
+                AddressType::P2SH => Self::P2SH_ID,
+                AddressType::P2PKH => Self::P2PKH_ID,
             },
-            OutputType::Burned => 4,
+            OutputType::Burned => Self::BURNED_ID,
+        }
+    }
+
+    /// Inverse of `id` for outputs stored with their address hash.
+    pub fn from_id(id: i32, address_hash: Option<&[u8]>) -> OutputType {
+        let addr_type = match id {
+            Self::OP_RETURN_ID => return OutputType::OpReturn,
+            Self::BURNED_ID => return OutputType::Burned,
+            Self::P2SH_ID => AddressType::P2SH,
+            Self::P2PKH_ID => AddressType::P2PKH,
+            _ => return OutputType::Unknown,
+        };
+        match address_hash {
+            Some(hash) if hash.len() == 20 =>
+                OutputType::Address(Address::from_bytes(addr_type, address_hash_from_slice(hash))),
+            _ => OutputType::Unknown,
         }
     }
 }
@@ -175,12 +290,30 @@ impl TxHistory {
         }
     }
 
+    /// History of just the txs, for tests of the tracking and validation steps.
+    #[cfg(test)]
+    pub(crate) fn with_txs(txs: Vec<HistoricTx>) -> Self {
+        TxHistory {
+            txs,
+            trade_offers: HashMap::new(),
+            pnd_txs: HashMap::new(),
+            sire_offers: HashMap::new(),
+            auctions: HashMap::new(),
+            pandas_slp: HashSet::new(),
+            mint_baton_vouts: HashMap::new(),
+            mint_batons: HashMap::new(),
+            supply_changes: Vec::new(),
+            trade_spends: Vec::new(),
+        }
+    }
+
     pub fn from_entries(entries: &[tx_result::TxEntry],
                         now: i64,
                         config: &SLPDEXConfig) -> Self {
         let mut historic_txs = Vec::with_capacity(entries.len());
         let mut trade_offers = HashMap::new();
         let mut pnd_txs = HashMap::new();
+        let mut mint_baton_vouts = HashMap::new();
         for entry in entries.iter() {
             let inputs = entry.inputs.iter()
                 .map(|input| {
@@ -230,6 +363,11 @@ impl TxHistory {
                 inputs,
                 outputs,
             };
+            if let TxType::SLP { slp_type: SLPTxType::Genesis, .. } |
+                   TxType::SLP { slp_type: SLPTxType::Mint, .. } = historic_tx.tx_type {
+                let baton_vout = entry.slp.as_ref().and_then(|slp| slp.detail.baton_vout);
+                mint_baton_vouts.insert(historic_txs.len(), baton_vout.map(|vout| vout as u8));
+            }
             entry.slp.as_ref().and_then(|slp| {
                 trade_offers.insert(
                     historic_txs.len(),
@@ -243,26 +381,79 @@ impl TxHistory {
             txs: historic_txs,
             trade_offers,
             pnd_txs,
+            sire_offers: HashMap::new(),
+            auctions: HashMap::new(),
             pandas_slp: HashSet::new(),
+            mint_baton_vouts,
+            mint_batons: HashMap::new(),
+            supply_changes: Vec::new(),
+            trade_spends: Vec::new(),
         }
     }
 
+    /// Address an input spends from, as far as its script tells: P2PKH spends push a signature
+    /// (ECDSA or Schnorr) and a compressed or uncompressed pubkey. Other spends, e.g. P2SH or P2PK,
+    /// can't be told apart by their script alone and are left `Unknown`; the address of all
+    /// inputs is taken from the outputs they spend, see `_resolve_input_addresses`.
     pub fn _process_input_script(script: &cashcontracts::Script) -> OutputType {
         use cashcontracts::{Op, OpCodeType::*};
         let ops = script.ops();
         if ops.len() == 0 { return OutputType::Unknown; }
         if ops[0] == Op::Code(OpReturn) { return OutputType::OpReturn; }
-        if ops.len() != 2 { return OutputType::Unknown }
-        match &ops[1] {
-            Op::Push(pubkey) if pubkey.len() == 33 => {
+        let is_pub_key = |push: &[u8]| match (push.len(), push.get(0)) {
+            (33, Some(0x02)) | (33, Some(0x03)) | (65, Some(0x04)) => true,
+            _ => false,
+        };
+        match &ops[..] {
+            [Op::Push(signature), Op::Push(pub_key)] if signature.len() > 0 && is_pub_key(pub_key) => {
                 OutputType::Address(
-                    Address::from_serialized_pub_key("bitcoincash", AddressType::P2PKH, pubkey)
+                    Address::from_serialized_pub_key("bitcoincash", AddressType::P2PKH, pub_key)
                 )
             },
             _ => OutputType::Unknown
         }
     }
 
+    /// Replaces the script based guess of the input addresses by the address of the outputs they
+    /// spend, if those are in the DB or in this history. Inputs spending other outputs keep
+    /// their guess.
+    fn _resolve_input_addresses(&mut self, db: &Db) -> Result<()> {
+        let input_txs = self.txs.iter()
+            .flat_map(|tx| tx.inputs.iter())
+            .map(|input| input.output_tx)
+            .collect::<HashSet<_>>();
+        if input_txs.len() == 0 {
+            return Ok(());
+        }
+        let spent_outputs = db.tx_outputs(input_txs.into_iter())?
+            .into_iter()
+            .map(|(outpoint, output)| {
+                let address_hash = output.address.as_ref().map(Vec::as_slice);
+                (outpoint, OutputType::from_id(output.output_type, address_hash))
+            })
+            .collect();
+        self._set_input_addresses(spent_outputs);
+        Ok(())
+    }
+
+    fn _set_input_addresses(&mut self, mut spent_outputs: HashMap<([u8; 32], i32), OutputType>) {
+        for tx in self.txs.iter() {
+            for (output_idx, output) in tx.outputs.iter().enumerate() {
+                spent_outputs.insert((tx.hash, output_idx as i32), output.output.clone());
+            }
+        }
+        for tx in self.txs.iter_mut() {
+            for input in tx.inputs.iter_mut() {
+                if let Some(spent_output) = spent_outputs.get(&(input.output_tx, input.output_idx)) {
+                    input.output = match spent_output {
+                        OutputType::Address(_) => spent_output.clone(),
+                        _ => OutputType::Unknown,
+                    };
+                }
+            }
+        }
+    }
+
     pub fn _process_output_script(script: &cashcontracts::Script) -> OutputType {
         use cashcontracts::{Op::*, OpCodeType::*};
         match script.ops() {
@@ -281,107 +472,40 @@ impl TxHistory {
         }
     }
 
+    /// Also returns the mint baton output of GENESIS and MINT txs.
     pub fn _process_slp_output(tx: &cashcontracts::Tx, db: &Db)
-            -> Result<Option<(TxType, Vec<SLPAmount>, Token)>> {
-        use cashcontracts::{Op::*, OpCodeType::*, serialize};
-        let script = tx.outputs()[0].script();
-        let script_hex = || hex::encode(script.to_vec());
-        let ops = script.ops();
-        match ops.get(1) {
-            Some(Push(lokad_id)) if lokad_id == b"SLP\0" => {},
-            _ => return Ok(None),
-        }
-        if ops.len() < 6 {
-            return Err(ErrorKind::InvalidSLPOutput(script_hex(),
-                                                   SLPError::TooFewPushops(ops.len())).into());
-        }
-        if !script.is_slp_safe() {
-            return Err(ErrorKind::InvalidSLPOutput(script_hex(),
-                                                   SLPError::NotSLPSafe).into());
-        }
-        match &ops {
-            &[Code(OpReturn), Push(_), Push(token_type), Push(tx_type), Push(token_ticker),
-              Push(token_name), Push(token_document_url), Push(token_document_hash), Push(decimals),
-              Push(mint_baton_vout), Push(initial_token_mint_quantity)] if tx_type == b"GENESIS" => {
-                let decimals = decimals[0];
-                return Ok(Some((
-                    TxType::SLP {
-                        slp_type: SLPTxType::Genesis,
-                        token_type: num::traits::FromPrimitive::from_u8(token_type[0]).unwrap(),
-                        token_hash: tx.hash(),
-                    },
-                    vec![SLPAmount::new(
-                        io::Cursor::new(&initial_token_mint_quantity).read_u64::<BigEndian>()? as i128,
-                        decimals as u32,
-                    )],
-                    Self::_fetch_token(&tx.hash(), db)?,
-                )))
-            },
-            _ => {},
-        }
-        match (&ops[0], &ops[1], &ops[2], &ops[3], &ops[4]) {
-            (Code(OpReturn), Push(_), Push(token_type), Push(tx_type), Push(token_id))
-                    if tx_type == b"SEND" => {
-                if token_type.len() > 2 || token_type.len() == 0 {
-                    return Err(ErrorKind::InvalidSLPOutput(
-                        script_hex(),
-                        SLPError::InvalidTokenTypeLength(hex::encode(token_type)),
-                    ).into());
-                }
-                if token_id.len() != 32 {
-                    return Err(ErrorKind::InvalidSLPOutput(
-                        script_hex(),
-                        SLPError::InvalidTokenHashLength(hex::encode(token_id))
-                    ).into())
-                }
-                let mut token_hash = [0; 32];
-                token_hash.copy_from_slice(&token_id.iter().rev().cloned().collect::<Vec<_>>());
-                let token = Self::_fetch_token(&token_hash, db)?;
-                let decimals = token.decimals as u32;
-                let token_type = serialize::vec_to_int(token_type);
-                let amounts = ops[5..].iter()
-                    .map(|op| {
-                        match op {
-                            Push(vec) => Ok(SLPAmount::from_slice(&vec, decimals)?),
-                            _ => unreachable!(),  // handled by is_slp_safe
-                        }
-                    })
-                    .collect::<Result<Vec<_>>>()?;
-                if amounts.len() > 19 {
-                    return Err(ErrorKind::InvalidSLPOutput(
-                        hex::encode(script.to_vec()),
-                        SLPError::TooManyAmounts(amounts.len()),
-                    ).into())
-                }
-                if let Some(token_type) = num::traits::FromPrimitive::from_u16(token_type as u16) {
-                    Ok(Some((
-                        TxType::SLP {
-                            slp_type: SLPTxType::from_bytes(tx_type)
-                                .ok_or_else(|| ErrorKind::InvalidSLPOutput(
-                                    script_hex(),
-                                    SLPError::InvalidSLPType(
-                                        format!(
-                                            "{} ({})",
-                                            String::from_utf8_lossy(tx_type),
-                                            hex::encode(tx_type),
-                                        )
-                                    )
-                                ))?,
-                            token_type,
-                            token_hash,
-                        },
-                        amounts,
-                        token,
-                    )))
-                } else {
-                    Ok(None)
-                }
+            -> Result<Option<(TxType, Vec<SLPAmount>, Token, Option<u8>)>> {
+        let message = match parse_slp_message(&tx.outputs()[0].script())? {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+        let token_hash = message.token_hash_of(&tx.hash());
+        let token = match (db.token(&token_hash)?, &message.slp_type) {
+            (Some(token), _) => token,
+            // the DB can't know new tokens yet, but NFT1 children still have to be validated
+            (None, SLPTxType::Genesis) => Token::from_genesis(token_hash, &message),
+            (None, _) => Self::_fetch_token(&token_hash, db)?,
+        };
+        let decimals = message.decimals.map(u32::from).unwrap_or(token.decimals as u32);
+        let amounts = message.amounts.iter()
+            .map(|&amount| SLPAmount::new(amount as i128, decimals))
+            .collect();
+        Ok(Some((
+            TxType::SLP {
+                slp_type: message.slp_type,
+                token_type: message.token_type,
+                token_hash,
             },
-            _ => { Err(ErrorKind::InvalidSLPOutput(script_hex(), SLPError::NoMatch).into()) }
-        }
+            amounts,
+            token,
+            message.mint_baton_vout,
+        )))
     }
 
-    pub fn _process_pnd1_tx(tx: &cashcontracts::Tx, db: &Db, config: &SLPDEXConfig) -> Result<Option<PND1Tx>> {
+    /// `tx` is unconfirmed, see `from_txs`.
+    pub fn _process_pnd1_tx(tx: &cashcontracts::Tx,
+                            db: &Db,
+                            config: &SLPDEXConfig) -> Result<Option<PND1Tx>> {
         use cashcontracts::{Op::*, OpCodeType::*, serialize};
         if tx.outputs().len() < 2 {
             return Ok(None);
@@ -392,6 +516,43 @@ impl TxHistory {
             Some(Push(lokad_id)) if lokad_id == b"PND1" => {},
             _ => return Ok(None),
         }
+        // Versioned PND1 txs carry a version push directly after the lokad id and may end with
+        // the recipient of the baby
+        let (version, recipient, unversioned_ops) = match (ops.len(), ops.get(2)) {
+            (10, Some(Push(version))) | (11, Some(Push(version))) => {
+                if version.as_slice() != [PND1_VERSION] {
+                    return Err(ErrorKind::InvalidPND(
+                        PNDError::UnsupportedVersion(hex::encode(version))
+                    ).into());
+                }
+                let recipient = match ops.get(10) {
+                    Some(Push(recipient)) => Some(
+                        Address::from_slice(AddressType::P2PKH, recipient)
+                            .ok_or_else(|| -> Error {
+                                ErrorKind::InvalidPND(
+                                    PNDError::InvalidRecipientAddress(hex::encode(recipient))
+                                ).into()
+                            })?
+                    ),
+                    Some(_) => return Err(ErrorKind::InvalidPND(
+                        PNDError::InvalidNumberOfPushops(ops.len() - 1)
+                    ).into()),
+                    None => None,
+                };
+                (Some(PND1_VERSION), recipient, [&ops[..2], &ops[3..10]].concat())
+            },
+            _ => (None, None, ops.to_vec()),
+        };
+        let ops: &[cashcontracts::Op] = &unversioned_ops;
+        if version.is_none() {
+            // unconfirmed txs end up in the next block at the earliest
+            let tx_height = db.header_tip()?.map(|(_, height)| height + 1).unwrap_or(0);
+            if tx_height >= config.pnd1_legacy_cutoff_height {
+                return Err(ErrorKind::InvalidPND(
+                    PNDError::LegacyMessageExpired(tx_height)
+                ).into());
+            }
+        }
         let fee_output = &tx.outputs()[1];
         if fee_output.value < config.panda_fee {
             return Err(ErrorKind::InvalidPND(PNDError::DoesntPayPandaFee).into())
@@ -496,41 +657,51 @@ impl TxHistory {
                         ).into()
                     })?;
                 let address = Address::from_serialized_pub_key("simpleledger", AddressType::P2PKH, &pubkey);
-                if father_output.address != Some(address.bytes().to_vec()) {
-                    return Err(ErrorKind::InvalidPND(
-                        PNDError::FatherNotOwnedByAddress(hex::encode(father_hash), address.cash_addr().to_string())
-                    ).into());
-                }
+                let recipient = recipient.unwrap_or_else(|| address.clone());
+                // A father owned by someone else can be bred with if it is offered as a sire and
+                // the tx pays the sire fee to its owner
+                let sire_fee = if father_output.address != Some(address.bytes().to_vec()) {
+                    let sire_offer = db.sire_offer(father.id, father.owner_tx, father.owner_tx_idx)?
+                        .ok_or_else(|| -> Error {
+                            ErrorKind::InvalidPND(
+                                PNDError::FatherNotOwnedByAddress(hex::encode(father_hash), address.cash_addr().to_string())
+                            ).into()
+                        })?;
+                    let sire_owner_script = P2PKHOutput {
+                        value: 0,
+                        address: Address::from_bytes(
+                            AddressType::P2PKH,
+                            address_hash_from_slice(&sire_offer.owner_address),
+                        ),
+                    }.script();
+                    let fee_satoshis = sire_offer.fee_satoshis as u64;
+                    let pays_sire_fee = tx.outputs().iter().skip(2).any(|output| {
+                        output.value >= fee_satoshis && output.script.ops() == sire_owner_script.ops()
+                    });
+                    if !pays_sire_fee {
+                        return Err(ErrorKind::InvalidPND(PNDError::DoesntPaySireFee(fee_satoshis)).into());
+                    }
+                    Some(fee_satoshis)
+                } else {
+                    None
+                };
                 if mother_output.address != Some(address.bytes().to_vec()) {
                     return Err(ErrorKind::InvalidPND(
                         PNDError::MotherNotOwnedByAddress(hex::encode(mother_hash), address.cash_addr().to_string())
                     ).into());
                 }
-                let pubkey_decoded = secp256k1::PublicKey::from_slice(pubkey)
-                    .map_err(|_| -> Error {
-                        ErrorKind::InvalidPND(
-                            PNDError::InvalidPubkey(hex::encode(pubkey))
-                        ).into()
-                    })?;
-                let signature_decoded = secp256k1::Signature::from_der(signature)
-                    .map_err(|_| -> Error {
-                        ErrorKind::InvalidPND(
-                            PNDError::InvalidSignature(hex::encode(signature))
-                        ).into()
-                    })?;
-                let message = format!("PANDA S3X:{}+{}",
-                                      tx_hash_to_hex(&tx_hash_from_slice(&father_token.hash)),
-                                      tx_hash_to_hex(&tx_hash_from_slice(&mother_token.hash)));
-                let message_hash = secp256k1::Message::from_slice(&single_sha256(message.as_bytes())).unwrap();
-                let curve = secp256k1::Secp256k1::new();
-                curve.verify(&message_hash, &signature_decoded, &pubkey_decoded)
-                    .map_err(|_| -> Error {
-                        ErrorKind::InvalidPND(
-                            PNDError::SignatureVerifyFail(message,
-                                                          hex::encode(signature),
-                                                          hex::encode(pubkey))
-                        ).into()
-                    })?;
+                let father_token_hash = tx_hash_from_slice(&father_token.hash);
+                let mother_token_hash = tx_hash_from_slice(&mother_token.hash);
+                let message = match version {
+                    Some(_) => pnd1_message(config,
+                                            &father_token_hash,
+                                            (&father_hash, father_output_idx as u32),
+                                            &mother_token_hash,
+                                            (&mother_hash, mother_output_idx as u32),
+                                            &recipient),
+                    None => pnd1_legacy_message(&father_token_hash, &mother_token_hash),
+                };
+                let signature_scheme = Self::_verify_pnd_signature(pubkey, signature, message)?;
                 return Ok(Some(PND1Tx {
                     father_id: father.id,
                     mother_id: mother.id,
@@ -541,7 +712,10 @@ impl TxHistory {
                     mother_output_idx: mother_output_idx as u32,
                     public_key: pubkey.to_vec(),
                     signature: signature.to_vec(),
+                    signature_scheme,
                     owner_address: address,
+                    recipient_address: recipient,
+                    sire_fee,
                 }))
             },
             _ => {
@@ -551,11 +725,242 @@ impl TxHistory {
         Ok(None)
     }
 
-    pub fn from_txs(txs: &[cashcontracts::Tx], now: i64, config: &SLPDEXConfig, db: &Db) -> Self {
+    fn _listed_panda(tx_hash: &[u8],
+                     output_idx: &[u8],
+                     pubkey: &[u8],
+                     errors: &_ListingErrors,
+                     db: &Db,
+                     config: &SLPDEXConfig) -> Result<_ListedPanda> {
+        let invalid = |err: PNDError| -> Error { ErrorKind::InvalidPND(err).into() };
+        if tx_hash.len() != 32 {
+            return Err(invalid((errors.invalid_hash)(hex::encode(tx_hash))));
+        }
+        let tx_hash = tx_hash_from_le_slice(tx_hash);
+        let output_idx = io::Cursor::new(output_idx).read_i32::<LittleEndian>()
+            .map_err(|_| invalid((errors.invalid_output_idx)(hex::encode(output_idx))))?;
+        let mut slp_txs = db.slp_txs(vec![tx_hash.clone()].into_iter())?;
+        let (panda_tx, _, token) = slp_txs.remove(&tx_hash)
+            .ok_or_else(|| invalid((errors.doesnt_exist)(hex::encode(tx_hash))))?;
+        if token.parent_token_hash != Some(config.panda_token_hash.to_vec()) {
+            return Err(invalid((errors.wrong_token)(
+                format!("{:?}", token.parent_token_hash
+                    .map(|token| tx_hash_to_hex(&tx_hash_from_slice(&token))))
+            )));
+        }
+        let mut tx_outputs = db.tx_outputs(vec![tx_hash.clone()].into_iter())?;
+        let output = tx_outputs.remove(&(tx_hash, output_idx))
+            .ok_or_else(|| {
+                invalid((errors.doesnt_exist)(format!("{}:{}", hex::encode(tx_hash), output_idx)))
+            })?;
+        let panda = get_panda_by_owner_utxo(panda_tx.id, output_idx, db.connection())?
+            .ok_or_else(|| {
+                invalid((errors.invalid_utxo)(format!("{}:{}", hex::encode(tx_hash), output_idx)))
+            })?;
+        let address = Address::from_serialized_pub_key("simpleledger", AddressType::P2PKH, pubkey);
+        if output.address != Some(address.bytes().to_vec()) {
+            return Err(invalid((errors.not_owned_by_address)(
+                hex::encode(tx_hash),
+                address.cash_addr().to_string(),
+            )));
+        }
+        Ok(_ListedPanda {
+            panda_id: panda.id,
+            tx_id: panda_tx.id,
+            tx_hash,
+            output_idx: output_idx as u32,
+            token_hash: tx_hash_from_slice(&token.hash),
+            owner_address: address,
+        })
+    }
+
+    fn _verify_pnd_signature(pubkey: &[u8], signature: &[u8], message: String) -> Result<SignatureScheme> {
+        let pubkey_decoded = secp256k1::PublicKey::from_slice(pubkey)
+            .map_err(|_| -> Error {
+                ErrorKind::InvalidPND(
+                    PNDError::InvalidPubkey(hex::encode(pubkey))
+                ).into()
+            })?;
+        let signature_scheme = SignatureScheme::detect(signature);
+        if !signature_scheme.is_well_formed(signature) {
+            return Err(ErrorKind::InvalidPND(
+                PNDError::InvalidSignature(hex::encode(signature))
+            ).into());
+        }
+        let message_hash = single_sha256(message.as_bytes());
+        if !signature_scheme.verify(&pubkey_decoded, &message_hash, signature) {
+            return Err(ErrorKind::InvalidPND(
+                PNDError::SignatureVerifyFail(message,
+                                              hex::encode(signature),
+                                              hex::encode(pubkey))
+            ).into());
+        }
+        Ok(signature_scheme)
+    }
+
+    fn _read_u64_push(push: &[u8], err: fn(String) -> PNDError) -> Result<u64> {
+        io::Cursor::new(push).read_u64::<LittleEndian>()
+            .map_err(|_| ErrorKind::InvalidPND(err(hex::encode(push))).into())
+    }
+
+    fn _read_u32_push(push: &[u8], err: fn(String) -> PNDError) -> Result<u32> {
+        io::Cursor::new(push).read_u32::<LittleEndian>()
+            .map_err(|_| ErrorKind::InvalidPND(err(hex::encode(push))).into())
+    }
+
+    pub fn _process_pnds_tx(tx: &cashcontracts::Tx, db: &Db, config: &SLPDEXConfig) -> Result<Option<SireOffer>> {
+        use cashcontracts::{Op::*, OpCodeType::*};
+        if tx.outputs().len() < 1 {
+            return Ok(None);
+        }
+        let op_return_script = tx.outputs()[0].script();
+        let ops = op_return_script.ops();
+        match ops.get(1) {
+            Some(Push(lokad_id)) if lokad_id == b"PNDS" => {},
+            _ => return Ok(None),
+        }
+        match ops {
+            &[Code(OpReturn), Push(_), Push(ref version), Push(ref sire_hash), Push(ref sire_output_idx),
+                   Push(ref fee_satoshis), Push(ref pubkey), Push(ref signature)] => {
+                if version.as_slice() != [PNDS_VERSION] {
+                    return Err(ErrorKind::InvalidPND(
+                        PNDError::UnsupportedVersion(hex::encode(version))
+                    ).into());
+                }
+                let fee_satoshis = Self::_read_u64_push(fee_satoshis, PNDError::InvalidSireFee)?;
+                let sire = Self::_listed_panda(sire_hash, sire_output_idx, pubkey, &_SIRE_ERRORS, db, config)?;
+                let message = sire_offer_message(config,
+                                                 &sire.token_hash,
+                                                 (&sire.tx_hash, sire.output_idx),
+                                                 fee_satoshis);
+                Self::_verify_pnd_signature(pubkey, signature, message)?;
+                Ok(Some(SireOffer {
+                    sire_id: sire.panda_id,
+                    sire_tx_id: sire.tx_id,
+                    sire_tx_hash: sire.tx_hash,
+                    sire_output_idx: sire.output_idx,
+                    fee_satoshis,
+                    public_key: pubkey.to_vec(),
+                    signature: signature.to_vec(),
+                    owner_address: sire.owner_address,
+                }))
+            },
+            _ => Err(ErrorKind::InvalidPND(PNDError::InvalidNumberOfPushops(ops.len() - 1)).into()),
+        }
+    }
+
+    pub fn _process_pnda_tx(tx: &cashcontracts::Tx, db: &Db, config: &SLPDEXConfig) -> Result<Option<PandaAuction>> {
+        use cashcontracts::{Op::*, OpCodeType::*};
+        if tx.outputs().len() < 1 {
+            return Ok(None);
+        }
+        let op_return_script = tx.outputs()[0].script();
+        let ops = op_return_script.ops();
+        match ops.get(1) {
+            Some(Push(lokad_id)) if lokad_id == b"PNDA" => {},
+            _ => return Ok(None),
+        }
+        match ops {
+            &[Code(OpReturn), Push(_), Push(ref version), Push(ref panda_hash), Push(ref panda_output_idx),
+                   Push(ref start_price), Push(ref end_price), Push(ref start_height),
+                   Push(ref duration_blocks), Push(ref pubkey), Push(ref signature)] => {
+                if version.as_slice() != [PNDA_VERSION] {
+                    return Err(ErrorKind::InvalidPND(
+                        PNDError::UnsupportedVersion(hex::encode(version))
+                    ).into());
+                }
+                let start_price = Self::_read_u64_push(start_price, PNDError::InvalidAuctionPrice)?;
+                let end_price = Self::_read_u64_push(end_price, PNDError::InvalidAuctionPrice)?;
+                let start_height = Self::_read_u32_push(start_height, PNDError::InvalidAuctionHeight)?;
+                let duration_blocks = Self::_read_u32_push(duration_blocks, PNDError::InvalidAuctionDuration)?;
+                if end_price > start_price {
+                    return Err(ErrorKind::InvalidPND(
+                        PNDError::InvalidAuctionPrice(format!("{} > {}", end_price, start_price))
+                    ).into());
+                }
+                // heights are i32 in the DB and `DutchAuction`, so the auction has to end below
+                // i32::MAX
+                if start_height > i32::MAX as u32 {
+                    return Err(ErrorKind::InvalidPND(
+                        PNDError::InvalidAuctionHeight(start_height.to_string())
+                    ).into());
+                }
+                let ends_in_range = start_height.checked_add(duration_blocks)
+                    .map(|end_height| end_height <= i32::MAX as u32)
+                    .unwrap_or(false);
+                if duration_blocks == 0 || !ends_in_range {
+                    return Err(ErrorKind::InvalidPND(
+                        PNDError::InvalidAuctionDuration(duration_blocks.to_string())
+                    ).into());
+                }
+                let panda = Self::_listed_panda(panda_hash, panda_output_idx, pubkey, &_AUCTION_ERRORS,
+                                                db, config)?;
+                let auction = DutchAuction {
+                    start_price,
+                    end_price,
+                    start_height: start_height as i32,
+                    duration_blocks: duration_blocks as i32,
+                };
+                let message = auction_message(config,
+                                              &panda.token_hash,
+                                              (&panda.tx_hash, panda.output_idx),
+                                              &auction);
+                Self::_verify_pnd_signature(pubkey, signature, message)?;
+                Ok(Some(PandaAuction {
+                    panda_id: panda.panda_id,
+                    panda_tx_id: panda.tx_id,
+                    panda_tx_hash: panda.tx_hash,
+                    panda_output_idx: panda.output_idx,
+                    token_hash: panda.token_hash,
+                    auction,
+                    seller_address: panda.owner_address,
+                }))
+            },
+            _ => Err(ErrorKind::InvalidPND(PNDError::InvalidNumberOfPushops(ops.len() - 1)).into()),
+        }
+    }
+
+    /// Outputs of the tx with their token amounts. Amount i belongs to output i + 1, amounts
+    /// without an output are burned and get an extra `Burned` output each.
+    fn _historic_outputs(tx: &cashcontracts::Tx,
+                         slp_amounts: &[SLPAmount],
+                         decimals: u32) -> Vec<HistoricTxOutput> {
+        tx.outputs().iter().enumerate()
+            .map(|(output_idx, output)| {
+                HistoricTxOutput {
+                    value_satoshis: output.value,
+                    value_token: if output_idx > 0 {
+                        slp_amounts.get(output_idx - 1).cloned()
+                    } else {
+                        None
+                    }.unwrap_or(SLPAmount::new(0, decimals)),
+                    output: Self::_process_output_script(&output.script),
+                }
+            })
+            .chain(
+                slp_amounts.iter().skip(tx.outputs().len().saturating_sub(1)).map(|amount| {
+                    HistoricTxOutput {
+                        value_satoshis: 0,
+                        value_token: *amount,
+                        output: OutputType::Burned,
+                    }
+                })
+            )
+            .collect()
+    }
+
+    /// History of unconfirmed txs, i.e. relayed by peers, submitted by clients or built by us.
+    /// Confirmed txs come from the chain source, see `from_entries`.
+    pub fn from_txs(txs: &[cashcontracts::Tx],
+                    now: i64,
+                    config: &SLPDEXConfig,
+                    db: &Db) -> Self {
         let mut historic_txs = Vec::new();
         let mut trade_offers = HashMap::new();
         let mut pnd_txs = HashMap::new();
+        let mut sire_offers = HashMap::new();
+        let mut auctions = HashMap::new();
         let mut pandas_slp = HashSet::new();
+        let mut mint_baton_vouts = HashMap::new();
         for tx in txs.iter() {
             let inputs = tx.inputs().iter()
                 .map(|input| {
@@ -566,7 +971,7 @@ impl TxHistory {
                     }
                 })
                 .collect::<Vec<_>>();
-            let (tx_type, slp_amounts, token) = tx.outputs()
+            let (tx_type, slp_amounts, token, mint_baton_vout) = tx.outputs()
                 .get(0)
                 .and_then(|output| {
                     match Self::_process_slp_output(tx, db) {
@@ -577,35 +982,12 @@ impl TxHistory {
                         },
                     }
                 })
-                .map(|(tx_type, slp_amounts, token)| {
-                    (tx_type, slp_amounts, Some(token))
+                .map(|(tx_type, slp_amounts, token, mint_baton_vout)| {
+                    (tx_type, slp_amounts, Some(token), mint_baton_vout)
                 })
-                .unwrap_or((TxType::Default, vec![], None));
-            let outputs = tx.outputs().iter().enumerate()
-                .map(|(output_idx, output)| {
-                    HistoricTxOutput {
-                        value_satoshis: output.value,
-                        value_token: if output_idx > 0 {
-                            slp_amounts.get(output_idx - 1).cloned()
-                        } else {
-                            None
-                        }.unwrap_or(SLPAmount::new(
-                            0,
-                            token.as_ref().map(|token| token.decimals as u32).unwrap_or(0),
-                        )),
-                        output: Self::_process_output_script(&output.script),
-                    }
-                })
-                .chain(
-                    slp_amounts.iter().skip(tx.outputs().len()).map(|amount| {
-                        HistoricTxOutput {
-                            value_satoshis: 0,
-                            value_token: *amount,
-                            output: OutputType::Burned,
-                        }
-                    })
-                )
-                .collect::<Vec<_>>();
+                .unwrap_or((TxType::Default, vec![], None, None));
+            let decimals = token.as_ref().map(|token| token.decimals as u32).unwrap_or(0);
+            let outputs = Self::_historic_outputs(tx, &slp_amounts, decimals);
             let historic_tx = HistoricTx {
                 hash: tx.hash(),
                 height: None,
@@ -630,17 +1012,44 @@ impl TxHistory {
                 Ok(None) => {},
                 Err(err) => eprintln!("PND error: {}", err),
             };
+            match Self::_process_pnds_tx(tx, db, config) {
+                Ok(Some(sire_offer)) => {sire_offers.insert(historic_txs.len(), sire_offer);},
+                Ok(None) => {},
+                Err(err) => eprintln!("PNDS error: {}", err),
+            };
+            match Self::_process_pnda_tx(tx, db, config) {
+                Ok(Some(auction)) => {auctions.insert(historic_txs.len(), auction);},
+                Ok(None) => {},
+                Err(err) => eprintln!("PNDA error: {}", err),
+            };
             if let Some(trade_offer) = trade_offer {
                 trade_offers.insert(historic_txs.len(), trade_offer);
             }
+            match &historic_tx.tx_type {
+                TxType::SLP { slp_type: SLPTxType::Genesis, .. } |
+                TxType::SLP { slp_type: SLPTxType::Mint, .. } => {
+                    mint_baton_vouts.insert(historic_txs.len(), mint_baton_vout);
+                },
+                _ => {},
+            }
             historic_txs.push(historic_tx);
         }
-        TxHistory {
+        let mut history = TxHistory {
             txs: historic_txs,
             trade_offers,
             pnd_txs,
+            sire_offers,
+            auctions,
             pandas_slp,
+            mint_baton_vouts,
+            mint_batons: HashMap::new(),
+            supply_changes: Vec::new(),
+            trade_spends: Vec::new(),
+        };
+        if let Err(err) = history._resolve_input_addresses(db) {
+            eprintln!("Resolving input addresses failed: {}", err);
         }
+        history
     }
 
     pub fn _fetch_token(token_hash: &[u8; 32], db: &Db) -> Result<Token> {
@@ -670,8 +1079,8 @@ impl TxHistory {
         }
     }
 
-    pub fn validate_slp(&mut self, tx_source: &TxSource, db: &Db, config: &SLPDEXConfig)
-                        -> Result<()> {
+    pub fn validate_slp(&mut self, chain_source: &dyn ChainSource, db: &Db,
+                        config: &SLPDEXConfig) -> Result<()> {
         let tx_to_check = self.txs.iter()
             .flat_map(|tx| {
                 tx.inputs.iter()
@@ -719,19 +1128,406 @@ impl TxHistory {
             println!("input sum: {}", input_sum);
             println!("output sum: {}", output_sum);
             if input_sum < output_sum {
-                tx.tx_type = TxType::Default;
-                tx.outputs.iter_mut().for_each(|output| {
-                    output.value_token = SLPAmount::new(0, 0);
+                self._invalidate_slp(i);
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the SLP txs by walking their DAG, see `SlpValidator`. Fails only if the DB
+    /// does, a tx whose parents can't be fetched is treated as invalid.
+    pub fn validate_slp_local(&mut self, validator: &mut SlpValidator) -> Result<()> {
+        for i in 0..self.txs.len() {
+            match self.txs[i].tx_type {
+                TxType::SLP {..} => {},
+                TxType::Default => continue,
+            }
+            let tx_hash = self.txs[i].hash;
+            match validator.validate(&tx_hash) {
+                Ok(true) => {},
+                Ok(false) => self._invalidate_slp(i),
+                // unknown, as a parent couldn't be fetched; only this tx is treated as invalid,
+                // and no verdict is persisted, so a resync can still validate it
+                Err(err) => {
+                    eprintln!("Validating SLP tx {} failed: {}", tx_hash_to_hex(&tx_hash), err);
+                    self._invalidate_slp(i);
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// NFT1 child GENESIS txs have to spend valid tokens of their group at input 0, otherwise
+    /// anyone could mint counterfeit children by copying the metadata. Children whose group
+    /// input can't be fetched are treated as invalid, like in `validate_slp_local`.
+    pub fn validate_nft1_children(&mut self, validator: &mut SlpValidator) {
+        for idx in 0..self.txs.len() {
+            match self.txs[idx].tx_type {
+                TxType::SLP { token_type: TokenType::NFT1Child, slp_type: SLPTxType::Genesis, .. } =>
+                    {},
+                _ => continue,
+            }
+            let tx_hash = self.txs[idx].hash;
+            match validator.group_input_token(&tx_hash) {
+                Ok(Some(_)) => {},
+                Ok(None) => self._invalidate_slp(idx),
+                Err(err) => {
+                    eprintln!("Validating NFT1 child {} failed: {}", tx_hash_to_hex(&tx_hash), err);
+                    self._invalidate_slp(idx);
+                },
+            }
+        }
+    }
+
+    /// Indices of the txs, each after the txs of this history it spends.
+    fn _topological_order(&self) -> Vec<usize> {
+        let indices = self.txs.iter().enumerate()
+            .map(|(idx, tx)| (tx.hash, idx))
+            .collect::<HashMap<_, _>>();
+        let mut order = Vec::with_capacity(self.txs.len());
+        let mut visited = vec![false; self.txs.len()];
+        for root in 0..self.txs.len() {
+            let mut stack = vec![(root, false)];
+            while let Some((idx, parents_done)) = stack.pop() {
+                if parents_done {
+                    order.push(idx);
+                    continue;
+                }
+                if visited[idx] {
+                    continue;
+                }
+                visited[idx] = true;
+                stack.push((idx, true));
+                stack.extend(
+                    self.txs[idx].inputs.iter()
+                        .filter_map(|input| indices.get(&input.output_tx))
+                        .filter(|&&parent| !visited[parent])
+                        .map(|&parent| (parent, false))
+                );
+            }
+        }
+        order
+    }
+
+    /// Index of the GENESIS or MINT txs of this history and the token they mint.
+    fn _minted_tokens(&self) -> HashMap<usize, [u8; 32]> {
+        self.txs.iter().enumerate()
+            .filter_map(|(idx, tx)| match &tx.tx_type {
+                TxType::SLP { token_hash, slp_type: SLPTxType::Genesis, .. } |
+                TxType::SLP { token_hash, slp_type: SLPTxType::Mint, .. } =>
+                    Some((idx, *token_hash)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Baton left by the GENESIS or MINT at `idx`.
+    fn _new_mint_baton(&self, idx: usize, token_hash: [u8; 32]) -> MintBaton {
+        MintBaton {
+            token_hash,
+            mint_tx: self.txs[idx].hash,
+            baton_vout: self.mint_baton_vouts.get(&idx).cloned()
+                .and_then(|vout| vout)
+                .map(i32::from),
+        }
+    }
+
+    /// Moves the mint batons along the GENESIS and MINT txs of this history. A MINT which doesn't
+    /// spend the current baton of its token is invalidated, and a baton spent by anything but a
+    /// valid MINT is destroyed. Txs the DB already has were tracked when they were first seen, so
+    /// they're skipped. MINTs of tokens whose baton we never saw start tracking their token.
+    pub fn track_mint_batons(&mut self, db: &Db) -> Result<()> {
+        let known_txs = db.txs(self.txs.iter().map(|tx| tx.hash))?
+            .into_iter()
+            .map(|(tx_hash, _)| tx_hash)
+            .collect::<HashSet<_>>();
+        let input_txs = self.txs.iter()
+            .flat_map(|tx| tx.inputs.iter().map(|input| input.output_tx))
+            .collect::<HashSet<_>>();
+        let baton_outputs = db.mint_batons_of_txs(input_txs.into_iter())?
+            .into_iter()
+            .filter_map(|baton| Some(((baton.mint_tx, baton.baton_vout?), baton)))
+            .collect::<HashMap<_, _>>();
+        let minted_tokens = self._minted_tokens().values().cloned().collect::<HashSet<_>>();
+        let tracked_tokens = db.mint_batons(minted_tokens.into_iter())?
+            .into_iter()
+            .map(|(token_hash, _)| token_hash)
+            .collect::<HashSet<_>>();
+        self._track_mint_batons(&known_txs, baton_outputs, tracked_tokens);
+        Ok(())
+    }
+
+    fn _track_mint_batons(&mut self,
+                          known_txs: &HashSet<[u8; 32]>,
+                          mut baton_outputs: HashMap<([u8; 32], i32), MintBaton>,
+                          mut tracked_tokens: HashSet<[u8; 32]>) {
+        for idx in self._topological_order() {
+            if known_txs.contains(&self.txs[idx].hash) {
+                continue;
+            }
+            let spent_batons = self.txs[idx].inputs.iter()
+                .filter_map(|input| baton_outputs.remove(&(input.output_tx, input.output_idx)))
+                .collect::<Vec<_>>();
+            let minted_token = match &self.txs[idx].tx_type {
+                TxType::SLP { token_hash, slp_type: SLPTxType::Genesis, .. } => Some(*token_hash),
+                TxType::SLP { token_hash, slp_type: SLPTxType::Mint, .. } => {
+                    let spends_baton = spent_batons.iter()
+                        .any(|baton| &baton.token_hash == token_hash);
+                    if tracked_tokens.contains(token_hash) && !spends_baton {
+                        self._invalidate_slp(idx);
+                        None
+                    } else {
+                        Some(*token_hash)
+                    }
+                },
+                _ => None,
+            };
+            for baton in spent_batons {
+                if Some(baton.token_hash) != minted_token {
+                    let destroyed = MintBaton { baton_vout: None, ..baton };
+                    self.mint_batons.insert(destroyed.token_hash, destroyed);
+                }
+            }
+            if let Some(token_hash) = minted_token {
+                let baton = self._new_mint_baton(idx, token_hash);
+                if let Some(baton_vout) = baton.baton_vout {
+                    baton_outputs.insert((baton.mint_tx, baton_vout), baton.clone());
+                }
+                tracked_tokens.insert(token_hash);
+                self.mint_batons.insert(token_hash, baton);
+            }
+        }
+    }
+
+    /// Records the batons of the GENESIS and MINT txs of a history SLPDB validated already, e.g.
+    /// of a resync, so `track_mint_batons` can check MINTs of tokens created before we listened.
+    /// Resynced histories are incomplete and arrive out of order across addresses, so batons
+    /// only move to mint txs at least as new as the current one, and nothing is invalidated.
+    pub fn record_mint_batons(&mut self, db: &Db) -> Result<()> {
+        let minted_tokens = self._minted_tokens().values().cloned().collect::<HashSet<_>>();
+        if minted_tokens.len() == 0 {
+            return Ok(());
+        }
+        let current_batons = db.mint_batons(minted_tokens.into_iter())?;
+        let mint_txs = db.txs(current_batons.values().map(|baton| baton.mint_tx))?;
+        let current_heights = current_batons.into_iter()
+            .filter_map(|(token_hash, baton)| {
+                Some((token_hash, mint_txs.get(&baton.mint_tx)?.height))
+            })
+            .collect::<HashMap<_, _>>();
+        self._record_mint_batons(current_heights);
+        Ok(())
+    }
+
+    /// `current_heights` has the height of the mint tx of the current batons, `None` if it's
+    /// unconfirmed.
+    fn _record_mint_batons(&mut self, current_heights: HashMap<[u8; 32], Option<i32>>) {
+        let height_order = |height: Option<i32>| height.unwrap_or(std::i32::MAX);
+        let minted_tokens = self._minted_tokens();
+        // between mint txs of the same block in this history, the topological order decides
+        let mut recorded_heights = HashMap::new();
+        for idx in self._topological_order() {
+            let token_hash = match minted_tokens.get(&idx) {
+                Some(token_hash) => *token_hash,
+                None => continue,
+            };
+            let height = height_order(self.txs[idx].height);
+            let recorded_height = recorded_heights.get(&token_hash);
+            let is_newer = match (recorded_height, current_heights.get(&token_hash)) {
+                (Some(&recorded_height), _) => height >= recorded_height,
+                (None, Some(&current_height)) => height > height_order(current_height),
+                (None, None) => true,
+            };
+            if is_newer {
+                recorded_heights.insert(token_hash, height);
+                let baton = self._new_mint_baton(idx, token_hash);
+                self.mint_batons.insert(token_hash, baton);
+            }
+        }
+    }
+
+    /// Token and amount of the token UTXOs spent by this history, from the DB and the
+    /// history itself.
+    fn _spent_tokens(&self, db: &Db) -> Result<HashMap<([u8; 32], i32), ([u8; 32], SLPAmount)>> {
+        let input_txs = self.txs.iter()
+            .flat_map(|tx| tx.inputs.iter().map(|input| input.output_tx))
+            .collect::<HashSet<_>>();
+        let tx_outputs = db.tx_outputs(input_txs.iter().cloned())?;
+        let slp_txs = db.slp_txs(input_txs.iter().cloned())?;
+        let mut spent_tokens = HashMap::new();
+        for ((tx_hash, output_idx), output) in tx_outputs {
+            if let Some((_, _, token)) = slp_txs.get(&tx_hash) {
+                let value_token = SLPAmount::from_numeric_decimals(&output.value_token_base,
+                                                                   token.decimals as u32);
+                if value_token.base_amount() > 0 {
+                    spent_tokens.insert((tx_hash, output_idx),
+                                        (tx_hash_from_slice(&token.hash), value_token));
+                }
+            }
+        }
+        for tx in self.txs.iter() {
+            if let TxType::SLP { token_hash, .. } = &tx.tx_type {
+                for (output_idx, output) in tx.outputs.iter().enumerate() {
+                    if output.value_token.base_amount() > 0 {
+                        spent_tokens.insert((tx.hash, output_idx as i32),
+                                            (*token_hash, output.value_token));
+                    }
+                }
+            }
+        }
+        Ok(spent_tokens)
+    }
+
+    /// Supply changes of the txs, which should be validated already: GENESIS and MINT issue
+    /// tokens, and token inputs which don't end up in a regular output are burned.
+    pub fn track_token_supply(&mut self, db: &Db) -> Result<()> {
+        let spent_tokens = self._spent_tokens(db)?;
+        self._track_token_supply(&spent_tokens);
+        Ok(())
+    }
+
+    fn _track_token_supply(&mut self,
+                           spent_tokens: &HashMap<([u8; 32], i32), ([u8; 32], SLPAmount)>) {
+        use SupplyChangeKind::*;
+        let mut supply_changes = Vec::new();
+        for tx in self.txs.iter() {
+            let mut input_sums = HashMap::<[u8; 32], SLPAmount>::new();
+            for input in tx.inputs.iter() {
+                if let Some(&(token_hash, amount)) = spent_tokens.get(&(input.output_tx,
+                                                                        input.output_idx)) {
+                    input_sums.entry(token_hash)
+                        .and_modify(|sum| *sum += amount)
+                        .or_insert(amount);
+                }
+            }
+            let mut changes = Vec::new();
+            match &tx.tx_type {
+                TxType::Default => {
+                    changes.extend(input_sums.into_iter()
+                        .map(|(token_hash, amount)| (token_hash, BurnInvalidSpend, amount)));
+                },
+                TxType::SLP { token_hash, slp_type, .. } => {
+                    let output_sum = tx.outputs.iter()
+                        .map(|output| output.value_token)
+                        .sum::<SLPAmount>();
+                    let op_return_sum = tx.outputs.iter()
+                        .filter(|output| match output.output {
+                            OutputType::Burned | OutputType::OpReturn => true,
+                            _ => false,
+                        })
+                        .map(|output| output.value_token.base_amount())
+                        .sum::<i128>();
+                    match slp_type {
+                        SLPTxType::Genesis => changes.push((*token_hash, Genesis, output_sum)),
+                        SLPTxType::Mint => changes.push((*token_hash, Mint, output_sum)),
+                        SLPTxType::Send | SLPTxType::Commit => {},
+                    }
+                    changes.push((*token_hash, BurnOpReturn,
+                                  SLPAmount::new(op_return_sum, output_sum.decimals())));
+                    for (input_token, input_sum) in input_sums {
+                        let not_carried = match slp_type {
+                            SLPTxType::Send if &input_token == token_hash =>
+                                input_sum.map(|base_amount| base_amount - output_sum.base_amount()),
+                            _ => input_sum,
+                        };
+                        changes.push((input_token, BurnNotCarried, not_carried));
+                    }
+                },
+            }
+            supply_changes.extend(changes.into_iter()
+                .filter(|(_, _, amount)| amount.base_amount() > 0)
+                .map(|(token_hash, kind, amount)| SupplyChange {
+                    tx_hash: tx.hash,
+                    token_hash,
+                    kind,
+                    amount,
+                    timestamp: tx.timestamp,
+                }));
+        }
+        self.supply_changes = supply_changes;
+    }
+
+    /// Classifies the spends of trade offer contract outputs, see `_trade_spend_kind`.
+    pub fn track_trade_spends(&mut self, db: &Db) -> Result<()> {
+        let spent_utxos = self.txs.iter()
+            .flat_map(|tx| tx.inputs.iter())
+            .map(|input| SpentUtxo { tx_hash: input.output_tx, vout: input.output_idx })
+            .collect::<Vec<_>>();
+        let mut offers = db.trade_offers_at(&spent_utxos)?
+            .into_iter()
+            .filter_map(|(token_hash, offer)| {
+                Some(((offer.tx, offer.output_idx?), (token_hash, offer)))
+            })
+            .collect::<HashMap<_, _>>();
+        for (idx, offer) in self.trade_offers.iter() {
+            if let (Some(output_idx), Some(token_hash)) = (offer.output_idx,
+                                                           self.txs[*idx].tx_type.token_hash()) {
+                offers.insert((offer.tx, output_idx), (*token_hash, offer.clone()));
+            }
+        }
+        let mut trade_spends = Vec::new();
+        for tx in self.txs.iter() {
+            for input in tx.inputs.iter() {
+                let (token_hash, offer) = match offers.get(&(input.output_tx, input.output_idx)) {
+                    Some(offer) => offer,
+                    None => continue,
+                };
+                trade_spends.push(TradeSpend {
+                    offer_tx: input.output_tx,
+                    offer_output_idx: input.output_idx,
+                    spending_tx: tx.hash,
+                    token_hash: *token_hash,
+                    kind: Self::_trade_spend_kind(tx, offer),
+                    price_per_token: offer.price_per_token.clone(),
+                    amount: offer.sell_amount_token,
+                    timestamp: tx.timestamp,
                 });
-                self.trade_offers.remove(&i);
-                self.pandas_slp.remove(&i);
             }
         }
+        self.trade_spends = trade_spends;
         Ok(())
     }
 
-    pub fn validate_slp_remote(&mut self, tx_source: &TxSource, db: &Db, config: &SLPDEXConfig)
-            -> Result<()> {
+    /// A spend of an offer is a fill if it pays at least the asking price for all offered tokens
+    /// to the receiving address and sends tokens to someone else. The maker can take back the
+    /// tokens and the satoshis locked with them, both to the receiving address; that's a cancel.
+    fn _trade_spend_kind(tx: &HistoricTx, offer: &TradeOffer) -> TradeSpendKind {
+        let is_maker = |output: &HistoricTxOutput| output.output.address()
+            .map(|address| address.bytes() == offer.receiving_address.bytes())
+            .unwrap_or(false);
+        let amount = offer.sell_amount_token;
+        let asking_price = offer.price_per_token.clone()
+            * Rational::from(amount.base_amount())
+            / Rational::from(10u64.pow(amount.decimals()));
+        let paid = tx.outputs.iter()
+            .filter(|output| is_maker(output))
+            .map(|output| output.value_satoshis)
+            .sum::<u64>();
+        let has_taker = tx.outputs.iter().any(|output| {
+            output.value_token.base_amount() > 0 &&
+                output.output.address().is_some() &&
+                !is_maker(output)
+        });
+        if has_taker && asking_price > 0 && Rational::from(paid) >= asking_price {
+            TradeSpendKind::Fill
+        } else {
+            TradeSpendKind::Cancel
+        }
+    }
+
+    fn _invalidate_slp(&mut self, idx: usize) {
+        let tx = &mut self.txs[idx];
+        tx.tx_type = TxType::Default;
+        tx.outputs.iter_mut().for_each(|output| {
+            output.value_token = SLPAmount::new(0, 0);
+        });
+        self.trade_offers.remove(&idx);
+        self.pandas_slp.remove(&idx);
+        self.mint_baton_vouts.remove(&idx);
+    }
+
+    pub fn validate_slp_remote(&mut self, chain_source: &dyn ChainSource, db: &Db,
+                               config: &SLPDEXConfig) -> Result<()> {
         let tx_to_check = self.txs.iter()
             .flat_map(|tx| {
                 tx.inputs.iter()
@@ -746,7 +1542,7 @@ impl TxHistory {
         let tx_to_check = tx_to_check.into_iter()
             .map(TxFilter::TxHash)
             .collect::<Vec<_>>();
-        let validity_map = tx_source
+        let validity_map = chain_source
             .request_slp_tx_validity(&tx_to_check, config, Confirmedness::Both)?
             .into_iter()
             .map(|validity| (cashcontracts::tx_hex_to_hash(&validity.tx.h).unwrap(), validity))
@@ -794,58 +1590,10 @@ impl TxHistory {
     }
 }
 
-struct _Price {
-    script_price: u32,
-    price_per_token: Rational,
-    power: u8,
-    is_inverted: bool,
-}
-
 impl TradeOffer {
-    const _FACTORS: [u64; 10] = [
-        1,
-        10,
-        100,
-        1_000,
-        10_000,
-        100_000,
-        1_000_000,
-        10_000_000,
-        100_000_000,
-        1_000_000_000,
-    ];
-
-    fn _decode_price(slp_decimals: i32, power_bytes: &[u8], price_bytes: &[u8]) -> Result<_Price> {
-        let is_inverted = power_bytes.get(1) == Some(&1);
-        let script_price = io::Cursor::new(price_bytes)
-            .read_u32::<BigEndian>()
-            .map_err(|_| Error::from(ErrorKind::InvalidTradeOffer(
-                TradeOfferError::InvalidPrice(price_bytes.to_vec())
-            )))?;
-        let factor = Self::_FACTORS[slp_decimals as usize];
-        let factor_rational = rug::Rational::from((factor, 1));
-        let price_per_token = if is_inverted {
-            if script_price == 0 {
-                return Err(ErrorKind::InvalidTradeOffer(
-                    TradeOfferError::InvalidPrice(price_bytes.to_vec())
-                ).into())
-            }
-            rug::Rational::from((1, script_price)) * factor_rational
-        } else {
-            rug::Rational::from((script_price, 1)) * factor_rational
-        };
-        Ok(_Price {
-            script_price,
-            price_per_token,
-            power: *power_bytes.get(0).ok_or_else(|| ErrorKind::InvalidTradeOffer(
-                TradeOfferError::InvalidPower(power_bytes.to_vec()),
-            ))?,
-            is_inverted,
-        })
-    }
-
     fn _contract_hash(output: &HistoricTxOutput,
-                      price: &_Price,
+                      version: ExchVersion,
+                      price: &ExchPrice,
                       tx_type: &TxType,
                       config: &SLPDEXConfig,
                       receiving_address: &cashcontracts::Address) -> Option<SLPAmount> {
@@ -854,31 +1602,25 @@ impl TradeOffer {
             TxType::Default => return None,
         };
         let address = output.output.address()?;
-        if address.addr_type() == AddressType::P2SH {
-            /*let hash = cashcontracts::hash160(
-                &cashcontracts::AdvancedTradeOffer {
-                    value: output.value_satoshis,
-                    lokad_id: b"EXCH".to_vec(),
-                    version: 2,
-                    power: price.power,
-                    is_inverted: price.is_inverted,
-                    token_id: token_hash.clone(),
-                    token_type: token_type as u8,
-                    sell_amount_token: output.value_token.base_amount() as u64,
-                    price: price.script_price,
-                    dust_amount: config.dust_limit,
-                    address: receiving_address.clone(),
-                    fee_address: Some(config.fee_address.clone()),
-                    fee_divisor: Some(config.fee_divisor.clone()),
-                    spend_params: None,
-                }.script().to_vec()
-            );
-            if address.bytes() == &hash {
-                Some(output.value_token)
-            } else {
-                None
-            }*/
-            None
+        if address.addr_type() != AddressType::P2SH {
+            return None
+        }
+        // the covenant pushes the token id in script order, like the SLP OP_RETURN it checks,
+        // token hashes are stored reversed; see `test_contract_hash`
+        let mut token_id = token_hash.clone();
+        token_id.reverse();
+        let script = version.contract(
+            config,
+            output.value_satoshis,
+            token_id,
+            token_type as u8,
+            output.value_token,
+            *price,
+            receiving_address,
+            None,
+        ).script().to_vec();
+        if &address.bytes()[..] == &cashcontracts::hash160(&script)[..] {
+            Some(output.value_token)
         } else {
             None
         }
@@ -890,50 +1632,54 @@ impl TradeOffer {
                       decimals: u32)
             -> Option<Self> {
         entry.inputs.iter().find_map(|input| {
-            if input.b0 == tx_result::StackItem::Str(base64::encode("EXCH")) &&
-                    input.b1 == (tx_result::StackItem::Op {op: 0x52}) {
-                let price = entry.slp.as_ref()
-                    .and_then(|slp| {
-                        Self::_decode_price(
-                            slp.detail.decimals,
-                            &base64::decode(input.b2.get_str()?).ok()?,
-                            &base64::decode(input.b3.get_str()?).ok()?,
-                        ).map_err(|err| {
-                            eprintln!("Trade offer error {}", err);
-                        }).ok()
-                    })?;
-                let receiving_address = Address::from_slice(
-                    AddressType::P2PKH,
-                    &base64::decode(input.b4.get_str()?).ok()?,
-                )?;
-                let output_idx: i32 = 1;
-                let contract_vals = tx.outputs.get(output_idx as usize)
-                    .and_then(|output: &HistoricTxOutput| {
-                        Some((
-                            output_idx,
-                            Self::_contract_hash(output,
-                                                 &price,
-                                                 &tx.tx_type,
-                                                 config,
-                                                 &receiving_address)?,
-                        ))
-                    });
-                Some(TradeOffer {
-                    tx: cashcontracts::tx_hex_to_hash(&entry.tx.h).unwrap(),
-                    output_idx: contract_vals.map(|(idx, _)| idx),
-                    input_tx: cashcontracts::tx_hex_to_hash(&input.e.h).unwrap(),
-                    input_idx: input.e.i,
-                    price_per_token: price.price_per_token,
-                    is_inverted: price.is_inverted,
-                    script_price: price.script_price as i64,
-                    sell_amount_token: contract_vals
-                        .map(|(_, amount)| amount)
-                        .unwrap_or(SLPAmount::new(0, decimals)),
-                    receiving_address,
-                })
-            } else {
-                None
+            if input.b0 != tx_result::StackItem::Str(base64::encode("EXCH")) {
+                return None
             }
+            let version = match input.b1 {
+                tx_result::StackItem::Op {op} => ExchVersion::from_op(op as u8),
+                _ => None,
+            }.filter(|version| version.is_supported(config))?;
+            let (price, price_per_token) = entry.slp.as_ref()
+                .and_then(|slp| {
+                    version.decode_price(
+                        slp.detail.decimals,
+                        &base64::decode(input.b2.get_str()?).ok()?,
+                        &base64::decode(input.b3.get_str()?).ok()?,
+                    ).map_err(|err| {
+                        eprintln!("Trade offer error {}", err);
+                    }).ok()
+                })?;
+            let receiving_address = Address::from_slice(
+                AddressType::P2PKH,
+                &base64::decode(input.b4.get_str()?).ok()?,
+            )?;
+            let output_idx = EXCH_CONTRACT_VOUT as i32;
+            let contract_vals = tx.outputs.get(output_idx as usize)
+                .and_then(|output: &HistoricTxOutput| {
+                    Some((
+                        output_idx,
+                        Self::_contract_hash(output,
+                                             version,
+                                             &price,
+                                             &tx.tx_type,
+                                             config,
+                                             &receiving_address)?,
+                    ))
+                });
+            Some(TradeOffer {
+                tx: cashcontracts::tx_hex_to_hash(&entry.tx.h).unwrap(),
+                output_idx: contract_vals.map(|(idx, _)| idx),
+                input_tx: cashcontracts::tx_hex_to_hash(&input.e.h).unwrap(),
+                input_idx: input.e.i,
+                exch_version: version,
+                price_per_token,
+                is_inverted: price.is_inverted,
+                script_price: price.script_price as i64,
+                sell_amount_token: contract_vals
+                    .map(|(_, amount)| amount)
+                    .unwrap_or(SLPAmount::new(0, decimals)),
+                receiving_address,
+            })
         })
     }
 
@@ -941,7 +1687,7 @@ impl TradeOffer {
                    tx: &cashcontracts::Tx,
                    config: &SLPDEXConfig,
                    token: &Token) -> Option<Self> {
-        use cashcontracts::{Op::*, OpCodeType::*};
+        use cashcontracts::Op::*;
         println!("validating trade offer {}", tx_hash_to_hex(&historic_tx.hash));
         if let TxType::Default = &historic_tx.tx_type {
             return None
@@ -950,9 +1696,11 @@ impl TradeOffer {
             let ops = input.script.ops();
             if ops.len() < 5 { return None; }
             match &input.script.ops()[..5] {
-                &[Push(ref exch), Code(Op2), Push(ref power), Push(ref price), Push(ref address)]
+                &[Push(ref exch), Code(ref version), Push(ref power), Push(ref price), Push(ref address)]
                         if exch.as_slice() == config.exch_lokad.as_bytes() => {
-                    let price = Self::_decode_price(token.decimals, power, price)
+                    let version = ExchVersion::from_op_code(version)
+                        .filter(|version| version.is_supported(config))?;
+                    let (price, price_per_token) = version.decode_price(token.decimals, power, price)
                         .map_err(|err| {
                             eprintln!("Trade offer error {}", err);
                         }).ok()?;
@@ -962,12 +1710,13 @@ impl TradeOffer {
                         address,
                     )?;
                     println!("succeed address decoding");
-                    let output_idx: i32 = 1;
+                    let output_idx = EXCH_CONTRACT_VOUT as i32;
                     let contract_vals = historic_tx.outputs.get(output_idx as usize)
                         .and_then(|output: &HistoricTxOutput| {
                             Some((
                                 output_idx,
                                 Self::_contract_hash(output,
+                                                     version,
                                                      &price,
                                                      &historic_tx.tx_type,
                                                      config,
@@ -980,7 +1729,8 @@ impl TradeOffer {
                         output_idx: contract_vals.map(|(idx, _)| idx),
                         input_tx: input.outpoint.tx_hash.clone(),
                         input_idx: input.outpoint.vout as i32,
-                        price_per_token: price.price_per_token,
+                        exch_version: version,
+                        price_per_token,
                         is_inverted: price.is_inverted,
                         script_price: price.script_price as i64,
                         sell_amount_token: contract_vals
@@ -1040,3 +1790,441 @@ impl std::fmt::Display for OutputType {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn _tx(hash: u8,
+           inputs: &[([u8; 32], i32)],
+           tx_type: TxType,
+           height: Option<i32>) -> HistoricTx {
+        HistoricTx {
+            hash: [hash; 32],
+            height,
+            timestamp: 0,
+            tx_type,
+            inputs: inputs.iter()
+                .map(|&(output_tx, output_idx)| HistoricTxInput {
+                    output_tx,
+                    output_idx,
+                    output: OutputType::Unknown,
+                })
+                .collect(),
+            outputs: vec![],
+        }
+    }
+
+    fn _slp(token: u8, slp_type: SLPTxType) -> TxType {
+        TxType::SLP { token_hash: [token; 32], token_type: TokenType::Standard, slp_type }
+    }
+
+    /// History of `txs`, whose GENESIS and MINT txs leave their baton at output 2.
+    fn _history(txs: Vec<HistoricTx>) -> TxHistory {
+        let mint_baton_vouts = txs.iter().enumerate()
+            .filter(|(_, tx)| match tx.tx_type {
+                TxType::SLP { slp_type: SLPTxType::Genesis, .. } |
+                TxType::SLP { slp_type: SLPTxType::Mint, .. } => true,
+                _ => false,
+            })
+            .map(|(idx, _)| (idx, Some(2)))
+            .collect();
+        TxHistory { mint_baton_vouts, ..TxHistory::with_txs(txs) }
+    }
+
+    fn _is_slp(tx: &HistoricTx) -> bool {
+        match tx.tx_type {
+            TxType::SLP {..} => true,
+            TxType::Default => false,
+        }
+    }
+
+    #[test]
+    fn test_topological_order() {
+        let a = _tx(1, &[([0xee; 32], 0)], TxType::Default, None);
+        let b = _tx(2, &[([1; 32], 0)], TxType::Default, None);
+        let c = _tx(3, &[([2; 32], 0), ([1; 32], 1)], TxType::Default, None);
+        let unrelated = _tx(4, &[([0xee; 32], 1)], TxType::Default, None);
+        let history = _history(vec![c, unrelated, b, a]);
+        let order = history._topological_order();
+        let position = |hash: u8| {
+            order.iter().position(|&idx| history.txs[idx].hash == [hash; 32]).unwrap()
+        };
+        assert_eq!(order.len(), 4);
+        assert!(position(1) < position(2));
+        assert!(position(2) < position(3));
+    }
+
+    #[test]
+    fn test_track_mint_batons() {
+        let token_hash = [1; 32];
+        let genesis = _tx(1, &[([0xee; 32], 0)], _slp(1, SLPTxType::Genesis), None);
+        let mint1 = _tx(2, &[([1; 32], 2)], _slp(1, SLPTxType::Mint), None);
+        let mint2 = _tx(3, &[([2; 32], 2)], _slp(1, SLPTxType::Mint), None);
+        let without_baton = _tx(4, &[([1; 32], 1)], _slp(1, SLPTxType::Mint), None);
+        let mut history = _history(vec![mint2, without_baton, mint1.clone(), genesis]);
+        history._track_mint_batons(&HashSet::new(), HashMap::new(), HashSet::new());
+        assert!(_is_slp(&history.txs[0]) && _is_slp(&history.txs[2]));
+        assert!(!_is_slp(&history.txs[1]));
+        assert_eq!(history.mint_batons[&token_hash].mint_tx, [3; 32]);
+        assert_eq!(history.mint_batons[&token_hash].baton_vout, Some(2));
+
+        // seeing mint1 again after the baton moved on changes nothing
+        let current_baton = MintBaton { token_hash, mint_tx: [3; 32], baton_vout: Some(2) };
+        let baton_outputs = vec![(([3; 32], 2), current_baton.clone())].into_iter().collect();
+        let tracked_tokens = vec![token_hash].into_iter().collect::<HashSet<_>>();
+        let mut history = _history(vec![mint1.clone()]);
+        let known_txs = vec![mint1.hash].into_iter().collect();
+        history._track_mint_batons(&known_txs, baton_outputs, tracked_tokens.clone());
+        assert!(_is_slp(&history.txs[0]));
+        assert!(history.mint_batons.is_empty());
+
+        // a baton spent by a SEND is destroyed
+        let send = _tx(5, &[([3; 32], 2)], _slp(1, SLPTxType::Send), None);
+        let baton_outputs = vec![(([3; 32], 2), current_baton)].into_iter().collect();
+        let mut history = _history(vec![send]);
+        history._track_mint_batons(&HashSet::new(), baton_outputs, tracked_tokens);
+        assert_eq!(history.mint_batons[&token_hash].baton_vout, None);
+
+        // MINTs of tokens whose baton we never saw start tracking it
+        let untracked_mint = _tx(6, &[([9; 32], 2)], _slp(9, SLPTxType::Mint), None);
+        let mut history = _history(vec![untracked_mint]);
+        history._track_mint_batons(&HashSet::new(), HashMap::new(), HashSet::new());
+        assert!(_is_slp(&history.txs[0]));
+        assert_eq!(history.mint_batons[&[9; 32]].mint_tx, [6; 32]);
+    }
+
+    #[test]
+    fn test_validate_nft1_children() {
+        use crate::slp_validator::test_txs::{typed_genesis, typed_send};
+        let group = typed_genesis(0x81, &[([0xff; 32], 0)], 0, vec![2], 10);
+        let group_hash = group.hash();
+        let fan_out = typed_send(0x81, &[(group_hash, 1)], &group_hash, &[1, 9]);
+        let child = |inputs: &[([u8; 32], u32)]| typed_genesis(0x41, inputs, 0, vec![], 1);
+        let valid_child = child(&[(fan_out.hash(), 1)]);
+        let counterfeit = child(&[(fan_out.hash(), 0)]);
+        let unknown_group = child(&[([0xdd; 32], 1)]);
+        let provider = vec![&group, &fan_out].into_iter()
+            .map(|tx| (tx.hash(), tx.clone()))
+            .collect::<HashMap<_, _>>();
+        let mut validator = SlpValidator::new(&provider, None);
+        let children = vec![valid_child, counterfeit, unknown_group];
+        validator.add_txs(&children);
+        let mut history = _history(children.iter()
+            .map(|child| {
+                let tx_type = TxType::SLP {
+                    token_hash: child.hash(),
+                    token_type: TokenType::NFT1Child,
+                    slp_type: SLPTxType::Genesis,
+                };
+                HistoricTx { hash: child.hash(), .._tx(0, &[], tx_type, None) }
+            })
+            .collect());
+        history.validate_nft1_children(&mut validator);
+        assert!(_is_slp(&history.txs[0]));
+        assert!(!_is_slp(&history.txs[1]));
+        assert!(!_is_slp(&history.txs[2]));
+    }
+
+    #[test]
+    fn test_historic_outputs() {
+        use crate::slp_validator::test_txs::{typed_tx, token_id};
+        let amounts = [600u64, 400, 0];
+        let mut pushes = vec![b"SEND".to_vec(), token_id(&[1; 32])];
+        pushes.extend(amounts.iter().map(|amount| amount.to_be_bytes().to_vec()));
+        // OP_RETURN and a single token output, so only the 600 have an output
+        let tx = typed_tx(0x01, &[([0xee; 32], 0)], pushes, 1);
+        let slp_amounts = amounts.iter()
+            .map(|&amount| SLPAmount::new(amount as i128, 0))
+            .collect::<Vec<_>>();
+        let outputs = TxHistory::_historic_outputs(&tx, &slp_amounts, 0);
+        let token_amounts = outputs.iter()
+            .map(|output| output.value_token.base_amount())
+            .collect::<Vec<_>>();
+        assert_eq!(token_amounts, vec![0, 600, 400, 0]);
+        match outputs[2].output {
+            OutputType::Burned => {},
+            _ => panic!("expected the 400 to be burned"),
+        }
+
+        let outputs = TxHistory::_historic_outputs(&tx, &[], 0);
+        assert_eq!(outputs.len(), 2);
+    }
+
+    #[test]
+    fn test_track_token_supply() {
+        use SupplyChangeKind::*;
+        let token_hash = [1; 32];
+        let output = |amount: i128, output: OutputType| HistoricTxOutput {
+            value_satoshis: 546,
+            value_token: SLPAmount::new(amount, 0),
+            output,
+        };
+        // spends 1000, sends 600, burns 100 to the OP_RETURN and doesn't carry 300
+        let mut send = _tx(2, &[([1; 32], 1)], _slp(1, SLPTxType::Send), None);
+        send.outputs = vec![output(0, OutputType::OpReturn), output(600, OutputType::Unknown),
+                            output(100, OutputType::Burned)];
+        let plain = _tx(3, &[([2; 32], 1)], TxType::Default, None);
+        let spent_tokens = vec![
+            (([1; 32], 1), (token_hash, SLPAmount::new(1000, 0))),
+            (([2; 32], 1), (token_hash, SLPAmount::new(600, 0))),
+        ].into_iter().collect();
+        let mut history = _history(vec![send, plain]);
+        history._track_token_supply(&spent_tokens);
+        let changes = history.supply_changes.iter()
+            .map(|change| (change.tx_hash[0], change.kind, change.amount.base_amount()))
+            .collect::<Vec<_>>();
+        assert_eq!(changes, vec![(2, BurnOpReturn, 100), (2, BurnNotCarried, 300),
+                                 (3, BurnInvalidSpend, 600)]);
+    }
+
+    #[test]
+    fn test_record_mint_batons() {
+        let token_hash = [1; 32];
+        let mint1 = _tx(2, &[([1; 32], 2)], _slp(1, SLPTxType::Mint), Some(100));
+        let mint2 = _tx(3, &[([2; 32], 2)], _slp(1, SLPTxType::Mint), Some(100));
+        let recorded = |current_height: Option<Option<i32>>| {
+            let mut history = _history(vec![mint2.clone(), mint1.clone()]);
+            history._record_mint_batons(
+                current_height.into_iter().map(|height| (token_hash, height)).collect()
+            );
+            history.mint_batons.get(&token_hash).map(|baton| baton.mint_tx)
+        };
+        assert_eq!(recorded(None), Some([3; 32]));
+        assert_eq!(recorded(Some(Some(99))), Some([3; 32]));
+        // already recorded, or the baton moved on since
+        assert_eq!(recorded(Some(Some(100))), None);
+        assert_eq!(recorded(Some(Some(150))), None);
+        assert_eq!(recorded(Some(None)), None);
+    }
+
+    fn _cash_addr(output: &OutputType) -> Option<String> {
+        output.address().map(|address| address.cash_addr().to_string())
+    }
+
+    #[test]
+    fn test_process_input_script() {
+        use cashcontracts::{Script, Op::*};
+        let signature = vec![0x30; 72];
+        let mut pub_key = vec![0x02];
+        pub_key.extend_from_slice(&[0x11; 32]);
+        let p2pkh_spend = Script::new(vec![Push(signature.clone()), Push(pub_key.clone())]);
+        let p2pkh_address = Address::from_serialized_pub_key(
+            "bitcoincash", AddressType::P2PKH, &pub_key,
+        );
+        assert_eq!(_cash_addr(&TxHistory::_process_input_script(&p2pkh_spend)),
+                   Some(p2pkh_address.cash_addr().to_string()));
+        // a P2SH spend of a 1-of-1 multisig: signature and redeem script
+        // OP_1 <pub key> OP_1 OP_CHECKMULTISIG
+        let mut redeem_script = vec![0x51, 0x21];
+        redeem_script.extend_from_slice(&pub_key);
+        redeem_script.extend_from_slice(&[0x51, 0xae]);
+        let p2sh_spend = Script::new(vec![Push(signature.clone()), Push(redeem_script)]);
+        let p2pk_spend = Script::new(vec![Push(signature.clone())]);
+        for script in &[p2sh_spend, p2pk_spend, Script::new(vec![])] {
+            match TxHistory::_process_input_script(script) {
+                OutputType::Unknown => {},
+                output => panic!("expected Unknown, got {}", output),
+            }
+        }
+    }
+
+    #[test]
+    fn test_process_output_script() {
+        use cashcontracts::{Script, Op::*, OpCodeType::*};
+        let p2pkh = Script::new(vec![
+            Code(OpDup), Code(OpHash160), Push(vec![1; 20]), Code(OpEqualVerify), Code(OpCheckSig),
+        ]);
+        assert_eq!(_cash_addr(&TxHistory::_process_output_script(&p2pkh)),
+                   Some(Address::from_bytes(AddressType::P2PKH, [1; 20]).cash_addr().to_string()));
+        let p2sh = Script::new(vec![Code(OpHash160), Push(vec![2; 20]), Code(OpEqual)]);
+        assert_eq!(_cash_addr(&TxHistory::_process_output_script(&p2sh)),
+                   Some(Address::from_bytes(AddressType::P2SH, [2; 20]).cash_addr().to_string()));
+        let mut pub_key = vec![0x02];
+        pub_key.extend_from_slice(&[0x11; 32]);
+        let p2pk = Script::new(vec![Push(pub_key), Code(OpCheckSig)]);
+        match TxHistory::_process_output_script(&p2pk) {
+            OutputType::Unknown => {},
+            output => panic!("expected Unknown, got {}", output),
+        }
+        let op_return = Script::new(vec![Code(OpReturn), Push(b"SLP\0".to_vec())]);
+        match TxHistory::_process_output_script(&op_return) {
+            OutputType::OpReturn => {},
+            output => panic!("expected OpReturn, got {}", output),
+        }
+    }
+
+    #[test]
+    fn test_set_input_addresses() {
+        let p2sh = Address::from_bytes(AddressType::P2SH, [2; 20]);
+        let p2pkh = Address::from_bytes(AddressType::P2PKH, [3; 20]);
+        let guessed = Address::from_bytes(AddressType::P2PKH, [9; 20]);
+        let output = |address: &Address| HistoricTxOutput {
+            value_satoshis: 546,
+            value_token: SLPAmount::new(0, 0),
+            output: OutputType::Address(address.clone()),
+        };
+        let mut parent = _tx(1, &[([0xee; 32], 0)], TxType::Default, None);
+        parent.outputs = vec![output(&p2sh)];
+        let mut child = _tx(2, &[([1; 32], 0), ([0xdb; 32], 0), ([0xdb; 32], 1), ([0xee; 32], 1)],
+                            TxType::Default, None);
+        for input in child.inputs.iter_mut() {
+            input.output = OutputType::Address(guessed.clone());
+        }
+        let mut history = _history(vec![parent, child]);
+        let spent_outputs = vec![
+            (([0xdb; 32], 0), OutputType::Address(p2pkh.clone())),
+            (([0xdb; 32], 1), OutputType::from_id(OutputType::UNKNOWN_ID, None)),
+        ].into_iter().collect();
+        history._set_input_addresses(spent_outputs);
+        let input_addresses = history.txs[1].inputs.iter()
+            .map(|input| _cash_addr(&input.output))
+            .collect::<Vec<_>>();
+        assert_eq!(input_addresses, vec![
+            Some(p2sh.cash_addr().to_string()),   // from this history
+            Some(p2pkh.cash_addr().to_string()),  // from the DB
+            None,                                 // spends an output without address
+            Some(guessed.cash_addr().to_string()),  // spent output unknown, keeps the guess
+        ]);
+    }
+
+    #[test]
+    fn test_output_type_from_id() {
+        let p2sh = OutputType::from_id(OutputType::P2SH_ID, Some(&[2; 20]));
+        assert_eq!(p2sh.id(), OutputType::P2SH_ID);
+        assert_eq!(_cash_addr(&p2sh),
+                   Some(Address::from_bytes(AddressType::P2SH, [2; 20]).cash_addr().to_string()));
+        let p2pkh = OutputType::from_id(OutputType::P2PKH_ID, Some(&[3; 20]));
+        assert_eq!(p2pkh.id(), OutputType::P2PKH_ID);
+        assert_eq!(OutputType::from_id(OutputType::P2PKH_ID, None).id(), OutputType::UNKNOWN_ID);
+        assert_eq!(OutputType::from_id(OutputType::OP_RETURN_ID, None).id(), OutputType::OP_RETURN_ID);
+    }
+
+    /// Ad and offer tx, signed with dummy signatures, selling 6.00 of 10.00 `token_hash` tokens
+    /// at 20 satoshis per base unit, built like wallets build them.
+    fn _exch_offer(config: &SLPDEXConfig, token_hash: &[u8; 32])
+            -> (crate::exch::ExchOfferTx, cashcontracts::Tx, cashcontracts::Tx) {
+        use cashcontracts::TxOutpoint;
+        let mut token_id = *token_hash;
+        token_id.reverse();
+        let offer = crate::exch::ExchOfferTx {
+            version: ExchVersion::V2,
+            token_id,
+            token_type: 0x01,
+            token_inputs: vec![(TxOutpoint { tx_hash: [0xe1; 32], vout: 1 }, 546)],
+            token_input_amount: SLPAmount::new(1000, 2),
+            sell_amount: SLPAmount::new(600, 2),
+            price: ExchPrice { power: 0, is_inverted: false, script_price: 20 },
+            maker_address: Address::from_bytes(AddressType::P2PKH, [5; 20]),
+            receiving_address: Address::from_bytes(AddressType::P2PKH, [6; 20]),
+            fee_inputs: vec![(TxOutpoint { tx_hash: [0xe2; 32], vout: 0 }, 100_000)],
+            fee_policy: config.fee_policy,
+        };
+        let ad_tx = offer.sign_ad(config, vec![vec![0x30; 72]], vec![0x02; 33]).unwrap();
+        let offer_tx = offer.sign_offer(config, &ad_tx, vec![vec![0x30; 72]; 2], vec![0x02; 33])
+            .unwrap();
+        (offer, ad_tx, offer_tx)
+    }
+
+    #[test]
+    fn test_contract_hash() {
+        let config = SLPDEXConfig::default();
+        let token_hash = {
+            let mut token_hash = [0; 32];
+            token_hash.iter_mut().enumerate().for_each(|(i, byte)| *byte = i as u8);
+            token_hash
+        };
+        let (offer, _, offer_tx) = _exch_offer(&config, &token_hash);
+        // SLP readers, and the covenant's own check of the accept tx's OP_RETURN, see the
+        // token id in script order, which parses to the token hash as stored
+        let message = parse_slp_message(&offer_tx.outputs()[0].script).unwrap().unwrap();
+        assert_eq!(message.token_hash, Some(token_hash));
+        let slp_amounts = message.amounts.iter()
+            .map(|&amount| SLPAmount::new(amount as i128, 2))
+            .collect::<Vec<_>>();
+        let outputs = TxHistory::_historic_outputs(&offer_tx, &slp_amounts, 2);
+        let contract_output = &outputs[EXCH_CONTRACT_VOUT as usize];
+        let contract_hash = |token_hash: [u8; 32]| TradeOffer::_contract_hash(
+            contract_output,
+            offer.version,
+            &offer.price,
+            &TxType::SLP { token_hash, token_type: TokenType::Standard, slp_type: SLPTxType::Send },
+            &config,
+            &offer.receiving_address,
+        ).map(|amount| amount.base_amount());
+        assert_eq!(contract_hash(token_hash), Some(600));
+        let mut unreversed = token_hash;
+        unreversed.reverse();
+        assert_eq!(contract_hash(unreversed), None);
+        assert_eq!(contract_hash([0; 32]), None);
+    }
+
+    #[test]
+    fn test_trade_spend_kind() {
+        let maker = Address::from_bytes(AddressType::P2PKH, [6; 20]);
+        let taker = Address::from_bytes(AddressType::P2PKH, [7; 20]);
+        // 6.00 tokens at 2 satoshis per token, asking 12 satoshis
+        let offer = TradeOffer {
+            tx: [1; 32],
+            output_idx: Some(1),
+            input_tx: [0; 32],
+            input_idx: 0,
+            exch_version: ExchVersion::V2,
+            price_per_token: Rational::from(2),
+            script_price: 2,
+            is_inverted: false,
+            sell_amount_token: SLPAmount::new(600, 2),
+            receiving_address: maker.clone(),
+        };
+        let output = |address: &Address, value_satoshis: u64, token_amount: i128| HistoricTxOutput {
+            value_satoshis,
+            value_token: SLPAmount::new(token_amount, 2),
+            output: OutputType::Address(address.clone()),
+        };
+        let spend = |outputs: Vec<HistoricTxOutput>| {
+            let mut tx = _tx(2, &[([1; 32], 1)], _slp(3, SLPTxType::Send), None);
+            tx.outputs = outputs;
+            TxHistory::_trade_spend_kind(&tx, &offer)
+        };
+        assert_eq!(spend(vec![output(&taker, 546, 600), output(&maker, 12, 0)]),
+                   TradeSpendKind::Fill);
+        assert_eq!(spend(vec![output(&taker, 546, 600), output(&maker, 11, 0)]),
+                   TradeSpendKind::Cancel);
+        // the maker taking back tokens and satoshis
+        assert_eq!(spend(vec![output(&maker, 546, 600), output(&maker, 10_000, 0)]),
+                   TradeSpendKind::Cancel);
+        assert_eq!(spend(vec![output(&maker, 10_546, 600)]), TradeSpendKind::Cancel);
+    }
+
+    #[test]
+    fn test_trade_offer_from_tx() {
+        use crate::slp_validator::test_txs::typed_genesis;
+        let config = SLPDEXConfig::default();
+        let genesis = typed_genesis(0x01, &[([0xee; 32], 0)], 2, vec![], 1000);
+        let genesis_message = parse_slp_message(&genesis.outputs()[0].script).unwrap().unwrap();
+        let token = Token::from_genesis(genesis.hash(), &genesis_message);
+        let (offer, ad_tx, offer_tx) = _exch_offer(&config, &token.hash);
+        let message = parse_slp_message(&offer_tx.outputs()[0].script).unwrap().unwrap();
+        let slp_amounts = message.amounts.iter()
+            .map(|&amount| SLPAmount::new(amount as i128, 2))
+            .collect::<Vec<_>>();
+        let mut historic_tx = _tx(0, &[], TxType::SLP {
+            token_hash: token.hash,
+            token_type: TokenType::Standard,
+            slp_type: SLPTxType::Send,
+        }, None);
+        historic_tx.hash = offer_tx.hash();
+        historic_tx.outputs = TxHistory::_historic_outputs(&offer_tx, &slp_amounts, 2);
+        let trade_offer = TradeOffer::from_tx(&historic_tx, &offer_tx, &config, &token).unwrap();
+        assert_eq!(trade_offer.tx, offer_tx.hash());
+        assert_eq!(trade_offer.output_idx, Some(EXCH_CONTRACT_VOUT as i32));
+        assert_eq!((trade_offer.input_tx, trade_offer.input_idx), (ad_tx.hash(), 0));
+        assert_eq!(trade_offer.exch_version, offer.version);
+        assert_eq!(trade_offer.sell_amount_token.base_amount(), 600);
+        assert_eq!(trade_offer.script_price, 20);
+        assert!(!trade_offer.is_inverted);
+        // 20 satoshis per base unit are 2000 satoshis per token
+        assert_eq!(trade_offer.price_per_token, Rational::from(2000));
+        assert_eq!(trade_offer.receiving_address.bytes(), offer.receiving_address.bytes());
+    }
+}
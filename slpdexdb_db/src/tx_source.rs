@@ -2,6 +2,7 @@ use cashcontracts::{Address, tx_hash_to_hex};
 use json::{JsonValue, object, array};
 use slpdexdb_base::SLPDEXConfig;
 use crate::endpoint::Endpoint;
+use crate::exch::ExchVersion;
 
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -119,6 +120,9 @@ pub mod tx_result {
         #[serde(rename = "versionType")]
         pub version_type: i32,
         pub outputs: Vec<TxSLPOutput>,
+        /// GENESIS and MINT only, `None` if the baton ends
+        #[serde(rename = "batonVout", default)]
+        pub baton_vout: Option<i32>,
     }
     #[derive(Serialize, Deserialize, Debug)]
     pub struct TxSLPOutput {
@@ -176,7 +180,11 @@ impl TxFilter {
                 match filter {
                     TxFilter::Exch => vec![
                         ("in.b0", JsonValue::String(config.exch_lokad_b64.to_string())),
-                        ("in.b1", object!{"op" => 0x50 + config.exch_version}),
+                        ("in.b1.op", object!{"$in" => JsonValue::Array(
+                            ExchVersion::all(config).iter()
+                                .map(|version| JsonValue::from(version.op()))
+                                .collect()
+                        )}),
                     ],
                     TxFilter::TokenId(token_id) => vec![
                         ("slp.detail.tokenIdHex", JsonValue::String(tx_hash_to_hex(token_id)))
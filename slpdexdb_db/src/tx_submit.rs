@@ -0,0 +1,240 @@
+use std::io;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use cashcontracts::{Tx, tx_hash_to_hex};
+use slpdexdb_base::{Result, ErrorKind, SLPDEXConfig, SubmitTxError};
+use crate::db::Db;
+use crate::tx_history::{TxHistory, SLPTxType, TokenType};
+use crate::slp_validator::{SlpValidator, SlpMessage, TxProvider, parse_slp_message};
+
+fn _reject(submit_tx_error: SubmitTxError) -> slpdexdb_base::Error {
+    ErrorKind::SubmitTxError(submit_tx_error).into()
+}
+
+/// Parses a raw tx submitted by a client.
+pub fn parse_submitted_tx(tx_hex: &str) -> Result<Tx> {
+    let tx_bytes = hex::decode(tx_hex.trim())?;
+    let mut cursor = io::Cursor::new(&tx_bytes);
+    let tx = Tx::read_from_stream(&mut cursor)
+        .map_err(|err| _reject(SubmitTxError::InvalidTx(err.to_string())))?;
+    let n_trailing = tx_bytes.len() - cursor.position() as usize;
+    if n_trailing > 0 {
+        return Err(_reject(SubmitTxError::TrailingBytes(n_trailing)));
+    }
+    Ok(tx)
+}
+
+/// Checks the token inputs of a client tx with `validator`, which must know the tx: every
+/// input carrying valid tokens or a mint baton has to be spent the way the SLP message of the
+/// tx intends, the tx itself has to be valid, and a SEND has to send all of its token inputs.
+fn _check_slp_inputs(tx: &Tx, validator: &mut SlpValidator) -> Result<()> {
+    let tx_hash = tx.hash();
+    let message = validator.message(&tx_hash)?.cloned();
+    let mut input_sum = 0u128;
+    for (input_idx, input) in tx.inputs().iter().enumerate() {
+        let (parent_hash, vout) = (input.outpoint.tx_hash, input.outpoint.vout);
+        let parent = match validator.message(&parent_hash)? {
+            Some(parent) => parent.clone(),
+            None => continue,
+        };
+        let amount = parent.amount_at(vout) as u128;
+        let is_baton = parent.mint_baton_vout.map(u32::from) == Some(vout);
+        if (amount == 0 && !is_baton) || !validator.validate(&parent_hash)? {
+            continue
+        }
+        let parent_token_hash = parent.token_hash_of(&parent_hash);
+        let is_same_token = |message: &SlpMessage| {
+            message.token_hash == Some(parent_token_hash) && message.token_type == parent.token_type
+        };
+        let is_intended = match &message {
+            Some(message @ SlpMessage { slp_type: SLPTxType::Send, .. })
+                    if !is_baton && is_same_token(message) => {
+                input_sum += amount;
+                true
+            },
+            Some(message @ SlpMessage { slp_type: SLPTxType::Mint, .. }) =>
+                is_baton && is_same_token(message),
+            // an NFT1 child GENESIS spends a group token at input 0 by design
+            Some(SlpMessage {
+                slp_type: SLPTxType::Genesis, token_type: TokenType::NFT1Child, ..
+            }) =>
+                !is_baton && input_idx == 0 && parent.token_type == TokenType::NFT1Parent,
+            _ => false,
+        };
+        if !is_intended {
+            return Err(_reject(SubmitTxError::BurnsTokens(
+                format!("{}:{}", tx_hash_to_hex(&parent_hash), vout)
+            )));
+        }
+    }
+    let message = match message {
+        Some(message) => message,
+        None => return Ok(()),
+    };
+    let token_hex = tx_hash_to_hex(&message.token_hash_of(&tx_hash));
+    if !validator.validate(&tx_hash)? {
+        return Err(_reject(match (&message.slp_type, message.token_type) {
+            (SLPTxType::Mint, _) => SubmitTxError::MintWithoutBaton(token_hex),
+            (SLPTxType::Genesis, TokenType::NFT1Child) =>
+                SubmitTxError::NFT1ChildWithoutGroup(token_hex),
+            _ => SubmitTxError::InsufficientTokenInputs(input_sum.to_string(),
+                                                        message.output_sum().to_string()),
+        }));
+    }
+    if let SLPTxType::Send = message.slp_type {
+        if input_sum > message.output_sum() {
+            return Err(_reject(SubmitTxError::BurnsTokenAmount(input_sum.to_string(),
+                                                               message.output_sum().to_string())));
+        }
+    }
+    Ok(())
+}
+
+/// Txs fetched for the validation of a client tx, `None` if the provider doesn't have them.
+/// Txs asked for but not fetched yet are recorded, so they can be fetched without holding the
+/// DB lock.
+#[derive(Default)]
+struct _FetchedTxs {
+    txs: HashMap<[u8; 32], Option<Tx>>,
+    missing: RefCell<HashSet<[u8; 32]>>,
+}
+
+impl TxProvider for _FetchedTxs {
+    fn fetch_tx(&self, tx_hash: &[u8; 32]) -> Result<Option<Tx>> {
+        match self.txs.get(tx_hash) {
+            Some(tx) => Ok(tx.clone()),
+            None => {
+                self.missing.borrow_mut().insert(*tx_hash);
+                Ok(None)
+            },
+        }
+    }
+}
+
+/// Checks a client tx before we relay it. Unlike `TxHistory::from_txs`, which drops invalid
+/// SLP/PND1 data of txs already on the network, this fails on it, on invalid SLP txs, and on
+/// txs that would burn tokens. Fails if the parent of an input can't be fetched from `provider`,
+/// as it might carry tokens. `db` isn't locked while fetching from `provider`; the checks are
+/// rerun after every round of fetches instead.
+pub fn prevalidate_tx(tx: &Tx,
+                      now: i64,
+                      config: &SLPDEXConfig,
+                      db: &Mutex<Db>,
+                      provider: &dyn TxProvider) -> Result<TxHistory> {
+    let mut fetched = _FetchedTxs::default();
+    loop {
+        let result = _prevalidate_tx(tx, now, config, &db.lock().unwrap(), &fetched);
+        let missing = fetched.missing.replace(HashSet::new());
+        if missing.len() == 0 {
+            return result;
+        }
+        for tx_hash in missing {
+            fetched.txs.insert(tx_hash, provider.fetch_tx(&tx_hash)?);
+        }
+    }
+}
+
+fn _prevalidate_tx(tx: &Tx,
+                   now: i64,
+                   config: &SLPDEXConfig,
+                   db: &Db,
+                   provider: &dyn TxProvider) -> Result<TxHistory> {
+    if let Some(output) = tx.outputs().get(0) {
+        parse_slp_message(&output.script())?;
+    }
+    TxHistory::_process_pnd1_tx(tx, db, config)?;
+    TxHistory::_process_pnds_tx(tx, db, config)?;
+    TxHistory::_process_pnda_tx(tx, db, config)?;
+    let mut validator = SlpValidator::new(provider, Some(db));
+    validator.add_txs(&[tx.clone()]);
+    _check_slp_inputs(tx, &mut validator)?;
+    Ok(TxHistory::from_txs(&[tx.clone()], now, config, db))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slp_validator::test_txs::*;
+
+    fn _check(tx: &Tx, parents: &[&Tx]) -> Result<()> {
+        let provider = parents.iter()
+            .map(|parent| (parent.hash(), (*parent).clone()))
+            .collect::<HashMap<_, _>>();
+        let mut validator = SlpValidator::new(&provider, None);
+        validator.add_txs(&[tx.clone()]);
+        _check_slp_inputs(tx, &mut validator)
+    }
+
+    fn _assert_rejected(result: Result<()>, expected: &str) {
+        match result {
+            Err(slpdexdb_base::Error(ErrorKind::SubmitTxError(err), _)) =>
+                assert!(format!("{:?}", err).starts_with(expected), "{:?} isn't {}", err, expected),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(()) => panic!("expected {}", expected),
+        }
+    }
+
+    #[test]
+    fn test_parse_submitted_tx() {
+        let tx = send(&[([1; 32], 1)], &[2; 32], &[1]);
+        let mut tx_ser = Vec::new();
+        tx.write_to_stream(&mut tx_ser).unwrap();
+        let tx_hex = hex::encode(&tx_ser);
+        assert_eq!(parse_submitted_tx(&format!(" {}\n", tx_hex)).unwrap().hash(), tx.hash());
+        match parse_submitted_tx(&format!("{}0000", tx_hex)) {
+            Err(slpdexdb_base::Error(
+                ErrorKind::SubmitTxError(SubmitTxError::TrailingBytes(2)), _,
+            )) => {},
+            _ => panic!("expected TrailingBytes(2)"),
+        }
+        assert!(parse_submitted_tx(&tx_hex[..20]).is_err());
+    }
+
+    #[test]
+    fn test_prevalidate_sends() {
+        let funding = send(&[], &[0xee; 32], &[]);
+        let genesis = typed_genesis(0x01, &[(funding.hash(), 1)], 0, vec![2], 1000);
+        let token_hash = genesis.hash();
+        let other = typed_genesis(0x01, &[(funding.hash(), 2)], 0, vec![], 10);
+        let parents = [&funding, &genesis, &other];
+        // a new token passes, whether we know it or not
+        assert!(_check(&genesis, &[&funding]).is_ok());
+        assert!(_check(&send(&[(token_hash, 1)], &token_hash, &[600, 400]), &parents).is_ok());
+        _assert_rejected(_check(&send(&[(token_hash, 1)], &token_hash, &[600]), &parents),
+                         "BurnsTokenAmount");
+        _assert_rejected(_check(&send(&[(token_hash, 1)], &token_hash, &[1001]), &parents),
+                         "InsufficientTokenInputs");
+        _assert_rejected(_check(&send(&[(token_hash, 1), (other.hash(), 1)], &token_hash, &[1000]),
+                                &parents),
+                         "BurnsTokens");
+        _assert_rejected(_check(&send(&[(token_hash, 2)], &token_hash, &[1]), &parents),
+                         "BurnsTokens");
+        // spending tokens without an SLP message burns them
+        _assert_rejected(_check(&send(&[(token_hash, 1)], &[0xee; 32], &[]), &parents),
+                         "BurnsTokens");
+        assert!(_check(&send(&[(funding.hash(), 1)], &[0xee; 32], &[]), &parents).is_ok());
+    }
+
+    #[test]
+    fn test_prevalidate_mints_and_children() {
+        let funding = send(&[], &[0xee; 32], &[]);
+        let genesis = typed_genesis(0x01, &[(funding.hash(), 1)], 0, vec![2], 1000);
+        let token_hash = genesis.hash();
+        let mint = |inputs: &[([u8; 32], u32)]| typed_tx(0x01, inputs, vec![
+            b"MINT".to_vec(), token_id(&token_hash), vec![2], 10u64.to_be_bytes().to_vec(),
+        ], 2);
+        let parents = [&funding, &genesis];
+        assert!(_check(&mint(&[(token_hash, 2)]), &parents).is_ok());
+        _assert_rejected(_check(&mint(&[(funding.hash(), 1)]), &parents), "MintWithoutBaton");
+
+        let group = typed_genesis(0x81, &[(funding.hash(), 1)], 0, vec![], 10);
+        let group_hash = group.hash();
+        let child = |inputs: &[([u8; 32], u32)]| typed_genesis(0x41, inputs, 0, vec![], 1);
+        let parents = [&funding, &group];
+        assert!(_check(&child(&[(group_hash, 1)]), &parents).is_ok());
+        _assert_rejected(_check(&child(&[(funding.hash(), 1)]), &parents), "NFT1ChildWithoutGroup");
+        _assert_rejected(_check(&child(&[(funding.hash(), 1), (group_hash, 1)]), &parents),
+                         "BurnsTokens");
+    }
+}
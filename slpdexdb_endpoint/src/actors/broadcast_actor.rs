@@ -1,13 +1,25 @@
 use actix::prelude::*;
-use slpdexdb_base::Error;
-use slpdexdb_db::{OutputType, Utxo, SpentUtxo, NewUtxo, TxDelta};
+use cashcontracts::Address;
+use slpdexdb_base::{Error, SLPDEXConfig};
+use slpdexdb_db::{OutputType, Utxo, SpentUtxo, NewUtxo, TxDelta, TradeOffer, AuctionListing,
+                   OrderBookDelta, TradeSpendKind};
+use slpdexdb_db::exch::ExchVersion;
 use slpdexdb_base::SLPAmount;
 use std::collections::{HashMap, HashSet};
 use std::convert::identity;
 use std::sync::Arc;
-use crate::msg::{NewTransactions, TxEvent, TxBroadcastEvent};
+use crate::msg::{NewTransactions, TxEvent, TxBroadcastEvent, AuctionEvent};
 
-pub struct UpdateDbUtxosActor;
+/// Updates the UTXO sets and broadcasts how the order books change with them.
+pub struct UpdateDbUtxosActor {
+    event_broadcast: Addr<BroadcastActor>,
+}
+
+impl UpdateDbUtxosActor {
+    pub fn new(event_broadcast: Addr<BroadcastActor>) -> Self {
+        UpdateDbUtxosActor { event_broadcast }
+    }
+}
 
 impl Actor for UpdateDbUtxosActor {
     type Context = Context<Self>;
@@ -17,8 +29,10 @@ impl Handler<NewTransactions> for UpdateDbUtxosActor {
     type Result = Result<(), Error>;
 
     fn handle(&mut self, msg: NewTransactions, _ctx: &mut Self::Context) -> Self::Result {
+        let db = msg.db.lock().unwrap();
         let mut add_utxos = Vec::new();
         let mut remove_utxos = Vec::new();
+        let mut new_offers = Vec::new();
         for (idx, tx) in msg.tx_history.txs.iter().enumerate() {
             let trade_offer = msg.tx_history.trade_offers.get(&idx);
             for input in tx.inputs.iter() {
@@ -32,8 +46,16 @@ impl Handler<NewTransactions> for UpdateDbUtxosActor {
                     vout: input.output_idx,
                 })
             }
+            let contract_idx = trade_offer.and_then(|trade_offer| trade_offer.output_idx);
+            if let (Some(offer), Some(output_idx), Some(token_hash)) =
+                    (trade_offer, contract_idx, tx.tx_type.token_hash()) {
+                let contract_utxo = SpentUtxo { tx_hash: tx.hash, vout: output_idx };
+                new_offers.push((contract_utxo, *token_hash, offer));
+            }
             for (output_idx, output) in tx.outputs.iter().enumerate() {
                 match &output.output {
+                    // added below, if it's a new offer
+                    _ if contract_idx == Some(output_idx as i32) => {},
                     OutputType::Address(address) => {
                         add_utxos.push(NewUtxo::Address {
                             tx_hash: tx.hash.clone(),
@@ -41,22 +63,82 @@ impl Handler<NewTransactions> for UpdateDbUtxosActor {
                             address: address.clone(),
                         });
                     },
-                    _ if trade_offer.is_some() => {
-                        add_utxos.push(NewUtxo::TradeOffer {
-                            tx_hash: tx.hash.clone(),
-                            vout: output_idx as i32,
-                        });
-                    },
                     _ => {},
                 }
             }
         }
-        msg.db.lock().unwrap().remove_utxos(&remove_utxos)?;
-        msg.db.lock().unwrap().add_utxos(&add_utxos)?;
+        // only offers entering or leaving the UTXO set change the order books, so txs seen
+        // twice don't count twice
+        let new_outpoints = new_offers.iter().map(|(utxo, _, _)| utxo.clone()).collect::<Vec<_>>();
+        let mut known_offers = db.spent_utxos(&new_outpoints)?;
+        known_offers.extend(
+            db.trade_offer_utxos_at(&new_outpoints)?
+                .into_iter()
+                .filter_map(|(_, offer)| Some((offer.tx, offer.output_idx?)))
+        );
+        let mut book_add_offers = HashMap::new();
+        for (utxo, token_hash, offer) in new_offers {
+            if known_offers.contains(&(utxo.tx_hash, utxo.vout)) {
+                continue
+            }
+            add_utxos.push(NewUtxo::TradeOffer { tx_hash: utxo.tx_hash, vout: utxo.vout });
+            book_add_offers
+                .entry((token_hash, offer.exch_version))
+                .or_insert_with(Vec::new)
+                .push(offer.clone());
+        }
+        // any input can take an offer, not just the ones of trade offer txs
+        let spent_utxos = msg.tx_history.txs.iter()
+            .flat_map(|tx| tx.inputs.iter())
+            .map(|input| SpentUtxo { tx_hash: input.output_tx, vout: input.output_idx })
+            .collect::<Vec<_>>();
+        let mut book_remove_offers = HashMap::new();
+        for (token_hash, offer) in db.trade_offer_utxos_at(&spent_utxos)? {
+            if let Some(output_idx) = offer.output_idx {
+                remove_utxos.push(SpentUtxo { tx_hash: offer.tx, vout: output_idx });
+            }
+            book_remove_offers
+                .entry((token_hash, offer.exch_version))
+                .or_insert_with(Vec::new)
+                .push(offer);
+        }
+        db.remove_utxos(&remove_utxos)?;
+        db.add_utxos(&add_utxos)?;
+        let deltas = _order_book_deltas(book_add_offers, book_remove_offers);
+        if deltas.len() > 0 {
+            self.event_broadcast
+                .do_send(TxBroadcastEvent::OrderBookDeltas {
+                    deltas,
+                    subscribers: msg.subscribers.clone(),
+                });
+        }
         Ok(())
     }
 }
 
+fn _order_book_deltas(mut add_offers: HashMap<([u8; 32], ExchVersion), Vec<TradeOffer>>,
+                      mut remove_offers: HashMap<([u8; 32], ExchVersion), Vec<TradeOffer>>)
+        -> Vec<OrderBookDelta> {
+    let books = add_offers.keys()
+        .chain(remove_offers.keys())
+        .cloned()
+        .collect::<HashSet<_>>();
+    books.into_iter()
+        .filter_map(|book| {
+            let (token_hash, exch_version) = book;
+            let add_offers = add_offers.remove(&book).unwrap_or_default();
+            let remove_offers = remove_offers.remove(&book).unwrap_or_default();
+            let decimals = add_offers.iter().chain(remove_offers.iter())
+                .next()?
+                .sell_amount_token
+                .decimals();
+            let delta = OrderBookDelta::new(token_hash, exch_version, decimals,
+                                            &add_offers, &remove_offers);
+            if delta.is_empty() { None } else { Some(delta) }
+        })
+        .collect()
+}
+
 pub struct BroadcastAddressUtxosActor {
     event_broadcast: Addr<BroadcastActor>,
 }
@@ -172,6 +254,169 @@ impl Handler<NewTransactions> for BroadcastTradeOfferUtxosActor {
     }
 }
 
+pub struct BroadcastTradeFillsActor {
+    event_broadcast: Addr<BroadcastActor>,
+}
+
+impl BroadcastTradeFillsActor {
+    pub fn new(event_broadcast: Addr<BroadcastActor>) -> Self {
+        BroadcastTradeFillsActor { event_broadcast }
+    }
+}
+
+impl Actor for BroadcastTradeFillsActor {
+    type Context = Context<Self>;
+}
+
+impl Handler<NewTransactions> for BroadcastTradeFillsActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: NewTransactions, _ctx: &mut Self::Context) -> Self::Result {
+        let mut token_fills = HashMap::new();
+        for spend in msg.tx_history.trade_spends.iter() {
+            if spend.kind == TradeSpendKind::Fill {
+                token_fills
+                    .entry(spend.token_hash)
+                    .or_insert_with(Vec::new)
+                    .push(spend.clone());
+            }
+        }
+        if token_fills.len() > 0 {
+            self.event_broadcast
+                .do_send(TxBroadcastEvent::TradeFills {
+                    fills: token_fills,
+                    subscribers: msg.subscribers.clone(),
+                });
+        }
+        Ok(())
+    }
+}
+
+pub struct BroadcastPandaTradeOffersActor {
+    event_broadcast: Addr<BroadcastActor>,
+    config: SLPDEXConfig,
+}
+
+impl BroadcastPandaTradeOffersActor {
+    pub fn new(event_broadcast: Addr<BroadcastActor>, config: SLPDEXConfig) -> Self {
+        BroadcastPandaTradeOffersActor { event_broadcast, config }
+    }
+}
+
+impl Actor for BroadcastPandaTradeOffersActor {
+    type Context = Context<Self>;
+}
+
+impl Handler<NewTransactions> for BroadcastPandaTradeOffersActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: NewTransactions, _ctx: &mut Self::Context) -> Self::Result {
+        let mut offer_txs = Vec::new();
+        let mut remove_utxos = Vec::new();
+        for (idx, tx) in msg.tx_history.txs.iter().enumerate() {
+            if !msg.tx_history.pandas_slp.contains(&idx) {
+                continue
+            }
+            if let Some(TradeOffer { output_idx: Some(_), .. }) = msg.tx_history.trade_offers.get(&idx) {
+                offer_txs.push(tx.hash.clone());
+            }
+            // any panda tx spends the panda's previous UTXO, which might have been an offer
+            remove_utxos.extend(tx.inputs.iter().map(|input| SpentUtxo {
+                tx_hash: input.output_tx,
+                vout: input.output_idx,
+            }));
+        }
+        if remove_utxos.len() == 0 {
+            return Ok(())
+        }
+        let add_utxos = if offer_txs.len() > 0 {
+            msg.db.lock().unwrap().pandas_for_sale(&self.config.panda_token_hash, Some(offer_txs))?
+        } else {
+            vec![]
+        };
+        self.event_broadcast
+            .do_send(TxBroadcastEvent::PandaTradeOfferUtxoDelta {
+                add_utxos,
+                remove_utxos,
+                subscribers: msg.subscribers.clone(),
+            });
+        Ok(())
+    }
+}
+
+pub struct UpdatePandaAuctionsActor {
+    event_broadcast: Addr<BroadcastActor>,
+}
+
+impl UpdatePandaAuctionsActor {
+    pub fn new(event_broadcast: Addr<BroadcastActor>) -> Self {
+        UpdatePandaAuctionsActor { event_broadcast }
+    }
+}
+
+impl Actor for UpdatePandaAuctionsActor {
+    type Context = Context<Self>;
+}
+
+impl Handler<NewTransactions> for UpdatePandaAuctionsActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: NewTransactions, _ctx: &mut Self::Context) -> Self::Result {
+        let db = msg.db.lock().unwrap();
+        let mut events = Vec::new();
+        for (idx, auction) in msg.tx_history.auctions.iter() {
+            events.push(AuctionEvent::Listed(AuctionListing {
+                auction_tx: msg.tx_history.txs[*idx].hash,
+                token_hash: auction.token_hash,
+                panda_tx_hash: auction.panda_tx_hash,
+                panda_output_idx: auction.panda_output_idx as i32,
+                auction: auction.auction,
+                seller_address: auction.seller_address.clone(),
+            }));
+        }
+        let tip_height = db.header_tip()?.map(|(_, height)| height).unwrap_or(0);
+        for (idx, tx) in msg.tx_history.txs.iter().enumerate() {
+            if !msg.tx_history.pandas_slp.contains(&idx) {
+                continue
+            }
+            let spent_utxos = tx.inputs.iter()
+                .map(|input| SpentUtxo { tx_hash: input.output_tx, vout: input.output_idx })
+                .collect::<Vec<_>>();
+            // unconfirmed txs end up in the next block at the earliest
+            let height = tx.height.unwrap_or(tip_height + 1);
+            let panda_address = tx.outputs.iter()
+                .find(|output| output.value_token.base_amount() > 0)
+                .and_then(|output| output.output.address());
+            for auction in db.open_auctions_at(&spent_utxos)? {
+                // the panda moved; it's a sale if it went to someone else and the seller got at
+                // least the price at the height of the spend
+                let is_seller = |address: &Address| address.bytes() == auction.seller_address.bytes();
+                let price = auction.auction.price_at(height);
+                let paid = tx.outputs.iter()
+                    .filter(|output| output.output.address().map(is_seller).unwrap_or(false))
+                    .map(|output| output.value_satoshis)
+                    .sum::<u64>();
+                let has_buyer = panda_address.map(|address| !is_seller(address)).unwrap_or(false);
+                if has_buyer && paid >= price {
+                    db.close_auction(&auction.auction_tx, &tx.hash, Some(paid))?;
+                    events.push(AuctionEvent::Sold { auction, tx_hash: tx.hash, price: paid });
+                } else {
+                    db.close_auction(&auction.auction_tx, &tx.hash, None)?;
+                    events.push(AuctionEvent::Cancelled { auction, tx_hash: tx.hash });
+                }
+            }
+        }
+        if events.len() > 0 {
+            self.event_broadcast
+                .do_send(TxBroadcastEvent::PandaAuctions {
+                    events,
+                    subscribers: msg.subscribers.clone(),
+                });
+        }
+        Ok(())
+    }
+}
+
 pub struct BroadcastTxHistoryActor {
     event_broadcast: Addr<BroadcastActor>,
 }
@@ -310,6 +555,45 @@ impl Handler<TxBroadcastEvent> for BroadcastActor {
                     }
                 }
             },
+            TxBroadcastEvent::OrderBookDeltas { deltas, subscribers } => {
+                let subscribers = subscribers.lock().unwrap();
+                for delta in deltas {
+                    if let Some(subscribers) = subscribers.subscribers_order_book.get(&delta.token_hash) {
+                        let new_msg = TxEvent::OrderBookDelta { delta: Arc::new(delta) };
+                        for subscriber in subscribers {
+                            subscriber.do_send(new_msg.clone()).unwrap();   // TODO: handle error
+                        }
+                    }
+                }
+            },
+            TxBroadcastEvent::TradeFills { fills, subscribers } => {
+                let subscribers = subscribers.lock().unwrap();
+                for (token_hash, fills) in fills {
+                    if let Some(subscribers) = subscribers.subscribers_order_book.get(&token_hash) {
+                        let new_msg = TxEvent::TradeFills { token_hash, fills: Arc::new(fills) };
+                        for subscriber in subscribers {
+                            subscriber.do_send(new_msg.clone()).unwrap();   // TODO: handle error
+                        }
+                    }
+                }
+            },
+            TxBroadcastEvent::PandaTradeOfferUtxoDelta { add_utxos, remove_utxos, subscribers } => {
+                let subscribers = subscribers.lock().unwrap();
+                let new_msg = TxEvent::PandaTradeOfferUtxoDelta {
+                    add_utxos: Arc::new(add_utxos),
+                    remove_utxos: Arc::new(remove_utxos),
+                };
+                for subscriber in subscribers.subscribers_panda_market.iter() {
+                    subscriber.do_send(new_msg.clone()).unwrap();   // TODO: handle error
+                }
+            },
+            TxBroadcastEvent::PandaAuctions { events, subscribers } => {
+                let subscribers = subscribers.lock().unwrap();
+                let new_msg = TxEvent::PandaAuctions { events: Arc::new(events) };
+                for subscriber in subscribers.subscribers_panda_market.iter() {
+                    subscriber.do_send(new_msg.clone()).unwrap();   // TODO: handle error
+                }
+            },
             TxBroadcastEvent::AddressNewTxDeltas { tx_deltas, subscribers } => {
                 let subscribers = subscribers.lock().unwrap();
                 for (address, tx_delta) in tx_deltas {
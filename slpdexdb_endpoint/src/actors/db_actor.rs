@@ -1,8 +1,10 @@
 use actix::prelude::*;
 use diesel::prelude::*;
 use slpdexdb_base::{Error, GENESIS};
-use slpdexdb_db::Db;
+use slpdexdb_db::{Db, SupplyChange, Candle};
+use std::time::{SystemTime, UNIX_EPOCH};
 use slpdexdb_node::{HeaderTipQuery, HeaderTip, AddHeadersQuery};
+use crate::msg::{FetchTokenBurns, FetchDestroyedNFTs, FetchCandles, CANDLE_COUNT};
 
 
 pub struct DbActor {
@@ -44,3 +46,28 @@ impl Handler<AddHeadersQuery> for DbActor {
         Ok(())
     }
 }
+
+impl Handler<FetchTokenBurns> for DbActor {
+    type Result = Result<Vec<SupplyChange>, Error>;
+
+    fn handle(&mut self, msg: FetchTokenBurns, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.db.token_burns(&msg.token_hash)?)
+    }
+}
+
+impl Handler<FetchCandles> for DbActor {
+    type Result = Result<Vec<Candle>, Error>;
+
+    fn handle(&mut self, msg: FetchCandles, _ctx: &mut Self::Context) -> Self::Result {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        Ok(self.db.candles(&msg.token_hash, msg.interval, CANDLE_COUNT, now)?)
+    }
+}
+
+impl Handler<FetchDestroyedNFTs> for DbActor {
+    type Result = Result<Vec<[u8; 32]>, Error>;
+
+    fn handle(&mut self, msg: FetchDestroyedNFTs, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.db.destroyed_nft1_children(&msg.group_hash)?)
+    }
+}
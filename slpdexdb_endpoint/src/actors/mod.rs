@@ -3,6 +3,7 @@ mod resync_actor;
 mod tx_actor;
 mod peers_actor;
 mod ws_actor;
+mod outgoing_tx_actor;
 pub mod broadcast_actor;
 
 pub use db_actor::*;
@@ -10,3 +11,4 @@ pub use resync_actor::*;
 pub use tx_actor::*;
 pub use peers_actor::*;
 pub use ws_actor::*;
+pub use outgoing_tx_actor::*;
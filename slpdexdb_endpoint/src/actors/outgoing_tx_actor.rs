@@ -0,0 +1,181 @@
+use actix::prelude::*;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use cashcontracts::tx_hash_to_hex;
+use slpdexdb_base::Error;
+use slpdexdb_db::{Db, OutgoingTx, OutgoingTxStatus};
+use slpdexdb_node::actors::{IncomingMsg, OutgoingMsg};
+use slpdexdb_node::messages::{InvMessage, ObjectType, RejectMessage, TxMessage};
+use slpdexdb_node::{MessagePacket, NodeMessage};
+use crate::msg::{RegisterOutgoing, FetchStuckOutgoingTxs};
+
+/// How often the outgoing txs are checked, in seconds.
+const CHECK_INTERVAL: u64 = 60;
+/// How often unconfirmed outgoing txs are rebroadcast, in seconds.
+const REBROADCAST_INTERVAL: i64 = 10 * 60;
+/// How often stuck txs are logged, in seconds.
+const STUCK_REPORT_INTERVAL: u64 = 10 * 60;
+/// Unconfirmed txs older than this are reported as stuck, in seconds.
+const STUCK_AFTER: i64 = 60 * 60;
+/// Unconfirmed txs older than this aren't rebroadcast anymore, in seconds.
+const GIVE_UP_AFTER: i64 = 24 * 60 * 60;
+/// After this many `reject`s, a tx isn't rebroadcast anymore.
+const MAX_REJECTS: i32 = 3;
+
+/// Rebroadcasts the txs we sent (births, fan-outs) until they confirm, based on the `inv` and
+/// `reject` messages of our peers. A tx is given up on once several rejects came in or it didn't
+/// confirm for `GIVE_UP_AFTER` seconds.
+pub struct OutgoingTxActor {
+    db: Arc<Mutex<Db>>,
+    outgoing_recipient: Option<Recipient<OutgoingMsg>>,
+}
+
+impl OutgoingTxActor {
+    pub fn new(db: Arc<Mutex<Db>>) -> Self {
+        OutgoingTxActor { db, outgoing_recipient: None }
+    }
+
+    fn _now() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+    }
+
+    fn _rebroadcast(&self) -> Result<(), Error> {
+        let outgoing = match &self.outgoing_recipient {
+            Some(outgoing) => outgoing,
+            None => return Ok(()),
+        };
+        let now = Self::_now();
+        let db = self.db.lock().unwrap();
+        let outgoing_txs = db.outgoing_txs(&[OutgoingTxStatus::Pending, OutgoingTxStatus::Seen])?;
+        // txs which confirmed while we weren't connected, found by the resync
+        let mined_txs = db.txs(outgoing_txs.iter().map(|outgoing_tx| outgoing_tx.hash))?
+            .into_iter()
+            .filter(|(_, tx)| tx.height.is_some())
+            .map(|(hash, _)| hash)
+            .collect::<HashSet<_>>();
+        db.update_outgoing_txs_status(mined_txs.iter().cloned(), OutgoingTxStatus::Confirmed, None)?;
+        for outgoing_tx in outgoing_txs.iter() {
+            if mined_txs.contains(&outgoing_tx.hash) {
+                continue
+            }
+            if outgoing_tx.is_expired(now, GIVE_UP_AFTER) {
+                eprintln!("giving up on {:?} tx {}", outgoing_tx.kind, tx_hash_to_hex(&outgoing_tx.hash));
+                db.update_outgoing_txs_status(
+                    vec![outgoing_tx.hash].into_iter(),
+                    OutgoingTxStatus::Rejected,
+                    Some(outgoing_tx.reject_reason.clone().unwrap_or("not confirmed in time".to_string())),
+                )?;
+                continue
+            }
+            if !outgoing_tx.is_due(now, REBROADCAST_INTERVAL) {
+                continue
+            }
+            println!("rebroadcasting {:?} tx {} ({} broadcasts so far)",
+                     outgoing_tx.kind, tx_hash_to_hex(&outgoing_tx.hash), outgoing_tx.n_broadcasts);
+            let packet = MessagePacket::from_payload(TxMessage::command(), outgoing_tx.raw_tx.clone());
+            if let Err(err) = outgoing.do_send(OutgoingMsg(packet)) {
+                // not counted as broadcast, so it's due again on the next check
+                eprintln!("rebroadcasting tx {} failed: {}", tx_hash_to_hex(&outgoing_tx.hash), err);
+                continue
+            }
+            db.record_outgoing_tx_broadcast(&outgoing_tx.hash, now)?;
+        }
+        Ok(())
+    }
+
+    fn _report_stuck_txs(&self) -> Result<(), Error> {
+        let db = self.db.lock().unwrap();
+        for stuck_tx in Self::_stuck_txs(&db, Self::_now())? {
+            eprintln!("stuck {:?} tx {}: {:?}, first broadcast at {}, {} broadcasts{}",
+                      stuck_tx.kind, tx_hash_to_hex(&stuck_tx.hash), stuck_tx.status,
+                      stuck_tx.first_broadcast, stuck_tx.n_broadcasts,
+                      stuck_tx.reject_reason.as_ref()
+                          .map(|reason| format!(", rejected: {}", reason))
+                          .unwrap_or(String::new()));
+        }
+        Ok(())
+    }
+
+    fn _stuck_txs(db: &Db, now: i64) -> Result<Vec<OutgoingTx>, Error> {
+        Ok(db.outgoing_txs(&[OutgoingTxStatus::Pending,
+                             OutgoingTxStatus::Seen,
+                             OutgoingTxStatus::Rejected])?
+            .into_iter()
+            .filter(|outgoing_tx| outgoing_tx.is_stuck(now, STUCK_AFTER))
+            .collect())
+    }
+}
+
+impl Actor for OutgoingTxActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(Duration::from_secs(CHECK_INTERVAL), |actor, _ctx| {
+            actor._rebroadcast()
+                .map_err(|err| eprintln!("rebroadcast failed: {}", err))
+                .unwrap_or(());
+        });
+        ctx.run_interval(Duration::from_secs(STUCK_REPORT_INTERVAL), |actor, _ctx| {
+            actor._report_stuck_txs()
+                .map_err(|err| eprintln!("reporting stuck txs failed: {}", err))
+                .unwrap_or(());
+        });
+    }
+}
+
+impl Handler<IncomingMsg<InvMessage>> for OutgoingTxActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: IncomingMsg<InvMessage>, _ctx: &mut Self::Context) -> Self::Result {
+        let tx_hashes = msg.0.inv_vectors.iter()
+            .filter(|inv_vector| inv_vector.type_id == ObjectType::Tx)
+            .map(|inv_vector| inv_vector.hash);
+        self.db.lock().unwrap().update_outgoing_txs_status(tx_hashes, OutgoingTxStatus::Seen, None)?;
+        Ok(())
+    }
+}
+
+impl Handler<IncomingMsg<RejectMessage>> for OutgoingTxActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: IncomingMsg<RejectMessage>, _ctx: &mut Self::Context) -> Self::Result {
+        let reject = &msg.0;
+        let tx_hash = match reject.hash {
+            Some(tx_hash) if reject.message == TxMessage::command() => tx_hash,
+            _ => return Ok(()),
+        };
+        let db = self.db.lock().unwrap();
+        let n_rejects = match db.record_outgoing_tx_reject(&tx_hash, &reject.reason_str())? {
+            Some(n_rejects) => n_rejects,
+            None => return Ok(()),
+        };
+        eprintln!("peer rejected tx {} (code {:#x}, {} rejects): {}",
+                  tx_hash_to_hex(&tx_hash), reject.ccode, n_rejects, reject.reason_str());
+        // a single peer's reject isn't final, e.g. it may just have a higher fee filter
+        if n_rejects >= MAX_REJECTS {
+            db.update_outgoing_txs_status(
+                vec![tx_hash].into_iter(),
+                OutgoingTxStatus::Rejected,
+                Some(reject.reason_str()),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Handler<FetchStuckOutgoingTxs> for OutgoingTxActor {
+    type Result = Result<Vec<OutgoingTx>, Error>;
+
+    fn handle(&mut self, _msg: FetchStuckOutgoingTxs, _ctx: &mut Self::Context) -> Self::Result {
+        Self::_stuck_txs(&self.db.lock().unwrap(), Self::_now())
+    }
+}
+
+impl Handler<RegisterOutgoing> for OutgoingTxActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: RegisterOutgoing, _ctx: &mut Self::Context) -> Self::Result {
+        self.outgoing_recipient = Some(msg.recipient);
+    }
+}
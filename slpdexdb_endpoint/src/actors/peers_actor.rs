@@ -5,26 +5,30 @@ use std::sync::Arc;
 use slpdexdb_base::Error;
 use slpdexdb_node::actors::{NodeActor, IncomingMsg, OutgoingMsg};
 use slpdexdb_node::DbActor;
-use slpdexdb_node::msg::Subscribe;
-use slpdexdb_node::messages::{TxMessage, BlockMessage};
+use slpdexdb_node::msg::{Subscribe, PeerFeeFilter, PeerDisconnected};
+use slpdexdb_node::messages::{TxMessage, BlockMessage, InvMessage, RejectMessage};
 use slpdexdb_node::NodeMessage;
 
 
-use crate::actors::TxActor;
+use crate::actors::{TxActor, OutgoingTxActor};
 use crate::msg::ConnectToPeer;
 
 
 pub struct PeersActor {
     tx_actor: Addr<TxActor>,
     db_actor: Addr<DbActor>,
+    outgoing_tx_actor: Addr<OutgoingTxActor>,
     nodes: Vec<Addr<NodeActor>>,
 }
 
 impl PeersActor {
-    pub fn new(tx_actor: Addr<TxActor>, db_actor: Addr<DbActor>) -> Self {
+    pub fn new(tx_actor: Addr<TxActor>,
+               db_actor: Addr<DbActor>,
+               outgoing_tx_actor: Addr<OutgoingTxActor>) -> Self {
         PeersActor {
             tx_actor,
             db_actor,
+            outgoing_tx_actor,
             nodes: Vec::new(),
         }
     }
@@ -57,8 +61,20 @@ impl Handler<ConnectToPeer> for PeersActor {
                     println!("connected");
                     let node = NodeActor::create_from_stream_db(stream, db_addr);
                     let node2 = node.clone();
+                    let node3 = node.clone();
+                    let node4 = node.clone();
+                    let node5 = node.clone();
+                    let node6 = node.clone();
+                    let own_addr3 = own_addr.clone();
+                    let own_addr4 = own_addr.clone();
+                    let own_addr5 = own_addr.clone();
+                    let own_addr6 = own_addr.clone();
                     node.send(Subscribe::Tx(own_addr.clone().recipient())).from_err()
                         .and_then(move |_| node2.send(Subscribe::Block(own_addr2.clone().recipient())).from_err())
+                        .and_then(move |_| node3.send(Subscribe::FeeFilter(own_addr3.recipient())).from_err())
+                        .and_then(move |_| node4.send(Subscribe::Inv(own_addr4.recipient())).from_err())
+                        .and_then(move |_| node5.send(Subscribe::Reject(own_addr5.recipient())).from_err())
+                        .and_then(move |_| node6.send(Subscribe::Disconnected(own_addr6.recipient())).from_err())
                         .and_then(move |_| own_addr.send(PeerConnected { node }).from_err())
                 })
                 .map_err(|err| {
@@ -93,6 +109,40 @@ impl Handler<IncomingMsg<BlockMessage>> for PeersActor {
     }
 }
 
+impl Handler<PeerFeeFilter> for PeersActor {
+    type Result = Response<(), Error>;
+
+    fn handle(&mut self, msg: PeerFeeFilter, _ctx: &mut Self::Context) -> Self::Result {
+        Response::fut(self.tx_actor.send(msg).from_err().and_then(identity))
+    }
+}
+
+impl Handler<PeerDisconnected> for PeersActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: PeerDisconnected, _ctx: &mut Self::Context) -> Self::Result {
+        println!("disconnected from {}", msg.peer_addr);
+        self.nodes.retain(|node| node.connected());
+        self.tx_actor.do_send(msg);
+    }
+}
+
+impl Handler<IncomingMsg<InvMessage>> for PeersActor {
+    type Result = Response<(), Error>;
+
+    fn handle(&mut self, msg: IncomingMsg<InvMessage>, _ctx: &mut Self::Context) -> Self::Result {
+        Response::fut(self.outgoing_tx_actor.send(msg).from_err().and_then(identity))
+    }
+}
+
+impl Handler<IncomingMsg<RejectMessage>> for PeersActor {
+    type Result = Response<(), Error>;
+
+    fn handle(&mut self, msg: IncomingMsg<RejectMessage>, _ctx: &mut Self::Context) -> Self::Result {
+        Response::fut(self.outgoing_tx_actor.send(msg).from_err().and_then(identity))
+    }
+}
+
 impl Handler<OutgoingMsg> for PeersActor {
     type Result = ();
 
@@ -2,50 +2,56 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use std::sync::Arc;
 use std::collections::HashSet;
 use actix::prelude::*;
-use cashcontracts::{Address, AddressType, tx_hex_to_hash, TxOutpoint};
-use slpdexdb_base::{Error, ErrorKind, SLPDEXConfig, PandaError};
+use cashcontracts::{Address, AddressType, tx_hex_to_hash, tx_hash_to_hex, TxOutpoint, Tx};
+use slpdexdb_base::{Error, ErrorKind, SLPDEXConfig, SLPAmount, PandaError, SignerError};
 use slpdexdb_node::actors::OutgoingMsg;
 use slpdexdb_node::messages::TxMessage;
 use slpdexdb_node::NodeMessage;
 use slpdexdb_db::{tx_hash_from_slice, tx_hash_from_le_slice};
-use slpdexdb_db::{Db, TxSource, TokenSource, UpdateSubject, UpdateSubjectType, UpdateHistory,
-                  TxHistory, TxFilter, Token, OutputType, Confirmedness, TxType, panda_tools};
-use crate::msg::{ResyncAddress, ProcessTransactions, NewTransactions, ProcessBlock, RegisterOutgoing};
+use slpdexdb_db::{Db, ChainSource, UpdateSubject, UpdateSubjectType, UpdateHistory,
+                  TxHistory, TxFilter, Token, OutputType, Confirmedness, TxType, OutgoingTxKind,
+                  OutgoingTxStatus, Utxo, panda_tools};
+use crate::msg::{ResyncAddress, ProcessTransactions, NewTransactions, ProcessBlock, RegisterOutgoing,
+                 TxEvent, AuctionEvent};
 use cryptopandas_base::genomics::{create_seed, mix_genes};
 use cryptopandas_base::utils::{pack_genes};
 use std::collections::HashMap;
 
 use slpdexdb_db::panda;
+use slpdexdb_db::fan_out::{fan_out_target, slp_fan_out_tx};
+use slpdexdb_db::signer::Signer;
+use slpdexdb_db::hd_wallet::{HdWallet, WalletRole, WalletChain};
+use slpdexdb_db::slp_validator::{SlpValidator, RestTxProvider, TxProvider};
+use slpdexdb_db::coin_select::{self, CoinSelectTarget, CoinSelectStrategy};
 
 
-fn _resync(db: &Db, config: &SLPDEXConfig) -> Result<(), Error> {
-    _init_panda_token(db, config)?;
-    //_resync_tokens(db)?;
-    //_resync_trade_offers(db, config, true)?;
-    //_resync_trade_offers(db, config, false)?;
+fn _resync(db: &Db, config: &SLPDEXConfig, chain_source: &dyn ChainSource) -> Result<(), Error> {
+    _init_panda_token(db, config, chain_source)?;
+    //_resync_tokens(db, chain_source)?;
+    //_resync_trade_offers(db, config, chain_source, true)?;
+    //_resync_trade_offers(db, config, chain_source, false)?;
     Ok(())
 }
 
-fn _init_panda_token(db: &Db, config: &SLPDEXConfig) -> Result<(), Error> {
+fn _init_panda_token(db: &Db, config: &SLPDEXConfig, chain_source: &dyn ChainSource)
+        -> Result<(), Error> {
     let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-    let token_source = TokenSource::new();
-    let tx_source = TxSource::new();
     let token_hash = tx_hex_to_hash("af5fb817275c12a403df832cf61af135d0cd7a63f9c0fedb10ff3b2b50799533").unwrap();
-    let token_entries = token_source.request_tokens(&[TxFilter::TokenId(token_hash.clone())])?;
+    let token_entries = chain_source.request_tokens(&[TxFilter::TokenId(token_hash.clone())])?;
     let tokens = token_entries.into_iter()
         .filter_map(|token_entry| {
             Token::from_entry(token_entry).map_err(|err| eprintln!("token error: {}", err)).ok()
         })
         .collect::<Vec<_>>();
-    let tx_entries = tx_source.request_txs(&[TxFilter::TxHash(token_hash)], config, Confirmedness::Confirmed)?;
-    let history = TxHistory::from_entries(&tx_entries, timestamp as i64, config);
+    let tx_entries = chain_source.request_txs(&[TxFilter::TxHash(token_hash)], config, Confirmedness::Confirmed)?;
+    let mut history = TxHistory::from_entries(&tx_entries, timestamp as i64, config);
+    history.record_mint_batons(db)?;
     db.add_tokens(&tokens)?;
     db.add_tx_history(&history)?;
     Ok(())
 }
 
-fn _resync_tokens(db: &Db) -> Result<(), Error> {
-    let token_source = TokenSource::new();
+fn _resync_tokens(db: &Db, chain_source: &dyn ChainSource) -> Result<(), Error> {
     loop {
         let current_height = db.header_tip()?.map(|(_, height)| height).unwrap_or(0);
         let subject = UpdateSubject {
@@ -56,7 +62,7 @@ fn _resync_tokens(db: &Db) -> Result<(), Error> {
         let last_update = db.last_update(subject.clone())?
             .unwrap_or(UpdateHistory::initial(subject));
         println!("last update: {:?}", last_update);
-        let token_entries = token_source.request_tokens(&last_update.next_filters())?;
+        let token_entries = chain_source.request_tokens(&last_update.next_filters())?;
         let tokens = token_entries.into_iter()
             .filter_map(|token_entry| {
                 Token::from_entry(token_entry).map_err(|err| eprintln!("token error: {}", err)).ok()
@@ -76,8 +82,10 @@ fn _resync_tokens(db: &Db) -> Result<(), Error> {
     Ok(())
 }
 
-fn _resync_trade_offers(db: &Db, config: &SLPDEXConfig, is_confirmed: bool) -> Result<(), Error> {
-    let tx_source = TxSource::new();
+fn _resync_trade_offers(db: &Db,
+                        config: &SLPDEXConfig,
+                        chain_source: &dyn ChainSource,
+                        is_confirmed: bool) -> Result<(), Error> {
     loop {
         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
         let current_height = db.header_tip()?.map(|(_, height)| height).unwrap_or(0);
@@ -90,7 +98,7 @@ fn _resync_trade_offers(db: &Db, config: &SLPDEXConfig, is_confirmed: bool) -> R
         };
         let last_update = db.last_update(subject.clone())?
                 .unwrap_or_else(|| UpdateHistory::initial(subject.clone()));
-        let tx_entries = tx_source.request_txs(&last_update.next_filters(), config, confirmedness)?;
+        let tx_entries = chain_source.request_txs(&last_update.next_filters(), config, confirmedness)?;
         let history = TxHistory::from_entries(&tx_entries, timestamp as i64, config);
         if history.txs.len() == 0 {
             break
@@ -105,9 +113,12 @@ fn _resync_trade_offers(db: &Db, config: &SLPDEXConfig, is_confirmed: bool) -> R
     Ok(())
 }
 
-fn _resync_address(db: &Db, config: &SLPDEXConfig, address: &Address, is_confirmed: bool) -> Result<(), Error> {
+fn _resync_address(db: &Db,
+                   config: &SLPDEXConfig,
+                   chain_source: &dyn ChainSource,
+                   address: &Address,
+                   is_confirmed: bool) -> Result<(), Error> {
     loop {
-        let tx_source = TxSource::new();
         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
         let current_height = db.header_tip()?.map(|(_, height)| height).unwrap_or(0);
         let confirmedness = if is_confirmed { Confirmedness::Confirmed }
@@ -120,9 +131,11 @@ fn _resync_address(db: &Db, config: &SLPDEXConfig, address: &Address, is_confirm
         let last_update = db.last_update(subject.clone())?
             .unwrap_or(UpdateHistory::initial(subject.clone()));
         println!("last update: {}", last_update);
-        let tx_entries = tx_source.request_txs(&last_update.next_filters(), config, confirmedness)?;
-        let history = TxHistory::from_entries(&tx_entries, timestamp as i64, config);
+        let tx_entries = chain_source.request_txs(&last_update.next_filters(), config, confirmedness)?;
+        let mut history = TxHistory::from_entries(&tx_entries, timestamp as i64, config);
         if history.txs.len() > 0 {
+            history.record_mint_batons(db)?;
+            history.track_trade_spends(db)?;
             db.add_tx_history(&history)?;
         }
         db.add_update_history(
@@ -140,16 +153,164 @@ fn _resync_address(db: &Db, config: &SLPDEXConfig, address: &Address, is_confirm
     Ok(())
 }
 
+/// Derives the addresses the operator always uses, then resyncs every derived address in use,
+/// including change addresses handed out earlier. The fee address is checked against the config
+/// at startup.
+fn _resync_wallet(db: &Db,
+                  config: &SLPDEXConfig,
+                  chain_source: &dyn ChainSource,
+                  wallet: &HdWallet) -> Result<(), Error> {
+    for &role in &[WalletRole::Fees, WalletRole::Births, WalletRole::FanOuts] {
+        wallet.used_signer(db, role, WalletChain::External, 0)?;
+    }
+    for wallet_address in db.wallet_addresses()? {
+        let address = Address::from_slice(AddressType::P2PKH, &wallet_address.address)
+            .ok_or_else(|| -> Error {
+                ErrorKind::SignerError(
+                    SignerError::InvalidWalletAddress(hex::encode(&wallet_address.address))
+                ).into()
+            })?;
+        _resync_address(db, config, chain_source, &address, true)?;
+        _resync_address(db, config, chain_source, &address, false)?;
+    }
+    Ok(())
+}
+
+/// Below this many group UTXOs at the births address, the tokens of the fan-outs address are
+/// split into new ones.
+const MIN_BIRTH_UTXOS: usize = 4;
+/// An SLP SEND has at most 19 amounts, one is kept for the token change.
+const MAX_FAN_OUT_OUTPUTS: usize = 18;
+
+fn _is_group_utxo(utxo: &Utxo, group_hash: &[u8; 32]) -> bool {
+    utxo.token_hash.as_ref() == Some(group_hash) && utxo.value_token.base_amount() == 1
+}
+
+fn _utxo_outpoint(utxo: &Utxo) -> (TxOutpoint, u64) {
+    (TxOutpoint { tx_hash: tx_hash_from_le_slice(&utxo.tx_hash), vout: utxo.vout as u32 },
+     utxo.value_satoshis)
+}
+
+/// A UTXO of the births address holding a single panda group token, for the next birth.
+fn _birth_group_utxo(db: &Db,
+                     births_address: &Address,
+                     group_hash: &[u8; 32]) -> Result<(TxOutpoint, u64), Error> {
+    db.utxos_address(births_address)?.iter()
+        .find(|utxo| _is_group_utxo(utxo, group_hash))
+        .map(_utxo_outpoint)
+        .ok_or_else(|| ErrorKind::PandaError(PandaError::NoParentUtxosLeft).into())
+}
+
+/// Splits the group tokens of the fan-outs address into single token UTXOs at the births
+/// address if births are running low. Returns the fan-out tx, if one was built.
+fn _refill_births(db: &Db,
+                  config: &SLPDEXConfig,
+                  wallet: &HdWallet) -> Result<Option<Tx>, Error> {
+    let group_hash = config.panda_token_hash;
+    let births_address = wallet.signer(WalletRole::Births, WalletChain::External, 0)?.address();
+    let n_birth_utxos = db.utxos_address(&births_address)?.iter()
+        .filter(|utxo| _is_group_utxo(utxo, &group_hash))
+        .count();
+    if n_birth_utxos >= MIN_BIRTH_UTXOS {
+        return Ok(None);
+    }
+    let fan_outs_signer = wallet.signer(WalletRole::FanOuts, WalletChain::External, 0)?;
+    let fan_outs_address = fan_outs_signer.address();
+    let token_utxos = db.utxos_address(&fan_outs_address)?.into_iter()
+        .filter(|utxo| utxo.token_hash == Some(group_hash))
+        .collect::<Vec<_>>();
+    let token_total = token_utxos.iter().map(|utxo| utxo.value_token.base_amount()).sum::<i128>();
+    if token_total <= 0 {
+        return Ok(None);
+    }
+    let n_outputs = token_total.min(MAX_FAN_OUT_OUTPUTS as i128) as usize;
+    let token_change = token_total - n_outputs as i128;
+    let token_inputs = token_utxos.iter().map(_utxo_outpoint).collect::<Vec<_>>();
+    let fee_policy = &config.fee_policy;
+    let target = fan_out_target(&token_inputs, n_outputs, token_change > 0,
+                                fee_policy.dust_limit, fee_policy);
+    let selection = db.select_coins(&fan_outs_address, &target, CoinSelectStrategy::BranchAndBound)?
+        .ok_or_else(|| -> Error {
+            ErrorKind::PandaError(PandaError::InsufficientFunds(target.amount)).into()
+        })?;
+    let inputs = token_inputs.into_iter().chain(selection.inputs).collect::<Vec<_>>();
+    let tx = slp_fan_out_tx(&fan_outs_signer, &births_address, &inputs, group_hash, 0x81,
+                            n_outputs, SLPAmount::new(1, 0), fee_policy.dust_limit,
+                            SLPAmount::new(token_change, 0), fee_policy)?;
+    Ok(Some(tx))
+}
+
+/// Adds UTXOs of `fee_address` to the fee inputs of `panda` if the PND1 fee output alone
+/// doesn't pay for the birth.
+fn _top_up_fee_inputs(db: &Db, panda: &mut panda::PandaTx, fee_signer: &dyn Signer) -> Result<(), Error> {
+    let missing = match panda.estimate(fee_signer) {
+        Ok(_) => return Ok(()),
+        Err(Error(ErrorKind::PandaError(PandaError::InsufficientFunds(missing)), _)) => missing,
+        Err(err) => return Err(err),
+    };
+    let utxos = db.utxos_address(&fee_signer.address())?.into_iter()
+        .filter(|utxo| {
+            !panda.fee_inputs.iter().any(|(outpoint, _)| {
+                outpoint.tx_hash == tx_hash_from_le_slice(&utxo.tx_hash) &&
+                    outpoint.vout == utxo.vout as u32
+            })
+        })
+        .collect::<Vec<_>>();
+    let target = CoinSelectTarget::new(missing, 0, &panda.fee_policy);
+    let selection = coin_select::select_coins(&utxos, &target, CoinSelectStrategy::LargestFirst)
+        .ok_or_else(|| -> Error {
+            ErrorKind::PandaError(PandaError::InsufficientFunds(missing)).into()
+        })?;
+    panda.fee_inputs.extend(selection.inputs);
+    Ok(())
+}
+
+/// Hands `tx` to our peers. A failure, e.g. a full mailbox during a burst of rebroadcasts, is only
+/// logged; the outgoing tx tracker then rebroadcasts the tx on its next check.
+fn _send_outgoing(outgoing: &Recipient<OutgoingMsg>, tx: &Tx) -> bool {
+    match outgoing.do_send(OutgoingMsg(TxMessage { tx: tx.clone() }.packet())) {
+        Ok(()) => true,
+        Err(err) => {
+            eprintln!("broadcasting tx {} failed: {}", tx_hash_to_hex(&tx.hash()), err);
+            false
+        },
+    }
+}
+
+fn _broadcast_fan_out(db: &Db,
+                      config: &SLPDEXConfig,
+                      wallet: &HdWallet,
+                      outgoing: &Recipient<OutgoingMsg>,
+                      timestamp: i64) -> Result<(), Error> {
+    let tx = match _refill_births(db, config, wallet)? {
+        Some(tx) => tx,
+        None => return Ok(()),
+    };
+    let is_sent = _send_outgoing(outgoing, &tx);
+    db.add_outgoing_tx(&tx, OutgoingTxKind::FanOut, timestamp, is_sent)?;
+    db.add_tx_history(&TxHistory::from_txs(&[tx], timestamp, config, db))?;
+    for &role in &[WalletRole::Births, WalletRole::FanOuts] {
+        db.update_utxo_set(&wallet.signer(role, WalletChain::External, 0)?.address())?;
+    }
+    Ok(())
+}
+
 pub struct ResyncActor {
     db: Db,
     config: SLPDEXConfig,
-    secret: Vec<u8>,
+    chain_source: Box<dyn ChainSource>,
+    signer: Box<dyn Signer>,
+    wallet: Option<HdWallet>,
     outgoing_recipient: Option<Recipient<OutgoingMsg>>,
 }
 
 impl ResyncActor {
-    pub fn new(db: Db, config: SLPDEXConfig, secret: Vec<u8>) -> Self {
-        ResyncActor { db, config, secret, outgoing_recipient: None }
+    pub fn new(db: Db,
+               config: SLPDEXConfig,
+               chain_source: Box<dyn ChainSource>,
+               signer: Box<dyn Signer>,
+               wallet: Option<HdWallet>) -> Self {
+        ResyncActor { db, config, chain_source, signer, wallet, outgoing_recipient: None }
     }
 }
 
@@ -157,9 +318,14 @@ impl Actor for ResyncActor {
     type Context = SyncContext<Self>;
 
     fn started(&mut self, _ctx: &mut Self::Context) {
-        _resync(&self.db, &self.config)
+        _resync(&self.db, &self.config, &*self.chain_source)
             .map_err(|err| eprintln!("resync failed: {}", err))
             .unwrap_or(());
+        if let Some(wallet) = &self.wallet {
+            _resync_wallet(&self.db, &self.config, &*self.chain_source, wallet)
+                .map_err(|err| eprintln!("wallet resync failed: {}", err))
+                .unwrap_or(());
+        }
     }
 }
 
@@ -168,8 +334,8 @@ impl Handler<ResyncAddress> for ResyncActor {
 
     fn handle(&mut self, msg: ResyncAddress, _ctx: &mut Self::Context) -> Self::Result {
         let address = msg.0;
-        _resync_address(&self.db, &self.config, &address, true)?;
-        _resync_address(&self.db, &self.config, &address, false)?;
+        _resync_address(&self.db, &self.config, &*self.chain_source, &address, true)?;
+        _resync_address(&self.db, &self.config, &*self.chain_source, &address, false)?;
         Ok(())
     }
 }
@@ -178,7 +344,6 @@ impl Handler<ProcessTransactions> for ResyncActor {
     type Result = Result<(), Error>;
 
     fn handle(&mut self, msg: ProcessTransactions, _ctx: &mut Self::Context) -> Self::Result {
-        let tx_source = TxSource::new();
         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
         let db = msg.db.lock().unwrap();
         let mut history = TxHistory::from_txs(&msg.txs, timestamp, &msg.config, &*db);
@@ -202,14 +367,35 @@ impl Handler<ProcessTransactions> for ResyncActor {
                 TxType::Default => false,
             }).count() == 0 &&
             relevant_addresses.len() == 0 {
-            return Ok(())
+            // plain txs only matter if they burn tokens by spending them
+            history.track_token_supply(&*db)?;
+            if history.supply_changes.len() == 0 {
+                return Ok(())
+            }
         }
-        history.validate_slp(&tx_source, &*db, &msg.config)?;
+        let rest_tx_provider;
+        let tx_provider: &dyn TxProvider = match self.chain_source.tx_provider() {
+            Some(tx_provider) => tx_provider,
+            None => {
+                rest_tx_provider = RestTxProvider::new(msg.config.raw_tx_url.clone());
+                &rest_tx_provider
+            },
+        };
+        let mut validator = SlpValidator::new(tx_provider, Some(&*db));
+        validator.add_txs(&msg.txs);
+        history.validate_slp_local(&mut validator)?;
+        history.validate_nft1_children(&mut validator);
+        history.track_mint_batons(&*db)?;
+        history.track_token_supply(&*db)?;
+        history.track_trade_spends(&*db)?;
         if history.txs.iter().filter(|tx| match tx.tx_type {
             TxType::SLP {..} => true,
             TxType::Default => false,
         }).count() == 0 &&
-            relevant_addresses.len() == 0 {
+            relevant_addresses.len() == 0 &&
+            history.mint_batons.len() == 0 &&
+            history.supply_changes.len() == 0 &&
+            history.trade_spends.len() == 0 {
             return Ok(())
         }
         db.add_tx_history(&history)?;
@@ -252,6 +438,15 @@ impl Handler<ProcessBlock> for ResyncActor {
 
         let outgoing = self.outgoing_recipient.as_ref().unwrap();
         let tx_set = msg.tx_hashes.into_iter().collect::<HashSet<_>>();
+        db.update_outgoing_txs_status(tx_set.iter().cloned(), OutgoingTxStatus::Confirmed, None)?;
+
+        // refill first, so the births of this block can spend the new group UTXOs already
+        if let Some(wallet) = &self.wallet {
+            _broadcast_fan_out(&db, &msg.config, wallet, outgoing, timestamp as i64)
+                .map_err(|err| eprintln!("refilling births failed: {}", err))
+                .unwrap_or(());
+        }
+
         let pending_pnd = db.pending_pnd()?;
         let mut born_pnds = Vec::new();
         for (pnd, tx) in pending_pnd {
@@ -285,17 +480,35 @@ impl Handler<ProcessBlock> for ResyncActor {
 
             let fee_vout = 1;
             let fee_output = tx_outputs.get(&(tx_hash.clone(), fee_vout)).unwrap();
-            let nft_outpoint = db.get_some_pandaop_utxo()?.ok_or_else(|| -> Error {
-                ErrorKind::PandaError(PandaError::NoParentUtxosLeft).into()
-            })?;
 
-            let panda = panda::PandaTx {
-                nft1_outpoint: TxOutpoint {
-                    tx_hash: tx_hash_from_le_slice(&nft_outpoint.tx_hash),
-                    vout: nft_outpoint.vout as u32,
+            // with a wallet, the NFT1 group UTXOs sit at the births address (see
+            // `_refill_births`) and change goes to a fresh address, so fees and births don't
+            // share a single address
+            let (births_signer, (nft1_outpoint, nft1_amount), change) = match &self.wallet {
+                Some(wallet) => {
+                    let births_signer = wallet.signer(WalletRole::Births, WalletChain::External, 0)?;
+                    let group_utxo = _birth_group_utxo(&db, &births_signer.address(),
+                                                       &self.config.panda_token_hash)?;
+                    let change = wallet.fresh_address(&db, WalletRole::Births, WalletChain::Change)?;
+                    (Some(births_signer), group_utxo, Some(change))
                 },
-                nft1_amount: 0x222,
-                secret_key: secp256k1::SecretKey::from_slice(&self.secret).unwrap(),
+                None => {
+                    let nft_outpoint = db.get_some_pandaop_utxo()?.ok_or_else(|| -> Error {
+                        ErrorKind::PandaError(PandaError::NoParentUtxosLeft).into()
+                    })?;
+                    let outpoint = TxOutpoint {
+                        tx_hash: tx_hash_from_le_slice(&nft_outpoint.tx_hash),
+                        vout: nft_outpoint.vout as u32,
+                    };
+                    (None, (outpoint, msg.config.fee_policy.dust_limit), None)
+                },
+            };
+            let nft1_signer = births_signer.as_ref().map(|signer| signer as &dyn Signer)
+                .unwrap_or(&*self.signer);
+
+            let mut panda = panda::PandaTx {
+                nft1_outpoint,
+                nft1_amount,
                 fee_inputs: vec![
                     (TxOutpoint {
                         tx_hash: tx_hash.clone(),
@@ -306,21 +519,27 @@ impl Handler<ProcessBlock> for ResyncActor {
                 panda_ticker: "PANDA".to_string(),
                 panda_name: pnd.name,
                 genome: new_genes_packed.to_vec(),
-                fee_per_kb: 1000,
-                dust_limit: 0x222,
+                fee_policy: msg.config.fee_policy,
+                change_address: change.as_ref().map(|(_, address)| address.clone()),
             };
-            let tx = panda.tx().map_err(|missing_funds| -> Error {
-                ErrorKind::PandaError(PandaError::InsufficientFunds(missing_funds)).into()
-            })?;
+            _top_up_fee_inputs(&db, &mut panda, &*self.signer)?;
+            let tx = panda.tx_with_signers(nft1_signer, &*self.signer)?;
             let token = panda.token(timestamp as i64, self.config.panda_token_hash, &tx);
 
-            outgoing.do_send(OutgoingMsg(TxMessage { tx: tx.clone() }.packet())).unwrap();
+            let is_sent = _send_outgoing(outgoing, &tx);
+            db.add_outgoing_tx(&tx, OutgoingTxKind::Birth, timestamp as i64, is_sent)?;
+            if let (Some(wallet), Some((change_idx, _))) = (&self.wallet, &change) {
+                wallet.used_signer(&db, WalletRole::Births, WalletChain::Change, *change_idx)?;
+            }
 
             let hash = tx.hash();
 
             db.add_tokens(&[token])?;
-            let tx_history = TxHistory::from_txs(&[tx], timestamp as i64, &self.config, &db);
-            db.add_tx_history(&tx_history);
+            let mut tx_history = TxHistory::from_txs(&[tx], timestamp as i64, &self.config, &db);
+            tx_history.track_token_supply(&db)?;
+            db.add_tx_history(&tx_history)?;
+            db.update_utxo_set(&nft1_signer.address())?;
+            db.update_utxo_set(&self.signer.address())?;
 
             let db_txs = db.txs(vec![hash.clone()].into_iter())?;
             let db_tx = db_txs.get(&hash).unwrap();
@@ -333,6 +552,21 @@ impl Handler<ProcessBlock> for ResyncActor {
                 db.connection(),
             )?;
         }
+
+        let auctions = db.active_auctions()?;
+        if auctions.len() > 0 {
+            let height = db.header_tip()?.map(|(_, height)| height).unwrap_or(0);
+            let tick = TxEvent::PandaAuctions {
+                events: Arc::new(vec![AuctionEvent::PriceTick { height, auctions }]),
+            };
+            // drop subscribers whose websocket is gone, a full mailbox only misses this tick
+            msg.subscribers.lock().unwrap().subscribers_panda_market.retain(|subscriber| {
+                match subscriber.do_send(tick.clone()) {
+                    Err(SendError::Closed(_)) => false,
+                    _ => true,
+                }
+            });
+        }
         Ok(())
     }
 }
@@ -1,17 +1,24 @@
 use actix::prelude::*;
 use std::collections::{HashSet, HashMap};
 use std::convert::identity;
-use slpdexdb_base::{Error, SLPDEXConfig};
-use slpdexdb_db::{Db, Utxo, TxDelta, TradeOffer};
+use std::time::{SystemTime, UNIX_EPOCH};
+use slpdexdb_base::{Error, SLPDEXConfig, PeerFeeFilters};
+use slpdexdb_db::{Db, Utxo, TxDelta, TradeOffer, PandaForSale, OrderBook, TradeOfferFilter, Candle};
+use slpdexdb_db::tx_submit::prevalidate_tx;
+use slpdexdb_db::slp_validator::RestTxProvider;
 use slpdexdb_node::actors::{IncomingMsg, OutgoingMsg};
 use slpdexdb_node::messages::{TxMessage, BlockMessage};
+use slpdexdb_node::msg::{PeerFeeFilter, PeerDisconnected};
 use crate::msg::{ActivateAddress, DeactivateAddress, ResyncAddress, FetchAddressUtxos,
-                 FetchAddressTxDeltas, FetchTradeOfferUtxos, SubscribeToEvent, UnsubscribeFromEvent,
-                 TxEvent, NewTransactions, ProcessTransactions, ProcessBlock};
+                 FetchAddressTxDeltas, FetchTradeOfferUtxos, FetchOrderBook, FetchCandles,
+                 FetchPandasForSale, SubscribeToEvent, UnsubscribeFromEvent, CANDLE_COUNT,
+                 TxEvent, NewTransactions, ProcessTransactions, ProcessBlock, SubmitTx,
+                 RegisterOutgoing};
 use crate::actors::ResyncActor;
 use crate::actors::broadcast_actor::{UpdateDbUtxosActor, BroadcastAddressUtxosActor,
                                      BroadcastTradeOfferUtxosActor, BroadcastTxHistoryActor,
-                                     BroadcastActor};
+                                     BroadcastPandaTradeOffersActor, UpdatePandaAuctionsActor,
+                                     BroadcastTradeFillsActor, BroadcastActor};
 use slpdexdb_node::NodeMessage;
 
 use cashcontracts::Address;
@@ -20,14 +27,18 @@ use std::sync::{Mutex, Arc};
 pub struct TxSubscribers {
     pub subscribers_address: HashMap<Address, HashSet<Recipient<TxEvent>>>,
     pub subscribers_token: HashMap<[u8; 32], HashSet<Recipient<TxEvent>>>,
+    pub subscribers_order_book: HashMap<[u8; 32], HashSet<Recipient<TxEvent>>>,
+    pub subscribers_panda_market: HashSet<Recipient<TxEvent>>,
 }
 
 pub struct TxActor {
     db: Arc<Mutex<Db>>,
     config: SLPDEXConfig,
+    peer_fee_filters: PeerFeeFilters,
     resync: Addr<ResyncActor>,
     subscribers: Arc<Mutex<TxSubscribers>>,
     broadcasts: Vec<Recipient<NewTransactions>>,
+    outgoing_recipient: Option<Recipient<OutgoingMsg>>,
 }
 
 impl TxActor {
@@ -36,18 +47,27 @@ impl TxActor {
                       resync: Addr<ResyncActor>) -> Addr<Self> {
         let broadcast = BroadcastActor::start(BroadcastActor);
         let broadcasts = vec![
-            UpdateDbUtxosActor::start(UpdateDbUtxosActor).recipient(),
+            UpdateDbUtxosActor::start(UpdateDbUtxosActor::new(broadcast.clone())).recipient(),
             BroadcastAddressUtxosActor::start(BroadcastAddressUtxosActor::new(broadcast.clone())).recipient(),
             BroadcastTradeOfferUtxosActor::start(BroadcastTradeOfferUtxosActor::new(broadcast.clone())).recipient(),
+            BroadcastTradeFillsActor::start(BroadcastTradeFillsActor::new(broadcast.clone())).recipient(),
             BroadcastTxHistoryActor::start(BroadcastTxHistoryActor::new(broadcast.clone())).recipient(),
+            BroadcastPandaTradeOffersActor::start(
+                BroadcastPandaTradeOffersActor::new(broadcast.clone(), config.clone())
+            ).recipient(),
+            UpdatePandaAuctionsActor::start(UpdatePandaAuctionsActor::new(broadcast.clone())).recipient(),
         ];
         Self::start(TxActor {
             db, config, resync,
+            peer_fee_filters: PeerFeeFilters::default(),
             subscribers: Arc::new(Mutex::new(TxSubscribers {
                 subscribers_address: HashMap::new(),
                 subscribers_token: HashMap::new(),
+                subscribers_order_book: HashMap::new(),
+                subscribers_panda_market: HashSet::new(),
             })),
             broadcasts,
+            outgoing_recipient: None,
         })
     }
 }
@@ -106,6 +126,54 @@ impl Handler<IncomingMsg<BlockMessage>> for TxActor {
     }
 }
 
+impl Handler<PeerFeeFilter> for TxActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: PeerFeeFilter, _ctx: &mut Self::Context) -> Self::Result {
+        // the config is handed to the resync actor with every block, which builds the panda txs
+        self.peer_fee_filters.insert(msg.peer_addr, msg.fee_per_kb);
+        self.config.fee_policy.set_peer_fee_filter(self.peer_fee_filters.median());
+        Ok(())
+    }
+}
+
+impl Handler<PeerDisconnected> for TxActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: PeerDisconnected, _ctx: &mut Self::Context) -> Self::Result {
+        self.peer_fee_filters.remove(&msg.peer_addr);
+        self.config.fee_policy.set_peer_fee_filter(self.peer_fee_filters.median());
+    }
+}
+
+impl Handler<SubmitTx> for TxActor {
+    type Result = Result<[u8; 32], Error>;
+
+    fn handle(&mut self, msg: SubmitTx, ctx: &mut Self::Context) -> Self::Result {
+        let SubmitTx(tx) = msg;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let tx_provider = RestTxProvider::new(self.config.raw_tx_url.clone());
+        prevalidate_tx(&tx, now, &self.config, &self.db, &tx_provider)?;
+        let outgoing = self.outgoing_recipient.as_ref()
+            .ok_or_else(|| Error::from("not connected to any peers"))?;
+        let tx_msg = TxMessage { tx };
+        outgoing.do_send(OutgoingMsg(tx_msg.packet()))
+            .map_err(|err| Error::from(format!("relaying the tx failed: {}", err)))?;
+        // peers don't echo our own txs back, so process it like one received from them
+        let tx_hash = tx_msg.tx.hash();
+        ctx.notify(IncomingMsg(Arc::new(tx_msg)));
+        Ok(tx_hash)
+    }
+}
+
+impl Handler<RegisterOutgoing> for TxActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: RegisterOutgoing, _ctx: &mut Self::Context) -> Self::Result {
+        self.outgoing_recipient = Some(msg.recipient);
+    }
+}
+
 impl Handler<ActivateAddress> for TxActor {
     type Result = Response<(), Error>;
 
@@ -170,6 +238,20 @@ impl Handler<SubscribeToEvent> for TxActor {
                         .insert(recipient.clone());
                 }
             },
+            SubscribeToEvent::OrderBooks(token_hashes, recipient) => {
+                for (_, subs) in subscribers.subscribers_order_book.iter_mut() {
+                    subs.remove(&recipient);
+                }
+                for token_hash in token_hashes {
+                    subscribers.subscribers_order_book
+                        .entry(token_hash)
+                        .or_insert_with(HashSet::new)
+                        .insert(recipient.clone());
+                }
+            },
+            SubscribeToEvent::PandaMarket(recipient) => {
+                subscribers.subscribers_panda_market.insert(recipient);
+            },
         };
     }
 }
@@ -183,6 +265,14 @@ impl Handler<UnsubscribeFromEvent> for TxActor {
             UnsubscribeFromEvent::Address(address, recipient) => {
                 subscribers.subscribers_address.get_mut(address).map(|subs| subs.remove(recipient));
             },
+            UnsubscribeFromEvent::OrderBooks(recipient) => {
+                for (_, subs) in subscribers.subscribers_order_book.iter_mut() {
+                    subs.remove(recipient);
+                }
+            },
+            UnsubscribeFromEvent::PandaMarket(recipient) => {
+                subscribers.subscribers_panda_market.remove(recipient);
+            },
         }
     }
 }
@@ -192,6 +282,42 @@ impl Handler<FetchTradeOfferUtxos> for TxActor {
 
     fn handle(&mut self, msg: FetchTradeOfferUtxos, _ctx: &mut Self::Context) -> Self::Result {
         let FetchTradeOfferUtxos(filter) = msg;
-        Ok(self.db.lock().unwrap().trade_offer_utxos(filter)?)
+        Ok(self.db.lock().unwrap().trade_offer_utxos(filter, None)?)
+    }
+}
+
+impl Handler<FetchOrderBook> for TxActor {
+    type Result = Result<OrderBook, Error>;
+
+    fn handle(&mut self, msg: FetchOrderBook, _ctx: &mut Self::Context) -> Self::Result {
+        let FetchOrderBook { token_hash, exch_versions } = msg;
+        if let Some(version) = exch_versions.iter().flatten()
+                .find(|version| !version.is_supported(&self.config)) {
+            return Err(Error::from(format!("unsupported EXCH version {}", *version as u8)));
+        }
+        let db = self.db.lock().unwrap();
+        let token = db.token(&token_hash)?.ok_or_else(|| Error::from("unknown token"))?;
+        let offers = db.trade_offer_utxos(TradeOfferFilter::TokenHash(token_hash),
+                                          exch_versions.as_ref().map(Vec::as_slice))?;
+        Ok(OrderBook::from_offers(token_hash, token.decimals as u32, &offers))
+    }
+}
+
+impl Handler<FetchCandles> for TxActor {
+    type Result = Result<Vec<Candle>, Error>;
+
+    fn handle(&mut self, msg: FetchCandles, _ctx: &mut Self::Context) -> Self::Result {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        Ok(self.db.lock().unwrap().candles(&msg.token_hash, msg.interval, CANDLE_COUNT, now)?)
+    }
+}
+
+impl Handler<FetchPandasForSale> for TxActor {
+    type Result = Result<Vec<PandaForSale>, Error>;
+
+    fn handle(&mut self, msg: FetchPandasForSale, _ctx: &mut Self::Context) -> Self::Result {
+        let FetchPandasForSale(filter) = msg;
+        let pandas = self.db.lock().unwrap().pandas_for_sale(&self.config.panda_token_hash, None)?;
+        Ok(filter.apply(pandas))
     }
 }
@@ -6,9 +6,13 @@ use slpdexdb_base::{Error, convert_numeric};
 use serde::Deserialize;
 use json::{object, JsonValue, stringify};
 use std::sync::Arc;
+use std::collections::HashMap;
 use crate::actors::TxActor;
-use crate::msg::{ActivateAddress, FetchAddressUtxos, FetchAddressTxDeltas, SubscribeToEvent,
-                 UnsubscribeFromEvent, TxEvent};
+use slpdexdb_db::{PandaSaleFilter, AuctionListing, PriceLevel, Candle, CandleInterval};
+use slpdexdb_db::tx_submit::parse_submitted_tx;
+use slpdexdb_db::exch::ExchVersion;
+use crate::msg::{ActivateAddress, FetchAddressUtxos, FetchAddressTxDeltas, FetchPandasForSale, FetchOrderBook, FetchCandles,
+                 SubscribeToEvent, UnsubscribeFromEvent, TxEvent, AuctionEvent, SubmitTx};
 
 
 #[derive(Deserialize)]
@@ -17,7 +21,28 @@ pub enum WsIncomingMessage {
     ListenToTokens {
         #[serde(rename = "tokenIdsHex")]
         token_ids_hex: Vec<String>,
-    }
+    },
+    ListenToOrderBooks {
+        #[serde(rename = "tokenIdsHex")]
+        token_ids_hex: Vec<String>,
+        /// Only include offers of these EXCH versions, all if missing
+        #[serde(rename = "exchVersions", default)]
+        exch_versions: Option<Vec<u8>>,
+    },
+    FetchCandles {
+        #[serde(rename = "tokenIdHex")]
+        token_id_hex: String,
+        #[serde(default)]
+        interval: CandleInterval,
+    },
+    ListenToPandaMarket {
+        #[serde(default)]
+        filter: PandaSaleFilter,
+    },
+    SubmitTx {
+        #[serde(rename = "txHex")]
+        tx_hex: String,
+    },
 }
 
 impl Message for WsIncomingMessage {
@@ -27,11 +52,14 @@ impl Message for WsIncomingMessage {
 pub struct WsActor {
     address: Address,
     tx: Addr<TxActor>,
+    panda_market_filter: Option<PandaSaleFilter>,
+    /// EXCH versions of the offers to include per subscribed order book, all if `None`
+    order_book_versions: HashMap<[u8; 32], Option<Vec<ExchVersion>>>,
 }
 
 impl WsActor {
     pub fn new(address: Address, tx: Addr<TxActor>) -> Self {
-        WsActor { address, tx }
+        WsActor { address, tx, panda_market_filter: None, order_book_versions: HashMap::new() }
     }
 }
 
@@ -72,6 +100,45 @@ impl Actor for WsActor {
     }
 }
 
+fn _auction_json(listing: &AuctionListing, height: Option<i32>) -> JsonValue {
+    object!{
+        "auctionTx" => tx_hash_to_hex(&listing.auction_tx),
+        "tokenIdHex" => tx_hash_to_hex(&listing.token_hash),
+        "tx" => tx_hash_to_hex(&listing.panda_tx_hash),
+        "vout" => listing.panda_output_idx,
+        "startPrice" => listing.auction.start_price.to_string(),
+        "endPrice" => listing.auction.end_price.to_string(),
+        "startHeight" => listing.auction.start_height,
+        "durationBlocks" => listing.auction.duration_blocks,
+        "currentPrice" => height.map(|height| listing.auction.price_at(height).to_string()),
+        "sellerAddress" => listing.seller_address.cash_addr(),
+    }
+}
+
+fn _price_level_json(level: &PriceLevel) -> JsonValue {
+    object!{
+        "pricePerToken" => format!("{}", convert_numeric::PrettyRational(
+            level.price_per_token.clone()
+        )),
+        "sellAmountToken" => format!("{}", level.sell_amount_token),
+        "sellAmountTokenBase" => level.sell_amount_token.base_amount().to_string(),
+        "numOffers" => level.num_offers,
+    }
+}
+
+pub fn candle_json(candle: &Candle) -> JsonValue {
+    object!{
+        "timestamp" => candle.start_timestamp,
+        "open" => format!("{}", convert_numeric::PrettyRational(candle.open.clone())),
+        "high" => format!("{}", convert_numeric::PrettyRational(candle.high.clone())),
+        "low" => format!("{}", convert_numeric::PrettyRational(candle.low.clone())),
+        "close" => format!("{}", convert_numeric::PrettyRational(candle.close.clone())),
+        "volumeToken" => format!("{}", candle.volume),
+        "volumeTokenBase" => candle.volume.base_amount().to_string(),
+        "numFills" => candle.num_fills,
+    }
+}
+
 impl StreamHandler<ws::Message, ws::ProtocolError> for WsActor {
     fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
         println!("WS: {:?}", msg);
@@ -92,6 +159,8 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for WsActor {
             ws::Message::Close(_) => {
                 self.tx.do_send(UnsubscribeFromEvent::Address(self.address.clone(),
                                                               ctx.address().recipient()));
+                self.tx.do_send(UnsubscribeFromEvent::OrderBooks(ctx.address().recipient()));
+                self.tx.do_send(UnsubscribeFromEvent::PandaMarket(ctx.address().recipient()));
                 ctx.stop();
             }
             ws::Message::Nop => (),
@@ -142,6 +211,7 @@ impl Handler<TxEvent> for WsActor {
                                     "outputVout" => trade_offer.output_idx,
                                     "inputTx" => tx_hash_to_hex(&trade_offer.input_tx),
                                     "inputVout" => trade_offer.input_idx,
+                                    "exchVersion" => trade_offer.exch_version as u8,
                                     "pricePerToken" => format!("{}", convert_numeric::PrettyRational(
                                         trade_offer.price_per_token.clone()
                                     )),
@@ -184,6 +254,146 @@ impl Handler<TxEvent> for WsActor {
                     }
                 ))
             },
+            TxEvent::OrderBookSnapshot { order_book } => {
+                ctx.text(stringify(
+                    object!{
+                        "type" => "OrderBook",
+                        "tokenIdHex" => tx_hash_to_hex(&order_book.token_hash),
+                        "bestPrice" => order_book.best_price().map(|price| format!(
+                            "{}", convert_numeric::PrettyRational(price.clone())
+                        )),
+                        "priceRange" => order_book.price_range().map(|price_range| format!(
+                            "{}", convert_numeric::PrettyRational(price_range)
+                        )),
+                        "totalSellAmountTokenBase" => order_book.total_sell_amount()
+                            .base_amount().to_string(),
+                        "levels" => JsonValue::Array(
+                            order_book.levels.iter().zip(order_book.depth())
+                                .map(|(level, (_, depth))| {
+                                    let mut level_json = _price_level_json(level);
+                                    level_json["depthTokenBase"] = depth.base_amount().to_string().into();
+                                    level_json
+                                })
+                                .collect()
+                        ),
+                    }
+                ))
+            },
+            TxEvent::OrderBookDelta { delta } => {
+                if let Some(Some(versions)) = self.order_book_versions.get(&delta.token_hash) {
+                    if !versions.contains(&delta.exch_version) {
+                        return Ok(());
+                    }
+                }
+                ctx.text(stringify(
+                    object!{
+                        "type" => "OrderBookDelta",
+                        "tokenIdHex" => tx_hash_to_hex(&delta.token_hash),
+                        "exchVersion" => delta.exch_version as u8,
+                        "levels" => JsonValue::Array(
+                            delta.levels.iter().map(_price_level_json).collect()
+                        ),
+                    }
+                ))
+            },
+            TxEvent::TradeFills { token_hash, fills } => {
+                ctx.text(stringify(
+                    object!{
+                        "type" => "TradeFills",
+                        "tokenIdHex" => tx_hash_to_hex(&token_hash),
+                        "fills" => JsonValue::Array(
+                            fills.iter()
+                                .map(|fill| object!{
+                                    "offerTx" => tx_hash_to_hex(&fill.offer_tx),
+                                    "offerVout" => fill.offer_output_idx,
+                                    "tx" => tx_hash_to_hex(&fill.spending_tx),
+                                    "pricePerToken" => format!("{}", convert_numeric::PrettyRational(
+                                        fill.price_per_token.clone()
+                                    )),
+                                    "amountToken" => format!("{}", fill.amount),
+                                    "amountTokenBase" => fill.amount.base_amount().to_string(),
+                                    "timestamp" => fill.timestamp,
+                                })
+                                .collect()
+                        ),
+                    }
+                ))
+            },
+            TxEvent::PandaTradeOfferUtxoDelta { add_utxos, remove_utxos } => {
+                let filter = match &self.panda_market_filter {
+                    Some(filter) => filter,
+                    None => return Ok(()),
+                };
+                ctx.text(stringify(
+                    object!{
+                        "type" => "PandaTradeOfferUtxo",
+                        "addUtxos" => JsonValue::Array(
+                            filter.apply(add_utxos.as_ref().clone()).iter()
+                                .map(|panda| object!{
+                                    "tx" => tx_hash_to_hex(&panda.offer_tx),
+                                    "outputVout" => panda.offer_output_idx,
+                                    "tokenIdHex" => tx_hash_to_hex(&panda.token_hash),
+                                    "priceSatoshis" => format!("{}", convert_numeric::PrettyRational(
+                                        panda.price_satoshis()
+                                    )),
+                                    "sellerAddress" => panda.seller_address.cash_addr(),
+                                    "attributes" => json::parse(
+                                        &serde_json::to_string(&panda.attributes).unwrap()
+                                    ).unwrap(),
+                                })
+                                .collect()
+                        ),
+                        "removeUtxos" => JsonValue::Array(
+                            remove_utxos.iter()
+                                .map(|utxo| object!{
+                                    "tx" => tx_hash_to_hex(&utxo.tx_hash),
+                                    "vout" => utxo.vout,
+                                })
+                                .collect()
+                        ),
+                    }
+                ))
+            },
+            TxEvent::PandaAuctions { events } => {
+                if self.panda_market_filter.is_none() {
+                    return Ok(());
+                }
+                ctx.text(stringify(
+                    object!{
+                        "type" => "PandaAuction",
+                        "events" => JsonValue::Array(
+                            events.iter()
+                                .map(|event| match event {
+                                    AuctionEvent::Listed(auction) => object!{
+                                        "event" => "listed",
+                                        "auction" => _auction_json(auction, None),
+                                    },
+                                    AuctionEvent::PriceTick { height, auctions } => object!{
+                                        "event" => "priceTick",
+                                        "height" => *height,
+                                        "auctions" => JsonValue::Array(
+                                            auctions.iter()
+                                                .map(|auction| _auction_json(auction, Some(*height)))
+                                                .collect()
+                                        ),
+                                    },
+                                    AuctionEvent::Sold { auction, tx_hash, price } => object!{
+                                        "event" => "sold",
+                                        "auction" => _auction_json(auction, None),
+                                        "tx" => tx_hash_to_hex(tx_hash),
+                                        "price" => price.to_string(),
+                                    },
+                                    AuctionEvent::Cancelled { auction, tx_hash } => object!{
+                                        "event" => "cancelled",
+                                        "auction" => _auction_json(auction, None),
+                                        "tx" => tx_hash_to_hex(tx_hash),
+                                    },
+                                })
+                                .collect()
+                        ),
+                    }
+                ))
+            },
         }
         Ok(())
     }
@@ -204,7 +414,117 @@ impl Handler<WsIncomingMessage> for WsActor {
                         .send(SubscribeToEvent::Tokens(token_hashes, ctx.address().recipient()))
                         .from_err()
                 )
-            }
+            },
+            WsIncomingMessage::ListenToOrderBooks { token_ids_hex, exch_versions } => {
+                let token_hashes = token_ids_hex.iter()
+                    .filter_map(|token_hash| tx_hex_to_hash(token_hash))
+                    .collect::<Vec<_>>();
+                let exch_versions = match exch_versions {
+                    Some(versions) => match versions.iter()
+                            .map(|&version| ExchVersion::from_version(version).ok_or(version))
+                            .collect::<Result<Vec<_>, _>>() {
+                        Ok(versions) => Some(versions),
+                        Err(version) => return Response::reply(Err(Error::from(
+                            format!("unknown EXCH version {}", version)
+                        ))),
+                    },
+                    None => None,
+                };
+                for token_hash in token_hashes.iter() {
+                    self.order_book_versions.insert(*token_hash, exch_versions.clone());
+                }
+                let tx = self.tx.clone();
+                let own_address = ctx.address();
+                let snapshots = futures::future::join_all(
+                    token_hashes.iter()
+                        .map(|token_hash| {
+                            let own_address = own_address.clone();
+                            let fetch = FetchOrderBook {
+                                token_hash: token_hash.clone(),
+                                exch_versions: exch_versions.clone(),
+                            };
+                            self.tx.send(fetch).from_err().and_then(identity)
+                                .and_then(move |order_book| own_address.send(
+                                    TxEvent::OrderBookSnapshot { order_book: Arc::new(order_book) }
+                                ).from_err().and_then(identity))
+                        })
+                        .collect::<Vec<_>>()
+                );
+                Response::fut(
+                    snapshots.and_then(move |_| {
+                        tx.send(SubscribeToEvent::OrderBooks(token_hashes, own_address.recipient()))
+                            .from_err()
+                    })
+                )
+            },
+            WsIncomingMessage::FetchCandles { token_id_hex, interval } => {
+                let token_hash = match tx_hex_to_hash(&token_id_hex) {
+                    Some(token_hash) => token_hash,
+                    None => return Response::reply(Err(Error::from("invalid token id"))),
+                };
+                ctx.spawn(
+                    self.tx.send(FetchCandles { token_hash, interval })
+                        .from_err()
+                        .and_then(identity)
+                        .into_actor(self)
+                        .then(move |result: Result<_, Error>, _actor, ctx| {
+                            match result {
+                                Ok(candles) => ctx.text(stringify(object!{
+                                    "type" => "Candles",
+                                    "tokenIdHex" => token_id_hex,
+                                    "candles" => JsonValue::Array(
+                                        candles.iter().map(candle_json).collect()
+                                    ),
+                                })),
+                                Err(err) => eprintln!("Error: {}", err),
+                            }
+                            fut::ok(())
+                        })
+                );
+                Response::reply(Ok(()))
+            },
+            WsIncomingMessage::ListenToPandaMarket { filter } => {
+                self.panda_market_filter = Some(filter.clone());
+                let tx = self.tx.clone();
+                let own_address = ctx.address();
+                let own_address2 = ctx.address();
+                Response::fut(
+                    self.tx.send(FetchPandasForSale(filter)).from_err().and_then(identity)
+                        .and_then(move |pandas| own_address.send(
+                            TxEvent::PandaTradeOfferUtxoDelta { add_utxos: Arc::new(pandas),
+                                                                remove_utxos: Arc::new(vec![]) }
+                        ).from_err().and_then(identity))
+                        .and_then(move |_| {
+                            tx.send(SubscribeToEvent::PandaMarket(own_address2.recipient()))
+                                .from_err()
+                        })
+                )
+            },
+            WsIncomingMessage::SubmitTx { tx_hex } => {
+                let submitted = futures::future::result(parse_submitted_tx(&tx_hex))
+                    .and_then({
+                        let tx = self.tx.clone();
+                        move |parsed_tx| tx.send(SubmitTx(parsed_tx)).from_err().and_then(identity)
+                    });
+                ctx.spawn(
+                    submitted
+                        .into_actor(self)
+                        .then(|result, _actor, ctx| {
+                            ctx.text(stringify(match result {
+                                Ok(tx_hash) => object!{
+                                    "type" => "TxSubmitted",
+                                    "tx" => tx_hash_to_hex(&tx_hash),
+                                },
+                                Err(err) => object!{
+                                    "type" => "TxRejected",
+                                    "error" => err.to_string(),
+                                },
+                            }));
+                            fut::ok(())
+                        })
+                );
+                Response::reply(Ok(()))
+            },
         }
     }
 }
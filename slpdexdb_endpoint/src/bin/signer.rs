@@ -0,0 +1,37 @@
+//! Out-of-process signer for the endpoint, serving the operator key from an encrypted keystore.
+//!
+//!     signer create <keystore>          encrypts the hex SECRET into a new keystore
+//!     signer create-wallet <keystore>   encrypts the hex wallet seed read from stdin into a
+//!                                       new keystore, for the endpoint's WALLET_KEYSTORE
+//!     signer serve <keystore> <socket>  serves the keystore's key on a Unix socket
+//!
+//! The keystore password is read from KEYSTORE_PASSWORD.
+
+use slpdexdb_base::Result;
+use std::io::BufRead;
+use slpdexdb_db::signer::{KeystoreSigner, serve_unix_socket, write_keystore};
+
+fn main() -> Result<()> {
+    let args = std::env::args().collect::<Vec<_>>();
+    let password = std::env::var("KEYSTORE_PASSWORD")?;
+    match (args.get(1).map(String::as_str), args.get(2), args.get(3)) {
+        (Some("create"), Some(keystore_path), None) => {
+            let secret = hex::decode(std::env::var("SECRET")?)?;
+            let secret_key = secp256k1::SecretKey::from_slice(&secret)
+                .map_err(|err| format!("invalid secret: {}", err))?;
+            KeystoreSigner::create(keystore_path, &secret_key, &password)?;
+        },
+        (Some("create-wallet"), Some(keystore_path), None) => {
+            let mut seed = String::new();
+            std::io::stdin().lock().read_line(&mut seed)?;
+            write_keystore(keystore_path, &hex::decode(seed.trim())?, &password)?;
+        },
+        (Some("serve"), Some(keystore_path), Some(socket_path)) => {
+            let signer = KeystoreSigner::open(keystore_path, &password)?;
+            serve_unix_socket(socket_path, &signer)?;
+        },
+        _ => eprintln!("usage: signer create <keystore> | signer create-wallet <keystore> | \
+                        signer serve <keystore> <socket>"),
+    }
+    Ok(())
+}
@@ -6,15 +6,23 @@ use std::net;
 use std::sync::{Arc, Mutex};
 use actix::prelude::*;
 use diesel::prelude::*;
+use futures::Future;
+use json::{object, JsonValue};
 
 use actix_web::{middleware, web, App, HttpResponse, HttpRequest, HttpServer};
 use actix_web_actors::ws;
 
-use cashcontracts::{Address};
-use slpdexdb_base::SLPDEXConfig;
-use slpdexdb_db::Db;
-use crate::actors::{TxActor, ResyncActor, PeersActor, WsActor};
-use crate::msg::{ConnectToPeer, RegisterOutgoing};
+use cashcontracts::{Address, tx_hash_to_hex, tx_hex_to_hash};
+use slpdexdb_base::{SLPDEXConfig, ErrorKind, SignerError};
+use slpdexdb_db::{Db, CandleInterval, ChainSource, BitDbChainSource, RpcChainSource,
+                  FixtureChainSource};
+use serde::Deserialize;
+use slpdexdb_db::signer::{Signer, InMemorySigner, KeystoreSigner, UnixSocketSigner};
+use slpdexdb_db::hd_wallet::{HdWallet, WalletRole, WalletChain};
+use slpdexdb_db::tx_submit::parse_submitted_tx;
+use crate::actors::{TxActor, ResyncActor, PeersActor, WsActor, OutgoingTxActor, DbActor, candle_json};
+use crate::msg::{ConnectToPeer, RegisterOutgoing, FetchStuckOutgoingTxs, SubmitTx, FetchTokenBurns,
+                 FetchDestroyedNFTs, FetchCandles};
 
 pub fn connect_db() -> Db {
     let connection_str = std::env::var("DATABASE_URL").expect("DATABASE_URL");
@@ -22,6 +30,71 @@ pub fn connect_db() -> Db {
     Db::new(connection)
 }
 
+/// The default config, with the ceiling for the peers' fee filters optionally taken from
+/// `MAX_PEER_FEE_FILTER` (in satoshis per 1000 bytes) and the raw tx API from `RAW_TX_URL`.
+pub fn operator_config() -> slpdexdb_base::Result<SLPDEXConfig> {
+    let mut config = SLPDEXConfig::default();
+    if let Ok(max_fee_filter) = std::env::var("MAX_PEER_FEE_FILTER") {
+        config.fee_policy.max_peer_fee_filter_per_kb = max_fee_filter.parse()?;
+    }
+    if let Ok(raw_tx_url) = std::env::var("RAW_TX_URL") {
+        config.raw_tx_url = raw_tx_url;
+    }
+    Ok(config)
+}
+
+/// The operator wallet of the seed in the encrypted keystore at `WALLET_KEYSTORE`, unlocked with
+/// `KEYSTORE_PASSWORD`, if set. Its fee address must be the fee address of `config`.
+pub fn operator_wallet(config: &SLPDEXConfig) -> slpdexdb_base::Result<Option<HdWallet>> {
+    let keystore_path = match std::env::var("WALLET_KEYSTORE") {
+        Ok(keystore_path) => keystore_path,
+        Err(_) => return Ok(None),
+    };
+    let password = std::env::var("KEYSTORE_PASSWORD")?;
+    let wallet = HdWallet::open_keystore(keystore_path, &password)?;
+    let fee_address = wallet.signer(WalletRole::Fees, WalletChain::External, 0)?.address();
+    if fee_address != config.fee_address {
+        return Err(ErrorKind::SignerError(SignerError::FeeAddressMismatch(
+            config.fee_address.cash_addr().to_string(),
+            fee_address.cash_addr().to_string(),
+        )).into());
+    }
+    Ok(Some(wallet))
+}
+
+/// The operator key, from (in order of preference) the fee key of the operator wallet, an
+/// out-of-process signer at `SIGNER_SOCKET`, an encrypted keystore at `KEYSTORE` unlocked with
+/// `KEYSTORE_PASSWORD`, or the hex `SECRET`. Without a wallet, all operator UTXOs must be
+/// locked to this key.
+pub fn operator_signer(wallet: Option<&HdWallet>) -> slpdexdb_base::Result<Box<dyn Signer>> {
+    if let Some(wallet) = wallet {
+        return Ok(Box::new(wallet.signer(WalletRole::Fees, WalletChain::External, 0)?));
+    }
+    if let Ok(socket_path) = std::env::var("SIGNER_SOCKET") {
+        return Ok(Box::new(UnixSocketSigner::connect(socket_path)?));
+    }
+    if let Ok(keystore_path) = std::env::var("KEYSTORE") {
+        let password = std::env::var("KEYSTORE_PASSWORD")?;
+        return Ok(Box::new(KeystoreSigner::open(keystore_path, &password)?));
+    }
+    Ok(Box::new(InMemorySigner::from_slice(&hex::decode(std::env::var("SECRET")?)?)?))
+}
+
+/// Where txs and tokens are resynced from: the fixture files in `CHAIN_FIXTURES`, the node
+/// JSON-RPC at `NODE_RPC_URL` with `NODE_RPC_USER` and `NODE_RPC_PASSWORD`, or BitDB and SLPDB.
+pub fn chain_source() -> slpdexdb_base::Result<Box<dyn ChainSource>> {
+    if let Ok(fixtures_dir) = std::env::var("CHAIN_FIXTURES") {
+        return Ok(Box::new(FixtureChainSource::from_dir(fixtures_dir)?));
+    }
+    if let Ok(url) = std::env::var("NODE_RPC_URL") {
+        let user = std::env::var("NODE_RPC_USER")?;
+        let password = std::env::var("NODE_RPC_PASSWORD")?;
+        eprintln!("txs by hash from the node at {}, address and EXCH queries from BitDB", url);
+        return Ok(Box::new(RpcChainSource::new(url, user, password)));
+    }
+    Ok(Box::new(BitDbChainSource::new()))
+}
+
 fn index(r: HttpRequest,
          stream: web::Payload,
          path: web::Path<(String,)>,
@@ -33,23 +106,132 @@ fn index(r: HttpRequest,
     ws::start(WsActor::new(address, tx.get_ref().clone()), &r, stream)
 }
 
+/// Relays a raw tx (hex body) to our peers if it passes the pre-validation.
+fn submit_tx(body: String, tx: web::Data<Addr<TxActor>>)
+        -> impl Future<Item=HttpResponse, Error=actix_web::Error> {
+    let tx = tx.get_ref().clone();
+    futures::future::result(parse_submitted_tx(&body))
+        .and_then(move |parsed_tx| tx.send(SubmitTx(parsed_tx)).from_err().and_then(|x| x))
+        .then(|result| Ok(match result {
+            Ok(tx_hash) => HttpResponse::Ok()
+                .content_type("application/json")
+                .body(object!{"tx" => tx_hash_to_hex(&tx_hash)}.dump()),
+            Err(err) => HttpResponse::BadRequest()
+                .content_type("application/json")
+                .body(object!{"error" => err.to_string()}.dump()),
+        }))
+}
+
+/// Outgoing txs that were rejected or haven't confirmed in time, for operators.
+fn stuck_txs(outgoing_tx: web::Data<Addr<OutgoingTxActor>>)
+        -> impl Future<Item=HttpResponse, Error=actix_web::Error> {
+    outgoing_tx.send(FetchStuckOutgoingTxs)
+        .from_err()
+        .and_then(|stuck_txs| {
+            let stuck_txs = stuck_txs.map_err(|err| actix_web::error::ErrorInternalServerError(err))?;
+            let json = JsonValue::Array(stuck_txs.iter()
+                .map(|stuck_tx| object!{
+                    "tx" => tx_hash_to_hex(&stuck_tx.hash),
+                    "kind" => format!("{:?}", stuck_tx.kind),
+                    "status" => format!("{:?}", stuck_tx.status),
+                    "rejectReason" => stuck_tx.reject_reason.clone(),
+                    "firstBroadcast" => stuck_tx.first_broadcast,
+                    "lastBroadcast" => stuck_tx.last_broadcast,
+                    "numBroadcasts" => stuck_tx.n_broadcasts,
+                    "numRejects" => stuck_tx.n_rejects,
+                })
+                .collect());
+            Ok(HttpResponse::Ok().content_type("application/json").body(json.dump()))
+        })
+}
+
+/// Burns of the token, newest last.
+fn token_burns(path: web::Path<(String,)>, db: web::Data<Addr<DbActor>>)
+        -> impl Future<Item=HttpResponse, Error=actix_web::Error> {
+    let token_hash = tx_hex_to_hash(&path.0)
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("invalid token id"));
+    let db = db.get_ref().clone();
+    futures::future::result(token_hash)
+        .and_then(move |token_hash| db.send(FetchTokenBurns { token_hash }).from_err())
+        .and_then(|burns| {
+            let burns = burns.map_err(|err| actix_web::error::ErrorInternalServerError(err))?;
+            let json = JsonValue::Array(burns.iter()
+                .map(|burn| object!{
+                    "tx" => tx_hash_to_hex(&burn.tx_hash),
+                    "kind" => format!("{:?}", burn.kind),
+                    "amount" => burn.amount.to_string(),
+                    "timestamp" => burn.timestamp,
+                })
+                .collect());
+            Ok(HttpResponse::Ok().content_type("application/json").body(json.dump()))
+        })
+}
+
+#[derive(Deserialize)]
+struct CandlesQuery {
+    #[serde(default)]
+    interval: CandleInterval,
+}
+
+/// OHLC candles of the token's fills, oldest first; `?interval=` one of 5m, 1h (default), 4h, 1d.
+fn token_candles(path: web::Path<(String,)>,
+                 query: web::Query<CandlesQuery>,
+                 db: web::Data<Addr<DbActor>>)
+        -> impl Future<Item=HttpResponse, Error=actix_web::Error> {
+    let token_hash = tx_hex_to_hash(&path.0)
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("invalid token id"));
+    let interval = query.interval;
+    let db = db.get_ref().clone();
+    futures::future::result(token_hash)
+        .and_then(move |token_hash| db.send(FetchCandles { token_hash, interval }).from_err())
+        .and_then(|candles| {
+            let candles = candles.map_err(|err| actix_web::error::ErrorInternalServerError(err))?;
+            let json = JsonValue::Array(candles.iter().map(candle_json).collect());
+            Ok(HttpResponse::Ok().content_type("application/json").body(json.dump()))
+        })
+}
+
+/// Token ids of the pandas which have been burned.
+fn destroyed_pandas(db: web::Data<Addr<DbActor>>, config: web::Data<SLPDEXConfig>)
+        -> impl Future<Item=HttpResponse, Error=actix_web::Error> {
+    db.send(FetchDestroyedNFTs { group_hash: config.panda_token_hash })
+        .from_err()
+        .and_then(|token_hashes| {
+            let token_hashes = token_hashes
+                .map_err(|err| actix_web::error::ErrorInternalServerError(err))?;
+            let json = JsonValue::Array(token_hashes.iter()
+                .map(|token_hash| tx_hash_to_hex(token_hash).into())
+                .collect());
+            Ok(HttpResponse::Ok().content_type("application/json").body(json.dump()))
+        })
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     std::env::set_var("RUST_LOG", "actix_server=info,actix_web=info");
     env_logger::init();
     let port = std::env::var("PORT").unwrap_or("7501".to_string());
+    let config = operator_config()?;
+    // fail before starting anything if the wallet can't be opened or doesn't match the config
+    let wallet = operator_wallet(&config)?;
     actix::System::run(move || {
-        let secret = hex::decode(std::env::var("SECRET").unwrap()).unwrap();
+        let resync_config = config.clone();
         let resync_addr = SyncArbiter::start(1, move || {
-            ResyncActor::new(connect_db(), SLPDEXConfig::default(), secret.clone())
+            let wallet = wallet.clone();
+            let signer = operator_signer(wallet.as_ref()).unwrap();
+            let chain_source = chain_source().unwrap();
+            ResyncActor::new(connect_db(), resync_config.clone(), chain_source, signer, wallet)
         });
-        let db_addr = actors::DbActor::create().unwrap();
+        let endpoint_db_addr = actors::DbActor::create().unwrap();
         let db_addr = slpdexdb_node::DbActor::start(slpdexdb_node::DbActor {
-            add_header_query: db_addr.clone().recipient(),
-            header_tip_query: db_addr.recipient(),
+            add_header_query: endpoint_db_addr.clone().recipient(),
+            header_tip_query: endpoint_db_addr.clone().recipient(),
         });
-        let tx_addr = TxActor::start_with(Arc::new(Mutex::new(connect_db())), SLPDEXConfig::default(), resync_addr.clone());
-        let peers_addr = PeersActor::start(PeersActor::new(tx_addr.clone(), db_addr));
+        let tx_addr = TxActor::start_with(Arc::new(Mutex::new(connect_db())), config.clone(), resync_addr.clone());
+        let outgoing_tx_addr = OutgoingTxActor::new(Arc::new(Mutex::new(connect_db()))).start();
+        let peers_addr = PeersActor::start(PeersActor::new(tx_addr.clone(), db_addr, outgoing_tx_addr.clone()));
         resync_addr.do_send(RegisterOutgoing {recipient: peers_addr.clone().recipient()} );
+        outgoing_tx_addr.do_send(RegisterOutgoing {recipient: peers_addr.clone().recipient()} );
+        tx_addr.do_send(RegisterOutgoing {recipient: peers_addr.clone().recipient()} );
         //let socket_addr = net::SocketAddr::from_str("147.135.131.25:8333").unwrap();
         //let socket_addr = net::SocketAddr::from_str("100.1.209.114:8333").unwrap();
         let socket_addr = net::SocketAddr::from_str("137.74.30.99:8333").unwrap();
@@ -65,9 +247,27 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             App::new()
                 .wrap(middleware::Logger::default())
                 .data(tx_addr.clone())
+                .data(outgoing_tx_addr.clone())
+                .data(endpoint_db_addr.clone())
+                .data(config.clone())
                 .service(
                     web::resource("/ws/{address}").route(web::get().to(index))
                 )
+                .service(
+                    web::resource("/tx").route(web::post().to_async(submit_tx))
+                )
+                .service(
+                    web::resource("/outgoing/stuck").route(web::get().to_async(stuck_txs))
+                )
+                .service(
+                    web::resource("/token/{token_id}/burns").route(web::get().to_async(token_burns))
+                )
+                .service(
+                    web::resource("/token/{token_id}/candles").route(web::get().to_async(token_candles))
+                )
+                .service(
+                    web::resource("/pandas/destroyed").route(web::get().to_async(destroyed_pandas))
+                )
         })
             .bind(format!("127.0.0.1:{}", port)).unwrap()
             .start();
@@ -3,7 +3,10 @@ use cashcontracts::Address;
 use slpdexdb_base::Error;
 use std::net;
 use slpdexdb_base::{SLPDEXConfig, BlockHeader};
-use slpdexdb_db::{Db, Utxo, SpentUtxo, TxDelta, TradeOfferFilter, TradeOffer, TxHistory};
+use slpdexdb_db::{Db, Utxo, SpentUtxo, TxDelta, TradeOfferFilter, TradeOffer, TxHistory, PandaForSale,
+                  PandaSaleFilter, AuctionListing, OutgoingTx, SupplyChange, OrderBook, OrderBookDelta,
+                  TradeSpend, Candle, CandleInterval};
+use slpdexdb_db::exch::ExchVersion;
 use slpdexdb_node::actors::{OutgoingMsg};
 use std::collections::{HashSet, HashMap};
 use std::sync::{Arc, Mutex};
@@ -42,6 +45,36 @@ impl Message for FetchTradeOfferUtxos {
     type Result = Result<Vec<TradeOffer>, Error>;
 }
 
+/// Current order book of a token, built from its live trade offers of `exch_versions`, or of all
+/// versions if `None`.
+pub struct FetchOrderBook {
+    pub token_hash: [u8; 32],
+    pub exch_versions: Option<Vec<ExchVersion>>,
+}
+
+impl Message for FetchOrderBook {
+    type Result = Result<OrderBook, Error>;
+}
+
+/// Number of candles returned by `FetchCandles`.
+pub const CANDLE_COUNT: i64 = 200;
+
+/// Most recent OHLC candles of the fills of a token.
+pub struct FetchCandles {
+    pub token_hash: [u8; 32],
+    pub interval: CandleInterval,
+}
+
+impl Message for FetchCandles {
+    type Result = Result<Vec<Candle>, Error>;
+}
+
+pub struct FetchPandasForSale(pub PandaSaleFilter);
+
+impl Message for FetchPandasForSale {
+    type Result = Result<Vec<PandaForSale>, Error>;
+}
+
 pub struct FetchAddressUtxos(pub Address);
 
 impl Message for FetchAddressUtxos {
@@ -57,6 +90,8 @@ impl Message for FetchAddressTxDeltas {
 pub enum SubscribeToEvent {
     Address(Address, Recipient<TxEvent>),
     Tokens(Vec<[u8; 32]>, Recipient<TxEvent>),
+    OrderBooks(Vec<[u8; 32]>, Recipient<TxEvent>),
+    PandaMarket(Recipient<TxEvent>),
 }
 
 impl Message for SubscribeToEvent {
@@ -65,12 +100,32 @@ impl Message for SubscribeToEvent {
 
 pub enum UnsubscribeFromEvent {
     Address(Address, Recipient<TxEvent>),
+    OrderBooks(Recipient<TxEvent>),
+    PandaMarket(Recipient<TxEvent>),
 }
 
 impl Message for UnsubscribeFromEvent {
     type Result = ();
 }
 
+#[derive(Clone)]
+pub enum AuctionEvent {
+    Listed(AuctionListing),
+    PriceTick {
+        height: i32,
+        auctions: Vec<AuctionListing>,
+    },
+    Sold {
+        auction: AuctionListing,
+        tx_hash: [u8; 32],
+        price: u64,
+    },
+    Cancelled {
+        auction: AuctionListing,
+        tx_hash: [u8; 32],
+    },
+}
+
 #[derive(Clone)]
 pub enum TxEvent {
     AddressUtxoDelta {
@@ -85,6 +140,23 @@ pub enum TxEvent {
     AddressNewTxDeltas {
         tx_deltas: Arc<Vec<TxDelta>>,
     },
+    OrderBookSnapshot {
+        order_book: Arc<OrderBook>,
+    },
+    OrderBookDelta {
+        delta: Arc<OrderBookDelta>,
+    },
+    TradeFills {
+        token_hash: [u8; 32],
+        fills: Arc<Vec<TradeSpend>>,
+    },
+    PandaTradeOfferUtxoDelta {
+        add_utxos: Arc<Vec<PandaForSale>>,
+        remove_utxos: Arc<Vec<SpentUtxo>>,
+    },
+    PandaAuctions {
+        events: Arc<Vec<AuctionEvent>>,
+    },
 }
 
 impl Message for TxEvent {
@@ -108,6 +180,23 @@ pub enum TxBroadcastEvent {
         tx_deltas: HashMap<Address, Vec<TxDelta>>,
         subscribers: SyncTxSubscribers,
     },
+    OrderBookDeltas {
+        deltas: Vec<OrderBookDelta>,
+        subscribers: SyncTxSubscribers,
+    },
+    TradeFills {
+        fills: HashMap<[u8; 32], Vec<TradeSpend>>,
+        subscribers: SyncTxSubscribers,
+    },
+    PandaTradeOfferUtxoDelta {
+        add_utxos: Vec<PandaForSale>,
+        remove_utxos: Vec<SpentUtxo>,
+        subscribers: SyncTxSubscribers,
+    },
+    PandaAuctions {
+        events: Vec<AuctionEvent>,
+        subscribers: SyncTxSubscribers,
+    },
 }
 
 impl Message for TxBroadcastEvent {
@@ -152,6 +241,13 @@ impl Message for ProcessBlock {
     type Result = Result<(), Error>;
 }
 
+/// Raw tx submitted by a client, to be checked and relayed to our peers.
+pub struct SubmitTx(pub cashcontracts::Tx);
+
+impl Message for SubmitTx {
+    type Result = Result<[u8; 32], Error>;
+}
+
 pub struct RegisterOutgoing {
     pub recipient: Recipient<OutgoingMsg>,
 }
@@ -159,3 +255,28 @@ pub struct RegisterOutgoing {
 impl Message for RegisterOutgoing {
     type Result = ();
 }
+
+/// Outgoing txs which were rejected or haven't confirmed for a long time.
+pub struct FetchStuckOutgoingTxs;
+
+impl Message for FetchStuckOutgoingTxs {
+    type Result = Result<Vec<OutgoingTx>, Error>;
+}
+
+/// Burn history of a token.
+pub struct FetchTokenBurns {
+    pub token_hash: [u8; 32],
+}
+
+impl Message for FetchTokenBurns {
+    type Result = Result<Vec<SupplyChange>, Error>;
+}
+
+/// NFT1 children of a group which have been burned, e.g. destroyed pandas.
+pub struct FetchDestroyedNFTs {
+    pub group_hash: [u8; 32],
+}
+
+impl Message for FetchDestroyedNFTs {
+    type Result = Result<Vec<[u8; 32]>, Error>;
+}
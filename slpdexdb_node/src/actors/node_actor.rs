@@ -11,10 +11,11 @@ use slpdexdb_base::Error;
 
 use crate::codec::MessageCodec;
 use crate::message::NodeMessage;
-use crate::messages::{VersionMessage, VerackMessage, InvMessage, HeadersMessage, TxMessage, BlockMessage};
+use crate::messages::{VersionMessage, VerackMessage, InvMessage, HeadersMessage, TxMessage, BlockMessage,
+                      FeeFilterMessage, RejectMessage};
 use crate::message_packet::MessagePacket;
 use crate::actors::{VersionActor, InvActor, BlockHeaderActor};
-use crate::msg::{Subscribe, HandshakeSuccess};
+use crate::msg::{Subscribe, HandshakeSuccess, PeerFeeFilter, PeerDisconnected};
 use crate::db_query::DbActor;
 
 pub struct IncomingMsg<M: NodeMessage>(pub Arc<M>);
@@ -32,6 +33,7 @@ impl Message for OutgoingMsg {
 
 pub struct NodeActor {
     framed: actix::io::FramedWrite<WriteHalf<TcpStream>, MessageCodec>,
+    peer_addr: SocketAddr,
 
     subscribers_version: Vec<Recipient<IncomingMsg<VersionMessage>>>,
     subscribers_verack: Vec<Recipient<IncomingMsg<VerackMessage>>>,
@@ -40,6 +42,9 @@ pub struct NodeActor {
     subscribers_tx: Vec<Recipient<IncomingMsg<TxMessage>>>,
     subscribers_handshake: Vec<Recipient<HandshakeSuccess>>,
     subscribers_block: Vec<Recipient<IncomingMsg<BlockMessage>>>,
+    subscribers_fee_filter: Vec<Recipient<PeerFeeFilter>>,
+    subscribers_reject: Vec<Recipient<IncomingMsg<RejectMessage>>>,
+    subscribers_disconnected: Vec<Recipient<PeerDisconnected>>,
 }
 
 impl NodeActor {
@@ -57,6 +62,7 @@ impl NodeActor {
                     MessageCodec,
                     ctx,
                 ),
+                peer_addr,
                 subscribers_handshake: Vec::new(),
                 subscribers_inv: Vec::new(),
                 subscribers_version: Vec::new(),
@@ -64,6 +70,9 @@ impl NodeActor {
                 subscribers_headers: Vec::new(),
                 subscribers_tx: Vec::new(),
                 subscribers_block: Vec::new(),
+                subscribers_fee_filter: Vec::new(),
+                subscribers_reject: Vec::new(),
+                subscribers_disconnected: Vec::new(),
             }
         });
         InvActor::start(InvActor { node: addr.clone() });
@@ -79,10 +88,24 @@ impl NodeActor {
             sub.do_send(IncomingMsg(Arc::clone(&msg)));
         }
     }
+
+    fn _broadcast_fee_filter(&self, msg: MessagePacket) {
+        let mut cur = io::Cursor::new(msg.payload());
+        let msg = FeeFilterMessage::from_stream(&mut cur).unwrap();  // TODO: handle error
+        for sub in self.subscribers_fee_filter.iter() {
+            sub.do_send(PeerFeeFilter { peer_addr: self.peer_addr, fee_per_kb: msg.fee_per_kb });
+        }
+    }
 }
 
 impl Actor for NodeActor {
     type Context = Context<Self>;
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        for sub in self.subscribers_disconnected.iter() {
+            sub.do_send(PeerDisconnected { peer_addr: self.peer_addr });
+        }
+    }
 }
 
 impl actix::io::WriteHandler<io::Error> for NodeActor {
@@ -102,6 +125,8 @@ impl StreamHandler<MessagePacket, io::Error> for NodeActor {
             b"headers" => Self::_broadcast(msg, &self.subscribers_headers),
             b"verack" => Self::_broadcast(msg, &self.subscribers_verack),
             b"tx" => Self::_broadcast(msg, &self.subscribers_tx),
+            b"feefilter" => self._broadcast_fee_filter(msg),
+            b"reject" => Self::_broadcast(msg, &self.subscribers_reject),
             b"block" => {
                 println!("block msg: {}", msg);
                 Self::_broadcast(msg, &self.subscribers_block)
@@ -124,6 +149,9 @@ impl Handler<Subscribe> for NodeActor {
             Subscribe::Headers(recipient) => self.subscribers_headers.push(recipient),
             Subscribe::Tx(recipient) => self.subscribers_tx.push(recipient),
             Subscribe::Block(recipient) => self.subscribers_block.push(recipient),
+            Subscribe::FeeFilter(recipient) => self.subscribers_fee_filter.push(recipient),
+            Subscribe::Reject(recipient) => self.subscribers_reject.push(recipient),
+            Subscribe::Disconnected(recipient) => self.subscribers_disconnected.push(recipient),
         }
     }
 }
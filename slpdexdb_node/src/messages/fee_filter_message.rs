@@ -0,0 +1,26 @@
+use crate::message_packet::MessagePacket;
+use crate::message::NodeMessage;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io;
+
+/// BIP133: the peer doesn't relay txs paying less than `fee_per_kb` satoshis per 1000 bytes.
+#[derive(Clone, Debug)]
+pub struct FeeFilterMessage {
+    pub fee_per_kb: u64,
+}
+
+impl NodeMessage for FeeFilterMessage {
+    fn command() -> &'static [u8] {
+        b"feefilter"
+    }
+
+    fn packet(&self) -> MessagePacket {
+        let mut payload = Vec::new();
+        payload.write_u64::<LittleEndian>(self.fee_per_kb).unwrap();
+        MessagePacket::from_payload(Self::command(), payload)
+    }
+
+    fn from_stream(stream: &mut impl io::Read) -> io::Result<Self> {
+        Ok(FeeFilterMessage { fee_per_kb: stream.read_u64::<LittleEndian>()? })
+    }
+}
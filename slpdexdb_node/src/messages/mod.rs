@@ -5,11 +5,15 @@ mod inv_message;
 mod tx_message;
 mod version_message;
 mod block_message;
+mod fee_filter_message;
+mod reject_message;
 
 pub use block_message::*;
+pub use fee_filter_message::*;
 pub use get_data_message::*;
 pub use get_headers_message::*;
 pub use headers_message::*;
 pub use inv_message::*;
+pub use reject_message::*;
 pub use tx_message::*;
 pub use version_message::*;
@@ -0,0 +1,90 @@
+use crate::message_packet::MessagePacket;
+use crate::message::NodeMessage;
+use cashcontracts::serialize::{read_var_str, write_var_str};
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use std::io::{self, Read, Write};
+
+/// Sent by a peer if it didn't accept a message, e.g. a tx below its fee filter.
+#[derive(Clone, Debug)]
+pub struct RejectMessage {
+    /// Command of the rejected message, e.g. `tx`
+    pub message: Vec<u8>,
+    pub ccode: u8,
+    pub reason: Vec<u8>,
+    /// Hash of the rejected tx or block, if any
+    pub hash: Option<[u8; 32]>,
+}
+
+impl NodeMessage for RejectMessage {
+    fn command() -> &'static [u8] {
+        b"reject"
+    }
+
+    fn packet(&self) -> MessagePacket {
+        let mut payload = Vec::new();
+        write_var_str(&mut payload, &self.message).unwrap();
+        payload.write_u8(self.ccode).unwrap();
+        write_var_str(&mut payload, &self.reason).unwrap();
+        if let Some(hash) = &self.hash {
+            payload.write(hash).unwrap();
+        }
+        MessagePacket::from_payload(Self::command(), payload)
+    }
+
+    fn from_stream(stream: &mut impl io::Read) -> io::Result<Self> {
+        let message = read_var_str(stream)?;
+        let ccode = stream.read_u8()?;
+        let reason = read_var_str(stream)?;
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data)?;
+        let hash = if data.len() >= 32 {
+            let mut hash = [0; 32];
+            hash.copy_from_slice(&data[..32]);
+            Some(hash)
+        } else {
+            None
+        };
+        Ok(RejectMessage { message, ccode, reason, hash })
+    }
+}
+
+impl RejectMessage {
+    pub fn reason_str(&self) -> String {
+        String::from_utf8_lossy(&self.reason).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reject_tx() {
+        // reject of a tx below the peer's fee filter, as sent by Bitcoin ABC
+        let mut payload = b"\x02tx".to_vec();
+        payload.push(0x42);
+        payload.extend_from_slice(b"\x15insufficient priority");
+        payload.extend_from_slice(&[0xab; 32]);
+        let reject = RejectMessage::from_stream(&mut io::Cursor::new(&payload)).unwrap();
+        assert_eq!(reject.message, b"tx");
+        assert_eq!(reject.ccode, 0x42);
+        assert_eq!(reject.reason_str(), "insufficient priority");
+        assert_eq!(reject.hash, Some([0xab; 32]));
+        assert_eq!(reject.packet().payload(), &payload[..]);
+    }
+
+    #[test]
+    fn test_reject_without_hash() {
+        let reject = RejectMessage {
+            message: b"version".to_vec(),
+            ccode: 0x11,
+            reason: b"obsolete".to_vec(),
+            hash: None,
+        };
+        let parsed = RejectMessage::from_stream(&mut io::Cursor::new(reject.packet().payload())).unwrap();
+        assert_eq!(parsed.message, b"version");
+        assert_eq!(parsed.ccode, 0x11);
+        assert_eq!(parsed.reason_str(), "obsolete");
+        assert_eq!(parsed.hash, None);
+    }
+}
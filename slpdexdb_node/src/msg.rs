@@ -1,7 +1,9 @@
 use actix::prelude::*;
+use std::net::SocketAddr;
 use slpdexdb_base::Error;
 use crate::actors::{NodeActor, IncomingMsg};
-use crate::messages::{VersionMessage, VerackMessage, InvMessage, HeadersMessage, TxMessage, BlockMessage};
+use crate::messages::{VersionMessage, VerackMessage, InvMessage, HeadersMessage, TxMessage, BlockMessage,
+                      RejectMessage};
 
 pub enum Subscribe {
     HandshakeSuccess(Recipient<HandshakeSuccess>),
@@ -11,6 +13,9 @@ pub enum Subscribe {
     Headers(Recipient<IncomingMsg<HeadersMessage>>),
     Tx(Recipient<IncomingMsg<TxMessage>>),
     Block(Recipient<IncomingMsg<BlockMessage>>),
+    FeeFilter(Recipient<PeerFeeFilter>),
+    Reject(Recipient<IncomingMsg<RejectMessage>>),
+    Disconnected(Recipient<PeerDisconnected>),
 }
 
 impl Message for Subscribe {
@@ -23,3 +28,23 @@ pub struct HandshakeSuccess;
 impl Message for HandshakeSuccess {
     type Result = Result<(), Error>;
 }
+
+/// A `feefilter` message, along with the peer which sent it.
+#[derive(Clone)]
+pub struct PeerFeeFilter {
+    pub peer_addr: SocketAddr,
+    pub fee_per_kb: u64,
+}
+
+impl Message for PeerFeeFilter {
+    type Result = Result<(), Error>;
+}
+
+#[derive(Clone)]
+pub struct PeerDisconnected {
+    pub peer_addr: SocketAddr,
+}
+
+impl Message for PeerDisconnected {
+    type Result = ();
+}
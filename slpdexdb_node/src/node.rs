@@ -8,7 +8,7 @@ use crate::messages::{
 };
 use crate::message::Message;
 use crate::tx_history::TxHistory;
-use crate::tx_source::TxSource;
+use crate::chain_source::BitDbChainSource;
 use crate::db::Db;
 use crate::config::SLPDEXConfig;
 use crate::adapter::NodeAdapter;
@@ -106,7 +106,7 @@ impl<A: NodeAdapter> Node<A> {
                         _ => {},
                     };
                 });
-                tx_history.validate_slp(&TxSource::new(), db, config)?;
+                tx_history.validate_slp(&BitDbChainSource::new(), db, config)?;
                 tx_history.txs.iter().for_each(|tx| {
                     match &tx.tx_type {
                         crate::tx_history::TxType::SLP {token_hash, ..} => println!("valid SLP token={}", tx_hash_to_hex(token_hash)),